@@ -0,0 +1,71 @@
+//! `cargo bench -p actually_usable_voxel_mesh_gen`, prints time and triangles per mesher.
+
+use std::{
+    collections::HashSet,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use actually_usable_voxel_mesh_gen::{
+    data::{ChunkData, MeshingMode, Side},
+    generate_indexed_mesh_for_chunk,
+};
+use common::Pos3;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const SIZE: i32 = 16;
+const RUNS: u32 = 50;
+
+struct BenchChunk {
+    blocks: HashSet<Pos3>,
+}
+
+impl ChunkData for BenchChunk {
+    fn has_neighbour(&self, pos: &Pos3, side: &Side) -> bool {
+        self.does_block_exits(&(*pos + side.side_to_rel_pos()))
+    }
+    fn get_chunk_size(&self) -> i32 {
+        SIZE
+    }
+    fn get_color_seed_for_block(&self, pos: &Pos3) -> String {
+        match pos.y % 4 {
+            0 => "minecraft:dirt".into(),
+            _ => "minecraft:stone".into(),
+        }
+    }
+    fn does_block_exits(&self, pos: &Pos3) -> bool {
+        self.blocks.contains(pos)
+    }
+}
+
+fn chunk(mut f: impl FnMut(Pos3) -> bool) -> BenchChunk {
+    let blocks = (0..SIZE)
+        .flat_map(|x| (0..SIZE).flat_map(move |y| (0..SIZE).map(move |z| Pos3::new(x, y, z))))
+        .filter(|p| f(*p))
+        .collect();
+    BenchChunk { blocks }
+}
+
+fn bench(name: &str, chunk: &BenchChunk) {
+    for mode in [MeshingMode::Naive, MeshingMode::Greedy] {
+        let mut total = Duration::ZERO;
+        let mut triangles = 0;
+        for _ in 0..RUNS {
+            let start = Instant::now();
+            let mesh = black_box(generate_indexed_mesh_for_chunk(black_box(chunk), mode));
+            total += start.elapsed();
+            triangles = mesh.triangle_count();
+        }
+        println!(
+            "{name:<10} {mode:<7?} {:>10.2?} {triangles:>7} triangles",
+            total / RUNS
+        );
+    }
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(0);
+    bench("solid", &chunk(|_| true));
+    bench("terrain", &chunk(|p| p.y < 6 + (p.x + p.z) % 4));
+    bench("random", &chunk(|_| rng.gen_bool(0.5)));
+}
@@ -1,5 +1,6 @@
 use common::Pos3;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Side {
     PosX,
     NegX,
@@ -10,6 +11,14 @@ pub enum Side {
 }
 
 impl Side {
+    pub const ALL: [Side; 6] = [
+        Side::PosX,
+        Side::NegX,
+        Side::PosY,
+        Side::NegY,
+        Side::PosZ,
+        Side::NegZ,
+    ];
     pub fn side_to_rel_pos(&self) -> Pos3 {
         match self {
             Side::NegX => Pos3 { x: -1, y: 0, z: 0 },
@@ -23,10 +32,31 @@ impl Side {
 }
 
 pub trait ChunkData: std::marker::Send + std::marker::Sync {
-    fn has_neighbour(&self, pos: &Pos3, side: &Side) -> bool;
+    /// Whether the block next to `pos` on `side` hides that face. Looks into the neighbouring
+    /// chunk for blocks on the border, see [`ChunkData::get_neighbour_chunk`]
+    fn has_neighbour(&self, pos: &Pos3, side: &Side) -> bool {
+        let size = self.get_chunk_size();
+        let neighbour_pos = *pos + side.side_to_rel_pos();
+        let inside = |c: i32| (0..size).contains(&c);
+        if inside(neighbour_pos.x) && inside(neighbour_pos.y) && inside(neighbour_pos.z) {
+            return self.does_block_exits(&neighbour_pos);
+        }
+        let wrapped = Pos3::new(
+            neighbour_pos.x.rem_euclid(size),
+            neighbour_pos.y.rem_euclid(size),
+            neighbour_pos.z.rem_euclid(size),
+        );
+        self.get_neighbour_chunk(side)
+            .is_some_and(|chunk| chunk.does_block_exits(&wrapped))
+    }
     fn get_chunk_size(&self) -> i32;
     fn get_color_seed_for_block(&self, pos: &Pos3) -> String;
     fn does_block_exits(&self, pos: &Pos3) -> bool;
+    /// The chunk touching this one on `side`, if it is known. Without it border faces are always
+    /// drawn
+    fn get_neighbour_chunk(&self, _side: &Side) -> Option<&dyn ChunkData> {
+        None
+    }
 }
 
 pub struct BlockFaceData {
@@ -34,3 +64,41 @@ pub struct BlockFaceData {
     pub normal: Pos3,
     pub color: [u8; 3],
 }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum MeshingMode {
+    /// One quad per visible block face
+    Naive,
+    /// Coplanar faces of the same color get merged into as few quads as possible
+    #[default]
+    Greedy,
+}
+
+/// Indexed triangle list, every quad shares its 4 vertices between its 2 triangles
+#[derive(Clone, Debug, Default)]
+pub struct IndexedMesh {
+    pub positions: Vec<Pos3>,
+    pub normals: Vec<Pos3>,
+    pub colors: Vec<[u8; 3]>,
+    pub indices: Vec<u32>,
+}
+
+impl IndexedMesh {
+    pub fn from_faces(faces: &[BlockFaceData]) -> IndexedMesh {
+        let mut mesh = IndexedMesh::default();
+        for face in faces {
+            let base = mesh.positions.len() as u32;
+            // faces come as the triangles (0, 1, 2) and (2, 1, 3) of a quad
+            let [a, b, c, _, _, d] = face.vertecies;
+            mesh.positions.extend([a, b, c, d]);
+            mesh.normals.extend([face.normal; 4]);
+            mesh.colors.extend([face.color; 4]);
+            mesh.indices
+                .extend([base, base + 1, base + 2, base + 2, base + 1, base + 3]);
+        }
+        mesh
+    }
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+}
@@ -0,0 +1,104 @@
+//! Greedy meshing: every slice of a chunk facing one side is a 2d grid of face colors, which
+//! gets covered with as few rectangles as possible.
+
+use common::Pos3;
+use rayon::prelude::*;
+
+use crate::{
+    block_color,
+    data::{BlockFaceData, ChunkData, Side},
+    stuff::get_vertecies_for_quad,
+};
+
+/// Same output as [`crate::generate_mesh_for_chunk`], but neighbouring faces that share a plane
+/// and a color come out as one quad
+pub fn generate_greedy_mesh_for_chunk(chunk_data: &impl ChunkData) -> Vec<BlockFaceData> {
+    let size = chunk_data.get_chunk_size();
+    // hashing the color seed is the slow part, so do it once per block instead of once per face
+    let colors = (0..size * size * size)
+        .into_par_iter()
+        .map(|i| {
+            let pos = Pos3::new(i % size, i / size % size, i / (size * size));
+            chunk_data
+                .does_block_exits(&pos)
+                .then(|| block_color(chunk_data, &pos))
+        })
+        .collect::<Vec<_>>();
+    Side::ALL
+        .into_par_iter()
+        .flat_map(|side| (0..size).into_par_iter().map(move |d| (side, d)))
+        .flat_map_iter(|(side, d)| mesh_slice(chunk_data, &colors, side, d))
+        .collect()
+}
+
+/// Index of the axis `side` faces along, then the two axes spanning its plane
+fn axes(side: &Side) -> [usize; 3] {
+    match side {
+        Side::PosX | Side::NegX => [0, 1, 2],
+        Side::PosY | Side::NegY => [1, 0, 2],
+        Side::PosZ | Side::NegZ => [2, 0, 1],
+    }
+}
+
+fn pos_on_axes(axes: [usize; 3], d: i32, u: i32, v: i32) -> Pos3 {
+    let mut p = [0; 3];
+    p[axes[0]] = d;
+    p[axes[1]] = u;
+    p[axes[2]] = v;
+    Pos3::new(p[0], p[1], p[2])
+}
+
+/// Quads for the faces of `side` in the layer `d` blocks along its axis
+fn mesh_slice(
+    chunk_data: &impl ChunkData,
+    colors: &[Option<[u8; 3]>],
+    side: Side,
+    d: i32,
+) -> Vec<BlockFaceData> {
+    let size = chunk_data.get_chunk_size();
+    let axes = axes(&side);
+    let idx = |u: i32, v: i32| (v * size + u) as usize;
+    let mut mask = (0..size)
+        .flat_map(|v| (0..size).map(move |u| (u, v)))
+        .map(|(u, v)| {
+            let pos = pos_on_axes(axes, d, u, v);
+            let color = colors[(pos.x + pos.y * size + pos.z * size * size) as usize];
+            color.filter(|_| !chunk_data.has_neighbour(&pos, &side))
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = Vec::new();
+    for v in 0..size {
+        let mut u = 0;
+        while u < size {
+            let Some(color) = mask[idx(u, v)] else {
+                u += 1;
+                continue;
+            };
+            let mut w = 1;
+            while u + w < size && mask[idx(u + w, v)] == Some(color) {
+                w += 1;
+            }
+            let mut h = 1;
+            while v + h < size && (u..u + w).all(|u| mask[idx(u, v + h)] == Some(color)) {
+                h += 1;
+            }
+            for dv in 0..h {
+                for du in 0..w {
+                    mask[idx(u + du, v + dv)] = None;
+                }
+            }
+
+            let origin = pos_on_axes(axes, d, u, v);
+            let vertecies =
+                get_vertecies_for_quad(&side, pos_on_axes(axes, 1, w, h)).map(|p| p + origin);
+            out.push(BlockFaceData {
+                vertecies,
+                normal: side.side_to_rel_pos(),
+                color,
+            });
+            u += w;
+        }
+    }
+    out
+}
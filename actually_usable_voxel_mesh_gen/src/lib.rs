@@ -1,7 +1,9 @@
-use data::{BlockFaceData, ChunkData, Side};
+use data::{BlockFaceData, ChunkData, IndexedMesh, MeshingMode, Side};
+use greedy::generate_greedy_mesh_for_chunk;
 use rayon::prelude::*;
 use stuff::get_vertecies_from_side;
 pub mod data;
+pub mod greedy;
 mod stuff;
 pub mod util;
 use common::Pos3;
@@ -37,26 +39,16 @@ fn get_face_data_for_block(chunk_data: &impl ChunkData, pos: Pos3) -> Vec<BlockF
     if !chunk_data.does_block_exits(&pos) {
         return out;
     }
-    let color: [u8; 3] = util::string_to_color(&chunk_data.get_color_seed_for_block(&pos))[0..3]
-        .try_into()
-        .unwrap();
-    for side in [
-        Side::PosX,
-        Side::NegX,
-        Side::PosY,
-        Side::NegY,
-        Side::PosZ,
-        Side::NegZ,
-    ] {
+    let color = block_color(chunk_data, &pos);
+    for side in Side::ALL {
         if !chunk_data.has_neighbour(&pos, &side) {
             let vertecies = get_vertecies_from_side(&side)
                 .into_iter()
-                .map(|p| p + &pos)
+                .map(|p| p + pos)
                 .collect::<Vec<Pos3>>()
                 .try_into()
                 .unwrap();
             let normal = side.side_to_rel_pos();
-            let color = color.clone();
             let data = BlockFaceData {
                 color,
                 normal,
@@ -67,3 +59,21 @@ fn get_face_data_for_block(chunk_data: &impl ChunkData, pos: Pos3) -> Vec<BlockF
     }
     out
 }
+
+/// Both meshers as indexed geometry, use [`MeshingMode::Naive`] for debugging the greedy one
+pub fn generate_indexed_mesh_for_chunk(
+    chunk_data: &impl ChunkData,
+    mode: MeshingMode,
+) -> IndexedMesh {
+    let faces = match mode {
+        MeshingMode::Naive => generate_mesh_for_chunk(chunk_data),
+        MeshingMode::Greedy => generate_greedy_mesh_for_chunk(chunk_data),
+    };
+    IndexedMesh::from_faces(&faces)
+}
+
+fn block_color(chunk_data: &impl ChunkData, pos: &Pos3) -> [u8; 3] {
+    util::string_to_color(&chunk_data.get_color_seed_for_block(pos))[0..3]
+        .try_into()
+        .unwrap()
+}
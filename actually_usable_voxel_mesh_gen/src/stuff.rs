@@ -49,3 +49,9 @@ pub fn get_vertecies_from_side(side: &Side) -> [Pos3; 6] {
         points[0], points[1], points[2], points[2], points[1], points[3],
     ]
 }
+
+/// Same as [`get_vertecies_from_side`], but for a quad of `size` blocks. The component of `size`
+/// along the normal of `side` has to be 1
+pub fn get_vertecies_for_quad(side: &Side, size: Pos3) -> [Pos3; 6] {
+    get_vertecies_from_side(side).map(|p| p.multiply(size))
+}
@@ -0,0 +1,289 @@
+//! Checks the greedy mesher against the naive one, which is simple enough to trust.
+
+use std::collections::{HashMap, HashSet};
+
+use actually_usable_voxel_mesh_gen::{
+    data::{BlockFaceData, ChunkData, IndexedMesh, MeshingMode, Side},
+    generate_indexed_mesh_for_chunk, generate_mesh_for_chunk,
+    greedy::generate_greedy_mesh_for_chunk,
+};
+use common::Pos3;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const SIZE: i32 = 16;
+
+struct TestChunk {
+    blocks: HashMap<Pos3, String>,
+    neighbours: Vec<(Side, TestChunk)>,
+}
+
+impl TestChunk {
+    fn filled(f: impl Fn(Pos3) -> Option<String>) -> TestChunk {
+        let mut blocks = HashMap::new();
+        for x in 0..SIZE {
+            for y in 0..SIZE {
+                for z in 0..SIZE {
+                    let pos = Pos3::new(x, y, z);
+                    if let Some(id) = f(pos) {
+                        blocks.insert(pos, id);
+                    }
+                }
+            }
+        }
+        TestChunk {
+            blocks,
+            neighbours: Vec::new(),
+        }
+    }
+
+    fn random(seed: u64, fill: f64, ids: &[&str]) -> TestChunk {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut blocks = HashMap::new();
+        for x in 0..SIZE {
+            for y in 0..SIZE {
+                for z in 0..SIZE {
+                    if rng.gen_bool(fill) {
+                        let id = ids[rng.gen_range(0..ids.len())];
+                        blocks.insert(Pos3::new(x, y, z), id.to_owned());
+                    }
+                }
+            }
+        }
+        TestChunk {
+            blocks,
+            neighbours: Vec::new(),
+        }
+    }
+}
+
+impl ChunkData for TestChunk {
+    fn get_chunk_size(&self) -> i32 {
+        SIZE
+    }
+    fn get_color_seed_for_block(&self, pos: &Pos3) -> String {
+        self.blocks.get(pos).cloned().unwrap_or_default()
+    }
+    fn does_block_exits(&self, pos: &Pos3) -> bool {
+        self.blocks.contains_key(pos)
+    }
+    fn get_neighbour_chunk(&self, side: &Side) -> Option<&dyn ChunkData> {
+        self.neighbours
+            .iter()
+            .find(|(s, _)| s == side)
+            .map(|(_, chunk)| chunk as &dyn ChunkData)
+    }
+}
+
+fn test_chunks() -> Vec<TestChunk> {
+    let mut chunks = vec![
+        TestChunk::filled(|_| Some("minecraft:stone".into())),
+        TestChunk::filled(|p| (p == Pos3::new(3, 4, 5)).then(|| "minecraft:dirt".into())),
+        // terrain with stripes of ore, so faces of different colors share a plane
+        TestChunk::filled(|p| {
+            (p.y < 4 + (p.x + p.z) % 5).then(|| match p.x % 3 {
+                0 => "minecraft:iron_ore".into(),
+                _ => "minecraft:stone".into(),
+            })
+        }),
+    ];
+    for (seed, fill) in [(1, 0.1), (2, 0.5), (3, 0.9)] {
+        chunks.push(TestChunk::random(
+            seed,
+            fill,
+            &["minecraft:stone", "minecraft:dirt"],
+        ));
+    }
+    chunks.push(TestChunk::random(4, 0.7, &["minecraft:stone"]));
+    chunks
+}
+
+fn min_max(face: &BlockFaceData) -> (Pos3, Pos3) {
+    let v = &face.vertecies;
+    let min = |f: fn(&Pos3) -> i32| v.iter().map(f).min().unwrap();
+    let max = |f: fn(&Pos3) -> i32| v.iter().map(f).max().unwrap();
+    (
+        Pos3::new(min(|p| p.x), min(|p| p.y), min(|p| p.z)),
+        Pos3::new(max(|p| p.x), max(|p| p.y), max(|p| p.z)),
+    )
+}
+
+/// Splits every quad back into block sized faces, panics if two quads cover the same face
+fn unit_faces(faces: &[BlockFaceData]) -> HashSet<(Pos3, Pos3, [u8; 3])> {
+    let mut out = HashSet::new();
+    for face in faces {
+        let (min, max) = min_max(face);
+        // a quad is flat along its normal
+        let max = Pos3::new(
+            max.x.max(min.x + 1),
+            max.y.max(min.y + 1),
+            max.z.max(min.z + 1),
+        );
+        for x in min.x..max.x {
+            for y in min.y..max.y {
+                for z in min.z..max.z {
+                    let unit = (Pos3::new(x, y, z), face.normal, face.color);
+                    assert!(out.insert(unit), "{unit:?} is covered twice");
+                }
+            }
+        }
+    }
+    out
+}
+
+fn cross(a: Pos3, b: Pos3) -> Pos3 {
+    Pos3::new(
+        a.y * b.z - a.z * b.y,
+        a.z * b.x - a.x * b.z,
+        a.x * b.y - a.y * b.x,
+    )
+}
+
+fn key(p: Pos3) -> (i32, i32, i32) {
+    (p.x, p.y, p.z)
+}
+
+/// Every edge has to be walked as often in one direction as in the other. Axis aligned edges get
+/// split into block long pieces first, so T-junctions between quads of different sizes still
+/// count as closed.
+fn assert_watertight(mesh: &IndexedMesh) {
+    let mut edges = HashMap::<((i32, i32, i32), (i32, i32, i32)), i32>::new();
+    let mut walk = |from: Pos3, to: Pos3| {
+        let (a, b, dir) = match key(from) < key(to) {
+            true => (from, to, 1),
+            false => (to, from, -1),
+        };
+        *edges.entry((key(a), key(b))).or_default() += dir;
+    };
+    for tri in mesh.indices.chunks(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| mesh.positions[tri[i] as usize]);
+        for (from, to) in [(a, b), (b, c), (c, a)] {
+            let d = to - from;
+            let axis_aligned = [d.x != 0, d.y != 0, d.z != 0]
+                .iter()
+                .filter(|b| **b)
+                .count()
+                == 1;
+            if !axis_aligned {
+                walk(from, to);
+                continue;
+            }
+            let len = d.x.abs() + d.y.abs() + d.z.abs();
+            let step = Pos3::new(d.x.signum(), d.y.signum(), d.z.signum());
+            for i in 0..len {
+                walk(from + step.scale(i), from + step.scale(i + 1));
+            }
+        }
+    }
+    let open = edges.iter().filter(|(_, c)| **c != 0).collect::<Vec<_>>();
+    assert!(open.is_empty(), "open edges: {open:?}");
+}
+
+/// Triangles have to wind counter clockwise when looked at from where their normal points
+fn assert_facing_out(mesh: &IndexedMesh) {
+    for tri in mesh.indices.chunks(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| tri[i] as usize);
+        let normal = cross(
+            mesh.positions[b] - mesh.positions[a],
+            mesh.positions[c] - mesh.positions[a],
+        );
+        let expected = mesh.normals[a];
+        let dot = normal.x * expected.x + normal.y * expected.y + normal.z * expected.z;
+        assert!(dot > 0, "triangle {tri:?} faces the wrong way");
+        assert_eq!(mesh.normals[a], mesh.normals[b]);
+        assert_eq!(mesh.normals[a], mesh.normals[c]);
+    }
+}
+
+#[test]
+fn greedy_covers_the_same_faces() {
+    for chunk in test_chunks() {
+        let naive = unit_faces(&generate_mesh_for_chunk(&chunk));
+        let greedy = unit_faces(&generate_greedy_mesh_for_chunk(&chunk));
+        assert_eq!(naive, greedy);
+    }
+}
+
+#[test]
+fn greedy_never_needs_more_triangles() {
+    for chunk in test_chunks() {
+        let naive = generate_indexed_mesh_for_chunk(&chunk, MeshingMode::Naive);
+        let greedy = generate_indexed_mesh_for_chunk(&chunk, MeshingMode::Greedy);
+        assert!(greedy.triangle_count() <= naive.triangle_count());
+    }
+
+    let solid = &test_chunks()[0];
+    let naive = generate_indexed_mesh_for_chunk(solid, MeshingMode::Naive);
+    let greedy = generate_indexed_mesh_for_chunk(solid, MeshingMode::Greedy);
+    assert_eq!(naive.triangle_count(), (6 * SIZE * SIZE * 2) as usize);
+    assert_eq!(greedy.triangle_count(), 6 * 2);
+
+    // nothing to merge for a lone block
+    let single = &test_chunks()[1];
+    let naive = generate_indexed_mesh_for_chunk(single, MeshingMode::Naive);
+    let greedy = generate_indexed_mesh_for_chunk(single, MeshingMode::Greedy);
+    assert_eq!(naive.triangle_count(), 12);
+    assert_eq!(greedy.triangle_count(), 12);
+}
+
+#[test]
+fn meshes_are_watertight_and_face_out() {
+    for chunk in test_chunks() {
+        for mode in [MeshingMode::Naive, MeshingMode::Greedy] {
+            let mesh = generate_indexed_mesh_for_chunk(&chunk, mode);
+            assert_watertight(&mesh);
+            assert_facing_out(&mesh);
+        }
+    }
+}
+
+#[test]
+fn indexed_meshes_share_quad_vertices() {
+    let chunk = &test_chunks()[2];
+    let faces = generate_greedy_mesh_for_chunk(chunk);
+    let mesh = IndexedMesh::from_faces(&faces);
+    assert_eq!(mesh.positions.len(), faces.len() * 4);
+    assert_eq!(mesh.normals.len(), mesh.positions.len());
+    assert_eq!(mesh.colors.len(), mesh.positions.len());
+    assert_eq!(mesh.indices.len(), faces.len() * 6);
+    assert!(mesh
+        .indices
+        .iter()
+        .all(|i| (*i as usize) < mesh.positions.len()));
+    // the same triangles as the unindexed faces
+    for (face, tri) in faces.iter().zip(mesh.indices.chunks(6)) {
+        let from_indices = tri.iter().map(|i| mesh.positions[*i as usize]);
+        assert!(from_indices.eq(face.vertecies.iter().copied()));
+    }
+}
+
+#[test]
+fn faces_against_neighbouring_chunks_are_culled() {
+    let stone = || "minecraft:stone".to_owned();
+    let mut chunk = TestChunk::filled(|_| Some(stone()));
+    // only the lower half of the chunk above is filled
+    chunk.neighbours.push((
+        Side::PosY,
+        TestChunk::filled(|p| (p.y == 0 && p.x < 8).then(stone)),
+    ));
+    chunk
+        .neighbours
+        .push((Side::NegX, TestChunk::filled(|_| Some(stone()))));
+    chunk
+        .neighbours
+        .push((Side::PosZ, TestChunk::filled(|_| None)));
+
+    let faces = unit_faces(&generate_mesh_for_chunk(&chunk));
+    let count = |side: Side| {
+        let normal = side.side_to_rel_pos();
+        faces.iter().filter(|(_, n, _)| *n == normal).count()
+    };
+    assert_eq!(count(Side::NegX), 0);
+    assert_eq!(count(Side::PosY), (8 * SIZE) as usize);
+    // nothing there or not loaded, both get drawn
+    assert_eq!(count(Side::PosZ), (SIZE * SIZE) as usize);
+    assert_eq!(count(Side::PosX), (SIZE * SIZE) as usize);
+
+    assert_eq!(faces, unit_faces(&generate_greedy_mesh_for_chunk(&chunk)));
+    let greedy = generate_indexed_mesh_for_chunk(&chunk, MeshingMode::Greedy);
+    assert_eq!(greedy.triangle_count(), 5 * 2);
+}
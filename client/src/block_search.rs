@@ -0,0 +1,168 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use common::{
+    client_packets::{C2SPackets, S2CPackets},
+    world_data::Block,
+    Pos3,
+};
+use smooth_bevy_cameras::LookTransform;
+
+use crate::{
+    util::{format_age, pos3_to_vec3, unix_now},
+    InputState, WorldState,
+};
+
+pub struct BlockSearchPlugin;
+
+impl Plugin for BlockSearchPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(BlockSearchState::default());
+        app.add_systems(
+            Update,
+            (
+                receive_results,
+                block_search_window,
+                highlight_results,
+                focus_selected,
+            )
+                .chain(),
+        );
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct BlockSearchState {
+    pattern: String,
+    /// The (world, pattern) the last search was for
+    searched: Option<(String, String)>,
+    results: Vec<Block>,
+    truncated: bool,
+    waiting: bool,
+    /// Index into `results` the camera should look at
+    selected: Option<usize>,
+    focus: bool,
+}
+
+fn receive_results(
+    mut state: ResMut<BlockSearchState>,
+    worlds: Res<WorldState>,
+    mut ws_reader: EventReader<S2CPackets>,
+) {
+    if worlds.is_changed() && state.searched.as_ref().map(|(w, _)| w) != worlds.curr_world.as_ref()
+    {
+        state.results.clear();
+        state.selected = None;
+    }
+    for p in ws_reader.read() {
+        match p {
+            S2CPackets::BlockSearchResults {
+                world,
+                pattern,
+                blocks,
+                truncated,
+            } if state.searched.as_ref() == Some(&(world.clone(), pattern.clone())) => {
+                state.results = blocks.clone();
+                state.truncated = *truncated;
+                state.waiting = false;
+                state.selected = None;
+            }
+            // a found block was mined or replaced
+            S2CPackets::WorldUpdate(block) => {
+                let gone =
+                    |b: &Block| b.pos == block.pos && b.world == block.world && b.id != block.id;
+                if state.results.iter().any(gone) {
+                    state.results.retain(|b| !gone(b));
+                    state.selected = None;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn block_search_window(
+    mut state: ResMut<BlockSearchState>,
+    worlds: Res<WorldState>,
+    mut contexts: EguiContexts,
+    mut input_state: ResMut<InputState>,
+    mut ws_writer: EventWriter<C2SPackets>,
+) {
+    let Some(world) = worlds.curr_world.clone() else {
+        return;
+    };
+    let state = &mut *state;
+    let now = unix_now();
+    let window = egui::Window::new("Block Search")
+        .default_open(false)
+        .default_size([300.0, 300.0])
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                let input =
+                    ui.add(egui::TextEdit::singleline(&mut state.pattern).hint_text("*_ore"));
+                let submitted = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if (submitted || ui.button("Search").clicked()) && !state.pattern.is_empty() {
+                    state.searched = Some((world.clone(), state.pattern.clone()));
+                    state.waiting = true;
+                    ws_writer.send(C2SPackets::SearchBlocks {
+                        world: world.clone(),
+                        pattern: state.pattern.clone(),
+                    });
+                }
+            });
+            if state.waiting {
+                ui.spinner();
+                return;
+            }
+            if state.searched.is_some() {
+                let more = if state.truncated { "+" } else { "" };
+                ui.label(format!("{}{more} found", state.results.len()));
+            }
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for (i, block) in state.results.iter().enumerate() {
+                    let Pos3 { x, y, z } = block.pos;
+                    let mut text = format!("{x} {y} {z}  {}{}", block.id, block.state);
+                    if let Some(o) = block.observed {
+                        text += &format!("  {} ago", format_age(now.saturating_sub(o.at)));
+                    }
+                    if ui
+                        .selectable_label(state.selected == Some(i), text)
+                        .clicked()
+                    {
+                        state.selected = Some(i);
+                        state.focus = true;
+                    }
+                }
+            });
+        });
+    input_state.block_camera_updates |= window.is_some_and(|w| w.response.hovered());
+}
+
+fn highlight_results(state: Res<BlockSearchState>, mut gizmos: Gizmos) {
+    for (i, block) in state.results.iter().enumerate() {
+        let (color, size) = match state.selected == Some(i) {
+            true => (Color::WHITE, 1.2),
+            false => (Color::YELLOW, 1.05),
+        };
+        let center = pos3_to_vec3(block.pos) + Vec3::splat(0.5);
+        gizmos.cuboid(
+            Transform::from_translation(center).with_scale(Vec3::splat(size)),
+            color,
+        );
+    }
+}
+
+/// Moves the camera to the clicked result, keeping its distance and angle
+fn focus_selected(mut state: ResMut<BlockSearchState>, mut cams: Query<&mut LookTransform>) {
+    if !state.focus {
+        return;
+    }
+    state.focus = false;
+    let Some(block) = state.selected.and_then(|i| state.results.get(i)) else {
+        return;
+    };
+    let target = pos3_to_vec3(block.pos) + Vec3::splat(0.5);
+    for mut cam in cams.iter_mut() {
+        cam.eye = (cam.eye - cam.target) + target;
+        cam.target = target;
+    }
+}
@@ -0,0 +1,517 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+};
+
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, render_asset::RenderAssetUsages, render_resource::PrimitiveTopology},
+};
+use bevy_egui::{egui, EguiContexts};
+use common::{
+    blueprint::{compare, Blueprint, BlueprintStatus},
+    client_packets::{BuildStatus, C2SPackets, S2CPackets},
+    world_data::{get_chunk_containing_block, get_chunk_relative_pos, Knowledge, CHUNK_SIZE},
+    Pos3,
+};
+use smooth_bevy_cameras::LookTransform;
+
+use crate::{
+    components::ChunkInstance,
+    events::ActiveTurtleRes,
+    turtle_stuff::TurtleInstance,
+    util::{pos3_to_vec3, vec3_to_pos3},
+    voxel_meshing::{
+        data::{ChunkData, MeshingMode, Side},
+        generate_indexed_mesh_for_chunk,
+    },
+    InputState, WorldState,
+};
+
+/// Files with these extensions get imported as blueprints when dropped on the window
+const EXTENSIONS: &[&str] = &["schem", "nbt", "litematic"];
+/// While chunks stream in the ghosts are rebuilt at most this often
+const REBUILD_SECS: f32 = 0.5;
+/// Ghost faces sit this far in front of the blocks they cover, so they don't z-fight
+const INFLATE: f32 = 0.005;
+const STATUSES: [BlueprintStatus; 4] = [
+    BlueprintStatus::Matching,
+    BlueprintStatus::Missing,
+    BlueprintStatus::Wrong,
+    BlueprintStatus::Unknown,
+];
+
+pub struct BlueprintPlugin;
+
+impl Plugin for BlueprintPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(BlueprintState::default());
+        app.add_systems(Startup, setup_ghost_materials);
+        app.add_systems(
+            Update,
+            (
+                request_blueprints,
+                receive_blueprints,
+                blueprint_file_drop,
+                import_window,
+                blueprint_window,
+                update_ghosts,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// A dropped schematic waiting for a name and a position
+struct PendingImport {
+    file_name: String,
+    data: Vec<u8>,
+    name: String,
+    anchor: Pos3,
+}
+
+#[derive(Resource, Default)]
+pub struct BlueprintState {
+    blueprints: Vec<Blueprint>,
+    /// The world the blueprints were asked for
+    requested: Option<String>,
+    pending: Option<PendingImport>,
+    error: Option<String>,
+    hidden: HashSet<BlueprintStatus>,
+    /// Blocks per status for every blueprint, as of the last rebuild
+    counts: HashMap<String, HashMap<BlueprintStatus, usize>>,
+    /// Last build status per turtle index
+    builds: HashMap<i32, BuildStatus>,
+    dirty: bool,
+    since_rebuild: f32,
+}
+
+/// One status of one blueprint, drawn as a translucent mesh
+#[derive(Component)]
+pub struct Ghost;
+
+#[derive(Resource)]
+struct GhostMaterials(HashMap<BlueprintStatus, Handle<StandardMaterial>>);
+
+fn status_color(status: BlueprintStatus) -> Color {
+    match status {
+        BlueprintStatus::Matching => Color::rgba(0.2, 0.9, 0.3, 0.25),
+        BlueprintStatus::Missing => Color::rgba(0.2, 0.5, 1., 0.45),
+        BlueprintStatus::Wrong => Color::rgba(1., 0.2, 0.1, 0.55),
+        BlueprintStatus::Unknown => Color::rgba(0.7, 0.7, 0.7, 0.2),
+    }
+}
+
+fn setup_ghost_materials(mut commands: Commands, mut materials: ResMut<Assets<StandardMaterial>>) {
+    let materials = STATUSES
+        .into_iter()
+        .map(|status| {
+            let material = StandardMaterial {
+                base_color: status_color(status),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..Default::default()
+            };
+            (status, materials.add(material))
+        })
+        .collect();
+    commands.insert_resource(GhostMaterials(materials));
+}
+
+fn request_blueprints(
+    mut state: ResMut<BlueprintState>,
+    worlds: Res<WorldState>,
+    mut ws_writer: EventWriter<C2SPackets>,
+) {
+    if !worlds.is_changed() || state.requested == worlds.curr_world {
+        return;
+    }
+    state.requested.clone_from(&worlds.curr_world);
+    state.blueprints.clear();
+    state.builds.clear();
+    state.dirty = true;
+    if let Some(world) = &worlds.curr_world {
+        ws_writer.send(C2SPackets::RequestBlueprints(world.clone()));
+    }
+}
+
+fn receive_blueprints(mut state: ResMut<BlueprintState>, mut ws_reader: EventReader<S2CPackets>) {
+    for p in ws_reader.read() {
+        match p {
+            S2CPackets::Blueprints { world, blueprints }
+                if state.requested.as_ref() == Some(world) =>
+            {
+                state.blueprints.clone_from(blueprints);
+                state.dirty = true;
+            }
+            S2CPackets::BlueprintError { name, reason } => {
+                state.error = Some(format!("{name}: {reason}"));
+            }
+            S2CPackets::BuildUpdate(data) if state.requested.as_ref() == Some(&data.world) => {
+                state.builds.insert(data.index, data.data.clone());
+            }
+            _ => {}
+        }
+    }
+}
+
+fn camera_target(cams: &Query<&LookTransform>) -> Pos3 {
+    cams.get_single()
+        .map_or(Pos3::zero(), |cam| vec3_to_pos3(cam.target.floor()))
+}
+
+fn blueprint_file_drop(
+    mut state: ResMut<BlueprintState>,
+    mut dnd_evr: EventReader<FileDragAndDrop>,
+    cams: Query<&LookTransform>,
+) {
+    for ev in dnd_evr.read() {
+        let FileDragAndDrop::DroppedFile { path_buf, .. } = ev else {
+            continue;
+        };
+        let extension = path_buf
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase());
+        if !extension.is_some_and(|e| EXTENSIONS.contains(&e.as_str())) {
+            continue;
+        }
+        match fs::read(path_buf) {
+            Ok(data) => {
+                let name = |p: Option<&std::ffi::OsStr>| {
+                    p.map(|n| n.to_string_lossy().into_owned())
+                        .unwrap_or_default()
+                };
+                state.pending = Some(PendingImport {
+                    file_name: name(path_buf.file_name()),
+                    name: name(path_buf.file_stem()),
+                    data,
+                    anchor: camera_target(&cams),
+                });
+            }
+            Err(err) => state.error = Some(format!("{}: {err}", path_buf.display())),
+        }
+    }
+}
+
+fn import_window(
+    mut state: ResMut<BlueprintState>,
+    worlds: Res<WorldState>,
+    mut contexts: EguiContexts,
+    mut input_state: ResMut<InputState>,
+    mut ws_writer: EventWriter<C2SPackets>,
+    cams: Query<&LookTransform>,
+) {
+    let (Some(pending), Some(world)) = (&mut state.pending, &worlds.curr_world) else {
+        return;
+    };
+    let mut done = false;
+    let window = egui::Window::new(format!("Import {}", pending.file_name))
+        .collapsible(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Name");
+                ui.text_edit_singleline(&mut pending.name);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Min Corner");
+                ui.add(egui::DragValue::new(&mut pending.anchor.x).prefix("x "));
+                ui.add(egui::DragValue::new(&mut pending.anchor.y).prefix("y "));
+                ui.add(egui::DragValue::new(&mut pending.anchor.z).prefix("z "));
+                if ui.button("Camera Target").clicked() {
+                    pending.anchor = camera_target(&cams);
+                }
+            });
+            ui.horizontal(|ui| {
+                let named = !pending.name.trim().is_empty();
+                if ui.add_enabled(named, egui::Button::new("Import")).clicked() {
+                    ws_writer.send(C2SPackets::ImportBlueprint {
+                        world: world.clone(),
+                        name: pending.name.trim().to_owned(),
+                        anchor: pending.anchor,
+                        file_name: pending.file_name.clone(),
+                        data: std::mem::take(&mut pending.data),
+                    });
+                    done = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    done = true;
+                }
+            });
+        });
+    input_state.block_camera_updates |= window.is_some_and(|w| w.response.hovered());
+    if done {
+        state.pending = None;
+        state.error = None;
+    }
+}
+
+fn build_status_text(status: &BuildStatus) -> String {
+    match status {
+        BuildStatus::Building {
+            remaining,
+            materials,
+            ..
+        } => {
+            let materials = materials
+                .iter()
+                .map(|(item, count)| format!("{count} {item}"))
+                .collect::<Vec<_>>();
+            format!("{remaining} blocks left, needs {}", materials.join(", "))
+        }
+        BuildStatus::MissingMaterial { item, .. } => format!("Out of {item}"),
+        BuildStatus::Done { skipped: 0, .. } => "Done".into(),
+        BuildStatus::Done { skipped, .. } => format!("Done, skipped {skipped} blocks"),
+        BuildStatus::Stopped { .. } => "Stopped".into(),
+    }
+}
+
+fn build_blueprint(status: &BuildStatus) -> &str {
+    match status {
+        BuildStatus::Building { blueprint, .. }
+        | BuildStatus::MissingMaterial { blueprint, .. }
+        | BuildStatus::Done { blueprint, .. }
+        | BuildStatus::Stopped { blueprint } => blueprint,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn blueprint_window(
+    mut state: ResMut<BlueprintState>,
+    worlds: Res<WorldState>,
+    mut contexts: EguiContexts,
+    mut input_state: ResMut<InputState>,
+    mut ws_writer: EventWriter<C2SPackets>,
+    mut cams: Query<&mut LookTransform>,
+    turtles: Query<&TurtleInstance>,
+    active_turtle: Res<ActiveTurtleRes>,
+) {
+    let Some(world) = worlds.curr_world.clone() else {
+        return;
+    };
+    let online = turtles
+        .iter()
+        .filter(|t| t.turtle.is_online && t.turtle.world == world)
+        .map(|t| t.turtle.index)
+        .collect::<Vec<_>>();
+    let state = &mut *state;
+    let window =
+        egui::Window::new("Blueprints")
+            .default_open(false)
+            .show(contexts.ctx_mut(), |ui| {
+                if let Some(error) = &state.error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+                ui.horizontal(|ui| {
+                    for status in STATUSES {
+                        let mut shown = !state.hidden.contains(&status);
+                        let [r, g, b, _] = status_color(status).as_rgba_u8();
+                        let text = egui::RichText::new(format!("{status:?}"))
+                            .color(egui::Color32::from_rgb(r, g, b));
+                        if ui.checkbox(&mut shown, text).changed() {
+                            match shown {
+                                true => state.hidden.remove(&status),
+                                false => state.hidden.insert(status),
+                            };
+                            state.dirty = true;
+                        }
+                    }
+                });
+                if state.blueprints.is_empty() {
+                    ui.label("Drop a .schem, .nbt or .litematic file to import it");
+                }
+                for blueprint in &state.blueprints {
+                    ui.separator();
+                    let Pos3 { x, y, z } = blueprint.anchor;
+                    let size = blueprint.size();
+                    ui.label(format!(
+                        "{}  at {x} {y} {z}, {}x{}x{}",
+                        blueprint.name, size.x, size.y, size.z
+                    ));
+                    if let Some(counts) = state.counts.get(&blueprint.name) {
+                        let counts = STATUSES
+                            .iter()
+                            .map(|s| format!("{} {s:?}", counts.get(s).unwrap_or(&0)))
+                            .collect::<Vec<_>>();
+                        ui.label(counts.join(", "));
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("Focus").clicked() {
+                            let center = pos3_to_vec3(blueprint.anchor) + pos3_to_vec3(size) / 2.;
+                            for mut cam in cams.iter_mut() {
+                                cam.eye = (cam.eye - cam.target) + center;
+                                cam.target = center;
+                            }
+                        }
+                        if ui.button("Remove").clicked() {
+                            ws_writer.send(C2SPackets::RemoveBlueprint {
+                                world: world.clone(),
+                                name: blueprint.name.clone(),
+                            });
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        let mut build = |turtles: Vec<i32>| {
+                            ws_writer.send(C2SPackets::StartBuild {
+                                world: world.clone(),
+                                blueprint: blueprint.name.clone(),
+                                turtles,
+                            })
+                        };
+                        if online.contains(&active_turtle.0)
+                            && ui.button("Build With Turtle").clicked()
+                        {
+                            build(vec![active_turtle.0]);
+                        }
+                        if !online.is_empty() && ui.button("Build With All").clicked() {
+                            build(online.clone());
+                        }
+                        if ui.button("Stop Build").clicked() {
+                            ws_writer.send(C2SPackets::StopBuild {
+                                world: world.clone(),
+                                blueprint: blueprint.name.clone(),
+                            });
+                        }
+                    });
+                    let mut builds = state
+                        .builds
+                        .iter()
+                        .filter(|(_, status)| build_blueprint(status) == blueprint.name)
+                        .collect::<Vec<_>>();
+                    builds.sort_by_key(|(index, _)| **index);
+                    for (index, status) in builds {
+                        ui.label(format!("Turtle {index}: {}", build_status_text(status)));
+                    }
+                }
+            });
+    input_state.block_camera_updates |= window.is_some_and(|w| w.response.hovered());
+}
+
+/// The cells of one status, cut into chunk sized pieces for the mesher
+struct GhostPiece<'a> {
+    cells: &'a HashSet<Pos3>,
+    origin: Pos3,
+}
+
+impl ChunkData for GhostPiece<'_> {
+    fn does_block_exits(&self, pos: &Pos3) -> bool {
+        self.cells.contains(&(*pos + self.origin))
+    }
+    fn get_chunk_size(&self) -> i32 {
+        CHUNK_SIZE
+    }
+    fn get_color_seed_for_block(&self, _pos: &Pos3) -> String {
+        String::new()
+    }
+    // every cell is in the same set, neighbouring pieces don't need to be looked up
+    fn has_neighbour(&self, pos: &Pos3, side: &Side) -> bool {
+        self.cells
+            .contains(&(*pos + side.side_to_rel_pos() + self.origin))
+    }
+}
+
+fn ghost_mesh(cells: &HashSet<Pos3>) -> Mesh {
+    let pieces = cells
+        .iter()
+        .map(get_chunk_containing_block)
+        .collect::<HashSet<_>>();
+    let to_vec3 = |p: &Pos3| Vec3::new(p.x as f32, p.y as f32, p.z as f32);
+    let (mut positions, mut normals, mut indices) = (Vec::new(), Vec::new(), Vec::new());
+    for chunk in pieces {
+        let origin = chunk.scale(CHUNK_SIZE);
+        let data =
+            generate_indexed_mesh_for_chunk(&GhostPiece { cells, origin }, MeshingMode::Greedy);
+        let base = positions.len() as u32;
+        for (pos, normal) in data.positions.iter().zip(&data.normals) {
+            let normal = to_vec3(normal);
+            positions.push((to_vec3(&(*pos + origin)) + normal * INFLATE).to_array());
+            normals.push(normal.to_array());
+        }
+        indices.extend(data.indices.iter().map(|i| i + base));
+    }
+    let mut mesh = Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    );
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_indices(Indices::U32(indices));
+    mesh
+}
+
+/// Compares every blueprint against the loaded chunks and redraws the ghosts. Blocks in chunks
+/// that aren't loaded count as unknown
+#[allow(clippy::too_many_arguments)]
+fn update_ghosts(
+    mut state: ResMut<BlueprintState>,
+    time: Res<Time>,
+    chunks: Query<Ref<ChunkInstance>>,
+    mut unloaded: RemovedComponents<ChunkInstance>,
+    ghosts: Query<Entity, With<Ghost>>,
+    materials: Res<GhostMaterials>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut commands: Commands,
+) {
+    let state = &mut *state;
+    state.since_rebuild += time.delta_seconds();
+    let covered = |chunk: &Pos3| {
+        state.blueprints.iter().any(|b| {
+            let (min, max) = (
+                get_chunk_containing_block(&b.anchor),
+                get_chunk_containing_block(&b.max()),
+            );
+            (min.x..=max.x).contains(&chunk.x)
+                && (min.y..=max.y).contains(&chunk.y)
+                && (min.z..=max.z).contains(&chunk.z)
+        })
+    };
+    state.dirty |= chunks
+        .iter()
+        .any(|c| c.is_changed() && covered(c.get_chunk_pos()));
+    state.dirty |= unloaded.read().count() > 0;
+    if !state.dirty || state.since_rebuild < REBUILD_SECS {
+        return;
+    }
+    state.dirty = false;
+    state.since_rebuild = 0.;
+    ghosts
+        .iter()
+        .for_each(|e| commands.entity(e).despawn_recursive());
+    state.counts.clear();
+
+    let loaded = chunks
+        .iter()
+        .map(|c| (*c.get_chunk_pos(), c.into_inner()))
+        .collect::<HashMap<_, _>>();
+    let knowledge = |pos: &Pos3| {
+        loaded
+            .get(&get_chunk_containing_block(pos))
+            .map_or(Knowledge::Unknown, |c| {
+                c.get_knowledge(&get_chunk_relative_pos(pos))
+            })
+    };
+    for blueprint in &state.blueprints {
+        let counts = state.counts.entry(blueprint.name.clone()).or_default();
+        let mut cells = HashMap::<BlueprintStatus, HashSet<Pos3>>::new();
+        for (pos, planned) in blueprint.iter() {
+            let status = compare(planned, knowledge(&pos));
+            *counts.entry(status).or_default() += 1;
+            // planned air is only worth drawing where something is in the way
+            if planned.is_air && status != BlueprintStatus::Wrong {
+                continue;
+            }
+            if !state.hidden.contains(&status) {
+                cells.entry(status).or_default().insert(pos);
+            }
+        }
+        for (status, cells) in cells {
+            commands.spawn((
+                Ghost,
+                PbrBundle {
+                    mesh: meshes.add(ghost_mesh(&cells)),
+                    material: materials.0[&status].clone(),
+                    ..Default::default()
+                },
+            ));
+        }
+    }
+}
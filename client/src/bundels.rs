@@ -7,7 +7,7 @@ use common::world_data::CHUNK_SIZE;
 
 use crate::{
     components::{ChunkInstance, LerpTransform},
-    idk::{do_mesh_shit, ClientChunk},
+    idk::{do_mesh_shit, ChunkWithNeighbours, ClientChunk},
     util::pos3_to_vec3,
 };
 
@@ -30,7 +30,8 @@ impl ChunkBundle {
             bevy::render::render_resource::PrimitiveTopology::TriangleList,
             RenderAssetUsages::MAIN_WORLD,
         );
-        do_mesh_shit(&mut mesh, &chunk);
+        // chunk_update_mesh redoes this next frame, once the neighbours are known
+        do_mesh_shit(&mut mesh, &ChunkWithNeighbours::alone(&chunk));
         let end_pos = pos3_to_vec3(pos.scale(CHUNK_SIZE));
         let lerp_pos = end_pos - vec3(0., CHUNK_SIZE as f32, 0.);
         let mut lerp_comp = LerpTransform::new(lerp_pos, Quat::IDENTITY);
@@ -0,0 +1,221 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_egui::egui;
+use common::{
+    client_packets::{C2SPackets, S2CPackets},
+    world_data::{get_chunk_containing_block, Chunk, CHUNK_SIZE},
+    Pos3,
+};
+use smooth_bevy_cameras::LookTransform;
+
+use crate::{
+    bundels::ChunkBundle,
+    components::ChunkInstance,
+    idk::ClientChunk,
+    util::{pos3_to_vec3, unix_now},
+    voxel_meshing::data::Side,
+    WorldState,
+};
+
+/// Meshing is slow, so only this many streamed chunks get spawned per frame
+const CHUNKS_PER_FRAME: usize = 4;
+/// Explored air older than this is drawn fully faded
+const STALE_AFTER_SECS: u32 = 60 * 60;
+
+pub struct ChunkStreamingPlugin;
+
+impl Plugin for ChunkStreamingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ChunkStreamingState::default());
+        app.add_event::<SpawnChunk>();
+        app.add_systems(
+            Update,
+            (update_view, receive_chunks, spawn_streamed_chunks).chain(),
+        );
+        app.add_systems(Update, handle_chunk_spawning);
+        app.add_systems(Update, draw_explored_air.run_if(show_explored_air));
+    }
+}
+
+#[derive(Resource)]
+pub struct ChunkStreamingState {
+    /// Radius in chunks around the camera target the server sends
+    pub render_distance: u32,
+    /// Marks air the turtles have seen in the chunks around the camera target
+    pub show_explored_air: bool,
+    sent_render_distance: Option<u32>,
+    /// (world, chunk) the server was last told we look at
+    view: Option<(String, Pos3)>,
+    /// Chunks that arrived but aren't meshed yet, closest first
+    pending: VecDeque<Chunk>,
+}
+
+impl Default for ChunkStreamingState {
+    fn default() -> Self {
+        Self {
+            render_distance: 8,
+            show_explored_air: false,
+            sent_render_distance: None,
+            view: None,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+#[derive(Resource, Debug, DerefMut, Deref)]
+pub struct ChunkMat(pub Handle<StandardMaterial>);
+
+#[derive(Event, Debug, Deref, DerefMut)]
+pub struct SpawnChunk(pub Chunk);
+
+fn update_view(
+    mut state: ResMut<ChunkStreamingState>,
+    worlds: Res<WorldState>,
+    cams: Query<&LookTransform>,
+    chunks: Query<Entity, With<ChunkInstance>>,
+    mut commands: Commands,
+    mut ws_writer: EventWriter<C2SPackets>,
+) {
+    if state.sent_render_distance != Some(state.render_distance) {
+        state.sent_render_distance = Some(state.render_distance);
+        ws_writer.send(C2SPackets::SetChunkRenderDistance(state.render_distance));
+    }
+    let (Some(world), Ok(cam)) = (&worlds.curr_world, cams.get_single()) else {
+        return;
+    };
+    let target = cam.target.floor();
+    let center = get_chunk_containing_block(&Pos3::new(
+        target.x as i32,
+        target.y as i32,
+        target.z as i32,
+    ));
+    let old_world = state.view.as_ref().map(|(w, _)| w);
+    if old_world != Some(world) {
+        // nothing of the old world is valid anymore
+        chunks
+            .iter()
+            .for_each(|entity| commands.entity(entity).despawn_recursive());
+        state.pending.clear();
+    } else if state.view.as_ref().is_some_and(|(_, c)| *c == center) {
+        return;
+    }
+    state.view = Some((world.clone(), center));
+    ws_writer.send(C2SPackets::UpdateView {
+        world: world.clone(),
+        center,
+    });
+}
+
+fn receive_chunks(
+    mut state: ResMut<ChunkStreamingState>,
+    mut chunks: Query<(Entity, &mut ChunkInstance)>,
+    mut commands: Commands,
+    mut ws_reader: EventReader<S2CPackets>,
+) {
+    for p in ws_reader.read() {
+        let curr_world = state.view.as_ref().map(|(w, _)| w.clone());
+        match p {
+            S2CPackets::SetChunk { world, chunk } if Some(world) == curr_world.as_ref() => {
+                let pos = chunk.get_chunk_pos();
+                state.pending.retain(|c| c.get_chunk_pos() != pos);
+                state.pending.push_back(chunk.clone());
+            }
+            S2CPackets::UnloadChunks {
+                world,
+                chunks: unload,
+            } if Some(world) == curr_world.as_ref() => {
+                state
+                    .pending
+                    .retain(|c| !unload.contains(&c.get_chunk_pos()));
+                for (entity, mut chunk) in chunks.iter_mut() {
+                    let pos = *chunk.get_chunk_pos();
+                    if unload.contains(&pos) {
+                        commands.entity(entity).despawn_recursive();
+                    } else if Side::ALL
+                        .iter()
+                        .any(|side| unload.contains(&(pos + side.side_to_rel_pos())))
+                    {
+                        // its border faces are visible again
+                        chunk.set_changed();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn spawn_streamed_chunks(
+    mut state: ResMut<ChunkStreamingState>,
+    chunks: Query<(Entity, &ChunkInstance)>,
+    mut commands: Commands,
+    mut chunk_spawn: EventWriter<SpawnChunk>,
+) {
+    for _ in 0..CHUNKS_PER_FRAME {
+        let Some(chunk) = state.pending.pop_front() else {
+            break;
+        };
+        // the server sent a newer version of a chunk we already have
+        chunks
+            .iter()
+            .filter(|(_, c)| *c.get_chunk_pos() == chunk.get_chunk_pos())
+            .for_each(|(entity, _)| commands.entity(entity).despawn_recursive());
+        chunk_spawn.send(SpawnChunk(chunk));
+    }
+}
+
+fn handle_chunk_spawning(
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut commands: Commands,
+    mut event: EventReader<SpawnChunk>,
+    chunk_mat: Res<ChunkMat>,
+) {
+    for e in event.read() {
+        commands.spawn(ChunkBundle::new(
+            ClientChunk::from_chunk(e.0.clone()),
+            &mut meshes,
+            chunk_mat.clone(),
+        ));
+    }
+}
+
+fn show_explored_air(state: Res<ChunkStreamingState>) -> bool {
+    state.show_explored_air
+}
+
+fn draw_explored_air(
+    state: Res<ChunkStreamingState>,
+    chunks: Query<&ChunkInstance>,
+    mut gizmos: Gizmos,
+) {
+    let Some((_, center)) = &state.view else {
+        return;
+    };
+    let now = unix_now();
+    let near = |pos: &Pos3| {
+        let d = *pos - *center;
+        d.x.abs() <= 1 && d.y.abs() <= 1 && d.z.abs() <= 1
+    };
+    for chunk in chunks.iter().filter(|c| near(c.get_chunk_pos())) {
+        let offset = chunk.get_chunk_pos().scale(CHUNK_SIZE);
+        for (pos, _) in chunk.iter().filter(|(_, e)| e.is_air) {
+            // fresh air is bright, air nobody looked at in a while fades out
+            let age = chunk
+                .get_observation(&pos)
+                .map_or(STALE_AFTER_SECS, |o| now.saturating_sub(o.at));
+            let fresh = 1. - age.min(STALE_AFTER_SECS) as f32 / STALE_AFTER_SECS as f32;
+            let color = Color::rgba(0.2, 0.8, 1., 0.15 + 0.6 * fresh);
+            let translation = pos3_to_vec3(pos + offset) + Vec3::splat(0.5);
+            gizmos.cuboid(
+                Transform::from_translation(translation).with_scale(Vec3::splat(0.25)),
+                color,
+            );
+        }
+    }
+}
+
+pub fn render_distance_slider(ui: &mut egui::Ui, state: &mut ChunkStreamingState) {
+    ui.add(egui::Slider::new(&mut state.render_distance, 1..=32).text("Render Distance"));
+    ui.checkbox(&mut state.show_explored_air, "Show Explored Air");
+}
@@ -1,6 +1,11 @@
+use std::collections::{HashMap, HashSet};
+
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
-use common::client_packets::C2SPackets;
+use common::{
+    client_packets::{C2SPackets, S2CPackets},
+    turtle::Turtle,
+};
 
 use crate::{events::ActiveTurtleRes, turtle_stuff::TurtleInstance, ShowFileDialog};
 
@@ -8,10 +13,88 @@ pub struct ExecutableFilesPlugin;
 
 impl Plugin for ExecutableFilesPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, run_local_file_dialog);
+        app.insert_resource(ProgramsState::default());
+        app.add_systems(Update, (update_executables, run_local_file_dialog));
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ProgramsState {
+    /// Programs per (world, turtle index), as the server last told us
+    executables: HashMap<(String, i32), Vec<String>>,
+    /// Turtles we already asked, so opening the menu doesn't spam the server
+    requested: HashSet<(String, i32)>,
+    selected: Option<String>,
+    args: String,
+}
+
+fn update_executables(mut state: ResMut<ProgramsState>, mut ws_reader: EventReader<S2CPackets>) {
+    for p in ws_reader.read() {
+        if let S2CPackets::TurtleExecutables(data) = p {
+            state
+                .executables
+                .insert((data.world.clone(), data.index), data.data.clone());
+        }
     }
 }
 
+pub fn programs_menu(
+    ui: &mut egui::Ui,
+    state: &mut ProgramsState,
+    turtle: &Turtle,
+    ws: &mut EventWriter<C2SPackets>,
+) {
+    let key = (turtle.world.clone(), turtle.index);
+    ui.menu_button("Programs", |ui| {
+        if state.requested.insert(key.clone()) {
+            ws.send(C2SPackets::RequestExecutables {
+                index: turtle.index,
+                world: turtle.world.clone(),
+                refresh: false,
+            });
+        }
+        let programs = state.executables.get(&key).cloned().unwrap_or_default();
+        egui::ComboBox::from_label("Program")
+            .selected_text(state.selected.clone().unwrap_or_default())
+            .show_ui(ui, |ui| {
+                ui.style_mut().wrap = Some(false);
+                ui.set_min_width(60.0);
+                for p in programs.iter() {
+                    ui.selectable_value(&mut state.selected, Some(p.to_owned()), p);
+                }
+            });
+        ui.horizontal(|ui| {
+            ui.label("Args");
+            ui.text_edit_singleline(&mut state.args);
+        });
+        ui.horizontal(|ui| {
+            let selected = state.selected.clone().filter(|s| programs.contains(s));
+            if ui
+                .add_enabled(selected.is_some(), egui::Button::new("Run"))
+                .clicked()
+            {
+                ws.send(C2SPackets::RunProgram {
+                    index: turtle.index,
+                    world: turtle.world.clone(),
+                    name: selected.unwrap_or_default(),
+                    args: state.args.split_whitespace().map(str::to_owned).collect(),
+                });
+                ui.close_menu();
+            }
+            if ui.button("Refresh").clicked() {
+                ws.send(C2SPackets::RequestExecutables {
+                    index: turtle.index,
+                    world: turtle.world.clone(),
+                    refresh: true,
+                });
+            }
+            if ui.button("Close").clicked() {
+                ui.close_menu();
+            }
+        });
+    });
+}
+
 fn run_local_file_dialog(
     mut file_dialog: ResMut<ShowFileDialog>,
     mut contexts: EguiContexts,
@@ -38,6 +121,10 @@ fn run_local_file_dialog(
                         .map(|n| n.to_string_lossy())
                         .unwrap()
                 ));
+                ui.horizontal(|ui| {
+                    ui.label("Save As");
+                    ui.text_edit_singleline(&mut file_dialog.save_path);
+                });
                 ui.horizontal(|ui| {
                     if ui.button("Close").clicked() {
                         file_dialog.show = false;
@@ -46,7 +133,7 @@ fn run_local_file_dialog(
                     if ui.button("Send").clicked() {
                         file_dialog.show = false;
                         // send shit to turtle
-                        if let Some(t) = curr_turtle {
+                        if let Some(t) = &curr_turtle {
                             ws_writer.send(C2SPackets::SendLuaToTurtle {
                                 index: t.index,
                                 world: t.world.clone(),
@@ -55,6 +142,24 @@ fn run_local_file_dialog(
                             info!("Ok sending code to turtle: {}", &file_dialog.conntents);
                         }
                     }
+
+                    if ui
+                        .add_enabled(
+                            !file_dialog.save_path.is_empty(),
+                            egui::Button::new("Save To Turtle"),
+                        )
+                        .clicked()
+                    {
+                        file_dialog.show = false;
+                        if let Some(t) = &curr_turtle {
+                            ws_writer.send(C2SPackets::SaveFileToTurtle {
+                                index: t.index,
+                                world: t.world.clone(),
+                                path: file_dialog.save_path.clone(),
+                                contents: file_dialog.conntents.clone(),
+                            });
+                        }
+                    }
                 });
             });
     }
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_egui::egui;
+use common::{
+    client_packets::{C2SPackets, GoToStatus, S2CPackets},
+    turtle::Turtle,
+    Pos3,
+};
+
+pub struct GoToPlugin;
+
+impl Plugin for GoToPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(GoToState::default());
+        app.add_systems(Update, update_go_to_statuses);
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct GoToState {
+    pub target: Pos3,
+    /// Last known status per (world, turtle index)
+    statuses: HashMap<(String, i32), GoToStatus>,
+    /// Where jobs send turtles for fuel, per (world, turtle index)
+    homes: HashMap<(String, i32), Pos3>,
+}
+
+fn update_go_to_statuses(mut state: ResMut<GoToState>, mut ws_reader: EventReader<S2CPackets>) {
+    for p in ws_reader.read() {
+        match p {
+            S2CPackets::GoToUpdate(data) => {
+                state
+                    .statuses
+                    .insert((data.world.clone(), data.index), data.data.clone());
+            }
+            S2CPackets::TurtleHome(data) => {
+                let key = (data.world.clone(), data.index);
+                match data.data {
+                    Some(home) => state.homes.insert(key, home),
+                    None => state.homes.remove(&key),
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+fn status_text(status: &GoToStatus) -> String {
+    match status {
+        GoToStatus::Moving {
+            target,
+            remaining_moves,
+        } => format!(
+            "Moving to {} {} {}, {remaining_moves} moves left",
+            target.x, target.y, target.z
+        ),
+        GoToStatus::Replanning { .. } => "Replanning route".into(),
+        GoToStatus::Waiting { turtle, .. } => format!("Waiting for turtle {turtle}"),
+        GoToStatus::MakingWay { turtles, .. } => format!("Making way for turtles {turtles:?}"),
+        GoToStatus::Arrived { target } => {
+            format!("Arrived at {} {} {}", target.x, target.y, target.z)
+        }
+        GoToStatus::Failed { reason, .. } => format!("Failed: {reason}"),
+    }
+}
+
+pub fn go_to_menu(
+    ui: &mut egui::Ui,
+    state: &mut GoToState,
+    turtle: &Turtle,
+    ws: &mut EventWriter<C2SPackets>,
+) {
+    ui.menu_button("Go To", |ui| {
+        ui.horizontal(|ui| {
+            ui.label("x");
+            ui.add(egui::DragValue::new(&mut state.target.x));
+            ui.label("y");
+            ui.add(egui::DragValue::new(&mut state.target.y));
+            ui.label("z");
+            ui.add(egui::DragValue::new(&mut state.target.z));
+        });
+        if let Some(status) = state.statuses.get(&(turtle.world.clone(), turtle.index)) {
+            ui.label(status_text(status));
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Use Turtle Pos").clicked() {
+                state.target = turtle.position;
+            }
+            if ui.button("Go").clicked() {
+                ws.send(C2SPackets::GoTo {
+                    index: turtle.index,
+                    world: turtle.world.clone(),
+                    target: state.target.into(),
+                });
+            }
+            if ui.button("Close").clicked() {
+                ui.close_menu();
+            }
+        });
+        ui.separator();
+        let key = (turtle.world.clone(), turtle.index);
+        match state.homes.get(&key) {
+            Some(home) => ui.label(format!("Home at {} {} {}", home.x, home.y, home.z)),
+            None => ui.label("No home, jobs can only refuel from the inventory"),
+        };
+        ui.horizontal(|ui| {
+            let mut set_home = |home| {
+                ws.send(C2SPackets::SetHome {
+                    index: turtle.index,
+                    world: turtle.world.clone(),
+                    home,
+                })
+            };
+            if ui
+                .button("Set Home Here")
+                .on_hover_text("Jobs come back here for fuel, from a chest below")
+                .clicked()
+            {
+                set_home(Some(turtle.position));
+            }
+            if state.homes.contains_key(&key) && ui.button("Clear Home").clicked() {
+                set_home(None);
+            }
+        });
+    });
+}
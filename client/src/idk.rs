@@ -1,12 +1,15 @@
 use std::sync::Arc;
 
 use crate::voxel_meshing;
-use bevy::prelude::*;
+use bevy::{prelude::*, render::mesh::Indices};
 use common::{
-    world_data::{get_chunk_relative_pos, Chunk, CHUNK_SIZE},
+    world_data::{Chunk, CHUNK_SIZE},
     Pos3,
 };
-use voxel_meshing::{data::ChunkData, generate_mesh_for_chunk};
+use voxel_meshing::{
+    data::{ChunkData, MeshingMode, Side},
+    generate_indexed_mesh_for_chunk,
+};
 
 #[derive(Deref, DerefMut)]
 pub struct ClientChunk {
@@ -43,61 +46,54 @@ impl ChunkData for ClientChunk {
     fn get_chunk_size(&self) -> i32 {
         CHUNK_SIZE
     }
-    fn has_neighbour(&self, pos: &Pos3, side: &voxel_meshing::data::Side) -> bool {
-        let pos = get_chunk_relative_pos(pos);
-        let neighbour_pos = pos + side.side_to_rel_pos();
-        let mut oob = neighbour_pos.x < 0 || neighbour_pos.y < 0 || neighbour_pos.z < 0;
-        oob |= neighbour_pos.x > CHUNK_SIZE
-            || neighbour_pos.y > CHUNK_SIZE
-            || neighbour_pos.z > CHUNK_SIZE;
-        if oob {
-            return false;
+    fn get_color_seed_for_block(&self, pos: &Pos3) -> String {
+        self.get_block_id(pos).unwrap_or_default()
+    }
+}
+
+/// A chunk together with the loaded chunks around it, in the order of [`Side::ALL`]
+pub struct ChunkWithNeighbours<'a> {
+    pub chunk: &'a ClientChunk,
+    pub neighbours: [Option<&'a ClientChunk>; 6],
+}
+
+impl<'a> ChunkWithNeighbours<'a> {
+    pub fn alone(chunk: &'a ClientChunk) -> ChunkWithNeighbours<'a> {
+        ChunkWithNeighbours {
+            chunk,
+            neighbours: [None; 6],
         }
-        self.does_block_exits(&neighbour_pos)
+    }
+}
+
+impl ChunkData for ChunkWithNeighbours<'_> {
+    fn does_block_exits(&self, pos: &Pos3) -> bool {
+        self.chunk.does_block_exits(pos)
+    }
+    fn get_chunk_size(&self) -> i32 {
+        self.chunk.get_chunk_size()
     }
     fn get_color_seed_for_block(&self, pos: &Pos3) -> String {
-        self.get_block_id(pos).unwrap_or_default()
+        self.chunk.get_color_seed_for_block(pos)
+    }
+    fn get_neighbour_chunk(&self, side: &Side) -> Option<&dyn ChunkData> {
+        let i = Side::ALL.iter().position(|s| s == side)?;
+        self.neighbours[i].map(|c| c as &dyn ChunkData)
     }
 }
 
-pub fn do_mesh_shit(mesh: &mut Mesh, chunk: &ClientChunk) {
-    let data = generate_mesh_for_chunk(chunk);
-    let mut vertecies: Vec<[f32; 3]> = Vec::new();
-    let mut colors: Vec<[f32; 4]> = Vec::new();
-    let mut normals: Vec<[f32; 3]> = Vec::new();
-    data.into_iter().for_each(|bfd| {
-        let vertexes = bfd
-            .vertecies
-            .iter()
-            .map(|v| [v.x, v.y, v.z])
-            .collect::<Vec<[i32; 3]>>();
-        normals.append(
-            &mut vertexes
-                .iter()
-                .map(|_| {
-                    let mut color = bfd
-                        .color
-                        .iter()
-                        .map(|c| *c as f32 / 255f32)
-                        .collect::<Vec<f32>>();
-                    color.push(1.);
-                    colors.push(color.try_into().unwrap());
-                    // let i = i as u32 * 4u32;
-                    // mesh.set_indices(Some(Indices::U32(vec![i, i + 1, i + 2])));
-                    // mesh.set_indices(Some(Indices::U32(vec![i + 2, i + 1, i + 3])));
-                    let normal = &bfd.normal;
-                    [normal.x as f32, normal.y as f32, normal.z as f32]
-                })
-                .collect::<Vec<[f32; 3]>>(),
-        );
-        vertecies.append(
-            &mut vertexes
-                .into_iter()
-                .map(|v| [v[0] as f32, v[1] as f32, v[2] as f32])
-                .collect::<Vec<[f32; 3]>>(),
-        );
-    });
+pub fn do_mesh_shit(mesh: &mut Mesh, chunk: &ChunkWithNeighbours) {
+    let data = generate_indexed_mesh_for_chunk(chunk, MeshingMode::Greedy);
+    let to_f32 = |p: &Pos3| [p.x as f32, p.y as f32, p.z as f32];
+    let vertecies = data.positions.iter().map(to_f32).collect::<Vec<[f32; 3]>>();
+    let normals = data.normals.iter().map(to_f32).collect::<Vec<[f32; 3]>>();
+    let colors = data
+        .colors
+        .iter()
+        .map(|[r, g, b]| [*r as f32 / 255f32, *g as f32 / 255f32, *b as f32 / 255f32, 1.])
+        .collect::<Vec<[f32; 4]>>();
     mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vertecies);
     mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
     mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_indices(Indices::U32(data.indices));
 }
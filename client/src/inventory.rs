@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_egui::egui;
+use common::{
+    client_packets::{C2SPackets, InventoryStatus, S2CPackets},
+    turtle::{InventoryAction, Maybe, MoveDirection, Turtle},
+};
+use custom_egui_widgets::item_box::{ItemSlotActions, Side};
+
+pub struct InventoryPlugin;
+
+impl Plugin for InventoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InventoryState::default());
+        app.add_systems(Update, update_inventory_results);
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct InventoryState {
+    /// What came of the last inventory action per (world, turtle index)
+    results: HashMap<(String, i32), InventoryStatus>,
+}
+
+fn update_inventory_results(
+    mut state: ResMut<InventoryState>,
+    mut ws_reader: EventReader<S2CPackets>,
+) {
+    for p in ws_reader.read() {
+        if let S2CPackets::InventoryResult(data) = p {
+            state
+                .results
+                .insert((data.world.clone(), data.index), data.data.clone());
+        }
+    }
+}
+
+fn direction(side: Side) -> MoveDirection {
+    match side {
+        Side::Up => MoveDirection::Up,
+        Side::Forward => MoveDirection::Forward,
+        Side::Down => MoveDirection::Down,
+    }
+}
+
+/// No count means the whole stack
+fn count(amount: u8) -> Option<u32> {
+    (amount != 0).then_some(amount as u32)
+}
+
+fn action_text(action: &InventoryAction) -> String {
+    match action {
+        InventoryAction::Select { slot } => format!("Select slot {slot}"),
+        InventoryAction::Drop {
+            slot, direction, ..
+        } => format!("Drop slot {slot} {direction:?}"),
+        InventoryAction::Suck {
+            slot, direction, ..
+        } => format!("Suck {direction:?} into slot {slot}"),
+        InventoryAction::Split { from, to, count } => {
+            format!("Move {count} items from slot {from} to {to}")
+        }
+        InventoryAction::Merge { from, to } => format!("Merge slot {from} into {to}"),
+        InventoryAction::Give { slot, to, .. } => format!("Give slot {slot} to turtle {to}"),
+    }
+}
+
+/// Sends what the user did with a slot of the inventory window, `selected` is the selected slot
+pub fn send_slot_action(
+    ws: &mut EventWriter<C2SPackets>,
+    turtle: &Turtle,
+    selected: u8,
+    slot: u32,
+    action: ItemSlotActions,
+) {
+    let slot = slot as u8;
+    let action = match action {
+        ItemSlotActions::SelectSlot => InventoryAction::Select { slot },
+        ItemSlotActions::Transfer(amount) => InventoryAction::Split {
+            from: selected,
+            to: slot,
+            count: amount as u32,
+        },
+        ItemSlotActions::Merge { from } => InventoryAction::Merge {
+            from: from as u8,
+            to: slot,
+        },
+        ItemSlotActions::Split { from, count } => InventoryAction::Split {
+            from: from as u8,
+            to: slot,
+            count,
+        },
+        ItemSlotActions::Drop(side, amount) => InventoryAction::Drop {
+            slot,
+            direction: direction(side),
+            count: count(amount),
+        },
+        ItemSlotActions::Suck(side, amount) => InventoryAction::Suck {
+            slot,
+            direction: direction(side),
+            count: count(amount),
+        },
+        ItemSlotActions::Refuel => {
+            ws.send(C2SPackets::SendLuaToTurtle {
+                index: turtle.index,
+                world: turtle.world.clone(),
+                code: "turtle.refuel()".to_string(),
+            });
+            return;
+        }
+    };
+    ws.send(C2SPackets::TurtleInventory {
+        index: turtle.index,
+        world: turtle.world.clone(),
+        action,
+    });
+}
+
+/// The last result and buttons that give the selected stack to the turtles right next to
+/// `turtle`
+pub fn inventory_footer(
+    ui: &mut egui::Ui,
+    state: &InventoryState,
+    turtle: &Turtle,
+    others: &[Turtle],
+    amount: u8,
+    ws: &mut EventWriter<C2SPackets>,
+) {
+    let Maybe::Some(inventory) = &turtle.inventory else {
+        return;
+    };
+    for other in others {
+        let d = other.position - turtle.position;
+        if other.world != turtle.world || d.x.abs() + d.y.abs() + d.z.abs() != 1 {
+            continue;
+        }
+        if ui
+            .button(format!("Give to {}: {}", other.index, other.name))
+            .clicked()
+        {
+            ws.send(C2SPackets::TurtleInventory {
+                index: turtle.index,
+                world: turtle.world.clone(),
+                action: InventoryAction::Give {
+                    slot: inventory.selected_slot,
+                    to: other.index,
+                    count: count(amount),
+                },
+            });
+        }
+    }
+    match state.results.get(&(turtle.world.clone(), turtle.index)) {
+        Some(InventoryStatus::Done(action)) => {
+            ui.label(action_text(action));
+        }
+        Some(InventoryStatus::Failed { action, reason }) => {
+            ui.colored_label(
+                egui::Color32::LIGHT_RED,
+                format!("{} failed: {reason}", action_text(action)),
+            );
+        }
+        None => {}
+    }
+}
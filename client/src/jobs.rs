@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use common::{
+    client_packets::{C2SPackets, JobKind, JobStatus, S2CPackets, TaskKind, TunnelSize},
+    turtle::{TurnDir, Turtle},
+    Pos3,
+};
+
+use crate::{
+    events::ActiveTurtleRes, tasks::TaskState, turtle_stuff::TurtleInstance,
+    waypoints::WaypointState, InputState,
+};
+
+pub struct JobPlugin;
+
+impl Plugin for JobPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(JobState::default());
+        app.add_systems(Update, (update_job_statuses, job_window).chain());
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Quarry,
+    Tunnel,
+    BranchMine,
+    Survey,
+}
+
+#[derive(Resource)]
+pub struct JobState {
+    kind: Kind,
+    /// Quarry and survey size, along the turtle's right, forward and down
+    width: u32,
+    length: u32,
+    depth: u32,
+    tunnel_size: TunnelSize,
+    spacing: u32,
+    branch_length: u32,
+    /// Waypoint the job is moved to, instead of starting at the turtle
+    at: Option<String>,
+    /// Last known status per (world, turtle index)
+    statuses: HashMap<(String, i32), JobStatus>,
+}
+
+impl Default for JobState {
+    fn default() -> Self {
+        Self {
+            kind: Kind::Quarry,
+            width: 8,
+            length: 8,
+            depth: 8,
+            tunnel_size: TunnelSize::OneByTwo,
+            spacing: 3,
+            branch_length: 8,
+            at: None,
+            statuses: HashMap::new(),
+        }
+    }
+}
+
+impl JobState {
+    /// The corners of the box reaching forward and to the right of `turtle`, going down from
+    /// `top`
+    fn corners(&self, turtle: &Turtle, top: Pos3) -> (Pos3, Pos3) {
+        let forward = turtle.orientation.get_forward_vec();
+        let right = turtle.turn(TurnDir::Right).get_forward_vec();
+        let b = top
+            + forward.scale(self.length.max(1) as i32 - 1)
+            + right.scale(self.width.max(1) as i32 - 1)
+            - Pos3::new(0, self.depth.max(1) as i32 - 1, 0);
+        (
+            Pos3::new(top.x.min(b.x), top.y.min(b.y), top.z.min(b.z)),
+            Pos3::new(top.x.max(b.x), top.y.max(b.y), top.z.max(b.z)),
+        )
+    }
+
+    /// The job as set up in the window, starting at `turtle`
+    fn job(&self, turtle: &Turtle) -> JobKind {
+        let (start, direction) = (turtle.position, turtle.orientation);
+        match self.kind {
+            Kind::Quarry => {
+                let (min, max) = self.corners(turtle, start - Pos3::new(0, 1, 0));
+                JobKind::Quarry { min, max }
+            }
+            Kind::Survey => {
+                let (min, max) = self.corners(turtle, start);
+                JobKind::Survey { min, max }
+            }
+            Kind::Tunnel => JobKind::Tunnel {
+                start,
+                direction,
+                length: self.length,
+                size: self.tunnel_size,
+            },
+            Kind::BranchMine => JobKind::BranchMine {
+                start,
+                direction,
+                length: self.length,
+                spacing: self.spacing,
+                branch_length: self.branch_length,
+            },
+        }
+    }
+}
+
+fn update_job_statuses(mut state: ResMut<JobState>, mut ws_reader: EventReader<S2CPackets>) {
+    for p in ws_reader.read() {
+        if let S2CPackets::JobUpdate(data) = p {
+            state
+                .statuses
+                .insert((data.world.clone(), data.index), data.data.clone());
+        }
+    }
+}
+
+fn status_text(status: &JobStatus) -> String {
+    match status {
+        JobStatus::Running { step, steps, .. } => format!("Step {step} of {steps}"),
+        JobStatus::Surveying { coverage, .. } => format!("{coverage}% known"),
+        JobStatus::Refueling { needed } => format!("Refueling, needs {needed} fuel"),
+        JobStatus::Done => "Done".into(),
+        JobStatus::Failed { reason } => format!("Failed: {reason}"),
+        JobStatus::Stopped => "Stopped".into(),
+    }
+}
+
+fn job_window(
+    mut state: ResMut<JobState>,
+    mut contexts: EguiContexts,
+    mut input_state: ResMut<InputState>,
+    mut ws_writer: EventWriter<C2SPackets>,
+    turtles: Query<&TurtleInstance>,
+    active_turtle: Res<ActiveTurtleRes>,
+    waypoints: Res<WaypointState>,
+    tasks: Res<TaskState>,
+) {
+    let Some(turtle) = turtles
+        .iter()
+        .map(|t| &t.turtle)
+        .find(|t| t.index == active_turtle.0 && t.is_online)
+    else {
+        return;
+    };
+    let state = &mut *state;
+    let window = egui::Window::new("Jobs")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut state.kind, Kind::Quarry, "Quarry");
+                ui.radio_value(&mut state.kind, Kind::Tunnel, "Tunnel");
+                ui.radio_value(&mut state.kind, Kind::BranchMine, "Branch Mine");
+                ui.radio_value(&mut state.kind, Kind::Survey, "Survey");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Length");
+                ui.add(egui::DragValue::new(&mut state.length).clamp_range(1..=1024));
+                match state.kind {
+                    Kind::Quarry | Kind::Survey => {
+                        ui.label("Width");
+                        ui.add(egui::DragValue::new(&mut state.width).clamp_range(1..=64));
+                        ui.label("Depth");
+                        ui.add(egui::DragValue::new(&mut state.depth).clamp_range(1..=256));
+                    }
+                    Kind::Tunnel => {
+                        ui.radio_value(&mut state.tunnel_size, TunnelSize::OneByTwo, "1x2");
+                        ui.radio_value(&mut state.tunnel_size, TunnelSize::ThreeByThree, "3x3");
+                    }
+                    Kind::BranchMine => {
+                        ui.label("Spacing");
+                        ui.add(egui::DragValue::new(&mut state.spacing).clamp_range(1..=64));
+                        ui.label("Branch Length");
+                        ui.add(egui::DragValue::new(&mut state.branch_length).clamp_range(1..=64));
+                    }
+                }
+            });
+            ui.label(match state.kind {
+                Kind::Quarry => "Digs below the turtle, forward and to its right",
+                Kind::Survey => {
+                    "Looks at everything from the turtle down, forward and to its right"
+                }
+                Kind::Tunnel | Kind::BranchMine => "Digs forward from the turtle",
+            });
+            if state
+                .at
+                .as_ref()
+                .is_some_and(|at| !waypoints.waypoints.iter().any(|w| &w.name == at))
+            {
+                state.at = None;
+            }
+            egui::ComboBox::from_label("Start At")
+                .selected_text(state.at.as_deref().unwrap_or("Turtle"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut state.at, None, "Turtle");
+                    for w in &waypoints.waypoints {
+                        ui.selectable_value(&mut state.at, Some(w.name.clone()), &w.name);
+                    }
+                });
+            if let Some(status) = state.statuses.get(&(turtle.world.clone(), turtle.index)) {
+                ui.label(status_text(status));
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Start Job").clicked() {
+                    ws_writer.send(C2SPackets::StartJob {
+                        index: turtle.index,
+                        world: turtle.world.clone(),
+                        kind: state.job(turtle),
+                        at: state.at.clone(),
+                    });
+                }
+                if ui.button("Stop Job").clicked() {
+                    ws_writer.send(C2SPackets::StopJob {
+                        index: turtle.index,
+                        world: turtle.world.clone(),
+                    });
+                }
+                if ui
+                    .button("Queue")
+                    .on_hover_text("Any idle turtle can take it, see Tasks")
+                    .clicked()
+                {
+                    ws_writer.send(C2SPackets::QueueTask {
+                        world: turtle.world.clone(),
+                        kind: TaskKind::Job(state.job(turtle)),
+                        priority: tasks.priority,
+                    });
+                }
+            });
+        });
+    input_state.block_camera_updates |= window.is_some_and(|w| w.response.hovered());
+}
@@ -1,8 +1,17 @@
+pub mod block_search;
+pub mod blueprints;
+pub mod chunk_streaming;
 pub mod components;
 pub mod events;
 pub mod idk;
 pub mod input;
+pub mod inventory;
 pub mod executable_files;
+pub mod go_to;
+pub mod jobs;
+pub mod tasks;
+pub mod terminal;
+pub mod waypoints;
 use std::{sync::Arc, path::PathBuf};
 
 pub use actually_usable_voxel_mesh_gen as voxel_meshing;
@@ -34,10 +43,14 @@ pub struct BlockBlacklist {
 #[derive(Resource)]
 pub struct MiscState {
     pub hovered_block: Option<String>,
+    /// Where the block under the cursor is, if it is known
+    pub hovered_pos: Option<common::Pos3>,
 }
 #[derive(Resource)]
 pub struct ShowFileDialog {
     pub show: bool,
     pub conntents: String,
     pub file: PathBuf,
+    /// Where on the turtle the file gets saved
+    pub save_path: String,
 }
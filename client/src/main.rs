@@ -11,9 +11,9 @@ use common::{
     client_packets::{C2SPackets, S2CPackets, SetTurtlesData},
     turtle::{Item, Maybe, MoveDirection},
     // turtle_packets::TurtleUpDown,
-    world_data::{get_chunk_containing_block, Chunk},
+    world_data::{get_chunk_containing_block, get_chunk_relative_pos, Chunk, CHUNK_SIZE},
 };
-use custom_egui_widgets::item_box::{item_box, ItemSlotActions, TX};
+use custom_egui_widgets::item_box::{item_box, TX};
 use egui_code_editor::{CodeEditor, Syntax};
 use smooth_bevy_cameras::{
     controllers::orbit::{OrbitCameraBundle, OrbitCameraController, OrbitCameraPlugin},
@@ -26,16 +26,26 @@ use std::{
     path::PathBuf,
     sync::{mpsc, Arc},
 };
-use trc_client::executable_files::ExecutableFilesPlugin;
+use trc_client::block_search::BlockSearchPlugin;
+use trc_client::blueprints::BlueprintPlugin;
+use trc_client::chunk_streaming::{
+    render_distance_slider, ChunkMat, ChunkStreamingPlugin, ChunkStreamingState, SpawnChunk,
+};
+use trc_client::executable_files::{programs_menu, ExecutableFilesPlugin, ProgramsState};
+use trc_client::go_to::{go_to_menu, GoToPlugin, GoToState};
+use trc_client::inventory::{inventory_footer, send_slot_action, InventoryPlugin, InventoryState};
+use trc_client::jobs::JobPlugin;
+use trc_client::tasks::TaskPlugin;
+use trc_client::terminal::{terminal_button, TerminalPlugin, TerminalState};
+use trc_client::waypoints::WaypointPlugin;
 use trc_client::external_inv_support::ExternalInvSupportPlugin;
 use trc_client::{
-    bundels::ChunkBundle,
     components::ChunkInstance,
     events::{ActiveTurtleChanged, ActiveTurtleRes, EventsPlugin},
-    idk::ClientChunk,
     raycast::RaycastPlugin,
     systems::Systems,
     turtle_stuff::{turtle_spawner, SpawnTurtle, TurtleInstance, TurtleModels},
+    voxel_meshing::data::Side,
     ws::WS,
     BlockBlacklist, DoBlockRaymarch, MiscState, ShowFileDialog,
 };
@@ -69,8 +79,16 @@ fn main() {
         .add_plugins(EguiPlugin)
         .add_plugins(RaycastPlugin)
         .add_plugins(ExternalInvSupportPlugin)
+        .add_plugins(GoToPlugin)
+        .add_plugins(TerminalPlugin)
+        .add_plugins(ChunkStreamingPlugin)
+        .add_plugins(BlockSearchPlugin)
+        .add_plugins(BlueprintPlugin)
+        .add_plugins(JobPlugin)
+        .add_plugins(WaypointPlugin)
+        .add_plugins(TaskPlugin)
+        .add_plugins(InventoryPlugin)
         .add_event::<SpawnTurtle>()
-        .add_event::<SpawnChunk>()
         .insert_resource(AmbientLight {
             color: Color::WHITE,
             ..Default::default()
@@ -91,11 +109,13 @@ fn main() {
         })
         .insert_resource(MiscState {
             hovered_block: None,
+            hovered_pos: None,
         })
         .insert_resource(ShowFileDialog {
             show: false,
             conntents: "".into(),
             file: PathBuf::default(),
+            save_path: String::new(),
         })
         .insert_resource(DoBlockRaymarch(true))
         .insert_resource(DirectionalLightShadowMap { size: 1024 * 4 })
@@ -103,7 +123,6 @@ fn main() {
         .add_systems(Startup, ui_setup)
         .add_systems(Update, setup_turtles)
         .add_systems(Update, turtle_spawner)
-        .add_systems(Update, handle_chunk_spawning)
         .add_systems(
             Update,
             hanlde_world_updates.run_if(on_event::<S2CPackets>()),
@@ -131,6 +150,10 @@ fn file_drop(mut dnd_evr: EventReader<FileDragAndDrop>, mut dialog: ResMut<ShowF
             match fs::read_to_string(path_buf) {
                 Ok(text) => {
                     dialog.file.clone_from(path_buf);
+                    dialog.save_path = path_buf
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .unwrap_or_default();
                     dialog.show = true;
                     dialog.conntents = text;
                 }
@@ -147,7 +170,6 @@ fn handle_world_selection_updates(
 ) {
     if worlds.curr_world != *old {
         if let Some(curr) = &worlds.curr_world {
-            ws_writer.send(C2SPackets::RequestWorld(curr.to_owned()));
             ws_writer.send(C2SPackets::RequestTurtles(curr.to_owned()));
         }
     }
@@ -231,6 +253,11 @@ fn ui(
     mut do_block_march: ResMut<DoBlockRaymarch>,
     mut item_amount_modifier: Local<u8>,
     mut lua_code_str: Local<String>,
+    mut go_to_state: ResMut<GoToState>,
+    mut terminal_state: ResMut<TerminalState>,
+    mut programs_state: ResMut<ProgramsState>,
+    mut chunk_streaming_state: ResMut<ChunkStreamingState>,
+    inventory_state: Res<InventoryState>,
 ) {
     if **do_block_march && !input_state.block_camera_updates {
         if let Some(b) = misc_state.hovered_block.as_ref() {
@@ -268,6 +295,7 @@ fn ui(
                         ui.selectable_value(&mut worlds.curr_world, Some(w.to_owned()), w);
                     }
                 });
+                render_distance_slider(ui, &mut chunk_streaming_state);
                 // Turtles
                 let mut c = egui::ComboBox::from_label("Turtle");
                 if let Some(t) = &curr_turtle {
@@ -320,6 +348,9 @@ fn ui(
                             }
                         });
                     });
+                    programs_menu(ui, &mut programs_state, t, ws);
+                    go_to_menu(ui, &mut go_to_state, t, ws);
+                    terminal_button(ui, &mut terminal_state);
                 });
             }
         });
@@ -353,31 +384,18 @@ fn ui(
                                 }
                             }
                         });
+                    inventory_footer(
+                        ui,
+                        &inventory_state,
+                        t,
+                        &why,
+                        *item_amount_modifier,
+                        &mut ws_writer,
+                    );
                 });
 
             while let Ok((slot, action)) = rx.try_recv() {
-                match action {
-                    // ItemSlotActions::SelectSlot => ws_writer.send(C2SPackets::TurtleSelectSlot {
-                    //     index: t.index,
-                    //     world: t.world.clone(),
-                    //     slot,
-                    // }),
-                    ItemSlotActions::Transfer(amount) => {
-                        ws_writer.send(C2SPackets::SendLuaToTurtle {
-                            index: t.index,
-                            world: t.world.clone(),
-                            code: format!("turtle.transferTo({slot}, {amount})"),
-                        });
-                    }
-                    ItemSlotActions::Refuel => {
-                        ws_writer.send(C2SPackets::SendLuaToTurtle {
-                            index: t.index,
-                            world: t.world.clone(),
-                            code: "turtle.refuel()".to_string(),
-                        });
-                    }
-                    _ => (),
-                };
+                send_slot_action(&mut ws_writer, t, inv.selected_slot, slot, action);
             }
             input_state.block_camera_updates |= window
                 .zip(egui_input.0)
@@ -627,31 +645,25 @@ fn hanlde_world_updates(
                     chunk_spawn.send(SpawnChunk(chunk));
                 }
             }
+            // the neighbour culled its faces against this block
+            let rel_pos = get_chunk_relative_pos(block.get_pos());
+            for side in Side::ALL {
+                let p = rel_pos + side.side_to_rel_pos();
+                if [p.x, p.y, p.z].iter().all(|c| (0..CHUNK_SIZE).contains(c)) {
+                    continue;
+                }
+                let neighbour_pos = chunk_pos + side.side_to_rel_pos();
+                if let Some(mut chunk) = query
+                    .iter_mut()
+                    .find(|chunk| chunk.get_chunk_pos() == &neighbour_pos)
+                {
+                    chunk.set_changed();
+                }
+            }
         }
     }
 }
 
-#[derive(Resource, Debug, DerefMut, Deref)]
-struct ChunkMat(Handle<StandardMaterial>);
-
-#[derive(Event, Debug, Deref, DerefMut)]
-struct SpawnChunk(Chunk);
-
-fn handle_chunk_spawning(
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut commands: Commands,
-    mut event: EventReader<SpawnChunk>,
-    chunk_mat: Res<ChunkMat>,
-) {
-    for e in event.read() {
-        commands.spawn(ChunkBundle::new(
-            ClientChunk::from_chunk(e.0.clone()),
-            &mut meshes,
-            chunk_mat.clone(),
-        ));
-    }
-}
-
 // fn animate_light_direction(
 //     time: Res<Time>,
 //     mut query: Query<&mut Transform, With<DirectionalLight>>,
@@ -6,7 +6,9 @@ use bevy_mod_raycast::{
 use common::world_data::{get_chunk_containing_block, get_chunk_relative_pos};
 
 use crate::{
-    components::ChunkInstance, util::vec3_to_pos3, BlockBlacklist, DoBlockRaymarch, MiscState,
+    components::ChunkInstance,
+    util::{format_age, unix_now, vec3_to_pos3},
+    BlockBlacklist, DoBlockRaymarch, MiscState,
 };
 
 pub struct RaycastPlugin;
@@ -64,17 +66,31 @@ fn block_raycast(
             let chunk = chunk_q.iter().find(|chunk| chunk.get_chunk_pos() == &c_pos);
             chunk.and_then(|c| {
                 let rel = get_chunk_relative_pos(&pos);
-                let b = c.get_block_id(&rel)?;
-                let e = c.does_block_exist(&rel)
-                    && !block_blacklist.block_render_blacklist.contains(&b);
-                match e {
-                    true => Some(b),
-                    false => None,
+                let entry = c.get_entry(&rel)?;
+                let e =
+                    !entry.is_air && !block_blacklist.block_render_blacklist.contains(&entry.id);
+                if !e {
+                    return None;
                 }
+                // like minecraft:wheat[age=7]
+                let mut text = format!("{}{}", entry.id, entry.state);
+                if !entry.state.tags.is_empty() {
+                    let tags = entry.state.tags.iter().cloned().collect::<Vec<_>>();
+                    text += &format!("\n#{}", tags.join(" #"));
+                }
+                if let Some(o) = c.get_observation(&rel) {
+                    text += &format!(
+                        "\nseen {} ago by turtle {}",
+                        format_age(unix_now().saturating_sub(o.at)),
+                        o.by
+                    );
+                }
+                Some((pos, text))
             })
         })
     });
-    misc_state.hovered_block = block;
+    misc_state.hovered_pos = block.as_ref().map(|(pos, _)| *pos);
+    misc_state.hovered_block = block.map(|(_, text)| text);
     // check if the cursor is inside the window and get its position
     // then, ask bevy to convert into world coordinates, and truncate to discard Z
 }
@@ -1,3 +1,5 @@
+use std::collections::{HashMap, HashSet};
+
 use bevy::{prelude::*, render::render_asset::RenderAssetUsages};
 use common::client_packets::S2CPackets;
 use smooth_bevy_cameras::LookTransform;
@@ -5,9 +7,10 @@ use smooth_bevy_cameras::LookTransform;
 use crate::{
     components::{ChunkInstance, LerpTransform},
     events::{ActiveTurtleChanged, ActiveTurtleRes},
-    idk::do_mesh_shit,
+    idk::{do_mesh_shit, ChunkWithNeighbours, ClientChunk},
     turtle_stuff::{TurtleInstance, TurtleModels, TURTLE_LERP_TIME},
     util::{pos3_to_vec3, quat_from_dir},
+    voxel_meshing::data::Side,
     BlockBlacklist,
 };
 
@@ -99,16 +102,49 @@ pub fn update_cam_point_on_turtle_move(
 }
 
 pub fn chunk_update_mesh(
-    query: Query<(Entity, &ChunkInstance), Changed<ChunkInstance>>,
+    query: Query<(Entity, Ref<ChunkInstance>)>,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
 ) {
-    for (entity, chunk_instance) in query.iter() {
+    let mut remesh = HashSet::new();
+    for (_, chunk_instance) in query.iter() {
+        let pos = *chunk_instance.get_chunk_pos();
+        if chunk_instance.is_changed() {
+            remesh.insert(pos);
+        }
+        // a new chunk hides the border faces of the ones around it
+        if chunk_instance.is_added() {
+            remesh.extend(Side::ALL.map(|side| pos + side.side_to_rel_pos()));
+        }
+    }
+    if remesh.is_empty() {
+        return;
+    }
+    let chunks = query
+        .iter()
+        .map(|(_, c)| (*c.get_chunk_pos(), c.into_inner()))
+        .collect::<HashMap<_, _>>();
+    for (entity, chunk_instance) in query
+        .iter()
+        .filter(|(_, c)| remesh.contains(c.get_chunk_pos()))
+    {
+        let pos = *chunk_instance.get_chunk_pos();
+        let neighbours = Side::ALL.map(|side| {
+            chunks
+                .get(&(pos + side.side_to_rel_pos()))
+                .map(|c| -> &ClientChunk { c })
+        });
         let mut mesh = Mesh::new(
             bevy::render::render_resource::PrimitiveTopology::TriangleList,
             RenderAssetUsages::MAIN_WORLD,
         );
-        do_mesh_shit(&mut mesh, chunk_instance);
+        do_mesh_shit(
+            &mut mesh,
+            &ChunkWithNeighbours {
+                chunk: &chunk_instance,
+                neighbours,
+            },
+        );
         commands.entity(entity).remove::<Handle<Mesh>>();
         commands.entity(entity).insert(meshes.add(mesh));
     }
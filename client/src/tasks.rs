@@ -0,0 +1,208 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use common::{
+    client_packets::{C2SPackets, JobKind, S2CPackets, Task, TaskKind, TaskState},
+    world_data::{get_chunk_containing_block, CHUNK_SIZE},
+    Pos3,
+};
+use smooth_bevy_cameras::LookTransform;
+
+use crate::{util::vec3_to_pos3, waypoints::WaypointState, InputState, WorldState};
+
+pub struct TaskPlugin;
+
+impl Plugin for TaskPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TaskState::default());
+        app.add_systems(Update, (request_tasks, receive_tasks, task_window).chain());
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    MineChunk,
+    Deliver,
+}
+
+#[derive(Resource)]
+pub struct TaskState {
+    tasks: Vec<Task>,
+    /// The world the tasks were asked for
+    requested: Option<String>,
+    kind: Kind,
+    /// Used by the jobs window too, when it queues a job
+    pub priority: i32,
+    /// How far below the camera a mined chunk goes
+    depth: u32,
+    waypoint: Option<String>,
+}
+
+impl Default for TaskState {
+    fn default() -> Self {
+        Self {
+            tasks: Vec::new(),
+            requested: None,
+            kind: Kind::MineChunk,
+            priority: 0,
+            depth: 16,
+            waypoint: None,
+        }
+    }
+}
+
+fn request_tasks(
+    mut state: ResMut<TaskState>,
+    worlds: Res<WorldState>,
+    mut ws_writer: EventWriter<C2SPackets>,
+) {
+    if !worlds.is_changed() || state.requested == worlds.curr_world {
+        return;
+    }
+    state.requested.clone_from(&worlds.curr_world);
+    state.tasks.clear();
+    if let Some(world) = &worlds.curr_world {
+        ws_writer.send(C2SPackets::RequestTasks(world.clone()));
+    }
+}
+
+fn receive_tasks(mut state: ResMut<TaskState>, mut ws_reader: EventReader<S2CPackets>) {
+    for p in ws_reader.read() {
+        if let S2CPackets::Tasks { world, tasks } = p {
+            if state.requested.as_ref() == Some(world) {
+                state.tasks.clone_from(tasks);
+            }
+        }
+    }
+}
+
+fn kind_text(kind: &TaskKind) -> String {
+    match kind {
+        TaskKind::Job(JobKind::Quarry { min, max }) => format!(
+            "Quarry {} {} {} to {} {} {}",
+            min.x, min.y, min.z, max.x, max.y, max.z
+        ),
+        TaskKind::Job(JobKind::Tunnel { start, length, .. }) => format!(
+            "Tunnel of {length} from {} {} {}",
+            start.x, start.y, start.z
+        ),
+        TaskKind::Job(JobKind::BranchMine { start, length, .. }) => format!(
+            "Branch Mine of {length} from {} {} {}",
+            start.x, start.y, start.z
+        ),
+        TaskKind::Job(JobKind::Survey { min, max }) => format!(
+            "Survey {} {} {} to {} {} {}",
+            min.x, min.y, min.z, max.x, max.y, max.z
+        ),
+        TaskKind::Deliver { waypoint } => format!("Deliver to {waypoint}"),
+    }
+}
+
+fn state_text(task: &Task) -> String {
+    let state = match &task.state {
+        TaskState::Queued => "Queued".to_owned(),
+        TaskState::Assigned { turtle } => format!("Turtle {turtle}"),
+        TaskState::Failed { reason } => format!("Failed: {reason}"),
+    };
+    match task.retries {
+        0 => state,
+        n => format!("{state}, retried {n}x"),
+    }
+}
+
+/// The chunk column the camera looks at, from just below the camera target down
+fn chunk_quarry(target: Pos3, depth: u32) -> JobKind {
+    let chunk = get_chunk_containing_block(&target);
+    let (x, z) = (chunk.x * CHUNK_SIZE, chunk.z * CHUNK_SIZE);
+    JobKind::Quarry {
+        min: Pos3::new(x, target.y - depth.max(1) as i32, z),
+        max: Pos3::new(x + CHUNK_SIZE - 1, target.y - 1, z + CHUNK_SIZE - 1),
+    }
+}
+
+fn task_window(
+    mut state: ResMut<TaskState>,
+    worlds: Res<WorldState>,
+    waypoints: Res<WaypointState>,
+    mut contexts: EguiContexts,
+    mut input_state: ResMut<InputState>,
+    mut ws_writer: EventWriter<C2SPackets>,
+    cams: Query<&LookTransform>,
+) {
+    let Some(world) = worlds.curr_world.clone() else {
+        return;
+    };
+    let target = cams
+        .get_single()
+        .map_or(Pos3::zero(), |cam| vec3_to_pos3(cam.target.floor()));
+    let state = &mut *state;
+    let window = egui::Window::new("Tasks")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut state.kind, Kind::MineChunk, "Mine Chunk");
+                ui.radio_value(&mut state.kind, Kind::Deliver, "Deliver");
+            });
+            let kind = match state.kind {
+                Kind::MineChunk => {
+                    ui.horizontal(|ui| {
+                        ui.label("Depth");
+                        ui.add(egui::DragValue::new(&mut state.depth).clamp_range(1..=256));
+                    });
+                    ui.label("The chunk the camera looks at, from below the camera down");
+                    Some(TaskKind::Job(chunk_quarry(target, state.depth)))
+                }
+                Kind::Deliver => {
+                    egui::ComboBox::from_label("Waypoint")
+                        .selected_text(state.waypoint.as_deref().unwrap_or("None"))
+                        .show_ui(ui, |ui| {
+                            for w in &waypoints.waypoints {
+                                ui.selectable_value(
+                                    &mut state.waypoint,
+                                    Some(w.name.clone()),
+                                    &w.name,
+                                );
+                            }
+                        });
+                    ui.label("Drops everything into the chest below the waypoint");
+                    state
+                        .waypoint
+                        .clone()
+                        .map(|waypoint| TaskKind::Deliver { waypoint })
+                }
+            };
+            ui.horizontal(|ui| {
+                ui.label("Priority");
+                ui.add(egui::DragValue::new(&mut state.priority));
+                if let Some(kind) = kind {
+                    if ui.button("Queue").clicked() {
+                        ws_writer.send(C2SPackets::QueueTask {
+                            world: world.clone(),
+                            kind,
+                            priority: state.priority,
+                        });
+                    }
+                }
+            });
+            ui.separator();
+            if state.tasks.is_empty() {
+                ui.label("Nothing queued");
+            }
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("tasks").show(ui, |ui| {
+                    for task in &state.tasks {
+                        ui.label(task.priority.to_string());
+                        ui.label(kind_text(&task.kind));
+                        ui.label(state_text(task));
+                        if ui.button("Cancel").clicked() {
+                            ws_writer.send(C2SPackets::CancelTask {
+                                world: world.clone(),
+                                id: task.id,
+                            });
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+    input_state.block_camera_updates |= window.is_some_and(|w| w.response.hovered());
+}
@@ -0,0 +1,130 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use common::{
+    client_packets::{C2SPackets, S2CPackets},
+    std_out::Scrollback,
+};
+
+use crate::{events::ActiveTurtleRes, turtle_stuff::TurtleInstance, InputState, WorldState};
+
+pub struct TerminalPlugin;
+
+impl Plugin for TerminalPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TerminalState::default());
+        app.add_systems(
+            Update,
+            (update_subscription, update_scrollback, terminal_window).chain(),
+        );
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct TerminalState {
+    pub open: bool,
+    input: String,
+    /// The (world, turtle index) whose output we currently receive
+    subscribed: Option<(String, i32)>,
+    scrollback: Scrollback,
+}
+
+/// Only stay subscribed while the terminal is open, so the server doesn't stream output nobody reads
+fn update_subscription(
+    mut state: ResMut<TerminalState>,
+    world_state: Res<WorldState>,
+    active_turtle_res: Res<ActiveTurtleRes>,
+    mut ws_writer: EventWriter<C2SPackets>,
+) {
+    let wanted = world_state
+        .curr_world
+        .clone()
+        .filter(|_| state.open)
+        .map(|world| (world, active_turtle_res.0));
+    if wanted == state.subscribed {
+        return;
+    }
+    if let Some((world, index)) = state.subscribed.take() {
+        ws_writer.send(C2SPackets::UnsubscribeFromStdOut { index, world });
+    }
+    state.scrollback.clear();
+    if let Some((world, index)) = wanted.clone() {
+        ws_writer.send(C2SPackets::SubscribeToStdOut { index, world });
+    }
+    state.subscribed = wanted;
+}
+
+fn update_scrollback(mut state: ResMut<TerminalState>, mut ws_reader: EventReader<S2CPackets>) {
+    for p in ws_reader.read() {
+        let (data, replace) = match p {
+            S2CPackets::StdOutScrollback(data) => (data, true),
+            S2CPackets::StdOutFromTurtle(data) => (data, false),
+            _ => continue,
+        };
+        if state
+            .subscribed
+            .as_ref()
+            .is_some_and(|(world, index)| world == &data.world && *index == data.index)
+        {
+            if replace {
+                state.scrollback.clear();
+            }
+            state.scrollback.push(&data.data);
+        }
+    }
+}
+
+fn terminal_window(
+    mut state: ResMut<TerminalState>,
+    mut contexts: EguiContexts,
+    turtles: Query<&TurtleInstance>,
+    mut input_state: ResMut<InputState>,
+    mut ws_writer: EventWriter<C2SPackets>,
+) {
+    let Some((world, index)) = state.subscribed.clone() else {
+        return;
+    };
+    let name = turtles
+        .iter()
+        .find(|t| t.index == index && t.world == world)
+        .map(|t| t.name.clone())
+        .unwrap_or_default();
+    let state = &mut *state;
+    let mut open = state.open;
+    let window = egui::Window::new(format!("Terminal {index}: {name}"))
+        .id(egui::Id::new("turtle_terminal"))
+        .default_size([400.0, 300.0])
+        .open(&mut open)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::ScrollArea::vertical()
+                .stick_to_bottom(true)
+                .max_height(250.0)
+                .show(ui, |ui| {
+                    for line in state.scrollback.lines() {
+                        ui.monospace(line);
+                    }
+                });
+            ui.separator();
+            ui.horizontal(|ui| {
+                let input = ui.add(
+                    egui::TextEdit::singleline(&mut state.input)
+                        .code_editor()
+                        .hint_text("stdin"),
+                );
+                let submitted = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if submitted || ui.button("Send").clicked() {
+                    ws_writer.send(C2SPackets::StdInForTurtle {
+                        index,
+                        world: world.clone(),
+                        value: std::mem::take(&mut state.input),
+                    });
+                    input.request_focus();
+                }
+            });
+        });
+    state.open = open;
+    input_state.block_camera_updates |= window.is_some_and(|w| w.response.hovered());
+}
+
+pub fn terminal_button(ui: &mut egui::Ui, state: &mut TerminalState) {
+    ui.toggle_value(&mut state.open, "Terminal");
+}
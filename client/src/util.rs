@@ -1,6 +1,7 @@
 use std::fmt::Debug;
 
 use bevy::prelude::{Vec3, Quat, Mat3};
+use bevy::utils::SystemTime;
 use common::Pos3;
 
 #[inline]
@@ -25,3 +26,20 @@ pub fn pos3_to_vec3(val: Pos3) -> Vec3 {
 pub fn vec3_to_pos3(val: Vec3) -> Pos3 {
     Pos3::new(val.x as i32, val.y as i32, val.z as i32)
 }
+
+/// Seconds since the unix epoch, the unit of [`common::world_data::Observation::at`]
+pub fn unix_now() -> u32 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs().try_into().unwrap_or(u32::MAX))
+}
+
+/// Rough human readable age, like "3m" or "2d"
+pub fn format_age(secs: u32) -> String {
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
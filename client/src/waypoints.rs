@@ -0,0 +1,241 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiSettings};
+use common::{
+    client_packets::{C2SPackets, S2CPackets},
+    waypoint::{Target, Waypoint, WaypointKind},
+    Pos3,
+};
+
+use crate::{
+    events::ActiveTurtleRes, turtle_stuff::TurtleInstance, util::pos3_to_vec3, InputState,
+    MiscState, WorldState,
+};
+
+pub struct WaypointPlugin;
+
+impl Plugin for WaypointPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(WaypointState::default());
+        app.add_systems(
+            Update,
+            (
+                request_waypoints,
+                receive_waypoints,
+                waypoint_window,
+                place_waypoint,
+                draw_waypoints,
+            )
+                .chain(),
+        );
+    }
+}
+
+#[derive(Resource)]
+pub struct WaypointState {
+    pub waypoints: Vec<Waypoint>,
+    /// The world the waypoints were asked for
+    requested: Option<String>,
+    name: String,
+    kind: WaypointKind,
+    /// The next click on a block puts the waypoint on top of it
+    placing: bool,
+    error: Option<String>,
+}
+
+impl Default for WaypointState {
+    fn default() -> Self {
+        Self {
+            waypoints: Vec::new(),
+            requested: None,
+            name: String::new(),
+            kind: WaypointKind::Home,
+            placing: false,
+            error: None,
+        }
+    }
+}
+
+fn kind_color(kind: WaypointKind) -> Color {
+    match kind {
+        WaypointKind::Home => Color::rgb(0.3, 0.9, 0.3),
+        WaypointKind::FuelDepot => Color::rgb(1., 0.6, 0.1),
+        WaypointKind::DropOff => Color::rgb(0.3, 0.6, 1.),
+        WaypointKind::MineEntrance => Color::rgb(0.8, 0.3, 0.9),
+    }
+}
+
+fn request_waypoints(
+    mut state: ResMut<WaypointState>,
+    worlds: Res<WorldState>,
+    mut ws_writer: EventWriter<C2SPackets>,
+) {
+    if !worlds.is_changed() || state.requested == worlds.curr_world {
+        return;
+    }
+    state.requested.clone_from(&worlds.curr_world);
+    state.waypoints.clear();
+    if let Some(world) = &worlds.curr_world {
+        ws_writer.send(C2SPackets::RequestWaypoints(world.clone()));
+    }
+}
+
+fn receive_waypoints(mut state: ResMut<WaypointState>, mut ws_reader: EventReader<S2CPackets>) {
+    for p in ws_reader.read() {
+        match p {
+            S2CPackets::Waypoints { world, waypoints }
+                if state.requested.as_ref() == Some(world) =>
+            {
+                state.waypoints.clone_from(waypoints);
+            }
+            S2CPackets::WaypointError { name, reason } => {
+                state.error = Some(format!("{name}: {reason}"));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn waypoint_window(
+    mut state: ResMut<WaypointState>,
+    worlds: Res<WorldState>,
+    mut contexts: EguiContexts,
+    mut input_state: ResMut<InputState>,
+    mut ws_writer: EventWriter<C2SPackets>,
+    turtles: Query<&TurtleInstance>,
+    active_turtle: Res<ActiveTurtleRes>,
+) {
+    let Some(world) = worlds.curr_world.clone() else {
+        return;
+    };
+    let turtle = turtles
+        .iter()
+        .map(|t| &t.turtle)
+        .find(|t| t.index == active_turtle.0 && t.is_online && t.world == world);
+    let state = &mut *state;
+    let window =
+        egui::Window::new("Waypoints")
+            .default_open(false)
+            .show(contexts.ctx_mut(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut state.name).hint_text("Name"));
+                    egui::ComboBox::from_id_source("waypoint_kind")
+                        .selected_text(state.kind.label())
+                        .show_ui(ui, |ui| {
+                            for kind in WaypointKind::ALL {
+                                ui.selectable_value(&mut state.kind, kind, kind.label());
+                            }
+                        });
+                });
+                let label = if state.placing { "Cancel" } else { "Place" };
+                if ui
+                    .add_enabled(!state.name.trim().is_empty(), egui::Button::new(label))
+                    .on_hover_text("Click a block to put the waypoint on top of it")
+                    .clicked()
+                {
+                    state.placing = !state.placing;
+                }
+                if state.placing {
+                    ui.label("Click a block, needs Block Raycast");
+                }
+                if let Some(error) = &state.error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+                ui.separator();
+                let mut remove = None;
+                egui::Grid::new("waypoints").show(ui, |ui| {
+                    for waypoint in &state.waypoints {
+                        let Pos3 { x, y, z } = waypoint.position;
+                        ui.label(&waypoint.name);
+                        ui.label(waypoint.kind.label());
+                        ui.label(format!("{x} {y} {z}"));
+                        if let Some(turtle) = turtle {
+                            if ui.button("Go").clicked() {
+                                ws_writer.send(C2SPackets::GoTo {
+                                    index: turtle.index,
+                                    world: world.clone(),
+                                    target: Target::Waypoint(waypoint.name.clone()),
+                                });
+                            }
+                        }
+                        if ui.button("Remove").clicked() {
+                            remove = Some(waypoint.name.clone());
+                        }
+                        ui.end_row();
+                    }
+                });
+                if let Some(name) = remove {
+                    ws_writer.send(C2SPackets::RemoveWaypoint {
+                        world: world.clone(),
+                        name,
+                    });
+                }
+            });
+    input_state.block_camera_updates |= window.is_some_and(|w| w.response.hovered());
+}
+
+fn place_waypoint(
+    mut state: ResMut<WaypointState>,
+    worlds: Res<WorldState>,
+    misc_state: Res<MiscState>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    mut contexts: EguiContexts,
+    mut ws_writer: EventWriter<C2SPackets>,
+) {
+    if !state.placing
+        || !mouse_buttons.just_pressed(MouseButton::Left)
+        || contexts.ctx_mut().wants_pointer_input()
+    {
+        return;
+    }
+    let (Some(world), Some(block)) = (&worlds.curr_world, misc_state.hovered_pos) else {
+        return;
+    };
+    state.placing = false;
+    state.error = None;
+    ws_writer.send(C2SPackets::SaveWaypoint {
+        world: world.clone(),
+        waypoint: Waypoint {
+            name: state.name.trim().to_owned(),
+            kind: state.kind,
+            position: block + Pos3::new(0, 1, 0),
+        },
+    });
+}
+
+/// A pole with a box on top for every waypoint, labelled with its name
+fn draw_waypoints(
+    state: Res<WaypointState>,
+    mut gizmos: Gizmos,
+    mut contexts: EguiContexts,
+    egui_settings: Res<EguiSettings>,
+    cams: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+) {
+    let Ok((camera, cam_transform)) = cams.get_single() else {
+        return;
+    };
+    let painter = contexts
+        .ctx_mut()
+        .layer_painter(egui::LayerId::background());
+    for waypoint in &state.waypoints {
+        let color = kind_color(waypoint.kind);
+        let foot = pos3_to_vec3(waypoint.position) + Vec3::new(0.5, 0., 0.5);
+        let top = foot + Vec3::Y * 1.5;
+        gizmos.line(foot, top, color);
+        gizmos.cuboid(
+            Transform::from_translation(top).with_scale(Vec3::splat(0.3)),
+            color,
+        );
+        let Some(screen) = camera.world_to_viewport(cam_transform, top + Vec3::Y * 0.3) else {
+            continue;
+        };
+        let screen = screen / egui_settings.scale_factor;
+        let [r, g, b, _] = color.as_rgba_u8();
+        painter.text(
+            egui::pos2(screen.x, screen.y),
+            egui::Align2::CENTER_BOTTOM,
+            &waypoint.name,
+            egui::FontId::proportional(14.),
+            egui::Color32::from_rgb(r, g, b),
+        );
+    }
+}
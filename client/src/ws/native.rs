@@ -1,5 +1,13 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
 use bevy::{log::prelude::*, prelude::*};
-use common::client_packets::{C2SPackets, S2CPackets};
+use common::{
+    client_packets::{C2SPackets, Capability, HandshakeData, S2CPackets, PROTOCOL_VERSION},
+    wire,
+};
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use futures_util::{SinkExt, StreamExt};
 use serde_json::{from_str, to_string};
@@ -49,8 +57,20 @@ impl WsCommunicator {
             info!("test: {}", ip);
             let (ws, _) = connect_async(ip).await.unwrap();
             info!("Websocket Connection Established.^^");
-            ws.split()
+            let (mut ws_tx, ws_rx) = ws.split();
+            let handshake = C2SPackets::Handshake(HandshakeData {
+                version: PROTOCOL_VERSION,
+                capabilities: vec![Capability::BinaryEncoding],
+            });
+            ws_tx
+                .send(Message::Text(to_string(&handshake).unwrap()))
+                .await
+                .unwrap();
+            (ws_tx, ws_rx)
         });
+        // set once the server agreed to it, everything we send after that is binary
+        let binary = Arc::new(AtomicBool::new(false));
+        let read_binary = binary.clone();
 
         let (s2c_tx, s2c_rx) = unbounded::<S2CPackets>();
         let (c2s_tx, c2s_rx) = unbounded::<C2SPackets>();
@@ -65,10 +85,15 @@ impl WsCommunicator {
                 match e {
                     Some(Ok(Message::Text(msg))) => {
                         info!("message!");
-                        if let Ok(msg) = from_str::<S2CPackets>(&msg) {
-                            _ = s2c_tx.send(msg);
+                        match from_str::<S2CPackets>(&msg) {
+                            Ok(msg) => handle_packet(msg, &read_binary, &s2c_tx),
+                            Err(err) => error!("invalid packet from server: {err}"),
                         }
                     }
+                    Some(Ok(Message::Binary(msg))) => match wire::from_bytes::<S2CPackets>(&msg) {
+                        Ok(msg) => handle_packet(msg, &read_binary, &s2c_tx),
+                        Err(err) => error!("invalid packet from server: {err}"),
+                    },
                     Some(Ok(_fckit)) => {
                         // info!("non text msg {:#?}", fckit);
                     }
@@ -87,10 +112,11 @@ impl WsCommunicator {
             loop {
                 match c2s_rx.try_recv() {
                     Ok(w) => {
-                        ws_tx
-                            .send(Message::Text(to_string(&w).unwrap()))
-                            .await
-                            .unwrap();
+                        let msg = match binary.load(Ordering::Acquire) {
+                            true => Message::Binary(wire::to_bytes(&w).unwrap()),
+                            false => Message::Text(to_string(&w).unwrap()),
+                        };
+                        ws_tx.send(msg).await.unwrap();
                         info!("message send");
                     }
                     Err(err) => match err {
@@ -113,6 +139,20 @@ impl WsCommunicator {
     }
 }
 
+fn handle_packet(packet: S2CPackets, binary: &AtomicBool, s2c_tx: &Sender<S2CPackets>) {
+    match &packet {
+        S2CPackets::Handshake(data) => {
+            info!("server speaks protocol version {}", data.version);
+            if data.capabilities.contains(&Capability::BinaryEncoding) {
+                binary.store(true, Ordering::Release);
+            }
+        }
+        S2CPackets::ProtocolError(err) => error!("server didn't understand us: {err}"),
+        _ => {}
+    }
+    _ = s2c_tx.send(packet);
+}
+
 #[allow(dead_code)]
 fn test_ws(mut read: EventReader<S2CPackets>) {
     for p in read.read() {
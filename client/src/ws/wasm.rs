@@ -1,11 +1,14 @@
 use bevy::{log::prelude::*, prelude::*};
-use common::client_packets::{C2SPackets, S2CPackets};
+use common::{
+    client_packets::{C2SPackets, Capability, HandshakeData, S2CPackets, PROTOCOL_VERSION},
+    wire,
+};
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use futures_util::{stream::{SplitSink, SplitStream}, SinkExt, StreamExt};
 use gloo::net::websocket::{futures::WebSocket, Message};
 use gloo::render::{request_animation_frame, AnimationFrame};
 use serde_json::{from_str, to_string};
-use std::{rc::Rc, sync::{Mutex, Arc}};
+use std::{rc::Rc, sync::{atomic::{AtomicBool, Ordering}, Mutex, Arc}};
 use wasm_bindgen_futures::spawn_local;
 
 pub struct WS;
@@ -38,13 +41,15 @@ async fn read(
     mut ws_tx: SplitSink<WebSocket, Message>,
     c2s_rx: Receiver<C2SPackets>,
     old: Rc<Mutex<Option<AnimationFrame>>>,
+    binary: Arc<AtomicBool>,
 ) {
     match c2s_rx.try_recv() {
         Ok(w) => {
-            ws_tx
-                .send(Message::Text(to_string(&w).unwrap()))
-                .await
-                .unwrap();
+            let msg = match binary.load(Ordering::Acquire) {
+                true => Message::Bytes(wire::to_bytes(&w).unwrap()),
+                false => Message::Text(to_string(&w).unwrap()),
+            };
+            ws_tx.send(msg).await.unwrap();
             info!("message send");
         }
         Err(err) => match err {
@@ -55,7 +60,7 @@ async fn read(
         },
     };
     let w = old.clone();
-    let ran = request_animation_frame(move |_| spawn_local(read(ws_tx, c2s_rx, w)));
+    let ran = request_animation_frame(move |_| spawn_local(read(ws_tx, c2s_rx, w, binary)));
     old.lock().unwrap().replace(ran);
 }
 impl WsCommunicator {
@@ -66,6 +71,14 @@ impl WsCommunicator {
 
         let (s2c_tx, s2c_rx) = unbounded::<S2CPackets>();
         let (c2s_tx, c2s_rx) = unbounded::<C2SPackets>();
+        // queued before anything else, so it is the first packet the server sees
+        _ = c2s_tx.send(C2SPackets::Handshake(HandshakeData {
+            version: PROTOCOL_VERSION,
+            capabilities: vec![Capability::BinaryEncoding],
+        }));
+        // set once the server agreed to it, everything we send after that is binary
+        let binary = Arc::new(AtomicBool::new(false));
+        let read_binary = binary.clone();
         spawn_local(async move {
             // let mut ind = 0;
             loop {
@@ -77,10 +90,15 @@ impl WsCommunicator {
                 match e {
                     Some(Ok(Message::Text(msg))) => {
                         info!("message!");
-                        if let Ok(msg) = from_str::<S2CPackets>(&msg) {
-                            _ = s2c_tx.send(msg);
+                        match from_str::<S2CPackets>(&msg) {
+                            Ok(msg) => handle_packet(msg, &read_binary, &s2c_tx),
+                            Err(err) => error!("invalid packet from server: {err}"),
                         }
                     }
+                    Some(Ok(Message::Bytes(msg))) => match wire::from_bytes::<S2CPackets>(&msg) {
+                        Ok(msg) => handle_packet(msg, &read_binary, &s2c_tx),
+                        Err(err) => error!("invalid packet from server: {err}"),
+                    },
                     Some(Ok(_fckit)) => {
                         // info!("non text msg {:#?}", fckit);
                     }
@@ -96,7 +114,7 @@ impl WsCommunicator {
             }
         });
 
-        spawn_local(read(ws_tx, c2s_rx, Rc::new(Mutex::new(None))));
+        spawn_local(read(ws_tx, c2s_rx, Rc::new(Mutex::new(None)), binary));
 
         Self {
             from_server: s2c_rx,
@@ -105,6 +123,20 @@ impl WsCommunicator {
     }
 }
 
+fn handle_packet(packet: S2CPackets, binary: &AtomicBool, s2c_tx: &Sender<S2CPackets>) {
+    match &packet {
+        S2CPackets::Handshake(data) => {
+            info!("server speaks protocol version {}", data.version);
+            if data.capabilities.contains(&Capability::BinaryEncoding) {
+                binary.store(true, Ordering::Release);
+            }
+        }
+        S2CPackets::ProtocolError(err) => error!("server didn't understand us: {err}"),
+        _ => {}
+    }
+    _ = s2c_tx.send(packet);
+}
+
 #[no_mangle]
 #[allow(dead_code)]
 fn test_ws(mut read: EventReader<S2CPackets>) {
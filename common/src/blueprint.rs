@@ -0,0 +1,282 @@
+//! Plans of what should be built where, usually imported from a schematic.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    world_data::{run_length_decode, run_length_encode, Knowledge, PaletteEntry, World},
+    Pos3,
+};
+
+/// How a position of a blueprint compares to what the turtles have seen there
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlueprintStatus {
+    /// The planned block is there, or air is planned and there is nothing
+    Matching,
+    /// A block is planned, but there is air
+    Missing,
+    /// Some other block is there, or anything where air is planned
+    Wrong,
+    /// No turtle has looked there yet
+    Unknown,
+}
+
+/// Compares by id only, turtles have little say in how a block ends up facing
+pub fn compare(planned: &PaletteEntry, seen: Knowledge) -> BlueprintStatus {
+    match (planned.is_air, seen) {
+        (_, Knowledge::Unknown) => BlueprintStatus::Unknown,
+        (true, Knowledge::Air) => BlueprintStatus::Matching,
+        (true, Knowledge::Block(_)) => BlueprintStatus::Wrong,
+        (false, Knowledge::Air) => BlueprintStatus::Missing,
+        (false, Knowledge::Block(id)) if id == planned.id => BlueprintStatus::Matching,
+        (false, Knowledge::Block(_)) => BlueprintStatus::Wrong,
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(try_from = "BlueprintRepr", into = "BlueprintRepr")]
+pub struct Blueprint {
+    pub name: String,
+    pub world: String,
+    /// World position of the blueprints 0,0,0 corner
+    pub anchor: Pos3,
+    size: Pos3,
+    palette: Vec<PaletteEntry>,
+    /// Index into `palette` + 1 for every position, x first, then z, then y like chunks. 0 where
+    /// the blueprint doesn't care what is there
+    blocks: Vec<u16>,
+}
+
+impl Blueprint {
+    /// Empty, every component of `size` has to be positive
+    pub fn new(name: &str, world: &str, anchor: Pos3, size: Pos3) -> Blueprint {
+        assert!(
+            size.x > 0 && size.y > 0 && size.z > 0,
+            "blueprint of size {size:?}"
+        );
+        Blueprint {
+            name: name.to_owned(),
+            world: world.to_owned(),
+            anchor,
+            size,
+            palette: Vec::new(),
+            blocks: vec![0; volume(size)],
+        }
+    }
+
+    pub fn size(&self) -> Pos3 {
+        self.size
+    }
+
+    /// Corner with the highest coordinates, in world coordinates
+    pub fn max(&self) -> Pos3 {
+        self.anchor + self.size - Pos3::new(1, 1, 1)
+    }
+
+    fn index(&self, rel: &Pos3) -> Option<usize> {
+        let inside = |c: i32, size: i32| (0..size).contains(&c);
+        if !(inside(rel.x, self.size.x) && inside(rel.y, self.size.y) && inside(rel.z, self.size.z))
+        {
+            return None;
+        }
+        Some((rel.x + rel.z * self.size.x + rel.y * self.size.x * self.size.z) as usize)
+    }
+
+    fn pos_of(&self, i: usize) -> Pos3 {
+        let i = i as i32;
+        let layer = self.size.x * self.size.z;
+        Pos3::new(i % self.size.x, i / layer, i % layer / self.size.x)
+    }
+
+    /// Adds `entry` to the palette unless it is there already. The key is what
+    /// [`Blueprint::set_key`] takes, which saves comparing entries for every block of an import
+    pub fn palette_key(&mut self, entry: PaletteEntry) -> u16 {
+        let index = match self.palette.iter().position(|e| *e == entry) {
+            Some(i) => i,
+            None => {
+                self.palette.push(entry);
+                self.palette.len() - 1
+            }
+        };
+        u16::try_from(index + 1).expect("blueprint palette full")
+    }
+
+    /// `rel` is relative to [`Blueprint::anchor`], positions outside are ignored
+    pub fn set_key(&mut self, rel: Pos3, key: u16) {
+        debug_assert!(key as usize <= self.palette.len());
+        if let Some(i) = self.index(&rel) {
+            self.blocks[i] = key;
+        }
+    }
+
+    /// `rel` is relative to [`Blueprint::anchor`], positions outside are ignored
+    pub fn set(&mut self, rel: Pos3, entry: PaletteEntry) {
+        if self.index(&rel).is_some() {
+            let key = self.palette_key(entry);
+            self.set_key(rel, key);
+        }
+    }
+
+    /// What should be at the world position `pos`
+    pub fn get(&self, pos: &Pos3) -> Option<&PaletteEntry> {
+        let i = *self.blocks.get(self.index(&(*pos - self.anchor))?)?;
+        self.palette.get((i as usize).checked_sub(1)?)
+    }
+
+    /// Every planned position in world coordinates, air included
+    pub fn iter(&self) -> impl Iterator<Item = (Pos3, &PaletteEntry)> + '_ {
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| **b != 0)
+            .map(|(i, b)| (self.pos_of(i) + self.anchor, &self.palette[*b as usize - 1]))
+    }
+
+    /// How many positions the blueprint has an opinion on
+    pub fn planned(&self) -> usize {
+        self.blocks.iter().filter(|b| **b != 0).count()
+    }
+
+    /// Every planned position compared against `world`
+    pub fn diff<'a>(
+        &'a self,
+        world: &'a World,
+    ) -> impl Iterator<Item = (Pos3, &'a PaletteEntry, BlueprintStatus)> + 'a {
+        self.iter()
+            .map(|(pos, planned)| (pos, planned, compare(planned, world.get_knowledge(&pos))))
+    }
+}
+
+fn volume(size: Pos3) -> usize {
+    size.x as usize * size.y as usize * size.z as usize
+}
+
+/// Blueprints are mostly air or nothing, so they go over the wire run length encoded
+#[derive(Serialize, Deserialize)]
+struct BlueprintRepr {
+    name: String,
+    world: String,
+    anchor: Pos3,
+    size: Pos3,
+    palette: Vec<PaletteEntry>,
+    runs: Vec<(u16, u16)>,
+}
+
+impl From<Blueprint> for BlueprintRepr {
+    fn from(b: Blueprint) -> Self {
+        BlueprintRepr {
+            runs: run_length_encode(b.blocks.into_iter()),
+            name: b.name,
+            world: b.world,
+            anchor: b.anchor,
+            size: b.size,
+            palette: b.palette,
+        }
+    }
+}
+
+impl TryFrom<BlueprintRepr> for Blueprint {
+    type Error = String;
+    fn try_from(repr: BlueprintRepr) -> Result<Self, Self::Error> {
+        let size = repr.size;
+        if size.x <= 0 || size.y <= 0 || size.z <= 0 {
+            return Err(format!("blueprint of size {size:?}"));
+        }
+        if let Some((_, b)) = repr
+            .runs
+            .iter()
+            .find(|(_, b)| *b as usize > repr.palette.len())
+        {
+            return Err(format!("palette index {b} out of bounds"));
+        }
+        let len = repr.runs.iter().map(|(n, _)| *n as usize).sum::<usize>();
+        if len != volume(size) {
+            return Err(format!("{len} blocks in a blueprint of size {size:?}"));
+        }
+        Ok(Blueprint {
+            name: repr.name,
+            world: repr.world,
+            anchor: repr.anchor,
+            size,
+            palette: repr.palette,
+            blocks: run_length_decode(&repr.runs),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_data::{Block, BlockState};
+
+    fn entry(id: Option<&str>) -> PaletteEntry {
+        PaletteEntry {
+            id: id.unwrap_or_default().to_owned(),
+            is_air: id.is_none(),
+            state: BlockState::default(),
+        }
+    }
+
+    #[test]
+    fn blueprints_are_compared_against_the_world() {
+        let mut blueprint =
+            Blueprint::new("hut", "test", Pos3::new(10, 64, -5), Pos3::new(3, 2, 3));
+        let stone = entry(Some("minecraft:stone"));
+        for x in 0..3 {
+            for z in 0..3 {
+                blueprint.set(Pos3::new(x, 0, z), stone.clone());
+            }
+        }
+        blueprint.set(Pos3::new(1, 1, 1), entry(None));
+        assert_eq!(blueprint.planned(), 10);
+        assert_eq!(blueprint.max(), Pos3::new(12, 65, -3));
+        assert_eq!(blueprint.get(&Pos3::new(11, 64, -4)), Some(&stone));
+        assert_eq!(blueprint.get(&Pos3::new(11, 65, -5)), None);
+
+        let mut world = World::new("test");
+        let block = |id: Option<&str>, pos: Pos3| Block::new(id.map(Into::into), &pos, "test");
+        world.set_block(block(Some("minecraft:stone"), Pos3::new(10, 64, -5)));
+        world.set_block(block(None, Pos3::new(11, 64, -5)));
+        world.set_block(block(Some("minecraft:dirt"), Pos3::new(12, 64, -5)));
+        world.set_block(block(Some("minecraft:dirt"), Pos3::new(11, 65, -4)));
+
+        let status = |pos: Pos3| {
+            blueprint
+                .diff(&world)
+                .find(|(p, _, _)| *p == pos)
+                .map(|(_, _, s)| s)
+        };
+        assert_eq!(
+            status(Pos3::new(10, 64, -5)),
+            Some(BlueprintStatus::Matching)
+        );
+        assert_eq!(
+            status(Pos3::new(11, 64, -5)),
+            Some(BlueprintStatus::Missing)
+        );
+        assert_eq!(status(Pos3::new(12, 64, -5)), Some(BlueprintStatus::Wrong));
+        // air was planned there
+        assert_eq!(status(Pos3::new(11, 65, -4)), Some(BlueprintStatus::Wrong));
+        assert_eq!(
+            status(Pos3::new(10, 64, -4)),
+            Some(BlueprintStatus::Unknown)
+        );
+        assert_eq!(status(Pos3::new(10, 65, -4)), None);
+    }
+
+    #[test]
+    fn blueprints_survive_the_wire() {
+        let mut blueprint = Blueprint::new("wall", "test", Pos3::zero(), Pos3::new(300, 300, 2));
+        for x in 0..300 {
+            blueprint.set(Pos3::new(x, 5, 1), entry(Some("minecraft:cobblestone")));
+        }
+        let bytes = crate::wire::to_bytes(&blueprint).unwrap();
+        // long runs of nothing stay small
+        assert!(bytes.len() < 1000, "{} bytes", bytes.len());
+        let decoded: Blueprint = crate::wire::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, blueprint);
+
+        let mut repr = BlueprintRepr::from(blueprint);
+        repr.runs.pop();
+        assert!(Blueprint::try_from(repr).is_err());
+    }
+}
@@ -1,17 +1,44 @@
 use bevy::prelude::{Deref, DerefMut};
 
 use crate::{
-    turtle::{self, Turtle, TurtleInventory},
-    world_data::{Block, World},
+    blueprint::Blueprint,
+    turtle::{self, InventoryAction, Turtle, TurtleInventory},
+    waypoint::{Target, Waypoint},
+    world_data::{Block, Chunk, World},
     Pos3,
 };
 
-// Needed: start executable on turtle
+/// Bumped whenever the packets change in a way older clients can't understand
+pub const PROTOCOL_VERSION: u32 = 14;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    /// Can send and receive packets as MessagePack binary messages, see [`crate::wire`]
+    BinaryEncoding,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HandshakeData {
+    pub version: u32,
+    pub capabilities: Vec<Capability>,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, bevy::ecs::event::Event)]
 pub enum C2SPackets {
+    /// Always send as JSON, before anything else. Clients that skip it get JSON without extras
+    Handshake(HandshakeData),
     RequestTurtles(String),
     RequestWorlds,
+    /// Loads the whole world at once, prefer [`C2SPackets::UpdateView`] for anything big
     RequestWorld(String),
+    /// Stream the chunks around `center` (a chunk position, not a block position), replaces the
+    /// last view. The server sends [`S2CPackets::SetChunk`] closest first and unloads the rest
+    UpdateView {
+        world: String,
+        center: Pos3,
+    },
+    /// In chunks, capped by the server
+    SetChunkRenderDistance(u32),
     SendLuaToTurtle {
         index: i32,
         world: String,
@@ -19,8 +46,127 @@ pub enum C2SPackets {
     },
     StdInForTurtle {
         index: i32,
+        world: String,
         value: String,
     },
+    /// Start getting the output of a turtle, the server answers with its scrollback
+    SubscribeToStdOut {
+        index: i32,
+        world: String,
+    },
+    UnsubscribeFromStdOut {
+        index: i32,
+        world: String,
+    },
+    /// The server answers with its cached list, or asks the turtle if it has none yet or `refresh` is set
+    RequestExecutables {
+        index: i32,
+        world: String,
+        refresh: bool,
+    },
+    RunProgram {
+        index: i32,
+        world: String,
+        name: String,
+        args: Vec<String>,
+    },
+    SaveFileToTurtle {
+        index: i32,
+        world: String,
+        path: String,
+        contents: String,
+    },
+    /// Let the server pathfind the turtle to `target` and drive it there. Unknown waypoints are
+    /// answered with [`S2CPackets::WaypointError`]
+    GoTo {
+        index: i32,
+        world: String,
+        target: Target,
+    },
+    /// Find known blocks by id, `pattern` is a glob like `*_ore`. Ids without a namespace match
+    /// any namespace. Answered with [`S2CPackets::BlockSearchResults`]
+    SearchBlocks {
+        world: String,
+        pattern: String,
+    },
+    /// A `.schem`, `.nbt` or `.litematic` file, the format is taken from the extension of
+    /// `file_name`. Replaces the blueprint called `name`, every client gets
+    /// [`S2CPackets::Blueprints`] after it worked, the sender [`S2CPackets::BlueprintError`] if not
+    ImportBlueprint {
+        world: String,
+        name: String,
+        /// Where the min corner of the schematic goes
+        anchor: Pos3,
+        file_name: String,
+        data: Vec<u8>,
+    },
+    RequestBlueprints(String),
+    RemoveBlueprint {
+        world: String,
+        name: String,
+    },
+    /// Let `turtles` place the missing blocks of a blueprint, each one gets a strip of it.
+    /// Replaces a running build of the blueprint, turtles that are offline start once they
+    /// connect. Progress comes as [`S2CPackets::BuildUpdate`]
+    StartBuild {
+        world: String,
+        blueprint: String,
+        turtles: Vec<i32>,
+    },
+    StopBuild {
+        world: String,
+        blueprint: String,
+    },
+    /// Replaces whatever the turtle was doing, progress comes as [`S2CPackets::JobUpdate`]
+    StartJob {
+        index: i32,
+        world: String,
+        kind: JobKind,
+        /// A waypoint the job is moved to, so it starts there instead
+        at: Option<String>,
+    },
+    StopJob {
+        index: i32,
+        world: String,
+    },
+    /// Where the turtle goes to refuel when it runs low during a job, there should be a chest
+    /// with fuel below it. Every client gets [`S2CPackets::TurtleHome`]
+    SetHome {
+        index: i32,
+        world: String,
+        home: Option<Pos3>,
+    },
+    /// Adds the waypoint, or replaces the one with the same name. Every client gets
+    /// [`S2CPackets::Waypoints`]
+    SaveWaypoint {
+        world: String,
+        waypoint: Waypoint,
+    },
+    RemoveWaypoint {
+        world: String,
+        name: String,
+    },
+    RequestWaypoints(String),
+    /// Work for whichever idle turtle of the world is closest and has the fuel to get there.
+    /// Every client gets [`S2CPackets::Tasks`] whenever the queue changes, finished tasks leave it
+    QueueTask {
+        world: String,
+        kind: TaskKind,
+        priority: i32,
+    },
+    /// Takes the task out of the queue, a turtle working on it stops
+    CancelTask {
+        world: String,
+        id: i64,
+    },
+    RequestTasks(String),
+    /// Answered with [`S2CPackets::InventoryResult`] once the turtle did it, or right away if
+    /// it can't be done
+    TurtleInventory {
+        index: i32,
+        world: String,
+        action: InventoryAction,
+    },
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
@@ -45,10 +191,217 @@ pub struct SetTurtlesData {
     pub world: String,
 }
 
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum GoToStatus {
+    Moving {
+        target: Pos3,
+        remaining_moves: u32,
+    },
+    Replanning {
+        target: Pos3,
+    },
+    /// Another turtle is in the way
+    Waiting {
+        target: Pos3,
+        turtle: i32,
+    },
+    /// Getting out of the way of turtles that wait for this one
+    MakingWay {
+        target: Pos3,
+        turtles: Vec<i32>,
+    },
+    Arrived {
+        target: Pos3,
+    },
+    Failed {
+        target: Pos3,
+        reason: String,
+    },
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TunnelSize {
+    OneByTwo,
+    ThreeByThree,
+}
+
+/// Something a turtle works through on its own, see [`C2SPackets::StartJob`]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum JobKind {
+    /// Digs out everything between the two corners, top layer first. The turtle goes in from
+    /// above the `min` x and z corner
+    Quarry { min: Pos3, max: Pos3 },
+    /// Starts in front of `start`, the floor is at the height of `start` and wider tunnels are
+    /// centered on it
+    Tunnel {
+        start: Pos3,
+        direction: turtle::Orientation,
+        length: u32,
+        size: TunnelSize,
+    },
+    /// A one by two tunnel, with branches of `branch_length` to both sides every `spacing`
+    /// blocks
+    BranchMine {
+        start: Pos3,
+        direction: turtle::Orientation,
+        length: u32,
+        spacing: u32,
+        branch_length: u32,
+    },
+    /// Looks at every block between the two corners a turtle can see without digging, going to
+    /// the closest known air next to unknown blocks over and over
+    Survey { min: Pos3, max: Pos3 },
+}
+
+impl JobKind {
+    /// Where the turtle has to be to start the job
+    pub fn start(&self) -> Pos3 {
+        match *self {
+            JobKind::Quarry { min, max } | JobKind::Survey { min, max } => {
+                Pos3::new(min.x, max.y + 1, min.z)
+            }
+            JobKind::Tunnel { start, .. } | JobKind::BranchMine { start, .. } => start,
+        }
+    }
+
+    /// The same job, shifted so it starts at `start`
+    pub fn moved_to(self, start: Pos3) -> JobKind {
+        let offset = start - self.start();
+        match self {
+            JobKind::Quarry { min, max } => JobKind::Quarry {
+                min: min + offset,
+                max: max + offset,
+            },
+            JobKind::Tunnel {
+                direction,
+                length,
+                size,
+                ..
+            } => JobKind::Tunnel {
+                start,
+                direction,
+                length,
+                size,
+            },
+            JobKind::BranchMine {
+                direction,
+                length,
+                spacing,
+                branch_length,
+                ..
+            } => JobKind::BranchMine {
+                start,
+                direction,
+                length,
+                spacing,
+                branch_length,
+            },
+            JobKind::Survey { min, max } => JobKind::Survey {
+                min: min + offset,
+                max: max + offset,
+            },
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum JobStatus {
+    Running {
+        kind: JobKind,
+        step: u32,
+        steps: u32,
+    },
+    /// A survey has no fixed steps, `coverage` is the percentage of its blocks that are known
+    Surveying {
+        kind: JobKind,
+        coverage: u8,
+    },
+    /// Low on fuel, the turtle burns fuel from its inventory or gets some at its home
+    Refueling {
+        needed: u32,
+    },
+    Done,
+    Failed {
+        reason: String,
+    },
+    Stopped,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum InventoryStatus {
+    Done(InventoryAction),
+    Failed {
+        action: InventoryAction,
+        reason: String,
+    },
+}
+
+/// Something queued for the fleet of a world, see [`C2SPackets::QueueTask`]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum TaskKind {
+    Job(JobKind),
+    /// Goes to the waypoint and drops the whole inventory into the chest below it
+    Deliver {
+        waypoint: String,
+    },
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum TaskState {
+    Queued,
+    Assigned {
+        turtle: i32,
+    },
+    /// Stays in the queue until a client cancels it
+    Failed {
+        reason: String,
+    },
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Task {
+    pub id: i64,
+    pub kind: TaskKind,
+    /// Higher goes first, the same priority in the order they were queued
+    pub priority: i32,
+    pub state: TaskState,
+    /// How often the turtle working on it went offline, it goes back into the queue each time
+    pub retries: u32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum BuildStatus {
+    /// `materials` is what the blocks left still need, by item
+    Building {
+        blueprint: String,
+        remaining: u32,
+        materials: Vec<(String, u32)>,
+    },
+    /// The turtle has no `item` left, it goes on once its inventory has some
+    MissingMaterial {
+        blueprint: String,
+        item: String,
+    },
+    /// `skipped` blocks couldn't be placed, because the turtle couldn't get there or something
+    /// else was in the way
+    Done {
+        blueprint: String,
+        skipped: u32,
+    },
+    Stopped {
+        blueprint: String,
+    },
+}
+
 // Needed: turtle requesting input from client(might need to somehow sync that? or just first come
 // first serve)
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, bevy::ecs::event::Event)]
 pub enum S2CPackets {
+    /// Answer to [`C2SPackets::Handshake`], still JSON. Only has the capabilities both sides
+    /// support, binary encoding is used from the next packet on if it's in there
+    Handshake(HandshakeData),
+    /// The server couldn't understand a packet, or the client speaks another protocol version
+    ProtocolError(String),
     MovedTurtle(MovedTurtleData),
     TurtleInventoryUpdate(UpdateTurtleData<Box<TurtleInventory>>),
     TurtleFuelUpdate(UpdateTurtleData<i32>),
@@ -56,5 +409,80 @@ pub enum S2CPackets {
     Worlds(Vec<String>),
     WorldUpdate(Block),
     SetWorld(World),
-    StdOutFromTurtle { index: i32, value: String },
+    /// A chunk that came into view, replaces whatever the client had at that position
+    SetChunk {
+        world: String,
+        chunk: Chunk,
+    },
+    /// Chunks that left the view, some of them might have never been sent because they are empty
+    UnloadChunks {
+        world: String,
+        chunks: Vec<Pos3>,
+    },
+    /// Only send to clients subscribed to that turtle
+    StdOutFromTurtle(UpdateTurtleData<String>),
+    /// Recent output of a turtle, replaces whatever the client had
+    StdOutScrollback(UpdateTurtleData<String>),
+    GoToUpdate(UpdateTurtleData<GoToStatus>),
+    TurtleExecutables(UpdateTurtleData<Vec<String>>),
+    /// Most recently seen first, `truncated` is set if there were too many to send all of them
+    BlockSearchResults {
+        world: String,
+        pattern: String,
+        blocks: Vec<Block>,
+        truncated: bool,
+    },
+    /// Every blueprint of the world, replaces whatever the client had for it
+    Blueprints {
+        world: String,
+        blueprints: Vec<Blueprint>,
+    },
+    BlueprintError {
+        name: String,
+        reason: String,
+    },
+    BuildUpdate(UpdateTurtleData<BuildStatus>),
+    JobUpdate(UpdateTurtleData<JobStatus>),
+    /// Sent for every turtle with a home after the [`S2CPackets::SetTurtles`] answering
+    /// [`C2SPackets::RequestTurtles`], and whenever a home changes
+    TurtleHome(UpdateTurtleData<Option<Pos3>>),
+    /// Every waypoint of the world, replaces whatever the client had for it
+    Waypoints {
+        world: String,
+        waypoints: Vec<Waypoint>,
+    },
+    WaypointError {
+        name: String,
+        reason: String,
+    },
+    /// Every unfinished task of the world, highest priority first
+    Tasks {
+        world: String,
+        tasks: Vec<Task>,
+    },
+    /// Every client gets it, the changed inventory comes as [`S2CPackets::TurtleInventoryUpdate`]
+    InventoryResult(UpdateTurtleData<InventoryStatus>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moved_quarries_keep_their_size() {
+        let quarry = JobKind::Quarry {
+            min: Pos3::new(0, -4, 0),
+            max: Pos3::new(3, 0, 5),
+        };
+        assert_eq!(quarry.start(), Pos3::new(0, 1, 0));
+        let moved = quarry.moved_to(Pos3::new(10, 65, -3));
+        assert_eq!(moved.start(), Pos3::new(10, 65, -3));
+        assert_eq!(
+            moved,
+            JobKind::Quarry {
+                min: Pos3::new(10, 60, -3),
+                max: Pos3::new(13, 64, 2),
+            }
+        );
+    }
 }
@@ -4,7 +4,11 @@ pub mod util;
 pub mod extensions;
 pub mod remote_control_packets;
 pub use pos3::Pos3;
+pub mod blueprint;
 pub mod client_packets;
+pub mod std_out;
 pub mod turtle_packets;
 pub mod vec3d;
+pub mod waypoint;
+pub mod wire;
 pub mod world_data;
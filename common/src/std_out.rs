@@ -0,0 +1,67 @@
+use std::collections::VecDeque;
+
+/// How many lines of turtle output are kept around
+pub const SCROLLBACK_LINES: usize = 1000;
+
+/// The last [`SCROLLBACK_LINES`] lines a turtle printed, the last line might still be incomplete
+#[derive(Clone, Debug, Default)]
+pub struct Scrollback {
+    lines: VecDeque<String>,
+}
+
+impl Scrollback {
+    pub fn push(&mut self, text: &str) {
+        let mut parts = text.split('\n');
+        if let Some(first) = parts.next() {
+            match self.lines.back_mut() {
+                Some(line) => line.push_str(first),
+                None => self.lines.push_back(first.to_owned()),
+            }
+        }
+        self.lines.extend(parts.map(str::to_owned));
+        while self.lines.len() > SCROLLBACK_LINES {
+            self.lines.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().map(String::as_str)
+    }
+
+    /// Everything that is still in the scrollback, as it was printed
+    pub fn text(&self) -> String {
+        self.lines().collect::<Vec<_>>().join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_partial_lines() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push("hello ");
+        scrollback.push("world\nsecond");
+        scrollback.push(" line\n");
+        assert_eq!(
+            scrollback.lines().collect::<Vec<_>>(),
+            ["hello world", "second line", ""]
+        );
+        assert_eq!(scrollback.text(), "hello world\nsecond line\n");
+    }
+
+    #[test]
+    fn drops_old_lines() {
+        let mut scrollback = Scrollback::default();
+        for i in 0..SCROLLBACK_LINES + 10 {
+            scrollback.push(&format!("{i}\n"));
+        }
+        assert_eq!(scrollback.lines().count(), SCROLLBACK_LINES);
+        assert_eq!(scrollback.lines().next(), Some("11"));
+    }
+}
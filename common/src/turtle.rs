@@ -221,6 +221,11 @@ impl Turtle {
     pub fn get_forward_vec(&self) -> Pos3 {
         self.orientation.get_forward_vec()
     }
+
+    /// Fuel is -1 when the server config turns it off, see `util.fix_num_or_unlimited`
+    pub fn has_unlimited_fuel(&self) -> bool {
+        self.fuel < 0
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, Hash, PartialEq, Eq)]
@@ -232,6 +237,62 @@ pub enum MoveDirection {
     Left,
     Right,
 }
+
+impl MoveDirection {
+    /// Returns the position and orientation a turtle ends up with after doing this move
+    pub fn apply(&self, pos: Pos3, orientation: Orientation) -> (Pos3, Orientation) {
+        match self {
+            MoveDirection::Forward => (pos + orientation.get_forward_vec(), orientation),
+            MoveDirection::Back => (pos - orientation.get_forward_vec(), orientation),
+            MoveDirection::Up => (pos + Pos3::new(0, 1, 0), orientation),
+            MoveDirection::Down => (pos + Pos3::new(0, -1, 0), orientation),
+            MoveDirection::Left => (pos, get_rotated_orientation(orientation, TurnDir::Left)),
+            MoveDirection::Right => (pos, get_rotated_orientation(orientation, TurnDir::Right)),
+        }
+    }
+}
+
+/// Something a turtle does with its inventory. Slots go from 1 to 16 like in lua, `direction`
+/// is one of Forward, Up and Down
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum InventoryAction {
+    Select {
+        slot: u8,
+    },
+    /// Drops items of `slot` into the inventory on that side, or onto the ground. The whole
+    /// stack without a `count`
+    Drop {
+        slot: u8,
+        direction: MoveDirection,
+        count: Option<u32>,
+    },
+    /// Takes items out of the inventory on that side, into `slot` first. A stack without a
+    /// `count`
+    Suck {
+        slot: u8,
+        direction: MoveDirection,
+        count: Option<u32>,
+    },
+    /// Moves `count` items of `from` to `to`, which has to be empty or hold the same item
+    Split {
+        from: u8,
+        to: u8,
+        count: u32,
+    },
+    /// Moves as much of `from` onto the stack in `to` as fits
+    Merge {
+        from: u8,
+        to: u8,
+    },
+    /// Drops items of `slot` into the turtle with index `to`, which has to be right next to
+    /// this one. The turtle turns to it first if it is not in front, above or below
+    Give {
+        slot: u8,
+        to: i32,
+        count: Option<u32>,
+    },
+}
+
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
 pub enum Orientation {
     #[default]
@@ -1,5 +1,12 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+
 use crate::{
-    turtle::{TurtleInventory, Maybe, MoveDirection, Orientation, TurtleIndexType},
+    turtle::{InventoryAction, TurtleInventory, Maybe, MoveDirection, Orientation, TurtleIndexType},
     Pos3,
 };
 
@@ -12,6 +19,121 @@ pub struct SetupInfoData {
     pub world: String,
 }
 
+/// What `turtle.inspect` returns for a block. Lua tables turn into json loosely, so state values
+/// may be strings, numbers or bools, and tags may be a set (`{"minecraft:logs": true}`), a list
+/// or `{}` when empty. A bare name is accepted as well
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(from = "InspectRepr")]
+pub struct InspectInfo {
+    pub name: String,
+    pub state: BTreeMap<String, String>,
+    pub tags: BTreeSet<String>,
+}
+
+impl InspectInfo {
+    pub fn new(name: impl Into<String>) -> InspectInfo {
+        InspectInfo {
+            name: name.into(),
+            state: BTreeMap::new(),
+            tags: BTreeSet::new(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum InspectRepr {
+    Name(String),
+    Info {
+        name: String,
+        #[serde(default)]
+        state: BTreeMap<String, LuaScalar>,
+        #[serde(default)]
+        tags: LuaSet,
+    },
+}
+
+impl From<InspectRepr> for InspectInfo {
+    fn from(value: InspectRepr) -> Self {
+        match value {
+            InspectRepr::Name(name) => InspectInfo::new(name),
+            InspectRepr::Info { name, state, tags } => InspectInfo {
+                name,
+                state: state.into_iter().map(|(k, v)| (k, v.0)).collect(),
+                tags: tags.0,
+            },
+        }
+    }
+}
+
+/// A string, number or bool, as a string
+struct LuaScalar(String);
+
+impl<'de> serde::Deserialize<'de> for LuaScalar {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ScalarVisitor;
+        impl<'de> Visitor<'de> for ScalarVisitor {
+            type Value = LuaScalar;
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string, number or bool")
+            }
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(LuaScalar(v.to_owned()))
+            }
+            fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+                Ok(LuaScalar(v.to_string()))
+            }
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                Ok(LuaScalar(v.to_string()))
+            }
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(LuaScalar(v.to_string()))
+            }
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                // lua has no integers, `age = 7` may show up as 7.0
+                match v.fract() == 0. && v.abs() < i64::MAX as f64 {
+                    true => Ok(LuaScalar((v as i64).to_string())),
+                    false => Ok(LuaScalar(v.to_string())),
+                }
+            }
+        }
+        deserializer.deserialize_any(ScalarVisitor)
+    }
+}
+
+/// A list of strings, or a table with them as keys
+#[derive(Default)]
+struct LuaSet(BTreeSet<String>);
+
+impl<'de> serde::Deserialize<'de> for LuaSet {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SetVisitor;
+        impl<'de> Visitor<'de> for SetVisitor {
+            type Value = LuaSet;
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a list of strings or a table with string keys")
+            }
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut set = BTreeSet::new();
+                while let Some(v) = seq.next_element::<String>()? {
+                    set.insert(v);
+                }
+                Ok(LuaSet(set))
+            }
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut set = BTreeSet::new();
+                while let Some((k, present)) = map.next_entry::<String, bool>()? {
+                    if present {
+                        set.insert(k);
+                    }
+                }
+                Ok(LuaSet(set))
+            }
+        }
+        deserializer.deserialize_any(SetVisitor)
+    }
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub enum T2SPackets {
     Batch(Vec<T2SPackets>),
@@ -19,6 +141,22 @@ pub enum T2SPackets {
     Moved {
         direction: MoveDirection,
     },
+    /// Send after the turtle tried to move but couldn't, Blocks should be send before this
+    MoveFailed {
+        direction: MoveDirection,
+        reason: Maybe<String>,
+    },
+    /// Send after `turtle.place`, `placeUp` or `placeDown` didn't place anything, `direction` is
+    /// one of Forward, Up and Down
+    PlaceFailed {
+        direction: MoveDirection,
+        reason: Maybe<String>,
+    },
+    /// Same as [`T2SPackets::PlaceFailed`] for `turtle.dig`, `digUp` and `digDown`
+    DigFailed {
+        direction: MoveDirection,
+        reason: Maybe<String>,
+    },
     SetMaxFuel(i32),
     SetPos(Pos3),
     SetOrientation(Orientation),
@@ -27,13 +165,19 @@ pub enum T2SPackets {
     NameUpdate(String),
     FuelUpdate(i32),
     Blocks {
-        up: Maybe<String>,
-        down: Maybe<String>,
-        front: Maybe<String>,
+        up: Maybe<InspectInfo>,
+        down: Maybe<InspectInfo>,
+        front: Maybe<InspectInfo>,
     },
+    /// Every program the shell of the turtle can run
     Executables(Vec<String>),
     Ping,
     StdOut(String),
+    /// Answer to [`S2TPackets::Inventory`], `reason` is set if it failed
+    InventoryResult {
+        action: InventoryAction,
+        reason: Maybe<String>,
+    },
 }
 #[derive(serde::Serialize, serde::Deserialize)]
 pub enum S2TPackets {
@@ -41,4 +185,64 @@ pub enum S2TPackets {
     GetSetupInfo,
     GetExecutables,
     StdIn(String),
+    /// Same as typing `name args...` into the shell of the turtle
+    RunProgram {
+        name: String,
+        args: Vec<String>,
+    },
+    /// Write `contents` to `path` on the turtle, it answers with its executables afterwards
+    SaveFile {
+        path: String,
+        contents: String,
+    },
+    /// Answered with [`T2SPackets::InventoryResult`]. `towards` is where the turtle of an
+    /// [`InventoryAction::Give`] is: Forward, Up or Down, or the turn that faces it (Back turns
+    /// twice)
+    Inventory {
+        action: InventoryAction,
+        towards: Option<MoveDirection>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> InspectInfo {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn lua_inspect_tables_are_understood() {
+        let wheat = parse(
+            r#"{"name":"minecraft:wheat","state":{"age":7},"tags":{"minecraft:crops":true,"minecraft:bee_growables":true}}"#,
+        );
+        assert_eq!(wheat.state["age"], "7");
+        assert!(wheat.tags.contains("minecraft:crops"));
+        assert_eq!(wheat.tags.len(), 2);
+
+        let stairs = parse(
+            r#"{"name":"minecraft:oak_stairs","state":{"facing":"north","waterlogged":false,"half":"bottom"},"tags":{}}"#,
+        );
+        assert_eq!(stairs.state["facing"], "north");
+        assert_eq!(stairs.state["waterlogged"], "false");
+        assert!(stairs.tags.is_empty());
+
+        assert_eq!(
+            parse(r#"{"name":"minecraft:wheat","state":{"age":3.0},"tags":[]}"#).state["age"],
+            "3"
+        );
+        assert_eq!(
+            parse(r#""minecraft:stone""#),
+            InspectInfo::new("minecraft:stone")
+        );
+        assert_eq!(
+            parse(r#"{"name":"minecraft:stone"}"#),
+            InspectInfo::new("minecraft:stone")
+        );
+
+        // what the simulator and tests send comes back the same
+        let json = serde_json::to_string(&wheat).unwrap();
+        assert_eq!(parse(&json), wheat);
+    }
 }
@@ -0,0 +1,53 @@
+//! Named places of a world that turtles can be sent to by name.
+
+use serde::{Deserialize, Serialize};
+
+use crate::Pos3;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WaypointKind {
+    Home,
+    FuelDepot,
+    DropOff,
+    MineEntrance,
+}
+
+impl WaypointKind {
+    pub const ALL: [WaypointKind; 4] = [
+        WaypointKind::Home,
+        WaypointKind::FuelDepot,
+        WaypointKind::DropOff,
+        WaypointKind::MineEntrance,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            WaypointKind::Home => "Home",
+            WaypointKind::FuelDepot => "Fuel Depot",
+            WaypointKind::DropOff => "Drop-off Chest",
+            WaypointKind::MineEntrance => "Mine Entrance",
+        }
+    }
+}
+
+/// Names are unique per world
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Waypoint {
+    pub name: String,
+    pub kind: WaypointKind,
+    /// Where a turtle stands when it is at the waypoint
+    pub position: Pos3,
+}
+
+/// A position, or the name of a waypoint the server looks up
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    Pos(Pos3),
+    Waypoint(String),
+}
+
+impl From<Pos3> for Target {
+    fn from(pos: Pos3) -> Self {
+        Target::Pos(pos)
+    }
+}
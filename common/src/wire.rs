@@ -0,0 +1,161 @@
+//! Compact binary format for the client websocket, MessagePack as written by `rmp-serde`.
+//!
+//! Structs keep their field names, so types that look at what they got (`untagged`,
+//! `flatten`) decode just like they do from JSON. Both sides still need the same
+//! [`PROTOCOL_VERSION`](crate::client_packets::PROTOCOL_VERSION), it is negotiated in the
+//! handshake.
+
+use std::{
+    fmt::{self, Display},
+    io::Cursor,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+#[derive(Debug)]
+pub enum Error {
+    Encode(rmp_serde::encode::Error),
+    Decode(rmp_serde::decode::Error),
+    /// Input had bytes left after the value
+    TrailingBytes(usize),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Encode(err) => write!(f, "encoding failed: {err}"),
+            Error::Decode(err) => write!(f, "decoding failed: {err}"),
+            Error::TrailingBytes(n) => write!(f, "{n} trailing bytes after the value"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    rmp_serde::to_vec_named(value).map_err(Error::Encode)
+}
+
+pub fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    let mut deserializer = rmp_serde::Deserializer::new(Cursor::new(bytes));
+    let value = T::deserialize(&mut deserializer).map_err(Error::Decode)?;
+    // rmp stops after the value, whatever follows would be lost
+    match bytes.len() - deserializer.position() as usize {
+        0 => Ok(value),
+        n => Err(Error::TrailingBytes(n)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client_packets::{C2SPackets, S2CPackets, UpdateTurtleData},
+        turtle::{Item, Maybe, TurtleInventory},
+        turtle_packets::InspectInfo,
+        world_data::{Block, World},
+        Pos3,
+    };
+
+    fn round_trip<T: Serialize + DeserializeOwned>(value: &T) -> T {
+        from_bytes(&to_bytes(value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn packets_round_trip() {
+        let packet = C2SPackets::GoTo {
+            index: -3,
+            world: "wörld".into(),
+            target: Pos3::new(1, -64, 300).into(),
+        };
+        let C2SPackets::GoTo {
+            index,
+            world,
+            target,
+        } = round_trip(&packet)
+        else {
+            panic!("wrong variant");
+        };
+        assert_eq!(
+            (index, world.as_str(), target),
+            (-3, "wörld", Pos3::new(1, -64, 300).into())
+        );
+
+        let mut inv = TurtleInventory::default();
+        inv.inv[4] = Maybe::Some(Item {
+            count: 64,
+            name: "minecraft:cobblestone".into(),
+        });
+        let packet = S2CPackets::TurtleInventoryUpdate(UpdateTurtleData {
+            index: 1,
+            world: "w".into(),
+            data: Box::new(inv),
+        });
+        let S2CPackets::TurtleInventoryUpdate(data) = round_trip(&packet) else {
+            panic!("wrong variant");
+        };
+        assert!(
+            matches!(&data.data.inv[4], Maybe::Some(Item { count: 64, name }) if name == "minecraft:cobblestone")
+        );
+        assert!(matches!(data.data.inv[3], Maybe::None));
+    }
+
+    #[test]
+    fn untagged_types_round_trip() {
+        let mut info = InspectInfo::new("minecraft:wheat");
+        info.state.insert("age".into(), "7".into());
+        info.tags.insert("minecraft:crops".into());
+        assert_eq!(round_trip(&info), info);
+        let block = Block::inspected(Some(info), &Pos3::new(0, 1, 0), "w");
+        let back = round_trip(&block);
+        assert_eq!((back.id, back.state), (block.id, block.state));
+    }
+
+    #[test]
+    fn worlds_are_smaller_than_json() {
+        let mut world = World::new("big");
+        for x in 0..32 {
+            for z in 0..32 {
+                world.set_block(Block::new(
+                    Some("minecraft:stone".into()),
+                    &Pos3::new(x, -1, z),
+                    "big",
+                ));
+            }
+        }
+        let packet = S2CPackets::SetWorld(world);
+        let binary = to_bytes(&packet).unwrap();
+        let json = serde_json::to_vec(&packet).unwrap();
+        assert!(
+            binary.len() < json.len(),
+            "{} vs {}",
+            binary.len(),
+            json.len()
+        );
+        let S2CPackets::SetWorld(world) = from_bytes(&binary).unwrap() else {
+            panic!("wrong variant");
+        };
+        assert_eq!(
+            world.get_block(&Pos3::new(31, -1, 7)).map(|b| b.id),
+            Some("minecraft:stone".into())
+        );
+    }
+
+    #[test]
+    fn broken_input_is_an_error() {
+        let bytes = to_bytes(&C2SPackets::RequestWorld("test".into())).unwrap();
+        assert!(matches!(
+            from_bytes::<C2SPackets>(&bytes[..bytes.len() - 1]),
+            Err(Error::Decode(_))
+        ));
+        let mut long = bytes.clone();
+        long.push(0);
+        assert!(matches!(
+            from_bytes::<C2SPackets>(&long),
+            Err(Error::TrailingBytes(1))
+        ));
+        assert!(from_bytes::<C2SPackets>(&[200, 1]).is_err());
+    }
+}
@@ -1,4 +1,9 @@
-use crate::Pos3;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::{self, Display},
+};
+
+use crate::{turtle_packets::InspectInfo, Pos3};
 
 use serde::{Deserialize, Serialize};
 
@@ -29,47 +34,334 @@ pub struct World {
     chunks: Vec3D<Chunk>,
 }
 
-#[allow(dead_code)]
+const BLOCKS_PER_CHUNK: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// What a chunk knows about one kind of block, shared by every block of that kind
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PaletteEntry {
+    pub id: String,
+    pub is_air: bool,
+    pub state: BlockState,
+}
+
+impl PaletteEntry {
+    /// `pos` is global
+    pub fn to_block(&self, pos: Pos3, world: &str, observed: Option<Observation>) -> Block {
+        Block {
+            world: world.to_owned(),
+            id: self.id.clone(),
+            pos,
+            is_air: self.is_air,
+            observed,
+            state: self.state.clone(),
+        }
+    }
+    pub fn knowledge(&self) -> Knowledge<'_> {
+        match self.is_air {
+            true => Knowledge::Air,
+            false => Knowledge::Block(&self.id),
+        }
+    }
+}
+
+/// Block state properties and tags, what `turtle.inspect` reports besides the name
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BlockState {
+    /// Like `facing=north` or `age=7`, values are kept the way minecraft prints them
+    pub properties: BTreeMap<String, String>,
+    pub tags: BTreeSet<String>,
+}
+
+impl BlockState {
+    pub fn get(&self, property: &str) -> Option<&str> {
+        self.properties.get(property).map(String::as_str)
+    }
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+}
+
+/// The properties the way minecraft writes them after a block id, `[age=7,waterlogged=false]`.
+/// Empty without properties
+impl Display for BlockState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.properties.is_empty() {
+            return Ok(());
+        }
+        f.write_str("[")?;
+        for (i, (k, v)) in self.properties.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{k}={v}")?;
+        }
+        f.write_str("]")
+    }
+}
+
+/// What is known about a position in the world
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Knowledge<'a> {
+    /// No turtle has looked there yet
+    Unknown,
+    /// Explored and empty
+    Air,
+    Block(&'a str),
+}
+
+/// When a block was last seen, and by which turtle
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Observation {
+    /// Unix time in seconds
+    pub at: u32,
+    /// Index of the turtle
+    pub by: i32,
+}
+
+impl Observation {
+    /// Stands in for blocks without an observation in [`Chunk::observed`], no turtle was
+    /// around in 1970
+    const NONE: Observation = Observation { at: 0, by: 0 };
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(try_from = "ChunkRepr", into = "ChunkRepr")]
 pub struct Chunk {
-    /// Blocks Are Stored in Chunk Local Positions
-    blocks: Vec3D<Block>,
+    /// Index into `palette` + 1 for every block in chunk local positions, 0 means nothing is
+    /// known. Stays empty until the first block is set
+    blocks: Vec<u16>,
+    /// Entries no block uses anymore are reused, so there are never more than
+    /// `BLOCKS_PER_CHUNK + 1` and `u16` indices can't overflow
+    palette: Vec<PaletteEntry>,
+    /// How many blocks use each palette entry
+    uses: Vec<u16>,
+    /// Palette index of every entry in use
+    lookup: HashMap<PaletteEntry, u16>,
+    /// Palette indices without blocks, free for new entries
+    free: Vec<u16>,
+    /// Same layout as `blocks`, stays empty until the first observed block is set
+    observed: Vec<Observation>,
     /// the Chunk Positions. Not in Meters!
     pos: Pos3,
 }
 
+/// Index of a chunk local position in [`Chunk::blocks`], layer by layer so flat ground compresses
+fn block_index(pos: &Pos3) -> Option<usize> {
+    let inside = |c: i32| (0..CHUNK_SIZE).contains(&c);
+    if !(inside(pos.x) && inside(pos.y) && inside(pos.z)) {
+        return None;
+    }
+    Some((pos.x + pos.z * CHUNK_SIZE + pos.y * CHUNK_SIZE * CHUNK_SIZE) as usize)
+}
+
+fn index_to_pos(i: usize) -> Pos3 {
+    let i = i as i32;
+    Pos3::new(
+        i % CHUNK_SIZE,
+        i / (CHUNK_SIZE * CHUNK_SIZE),
+        i / CHUNK_SIZE % CHUNK_SIZE,
+    )
+}
+
 impl Chunk {
     pub fn does_block_exist(&self, pos: &Pos3) -> bool {
-        let out = self.blocks.get(pos);
-        out.is_some_and(|b| !b.is_air)
+        self.get_entry(pos).is_some_and(|b| !b.is_air)
     }
     pub fn new(pos: Pos3) -> Chunk {
         Chunk {
-            blocks: Vec3D::new(),
+            blocks: Vec::new(),
+            palette: Vec::new(),
+            uses: Vec::new(),
+            lookup: HashMap::new(),
+            free: Vec::new(),
+            observed: Vec::new(),
             pos,
         }
     }
-    // pub fn add_block(&mut self, pos: Pos3, name: &str) {
-    //     self.blocks.insert(
-    //         pos,
-    //         Block {
-    //             id: name.to_owned(),
-    //             pos: pos + self.pos.scale(CHUNK_SIZE),
-    //         },
-    //     )
-    // }
+    /// Palette index for `entry`, taking a free one if it isn't there yet
+    fn palette_index(&mut self, entry: PaletteEntry) -> u16 {
+        if let Some(i) = self.lookup.get(&entry) {
+            return *i;
+        }
+        let i = match self.free.pop() {
+            Some(i) => {
+                self.palette[i as usize] = entry.clone();
+                i
+            }
+            None => {
+                self.palette.push(entry.clone());
+                self.uses.push(0);
+                (self.palette.len() - 1) as u16
+            }
+        };
+        self.lookup.insert(entry, i);
+        i
+    }
     pub fn set_block(&mut self, block: Block) {
-        self.blocks
-            .insert(get_chunk_relative_pos(&block.pos), block);
+        let entry = PaletteEntry {
+            id: block.id,
+            is_air: block.is_air,
+            state: block.state,
+        };
+        let palette_index = self.palette_index(entry);
+        if self.blocks.is_empty() {
+            self.blocks = vec![0; BLOCKS_PER_CHUNK];
+        }
+        let i = block_index(&get_chunk_relative_pos(&block.pos)).unwrap();
+        self.uses[palette_index as usize] += 1;
+        let old = std::mem::replace(&mut self.blocks[i], palette_index + 1);
+        if let Some(old) = (old as usize).checked_sub(1) {
+            self.uses[old] -= 1;
+            if self.uses[old] == 0 {
+                self.lookup.remove(&self.palette[old]);
+                self.free.push(old as u16);
+            }
+        }
+        if block.observed.is_some() && self.observed.is_empty() {
+            self.observed = vec![Observation::NONE; BLOCKS_PER_CHUNK];
+        }
+        if let Some(o) = self.observed.get_mut(i) {
+            *o = block.observed.unwrap_or(Observation::NONE);
+        }
     }
     pub fn get_block_id(&self, pos: &Pos3) -> Option<String> {
-        self.blocks.get(pos).map(|block| block.id.clone())
+        self.get_entry(pos).map(|e| e.id.clone())
+    }
+    /// Whether the block at the chunk local `pos` was seen, and what it is
+    pub fn get_knowledge(&self, pos: &Pos3) -> Knowledge<'_> {
+        self.get_entry(pos)
+            .map_or(Knowledge::Unknown, PaletteEntry::knowledge)
+    }
+    /// When the block at the chunk local `pos` was last seen, if that was recorded
+    pub fn get_observation(&self, pos: &Pos3) -> Option<Observation> {
+        let o = *self.observed.get(block_index(pos)?)?;
+        (o != Observation::NONE).then_some(o)
+    }
+    /// Palette entry of the block at the chunk local `pos`
+    pub fn get_entry(&self, pos: &Pos3) -> Option<&PaletteEntry> {
+        let i = *self.blocks.get(block_index(pos)?)?;
+        self.palette.get((i as usize).checked_sub(1)?)
     }
     pub fn get_chunk_pos(&self) -> Pos3 {
         self.pos
     }
-    pub fn all_blocks(&self) -> Vec3D<Block> {
-        self.blocks.clone()
+    /// Every known block as chunk local position and palette entry
+    pub fn iter(&self) -> impl Iterator<Item = (Pos3, &PaletteEntry)> + '_ {
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| **b != 0)
+            .map(|(i, b)| (index_to_pos(i), &self.palette[*b as usize - 1]))
+    }
+    pub fn all_blocks(&self, world: &str) -> Vec3D<Block> {
+        let mut blocks = Vec3D::new();
+        let offset = self.pos.scale(CHUNK_SIZE);
+        for (pos, entry) in self.iter() {
+            let block = entry.to_block(pos + offset, world, self.get_observation(&pos));
+            blocks.insert(pos, block);
+        }
+        blocks
+    }
+}
+
+/// How chunks go over the wire: unused palette entries dropped and the blocks run length encoded
+#[derive(Serialize, Deserialize, Clone)]
+struct ChunkRepr {
+    pos: Pos3,
+    palette: Vec<PaletteEntry>,
+    /// (how many blocks, palette index + 1) in the order of [`Chunk::blocks`]
+    runs: Vec<(u16, u16)>,
+    observed: Vec<(u16, Observation)>,
+}
+
+/// (how often, value) for every run of equal values, runs longer than `u16::MAX` get split
+pub(crate) fn run_length_encode<T: PartialEq + Copy>(
+    values: impl Iterator<Item = T>,
+) -> Vec<(u16, T)> {
+    let mut runs: Vec<(u16, T)> = Vec::new();
+    for v in values {
+        match runs.last_mut() {
+            Some((count, last)) if *last == v && *count < u16::MAX => *count += 1,
+            _ => runs.push((1, v)),
+        }
+    }
+    runs
+}
+
+pub(crate) fn run_length_decode<T: Copy>(runs: &[(u16, T)]) -> Vec<T> {
+    let mut values = Vec::new();
+    for (count, v) in runs {
+        values.resize(values.len() + *count as usize, *v);
+    }
+    values
+}
+
+fn decode_chunk_runs<T: Copy>(runs: &[(u16, T)]) -> Result<Vec<T>, String> {
+    let values = run_length_decode(runs);
+    match values.len() {
+        0 | BLOCKS_PER_CHUNK => Ok(values),
+        len => Err(format!("chunk with {len} blocks")),
+    }
+}
+
+impl From<Chunk> for ChunkRepr {
+    fn from(chunk: Chunk) -> Self {
+        let mut used = vec![0u16; chunk.palette.len()];
+        let mut palette = Vec::new();
+        let blocks = chunk.blocks.iter().map(|b| match *b as usize {
+            0 => 0,
+            i => {
+                if used[i - 1] == 0 {
+                    palette.push(chunk.palette[i - 1].clone());
+                    used[i - 1] = palette.len() as u16;
+                }
+                used[i - 1]
+            }
+        });
+        let runs = run_length_encode(blocks);
+        ChunkRepr {
+            pos: chunk.pos,
+            palette,
+            runs,
+            observed: run_length_encode(chunk.observed.into_iter()),
+        }
+    }
+}
+
+impl TryFrom<ChunkRepr> for Chunk {
+    type Error = String;
+    fn try_from(repr: ChunkRepr) -> Result<Self, Self::Error> {
+        if let Some((_, b)) = repr
+            .runs
+            .iter()
+            .find(|(_, b)| *b as usize > repr.palette.len())
+        {
+            return Err(format!("palette index {b} out of bounds"));
+        }
+        let blocks = decode_chunk_runs(&repr.runs)?;
+        let mut uses = vec![0u16; repr.palette.len()];
+        for b in blocks.iter().filter(|b| **b != 0) {
+            uses[*b as usize - 1] += 1;
+        }
+        let mut lookup = HashMap::new();
+        let mut free = Vec::new();
+        for (i, entry) in repr.palette.iter().enumerate() {
+            match uses[i] {
+                0 => free.push(i as u16),
+                _ => {
+                    lookup.entry(entry.clone()).or_insert(i as u16);
+                }
+            }
+        }
+        Ok(Chunk {
+            blocks,
+            palette: repr.palette,
+            uses,
+            lookup,
+            free,
+            observed: decode_chunk_runs(&repr.observed)?,
+            pos: repr.pos,
+        })
     }
 }
 
@@ -81,6 +373,9 @@ pub struct Block {
     /// Global pos
     pub pos: Pos3,
     pub is_air: bool,
+    /// None for blocks from before observations were recorded
+    pub observed: Option<Observation>,
+    pub state: BlockState,
 }
 
 impl Block {
@@ -90,6 +385,31 @@ impl Block {
             id: ident.unwrap_or_default(),
             pos: *pos,
             world: world_name.to_owned(),
+            observed: None,
+            state: BlockState::default(),
+        }
+    }
+    /// What a turtle found with `turtle.inspect`, air for None
+    pub fn inspected(info: Option<InspectInfo>, pos: &Pos3, world_name: &str) -> Block {
+        match info {
+            Some(info) => Block {
+                state: BlockState {
+                    properties: info.state,
+                    tags: info.tags,
+                },
+                ..Block::new(Some(info.name), pos, world_name)
+            },
+            None => Block::new(None, pos, world_name),
+        }
+    }
+    pub fn with_observation(mut self, observation: Observation) -> Block {
+        self.observed = Some(observation);
+        self
+    }
+    pub fn knowledge(&self) -> Knowledge<'_> {
+        match self.is_air {
+            true => Knowledge::Air,
+            false => Knowledge::Block(&self.id),
         }
     }
     pub fn get_pos(&self) -> &Pos3 {
@@ -101,11 +421,25 @@ impl Block {
 }
 
 impl World {
-    pub fn get_block(&self, pos: &Pos3) -> Option<&Block> {
+    pub fn get_block(&self, pos: &Pos3) -> Option<Block> {
+        let chunk = self.chunks.get(&get_chunk_containing_block(pos))?;
+        let rel_pos = get_chunk_relative_pos(pos);
+        let entry = chunk.get_entry(&rel_pos)?;
+        Some(entry.to_block(*pos, &self.name, chunk.get_observation(&rel_pos)))
+    }
+
+    /// Palette entry of the block at the global `pos`, cheaper than [`World::get_block`]
+    pub fn get_entry(&self, pos: &Pos3) -> Option<&PaletteEntry> {
         self.chunks
             .get(&get_chunk_containing_block(pos))?
-            .blocks
-            .get(pos)
+            .get_entry(&get_chunk_relative_pos(pos))
+    }
+
+    pub fn get_knowledge(&self, pos: &Pos3) -> Knowledge<'_> {
+        match self.chunks.get(&get_chunk_containing_block(pos)) {
+            Some(chunk) => chunk.get_knowledge(&get_chunk_relative_pos(pos)),
+            None => Knowledge::Unknown,
+        }
     }
 
     pub fn set_block(&mut self, block: Block) {
@@ -116,6 +450,11 @@ impl World {
             .set_block(block);
     }
 
+    /// Replaces whatever chunk was at the same position
+    pub fn insert_chunk(&mut self, chunk: Chunk) {
+        self.chunks.insert(chunk.get_chunk_pos(), chunk);
+    }
+
     pub fn get_chunks(&self) -> &Vec3D<Chunk> {
         &self.chunks
     }
@@ -127,3 +466,256 @@ impl World {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    const IDS: [&str; 4] = [
+        "minecraft:stone",
+        "minecraft:dirt",
+        "minecraft:water",
+        "computercraft:turtle_advanced",
+    ];
+
+    /// A chunk with random blocks, next to the old `Vec3D<Block>` storage fed the same blocks
+    fn random_chunk(rng: &mut StdRng) -> (Chunk, Vec3D<Block>) {
+        let chunk_pos = Pos3::new(
+            rng.gen_range(-3..3),
+            rng.gen_range(-3..3),
+            rng.gen_range(-3..3),
+        );
+        let mut chunk = Chunk::new(chunk_pos);
+        let mut old = Vec3D::new();
+        for _ in 0..rng.gen_range(0..600) {
+            let pos = chunk_pos.scale(CHUNK_SIZE)
+                + Pos3::new(
+                    rng.gen_range(0..CHUNK_SIZE),
+                    rng.gen_range(0..CHUNK_SIZE),
+                    rng.gen_range(0..CHUNK_SIZE),
+                );
+            let id = rng
+                .gen_bool(0.8)
+                .then(|| IDS[rng.gen_range(0..IDS.len())].to_owned());
+            let mut block = Block::new(id, &pos, "test");
+            if rng.gen_bool(0.5) {
+                block = block.with_observation(Observation {
+                    at: rng.gen_range(1_700_000_000..1_700_000_100),
+                    by: rng.gen_range(0..3),
+                });
+            }
+            old.insert(get_chunk_relative_pos(&pos), block.clone());
+            chunk.set_block(block);
+        }
+        (chunk, old)
+    }
+
+    fn assert_same(chunk: &Chunk, old: &Vec3D<Block>) {
+        for x in -1..=CHUNK_SIZE {
+            for y in -1..=CHUNK_SIZE {
+                for z in -1..=CHUNK_SIZE {
+                    let pos = Pos3::new(x, y, z);
+                    let expected = old.get(&pos);
+                    assert_eq!(chunk.get_block_id(&pos), expected.map(|b| b.id.clone()));
+                    assert_eq!(
+                        chunk.does_block_exist(&pos),
+                        expected.is_some_and(|b| !b.is_air)
+                    );
+                    assert_eq!(
+                        chunk.get_knowledge(&pos),
+                        expected.map_or(Knowledge::Unknown, Block::knowledge)
+                    );
+                    assert_eq!(
+                        chunk.get_observation(&pos),
+                        expected.and_then(|b| b.observed)
+                    );
+                }
+            }
+        }
+        assert_eq!(chunk.iter().count(), old.iter().count());
+        let world = "test";
+        for (pos, block) in chunk.all_blocks(world).iter() {
+            let expected = old.get(pos).unwrap();
+            assert_eq!(block.pos, expected.pos);
+            assert_eq!(block.id, expected.id);
+            assert_eq!(block.is_air, expected.is_air);
+            assert_eq!(block.observed, expected.observed);
+        }
+    }
+
+    #[test]
+    fn palette_chunks_match_the_old_storage() {
+        let mut rng = StdRng::seed_from_u64(12);
+        for _ in 0..200 {
+            let (chunk, old) = random_chunk(&mut rng);
+            assert_same(&chunk, &old);
+        }
+    }
+
+    #[test]
+    fn chunks_survive_serde() {
+        let mut rng = StdRng::seed_from_u64(34);
+        for _ in 0..100 {
+            let (chunk, old) = random_chunk(&mut rng);
+            let json: Chunk =
+                serde_json::from_str(&serde_json::to_string(&chunk).unwrap()).unwrap();
+            assert_same(&json, &old);
+            let wire: Chunk =
+                crate::wire::from_bytes(&crate::wire::to_bytes(&chunk).unwrap()).unwrap();
+            assert_same(&wire, &old);
+            assert_eq!(wire.get_chunk_pos(), chunk.get_chunk_pos());
+        }
+    }
+
+    #[test]
+    fn serialized_chunks_are_compact() {
+        let mut chunk = Chunk::new(Pos3::new(0, -1, 0));
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let pos = Pos3::new(x, -16, z);
+                chunk.set_block(Block::new(Some("minecraft:dirt".into()), &pos, "test"));
+                chunk.set_block(Block::new(
+                    Some("minecraft:grass_block".into()),
+                    &pos,
+                    "test",
+                ));
+            }
+        }
+        let repr = ChunkRepr::from(chunk.clone());
+        // dirt isn't used anymore, and the layer is one run
+        assert_eq!(repr.palette.len(), 1);
+        assert_eq!(repr.runs, [(256, 1), (4096 - 256, 0)]);
+        let chunk = Chunk::try_from(repr).unwrap();
+        assert_eq!(
+            chunk.get_block_id(&Pos3::new(3, 0, 9)).as_deref(),
+            Some("minecraft:grass_block")
+        );
+    }
+
+    #[test]
+    fn palettes_reuse_unused_entries() {
+        let mut chunk = Chunk::new(Pos3::zero());
+        let pos = Pos3::new(2, 3, 4);
+        for age in 0..100 {
+            let mut info = InspectInfo::new("minecraft:wheat");
+            info.state.insert("age".into(), age.to_string());
+            chunk.set_block(Block::inspected(Some(info), &pos, "test"));
+            chunk.set_block(Block::new(
+                Some("minecraft:farmland".into()),
+                &Pos3::new(2, 2, 4),
+                "test",
+            ));
+        }
+        // the new age comes in before the old one is freed, the two slots take turns
+        assert_eq!(chunk.palette.len(), 3);
+        assert_eq!(chunk.get_entry(&pos).unwrap().state.get("age"), Some("99"));
+        assert_eq!(
+            chunk.get_block_id(&Pos3::new(2, 2, 4)).as_deref(),
+            Some("minecraft:farmland")
+        );
+
+        // every block different still fits
+        let mut chunk = Chunk::new(Pos3::zero());
+        for round in 0..20 {
+            for i in 0..BLOCKS_PER_CHUNK {
+                let id = format!("test:{round}_{i}");
+                chunk.set_block(Block::new(Some(id), &index_to_pos(i), "test"));
+            }
+        }
+        assert!(chunk.palette.len() <= BLOCKS_PER_CHUNK + 1);
+        assert_eq!(
+            chunk.get_block_id(&index_to_pos(7)).as_deref(),
+            Some("test:19_7")
+        );
+    }
+
+    #[test]
+    fn broken_chunks_are_rejected() {
+        let entry = PaletteEntry {
+            id: "minecraft:stone".into(),
+            is_air: false,
+            state: BlockState::default(),
+        };
+        let repr = |runs: Vec<(u16, u16)>| ChunkRepr {
+            pos: Pos3::zero(),
+            palette: vec![entry.clone()],
+            runs,
+            observed: Vec::new(),
+        };
+        assert!(Chunk::try_from(repr(vec![(4096, 2)])).is_err());
+        assert!(Chunk::try_from(repr(vec![(100, 1)])).is_err());
+        assert!(Chunk::try_from(repr(vec![(96, 0), (4000, 1)])).is_ok());
+        assert!(Chunk::try_from(repr(Vec::new())).is_ok());
+    }
+
+    #[test]
+    fn unknown_air_and_blocks_are_told_apart() {
+        let mut world = World::new("test");
+        let seen = Observation {
+            at: 1_700_000_000,
+            by: 4,
+        };
+        world.set_block(Block::new(None, &Pos3::new(0, 0, -1), "test").with_observation(seen));
+        world.set_block(Block::new(
+            Some("minecraft:stone".into()),
+            &Pos3::new(0, -1, 0),
+            "test",
+        ));
+        assert_eq!(world.get_knowledge(&Pos3::new(0, 0, -1)), Knowledge::Air);
+        assert_eq!(
+            world.get_knowledge(&Pos3::new(0, -1, 0)),
+            Knowledge::Block("minecraft:stone")
+        );
+        // same chunk, and a chunk nobody has been to
+        assert_eq!(
+            world.get_knowledge(&Pos3::new(1, 0, -1)),
+            Knowledge::Unknown
+        );
+        assert_eq!(
+            world.get_knowledge(&Pos3::new(99, 0, 0)),
+            Knowledge::Unknown
+        );
+
+        assert_eq!(
+            world.get_block(&Pos3::new(0, 0, -1)).unwrap().observed,
+            Some(seen)
+        );
+        assert_eq!(
+            world.get_block(&Pos3::new(0, -1, 0)).unwrap().observed,
+            None
+        );
+    }
+
+    #[test]
+    fn block_states_are_kept() {
+        let wheat = |age: &str| {
+            let mut info = InspectInfo::new("minecraft:wheat");
+            info.state.insert("age".into(), age.into());
+            info.tags.insert("minecraft:crops".into());
+            info
+        };
+        let mut chunk = Chunk::new(Pos3::zero());
+        for x in 0..4 {
+            let age = if x == 0 { "3" } else { "7" };
+            chunk.set_block(Block::inspected(
+                Some(wheat(age)),
+                &Pos3::new(x, 0, 0),
+                "test",
+            ));
+        }
+        // same id, different state, so two palette entries
+        assert_eq!(chunk.palette.len(), 2);
+
+        let chunk: Chunk =
+            crate::wire::from_bytes(&crate::wire::to_bytes(&chunk).unwrap()).unwrap();
+        let blocks = chunk.all_blocks("test");
+        let young = blocks.get(&Pos3::new(0, 0, 0)).unwrap();
+        assert_eq!(young.state.get("age"), Some("3"));
+        assert!(young.state.has_tag("minecraft:crops"));
+        let mature = blocks.get(&Pos3::new(3, 0, 0)).unwrap();
+        assert_eq!(mature.state.to_string(), "[age=7]");
+        assert_eq!(BlockState::default().to_string(), "");
+    }
+}
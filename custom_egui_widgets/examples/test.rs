@@ -61,6 +61,18 @@ impl eframe::App for App {
 
                     println!("Refuel with Slot: {id}")
                 }
+                custom_egui_widgets::item_box::ItemSlotActions::Merge { from } => {
+                    println!("Merge Slot {from} into Slot: {id}")
+                }
+                custom_egui_widgets::item_box::ItemSlotActions::Split { from, count } => {
+                    println!("Split {count} items of Slot {from} into Slot: {id}")
+                }
+                custom_egui_widgets::item_box::ItemSlotActions::Drop(side, amount) => {
+                    println!("Drop {amount} items of Slot {id} {side:?}")
+                }
+                custom_egui_widgets::item_box::ItemSlotActions::Suck(side, amount) => {
+                    println!("Suck {amount} items {side:?} into Slot {id}")
+                }
             }
         }
     }
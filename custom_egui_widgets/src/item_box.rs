@@ -2,10 +2,38 @@ use std::{rc::Rc, sync::mpsc};
 
 use egui::{Color32, FontId, Response, RichText, Slider, Stroke, Ui};
 
+/// The sides a turtle can drop to and suck from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Up,
+    Forward,
+    Down,
+}
+
 pub enum ItemSlotActions {
     SelectSlot,
     Transfer(u8),
     Refuel,
+    /// The stack of slot `from` was dragged onto this one
+    Merge {
+        from: u32,
+    },
+    /// Half of the stack of slot `from` was dragged onto this one, shift was held
+    Split {
+        from: u32,
+        count: u32,
+    },
+    /// Drop that many items of this slot
+    Drop(Side, u8),
+    /// Suck that many items into this slot
+    Suck(Side, u8),
+}
+
+/// What is carried around while a stack gets dragged
+struct DraggedStack {
+    slot: u32,
+    amount: u32,
+    half: bool,
 }
 
 pub type TX = mpsc::Sender<(u32, ItemSlotActions)>;
@@ -65,7 +93,7 @@ fn item_box_render(
     amount_modifier: &mut u8,
 ) -> Response {
     let desired_size = egui::Vec2::splat(2.0 * scale * ui.spacing().interact_size.y);
-    let (rect, mut response) = ui.allocate_exact_size(desired_size, egui::Sense::click());
+    let (rect, mut response) = ui.allocate_exact_size(desired_size, egui::Sense::click_and_drag());
     let luma = get_gray_scale(&color);
     let inver = 1.0 - luma.clamp(0.0, 1.0);
     let gray_color = Color32::from_gray(((255u8 as f32 * inver) as i64).try_into().unwrap());
@@ -87,9 +115,35 @@ fn item_box_render(
         tx.send((slot_id, ItemSlotActions::SelectSlot)).unwrap();
     }
 
+    if amount != 0 {
+        response.dnd_set_drag_payload(DraggedStack {
+            slot: slot_id,
+            amount,
+            half: ui.input(|i| i.modifiers.shift),
+        });
+    }
+    let hovered = response
+        .dnd_hover_payload::<DraggedStack>()
+        .is_some_and(|dragged| dragged.slot != slot_id);
+    if let Some(dragged) = response.dnd_release_payload::<DraggedStack>() {
+        let action = match dragged.half {
+            true => ItemSlotActions::Split {
+                from: dragged.slot,
+                count: (dragged.amount / 2).max(1),
+            },
+            false => ItemSlotActions::Merge { from: dragged.slot },
+        };
+        if dragged.slot != slot_id {
+            tx.send((slot_id, action)).unwrap();
+        }
+    }
+
     if ui.is_rect_visible(rect) {
         ui.painter().rect_filled(rect, 8.0 * scale, color);
-        if selected {
+        if hovered {
+            ui.painter()
+                .rect_stroke(rect, 8.0 * scale, Stroke::new(3.0 * scale, Color32::YELLOW));
+        } else if selected {
             ui.painter().rect_stroke(
                 rect,
                 8.0 * scale,
@@ -145,6 +199,24 @@ fn item_box_context_menu_render(
     if ui.button("Refuel using the Selected Slot").clicked() {
         tx.send((slot_id, ItemSlotActions::Refuel)).unwrap()
     }
+    ui.separator();
+    for (side, name) in [
+        (Side::Up, "Up"),
+        (Side::Forward, "Forward"),
+        (Side::Down, "Down"),
+    ] {
+        ui.horizontal(|ui| {
+            ui.label(name);
+            if amount != 0 && ui.button(format!("Drop {amount_modifier}")).clicked() {
+                tx.send((slot_id, ItemSlotActions::Drop(side, *amount_modifier)))
+                    .unwrap();
+            }
+            if ui.button(format!("Suck {amount_modifier}")).clicked() {
+                tx.send((slot_id, ItemSlotActions::Suck(side, *amount_modifier)))
+                    .unwrap();
+            }
+        });
+    }
     // if ui.button("Move Half of the Selected Stack here").clicked() {
     //     tx.send((slot_id, ItemSlotActions::Transfer(amount / 2)))
     //         .unwrap();
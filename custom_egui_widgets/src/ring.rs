@@ -53,7 +53,12 @@ impl CircleDisplay {
         let desired_size = ui.spacing().interact_size.y * egui::Vec2::splat(self.size);
         let (rect, response) =
             ui.allocate_exact_size(desired_size, egui::Sense::focusable_noninteractive());
-        let normalized = *value as f32 / *max_value as f32;
+        // turtles report -1 when fuel is turned off
+        let unlimited = *value < 0;
+        let normalized = match unlimited {
+            true => 1.,
+            false => (*value as f32 / (*max_value).max(1) as f32).clamp(0., 1.),
+        };
         if ui.is_rect_visible(rect) {
             let visuals = ui.style().noninteractive();
             let rect = rect.expand(visuals.expansion);
@@ -76,8 +81,12 @@ impl CircleDisplay {
                 ];
                 ui.painter().line_segment(points, stroke);
             }
+            let text = match unlimited {
+                true => "∞".to_owned(),
+                false => format!(" {}\n/{}", limit_number(value), limit_number(max_value)),
+            };
             let text = ui.painter().layout_no_wrap(
-                format!(" {}\n/{}", limit_number(value), limit_number(max_value)),
+                text,
                 FontId::monospace(self.font_size),
                 visuals.text_color(),
             );
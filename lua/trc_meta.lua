@@ -4,6 +4,9 @@
 ---@field Batch? T2SPacket[]
 ---@field SetupInfo? string
 ---@field Moved? {direction:MoveDir}
+---@field MoveFailed? {direction:MoveDir, reason: Maybe<string>}
+---@field PlaceFailed? {direction:MoveDir, reason: Maybe<string>}
+---@field DigFailed? {direction:MoveDir, reason: Maybe<string>}
 ---@field SetMaxFuel? integer
 ---@field SetPos? {x:integer,y:integer,z:integer}
 ---@field SetOrientation? orienation
@@ -11,9 +14,10 @@
 ---@field InventoryUpdate? {}
 ---@field NameUpdate? string
 ---@field FuelUpdate? integer
----@field Blocks? {up: Maybe<string>, down: Maybe<string>, front: Maybe<string>}
+---@field Blocks? {up: Maybe<InspectInfo>, down: Maybe<InspectInfo>, front: Maybe<InspectInfo>}
 ---@field Executables? string[]
 ---@field StdOut? string
+---@field InventoryResult? {action: table, reason: Maybe<string>}
 
 ---@alias T2SPacket T2SDataPacket
 ---| "Ping"
@@ -21,6 +25,9 @@
 ---@class S2TDataPacket
 ---@field RunLuaCode? string
 ---@field StdIn? string
+---@field RunProgram? {name: string, args: string[]}
+---@field SaveFile? {path: string, contents: string}
+---@field Inventory? {action: table, towards: MoveDir | nil}
 
 ---@alias S2TPacket S2TDataPacket | "GetSetupInfo" | "GetExecutables"
 
@@ -29,6 +36,8 @@
 ---Follows SemVer, if major = 0 then minor versions are considerd breaking
 ---@alias TRC_Version {major:integer,minor:integer,patch:integer}
 
+---@alias InspectInfo {name: string, state: table<string, string|number|boolean>, tags: table<string, boolean>}
+
 ---@generic T
 ---@alias Maybe
 ---| "None"
@@ -243,8 +243,30 @@ local function handle_ws_messages(msg)
                 util.run_function_with_injected_globals(code)
             end)
         end
+    elseif msg.StdIn then
+        util.push_std_in(msg.StdIn)
+    elseif msg.Inventory then
+        local inventory = msg.Inventory
+        functions:push(function()
+            local reason = util.run_inventory_action(inventory.action, inventory.towards)
+            util.send(ws, util.InventoryResult(inventory.action, reason))
+        end)
     elseif msg == "GetExecutables" then
-
+        util.send(ws, util.Executables())
+    elseif msg.RunProgram then
+        local program = msg.RunProgram
+        functions:push(function()
+            util.run_function_with_injected_globals(shell.execute, program.name, table.unpack(program.args))
+        end)
+    elseif msg.SaveFile then
+        local file = fs.open(msg.SaveFile.path, "w")
+        if file == nil then
+            log("Error Saving File: " .. msg.SaveFile.path)
+        else
+            file.write(msg.SaveFile.contents)
+            file.close()
+        end
+        util.send(ws, util.Executables())
     end
 end
 local ws_url = "ws://schmerver.mooo.com:9002"
@@ -121,6 +121,11 @@ function M.FuelUpdate()
     return { FuelUpdate = M.fix_num_or_unlimited(NativeTurtleApi.getFuelLevel()) }
 end
 
+---@return packet
+function M.Executables()
+    return { Executables = shell.programs() }
+end
+
 ---@class packet
 
 ---@param ... packet
@@ -133,9 +138,9 @@ function M.BatchPackets(...)
     return { Batch = stuff }
 end
 
----@param up Maybe<string>
----@param down Maybe<string>
----@param front Maybe<string>
+---@param up Maybe<InspectInfo>
+---@param down Maybe<InspectInfo>
+---@param front Maybe<InspectInfo>
 function M.ConstructBlocksPacket(up, down, front)
     return { Blocks = { up, down, front } }
 end
@@ -167,11 +172,20 @@ function M.get_label()
     return label
 end
 
+---@param data string | ccTweaked.turtle.inspectInfo
+---@return InspectInfo
+function M.inspect_info(data)
+    if type(data) == "string" then
+        return { name = data, state = {}, tags = {} }
+    end
+    return { name = data.name, state = data.state or {}, tags = data.tags or {} }
+end
+
 ---@param exits boolean
 ---@param data string | ccTweaked.turtle.inspectInfo
----@return Maybe<string>
+---@return Maybe<InspectInfo>
 function M.process_inspect(exits, data)
-    return M.get_maybe_using_bool(exits, M.fix_inspect(data))
+    return M.get_maybe_using_bool(exits, M.inspect_info(data))
 end
 
 ---@param ws ccTweaked.http.Websocket
@@ -196,17 +210,71 @@ function networked_turtle_api.refuel(count)
     local s, m = NativeTurtleApi.refuel(count)
     if s then
         ---@diagnostic disable-next-line: param-type-mismatch
-        M.send_blocks(NetworkedTurtleMoveWebsocket)
+        M.send(NetworkedTurtleMoveWebsocket, M.BatchPackets(M.FuelUpdate(), M.InventoryUpdate()))
     end
     return s, m
 end
 
+-- the inventory gets sent even if nothing was taken or dropped, so the server always hears back
+---@diagnostic disable-next-line: duplicate-set-field
+function networked_turtle_api.suck(count)
+    local s, m = NativeTurtleApi.suck(count)
+    ---@diagnostic disable-next-line: param-type-mismatch
+    M.send(NetworkedTurtleMoveWebsocket, M.InventoryUpdate())
+    return s, m
+end
+
+---@diagnostic disable-next-line: duplicate-set-field
+function networked_turtle_api.suckUp(count)
+    local s, m = NativeTurtleApi.suckUp(count)
+    ---@diagnostic disable-next-line: param-type-mismatch
+    M.send(NetworkedTurtleMoveWebsocket, M.InventoryUpdate())
+    return s, m
+end
+
+---@diagnostic disable-next-line: duplicate-set-field
+function networked_turtle_api.suckDown(count)
+    local s, m = NativeTurtleApi.suckDown(count)
+    ---@diagnostic disable-next-line: param-type-mismatch
+    M.send(NetworkedTurtleMoveWebsocket, M.InventoryUpdate())
+    return s, m
+end
+
+---@diagnostic disable-next-line: duplicate-set-field
+function networked_turtle_api.drop(count)
+    local s, m = NativeTurtleApi.drop(count)
+    ---@diagnostic disable-next-line: param-type-mismatch
+    M.send(NetworkedTurtleMoveWebsocket, M.InventoryUpdate())
+    return s, m
+end
+
+---@diagnostic disable-next-line: duplicate-set-field
+function networked_turtle_api.dropUp(count)
+    local s, m = NativeTurtleApi.dropUp(count)
+    ---@diagnostic disable-next-line: param-type-mismatch
+    M.send(NetworkedTurtleMoveWebsocket, M.InventoryUpdate())
+    return s, m
+end
+
+---@diagnostic disable-next-line: duplicate-set-field
+function networked_turtle_api.dropDown(count)
+    local s, m = NativeTurtleApi.dropDown(count)
+    ---@diagnostic disable-next-line: param-type-mismatch
+    M.send(NetworkedTurtleMoveWebsocket, M.InventoryUpdate())
+    return s, m
+end
+
 ---@diagnostic disable-next-line: duplicate-set-field
 function networked_turtle_api.place(text)
     local s, m = NativeTurtleApi.place(text)
     if s then
         ---@diagnostic disable-next-line: param-type-mismatch
         M.send_blocks(NetworkedTurtleMoveWebsocket)
+        ---@diagnostic disable-next-line: param-type-mismatch
+        M.send(NetworkedTurtleMoveWebsocket, M.InventoryUpdate())
+    else
+        ---@diagnostic disable-next-line: param-type-mismatch
+        M.send(NetworkedTurtleMoveWebsocket, M.ConstructPlaceFailedPacket("Forward", m))
     end
     return s, m
 end
@@ -217,6 +285,11 @@ function networked_turtle_api.placeUp(text)
     if s then
         ---@diagnostic disable-next-line: param-type-mismatch
         M.send_blocks(NetworkedTurtleMoveWebsocket)
+        ---@diagnostic disable-next-line: param-type-mismatch
+        M.send(NetworkedTurtleMoveWebsocket, M.InventoryUpdate())
+    else
+        ---@diagnostic disable-next-line: param-type-mismatch
+        M.send(NetworkedTurtleMoveWebsocket, M.ConstructPlaceFailedPacket("Up", m))
     end
     return s, m
 end
@@ -227,6 +300,11 @@ function networked_turtle_api.placeDown(text)
     if s then
         ---@diagnostic disable-next-line: param-type-mismatch
         M.send_blocks(NetworkedTurtleMoveWebsocket)
+        ---@diagnostic disable-next-line: param-type-mismatch
+        M.send(NetworkedTurtleMoveWebsocket, M.InventoryUpdate())
+    else
+        ---@diagnostic disable-next-line: param-type-mismatch
+        M.send(NetworkedTurtleMoveWebsocket, M.ConstructPlaceFailedPacket("Down", m))
     end
     return s, m
 end
@@ -237,6 +315,9 @@ function networked_turtle_api.dig(side)
     if s then
         ---@diagnostic disable-next-line: param-type-mismatch
         M.send_blocks(NetworkedTurtleMoveWebsocket)
+    else
+        ---@diagnostic disable-next-line: param-type-mismatch
+        M.send(NetworkedTurtleMoveWebsocket, M.ConstructDigFailedPacket("Forward", m))
     end
     return s, m
 end
@@ -247,6 +328,9 @@ function networked_turtle_api.digUp(side)
     if s then
         ---@diagnostic disable-next-line: param-type-mismatch
         M.send_blocks(NetworkedTurtleMoveWebsocket)
+    else
+        ---@diagnostic disable-next-line: param-type-mismatch
+        M.send(NetworkedTurtleMoveWebsocket, M.ConstructDigFailedPacket("Up", m))
     end
     return s, m
 end
@@ -257,6 +341,9 @@ function networked_turtle_api.digDown(side)
     if s then
         ---@diagnostic disable-next-line: param-type-mismatch
         M.send_blocks(NetworkedTurtleMoveWebsocket)
+    else
+        ---@diagnostic disable-next-line: param-type-mismatch
+        M.send(NetworkedTurtleMoveWebsocket, M.ConstructDigFailedPacket("Down", m))
     end
     return s, m
 end
@@ -283,6 +370,11 @@ networked_turtle_api.up = function()
         M.send(NetworkedTurtleMoveWebsocket, M.BatchPackets(M.ConstructMovePacket("Up"), M.FuelUpdate()))
         ---@diagnostic disable-next-line: param-type-mismatch
         M.send_blocks(NetworkedTurtleMoveWebsocket)
+    else
+        ---@diagnostic disable-next-line: param-type-mismatch
+        M.send_blocks(NetworkedTurtleMoveWebsocket)
+        ---@diagnostic disable-next-line: param-type-mismatch
+        M.send(NetworkedTurtleMoveWebsocket, M.ConstructMoveFailedPacket("Up", m))
     end
     return s, m
 end
@@ -294,6 +386,11 @@ networked_turtle_api.down = function()
         M.send(NetworkedTurtleMoveWebsocket, M.BatchPackets(M.ConstructMovePacket("Down"), M.FuelUpdate()))
         ---@diagnostic disable-next-line: param-type-mismatch
         M.send_blocks(NetworkedTurtleMoveWebsocket)
+    else
+        ---@diagnostic disable-next-line: param-type-mismatch
+        M.send_blocks(NetworkedTurtleMoveWebsocket)
+        ---@diagnostic disable-next-line: param-type-mismatch
+        M.send(NetworkedTurtleMoveWebsocket, M.ConstructMoveFailedPacket("Down", m))
     end
     return s, m
 end
@@ -305,6 +402,11 @@ networked_turtle_api.forward = function()
         M.send(NetworkedTurtleMoveWebsocket, M.BatchPackets(M.ConstructMovePacket("Forward"), M.FuelUpdate()))
         ---@diagnostic disable-next-line: param-type-mismatch
         M.send_blocks(NetworkedTurtleMoveWebsocket)
+    else
+        ---@diagnostic disable-next-line: param-type-mismatch
+        M.send_blocks(NetworkedTurtleMoveWebsocket)
+        ---@diagnostic disable-next-line: param-type-mismatch
+        M.send(NetworkedTurtleMoveWebsocket, M.ConstructMoveFailedPacket("Forward", m))
     end
     return s, m
 end
@@ -316,6 +418,11 @@ networked_turtle_api.back = function()
         M.send(NetworkedTurtleMoveWebsocket, M.BatchPackets(M.ConstructMovePacket("Back"), M.FuelUpdate()))
         ---@diagnostic disable-next-line: param-type-mismatch
         M.send_blocks(NetworkedTurtleMoveWebsocket)
+    else
+        ---@diagnostic disable-next-line: param-type-mismatch
+        M.send_blocks(NetworkedTurtleMoveWebsocket)
+        ---@diagnostic disable-next-line: param-type-mismatch
+        M.send(NetworkedTurtleMoveWebsocket, M.ConstructMoveFailedPacket("Back", m))
     end
     return s, m
 end
@@ -341,15 +448,189 @@ networked_turtle_api.turnRight = function()
     end
     return s, m
 end
+---@return boolean, string | ccTweaked.turtle.inspectInfo
+---@diagnostic disable-next-line: duplicate-set-field
+networked_turtle_api.inspect = function()
+    local exists, info = NativeTurtleApi.inspect()
+    ---@diagnostic disable-next-line: param-type-mismatch
+    M.send_blocks(NetworkedTurtleMoveWebsocket)
+    return exists, info
+end
+---@return boolean, string | ccTweaked.turtle.inspectInfo
+---@diagnostic disable-next-line: duplicate-set-field
+networked_turtle_api.inspectUp = function()
+    local exists, info = NativeTurtleApi.inspectUp()
+    ---@diagnostic disable-next-line: param-type-mismatch
+    M.send_blocks(NetworkedTurtleMoveWebsocket)
+    return exists, info
+end
+---@return boolean, string | ccTweaked.turtle.inspectInfo
+---@diagnostic disable-next-line: duplicate-set-field
+networked_turtle_api.inspectDown = function()
+    local exists, info = NativeTurtleApi.inspectDown()
+    ---@diagnostic disable-next-line: param-type-mismatch
+    M.send_blocks(NetworkedTurtleMoveWebsocket)
+    return exists, info
+end
 M.HijackedTurtleMovments = networked_turtle_api
 
+---@param name string
+---@param direction MoveDir
+---@return string
+local function side_function(name, direction)
+    if direction == "Up" or direction == "Down" then
+        return name .. direction
+    end
+    return name
+end
+
+---transferTo from any slot, the selection stays as it was
+---@param from integer
+---@param to integer
+---@param count integer | nil
+---@return boolean, string | nil
+local function transfer(from, to, count)
+    local selected = NativeTurtleApi.getSelectedSlot()
+    NativeTurtleApi.select(from)
+    local s = NativeTurtleApi.transferTo(to, count)
+    NativeTurtleApi.select(selected)
+    ---@diagnostic disable-next-line: param-type-mismatch
+    M.send(NetworkedTurtleMoveWebsocket, M.InventoryUpdate())
+    if not s then
+        return false, "Could not move any items"
+    end
+    return true
+end
+
+---drop or suck with `slot` selected, the selection stays as it was
+---@param name string "drop" or "suck"
+---@param slot integer
+---@param direction MoveDir
+---@param count integer | nil
+---@return boolean, string | nil
+local function with_slot(name, slot, direction, count)
+    local selected = NativeTurtleApi.getSelectedSlot()
+    NativeTurtleApi.select(slot)
+    local ok, s, m = pcall(NativeTurtleApi[side_function(name, direction)], count)
+    NativeTurtleApi.select(selected)
+    ---@diagnostic disable-next-line: param-type-mismatch
+    M.send(NetworkedTurtleMoveWebsocket, M.InventoryUpdate())
+    if not ok then
+        return false, tostring(s)
+    end
+    return s, m
+end
+
+---@type table<string, string[]>
+local turns_towards = {
+    Left = { "turnLeft" },
+    Right = { "turnRight" },
+    Back = { "turnRight", "turnRight" },
+}
+
+---@type table<string, string>
+local turned_back = { turnLeft = "turnRight", turnRight = "turnLeft" }
+
+---Does an action the server sent with Inventory, `towards` is where the other turtle of a Give is
+---@param action table
+---@param towards MoveDir | nil
+---@return string | nil reason why it failed
+function M.run_inventory_action(action, towards)
+    local ok, s, m = pcall(function()
+        if action.Select then
+            return networked_turtle_api.select(action.Select.slot)
+        elseif action.Drop then
+            return with_slot("drop", action.Drop.slot, action.Drop.direction, action.Drop.count)
+        elseif action.Suck then
+            return with_slot("suck", action.Suck.slot, action.Suck.direction, action.Suck.count)
+        elseif action.Split then
+            return transfer(action.Split.from, action.Split.to, action.Split.count)
+        elseif action.Merge then
+            return transfer(action.Merge.from, action.Merge.to, nil)
+        elseif action.Give then
+            local turns = turns_towards[towards] or {}
+            for _, turn in ipairs(turns) do
+                networked_turtle_api[turn]()
+            end
+            local direction = "Forward"
+            if towards == "Up" or towards == "Down" then
+                direction = towards
+            end
+            local s, m = with_slot("drop", action.Give.slot, direction, action.Give.count)
+            -- facing the way it did before, whether the drop worked or not
+            for i = #turns, 1, -1 do
+                networked_turtle_api[turned_back[turns[i]]]()
+            end
+            return s, m
+        end
+        return false, "Unknown action"
+    end)
+    if not ok then
+        return tostring(s)
+    end
+    if not s then
+        return m or "Failed"
+    end
+    return nil
+end
+
+---@param action table
+---@param reason string | nil
+---@return packet
+function M.InventoryResult(action, reason)
+    return { InventoryResult = { action = action, reason = M.maybe(reason) } }
+end
+
+---@type string[]
+local std_in_lines = {}
+local native_print, native_write, native_read = print, write, read
+
+---Queues a line send by a client, for the next `read` of code run by the server
+---@param line string
+function M.push_std_in(line)
+    table.insert(std_in_lines, line)
+    os.queueEvent("trc_std_in")
+end
+
+---@param text string
+function M.send_std_out(text)
+    ---@diagnostic disable-next-line: param-type-mismatch
+    M.send(NetworkedTurtleMoveWebsocket, { StdOut = text })
+end
+
+local networked_print = function(...)
+    local parts = {}
+    for i = 1, select("#", ...) do
+        parts[i] = tostring(select(i, ...))
+    end
+    M.send_std_out(table.concat(parts, "\t") .. "\n")
+    return native_print(...)
+end
+
+local networked_write = function(text)
+    M.send_std_out(tostring(text))
+    return native_write(text)
+end
+
+local networked_read = function()
+    while #std_in_lines == 0 do
+        os.pullEvent("trc_std_in")
+    end
+    local line = table.remove(std_in_lines, 1)
+    -- echo it, just like the native read would
+    networked_print(line)
+    return line
+end
+
 ---@generic T
 ---@param func fun(): T
 ---@return T | nil value, string| nil error
 function M.run_function_with_injected_globals(func, ...)
     turtle = M.HijackedTurtleMovments
+    print, write, read = networked_print, networked_write, networked_read
     local ok, value = pcall(func, ...)
     turtle = NativeTurtleApi
+    print, write, read = native_print, native_write, native_read
     if not ok then
         log("ERROR: " .. value)
         return nil, value
@@ -370,6 +651,27 @@ function M.ConstructMovePacket(dir)
     return { Moved = { direction = dir } }
 end
 
+---@param dir MoveDir
+---@param reason string | nil
+---@return packet
+function M.ConstructMoveFailedPacket(dir, reason)
+    return { MoveFailed = { direction = dir, reason = M.maybe(reason) } }
+end
+
+---@param dir MoveDir
+---@param reason string | nil
+---@return packet
+function M.ConstructPlaceFailedPacket(dir, reason)
+    return { PlaceFailed = { direction = dir, reason = M.maybe(reason) } }
+end
+
+---@param dir MoveDir
+---@param reason string | nil
+---@return packet
+function M.ConstructDigFailedPacket(dir, reason)
+    return { DigFailed = { direction = dir, reason = M.maybe(reason) } }
+end
+
 ---@class Queue<T>: { [ integer ]:T, first: integer, last: integer, push: fun(self: Queue<T>,item: T), pop_handler: fun(self: Queue<T>,callback: fun(value: T)), get_amount_in_queue: fun(self: Queue<T>): integer }
 
 
@@ -0,0 +1,33 @@
+-- NULL until the turtle reported its inventory once
+ALTER TABLE turtles ADD COLUMN selected_slot INTEGER;
+
+-- What every turtle carries right now, empty slots have no row
+CREATE TABLE IF NOT EXISTS turtle_inventories (
+        world TEXT NOT NULL,
+        turtle_id INTEGER NOT NULL,
+        slot INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        count INTEGER NOT NULL,
+        PRIMARY KEY (world,turtle_id,slot),
+        FOREIGN KEY (world,turtle_id)
+		REFERENCES turtles (world,id)
+		ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+-- Every slot change, the content of a slot at some time is its latest row before that time.
+-- An emptied slot is stored with name NULL and count 0
+CREATE TABLE IF NOT EXISTS turtle_inventory_history (
+        world TEXT NOT NULL,
+        turtle_id INTEGER NOT NULL,
+        slot INTEGER NOT NULL,
+        name TEXT,
+        count INTEGER NOT NULL,
+        -- unix time in milliseconds
+        changed_at INTEGER NOT NULL,
+        FOREIGN KEY (world,turtle_id)
+		REFERENCES turtles (world,id)
+		ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS turtle_inventory_history_by_turtle
+        ON turtle_inventory_history (world,turtle_id,changed_at);
@@ -0,0 +1,2 @@
+-- chunk streaming looks blocks up by chunk_key
+CREATE INDEX IF NOT EXISTS blocks_by_chunk_key ON blocks (world,chunk_key);
@@ -0,0 +1,3 @@
+-- When a block was last seen, and by which turtle. NULL for blocks stored before this
+ALTER TABLE blocks ADD COLUMN observed_at INTEGER;
+ALTER TABLE blocks ADD COLUMN observed_by INTEGER;
@@ -0,0 +1,2 @@
+-- Properties and tags from turtle.inspect as json, NULL for blocks without any
+ALTER TABLE blocks ADD COLUMN state TEXT;
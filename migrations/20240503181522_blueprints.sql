@@ -0,0 +1,10 @@
+-- Imported schematics, data is the JSON of common::blueprint::Blueprint
+CREATE TABLE IF NOT EXISTS blueprints (
+        world TEXT NOT NULL,
+        name TEXT NOT NULL,
+        data TEXT NOT NULL,
+        PRIMARY KEY (world,name),
+        FOREIGN KEY (world)
+		REFERENCES worlds (name)
+		ON UPDATE CASCADE ON DELETE CASCADE
+);
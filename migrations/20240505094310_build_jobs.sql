@@ -0,0 +1,11 @@
+-- Turtles building a blueprint, each one places the blocks of its own strip
+CREATE TABLE IF NOT EXISTS build_jobs (
+        world TEXT NOT NULL,
+        blueprint TEXT NOT NULL,
+        turtle INTEGER NOT NULL,
+        done BOOLEAN NOT NULL DEFAULT FALSE,
+        PRIMARY KEY (world,blueprint,turtle),
+        FOREIGN KEY (world,blueprint)
+		REFERENCES blueprints (world,name)
+		ON UPDATE CASCADE ON DELETE CASCADE
+);
@@ -0,0 +1,12 @@
+-- The job each turtle works on, kind is the JSON of common::client_packets::JobKind and step the
+-- index of the next step
+CREATE TABLE IF NOT EXISTS jobs (
+        world TEXT NOT NULL,
+        turtle INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        step INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (world,turtle),
+        FOREIGN KEY (world)
+		REFERENCES worlds (name)
+		ON UPDATE CASCADE ON DELETE CASCADE
+);
@@ -0,0 +1,10 @@
+-- Where a turtle refuels when it runs low during a job
+CREATE TABLE IF NOT EXISTS turtle_homes (
+        world TEXT NOT NULL,
+        turtle INTEGER NOT NULL,
+        position TEXT NOT NULL,
+        PRIMARY KEY (world,turtle),
+        FOREIGN KEY (world)
+		REFERENCES worlds (name)
+		ON UPDATE CASCADE ON DELETE CASCADE
+);
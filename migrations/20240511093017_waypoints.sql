@@ -0,0 +1,11 @@
+-- Named places of a world, kind is the JSON of common::waypoint::WaypointKind
+CREATE TABLE IF NOT EXISTS waypoints (
+        world TEXT NOT NULL,
+        name TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        position TEXT NOT NULL,
+        PRIMARY KEY (world,name),
+        FOREIGN KEY (world)
+		REFERENCES worlds (name)
+		ON UPDATE CASCADE ON DELETE CASCADE
+);
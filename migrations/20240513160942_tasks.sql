@@ -0,0 +1,15 @@
+-- Work queued for every turtle of a world. kind is the JSON of common::client_packets::TaskKind,
+-- turtle is set while one works on it and step is where a job task left off
+CREATE TABLE IF NOT EXISTS tasks (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        world TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        priority INTEGER NOT NULL,
+        turtle INTEGER,
+        step INTEGER NOT NULL DEFAULT 0,
+        retries INTEGER NOT NULL DEFAULT 0,
+        failure TEXT,
+        FOREIGN KEY (world)
+		REFERENCES worlds (name)
+		ON UPDATE CASCADE ON DELETE CASCADE
+);
@@ -0,0 +1,40 @@
+-- chunk_key becomes the sign aware interleaving of the chunk position (db::pos_to_key): 21 bits
+-- per axis, offset by 2^20, bit b of x, y and z at 3b, 3b+1 and 3b+2. Old keys threw the sign
+-- away and mixed x into z, so every row is recomputed from world_pos "x;y;z"
+CREATE TEMP TABLE block_chunk_keys AS
+WITH RECURSIVE
+        bits(b) AS (SELECT 0 UNION ALL SELECT b + 1 FROM bits WHERE b < 20),
+        split AS (
+                SELECT rowid AS row, world_pos AS pos, instr(world_pos, ';') AS sep FROM blocks
+        ),
+        coords AS (
+                SELECT
+                        row,
+                        CAST(substr(pos, 1, sep - 1) AS INTEGER) AS x,
+                        substr(pos, sep + 1) AS rest
+                FROM split
+        ),
+        chunks AS (
+                SELECT
+                        row,
+                        ((x >> 4) + 1048576) & 2097151 AS cx,
+                        ((CAST(substr(rest, 1, instr(rest, ';') - 1) AS INTEGER) >> 4) + 1048576)
+                                & 2097151 AS cy,
+                        ((CAST(substr(rest, instr(rest, ';') + 1) AS INTEGER) >> 4) + 1048576)
+                                & 2097151 AS cz
+                FROM coords
+        )
+SELECT
+        row,
+        SUM(
+                (((cx >> b) & 1) << (3 * b))
+                | (((cy >> b) & 1) << (3 * b + 1))
+                | (((cz >> b) & 1) << (3 * b + 2))
+        ) AS chunk_key
+FROM chunks, bits
+GROUP BY row;
+
+UPDATE blocks
+SET chunk_key = (SELECT chunk_key FROM block_chunk_keys WHERE block_chunk_keys.row = blocks.rowid);
+
+DROP TABLE block_chunk_keys;
@@ -1 +1,206 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
 
+use common::{
+    turtle::{MoveDirection, Orientation},
+    world_data::{Knowledge, World},
+    Pos3,
+};
+use thiserror::Error;
+
+const MOVES: [MoveDirection; 6] = [
+    MoveDirection::Forward,
+    MoveDirection::Back,
+    MoveDirection::Up,
+    MoveDirection::Down,
+    MoveDirection::Left,
+    MoveDirection::Right,
+];
+
+#[derive(Clone, Debug)]
+pub struct PathfindingOptions {
+    /// Extra cost for moving into a block no turtle has seen yet
+    pub unknown_block_penalty: u32,
+    /// Gives up after this many expanded nodes, unknown space is unbounded after all
+    pub max_visited_nodes: usize,
+    /// Blocks that are known but that a turtle can still move through
+    pub passable_blocks: Vec<String>,
+}
+
+impl Default for PathfindingOptions {
+    fn default() -> Self {
+        Self {
+            unknown_block_penalty: 4,
+            max_visited_nodes: 200_000,
+            passable_blocks: vec![
+                "minecraft:water".into(),
+                // other turtles don't stay where they were seen
+                "computercraft:turtle_normal".into(),
+                "computercraft:turtle_advanced".into(),
+            ],
+        }
+    }
+}
+
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+pub enum PathfindingError {
+    #[error("the goal {0:?} is a known solid block")]
+    GoalBlocked(Pos3),
+    #[error("no path found after visiting {0} nodes")]
+    SearchLimitReached(usize),
+    #[error("there is no path to the goal")]
+    NoPath,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Passability {
+    Free,
+    Unknown,
+    Blocked,
+}
+
+fn passability(world: &World, pos: &Pos3, options: &PathfindingOptions) -> Passability {
+    match world.get_knowledge(pos) {
+        Knowledge::Unknown => Passability::Unknown,
+        Knowledge::Air => Passability::Free,
+        Knowledge::Block(id) if options.passable_blocks.iter().any(|b| b == id) => {
+            Passability::Free
+        }
+        Knowledge::Block(_) => Passability::Blocked,
+    }
+}
+
+fn heuristic(from: &Pos3, to: &Pos3) -> u32 {
+    from.x.abs_diff(to.x) + from.y.abs_diff(to.y) + from.z.abs_diff(to.z)
+}
+
+type Node = (Pos3, Orientation);
+
+/// Searches the known world for the cheapest sequence of moves from `start` to `goal`.
+/// Turns count as moves, unknown blocks are assumed to be passable but cost
+/// [`PathfindingOptions::unknown_block_penalty`] extra.
+pub fn find_path(
+    world: &World,
+    start: Pos3,
+    goal: Pos3,
+    orientation: Orientation,
+    options: &PathfindingOptions,
+) -> Result<Vec<MoveDirection>, PathfindingError> {
+    find_path_avoiding(world, start, goal, orientation, options, |_, _| false)
+}
+
+/// Like [`find_path`], but never enters a block while `blocked` says it is taken. It gets the
+/// block and the number of moves it takes to get there, so it can tell when other turtles will
+/// be in the way.
+pub fn find_path_avoiding(
+    world: &World,
+    start: Pos3,
+    goal: Pos3,
+    orientation: Orientation,
+    options: &PathfindingOptions,
+    blocked: impl Fn(Pos3, u32) -> bool,
+) -> Result<Vec<MoveDirection>, PathfindingError> {
+    if passability(world, &goal, options) == Passability::Blocked {
+        return Err(PathfindingError::GoalBlocked(goal));
+    }
+    search(
+        world,
+        (start, orientation),
+        options,
+        blocked,
+        |pos| pos == goal,
+        |pos| heuristic(&pos, &goal),
+    )
+}
+
+/// The cheapest way to the closest known free block that `avoid` is fine with, for getting out
+/// of the way of other turtles. Takes the same `blocked` as [`find_path_avoiding`].
+pub fn find_refuge(
+    world: &World,
+    start: Pos3,
+    orientation: Orientation,
+    options: &PathfindingOptions,
+    blocked: impl Fn(Pos3, u32) -> bool,
+    avoid: impl Fn(Pos3) -> bool,
+) -> Result<Vec<MoveDirection>, PathfindingError> {
+    search(
+        world,
+        (start, orientation),
+        options,
+        blocked,
+        |pos| passability(world, &pos, options) == Passability::Free && !avoid(pos),
+        |_| 0,
+    )
+}
+
+fn search(
+    world: &World,
+    start_node: Node,
+    options: &PathfindingOptions,
+    blocked: impl Fn(Pos3, u32) -> bool,
+    is_goal: impl Fn(Pos3) -> bool,
+    heuristic: impl Fn(Pos3) -> u32,
+) -> Result<Vec<MoveDirection>, PathfindingError> {
+    let mut open = BinaryHeap::new();
+    // cost and number of moves, turns are free of penalties but still take their time
+    let mut cost_so_far: HashMap<Node, (u32, u32)> = HashMap::new();
+    let mut came_from: HashMap<Node, (Node, MoveDirection)> = HashMap::new();
+    // the heap only holds indices into this, Pos3 and Orientation have no ordering
+    let mut queued: Vec<Node> = vec![start_node];
+    open.push(Reverse((heuristic(start_node.0), 0usize)));
+    cost_so_far.insert(start_node, (0, 0));
+    let mut closed: HashSet<Node> = HashSet::new();
+
+    while let Some(Reverse((_, index))) = open.pop() {
+        let node = queued[index];
+        if is_goal(node.0) {
+            return Ok(reconstruct_path(&came_from, node));
+        }
+        // a node can be queued multiple times if a cheaper way to it was found later on
+        if !closed.insert(node) {
+            continue;
+        }
+        if closed.len() > options.max_visited_nodes {
+            return Err(PathfindingError::SearchLimitReached(
+                options.max_visited_nodes,
+            ));
+        }
+        let (node_cost, node_moves) = cost_so_far[&node];
+        for dir in MOVES {
+            let next = dir.apply(node.0, node.1);
+            let step_cost = match (next.0 == node.0, passability(world, &next.0, options)) {
+                // turning in place never depends on the surroundings
+                (true, _) => 1,
+                (false, _) if blocked(next.0, node_moves + 1) => continue,
+                (false, Passability::Free) => 1,
+                (false, Passability::Unknown) => 1 + options.unknown_block_penalty,
+                (false, Passability::Blocked) => continue,
+            };
+            let new_cost = node_cost + step_cost;
+            if cost_so_far.get(&next).is_some_and(|(c, _)| *c <= new_cost) {
+                continue;
+            }
+            cost_so_far.insert(next, (new_cost, node_moves + 1));
+            came_from.insert(next, (node, dir));
+            open.push(Reverse((new_cost + heuristic(next.0), queued.len())));
+            queued.push(next);
+        }
+    }
+    Err(PathfindingError::NoPath)
+}
+
+fn reconstruct_path(
+    came_from: &HashMap<Node, (Node, MoveDirection)>,
+    end: Node,
+) -> Vec<MoveDirection> {
+    let mut path = Vec::new();
+    let mut current = end;
+    while let Some((prev, dir)) = came_from.get(&current) {
+        path.push(*dir);
+        current = *prev;
+    }
+    path.reverse();
+    path
+}
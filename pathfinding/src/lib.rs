@@ -1,12 +1,298 @@
-
-
 pub mod a_star;
 
+pub use a_star::{
+    find_path, find_path_avoiding, find_refuge, PathfindingError, PathfindingOptions,
+};
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use common::{
+        turtle::{MoveDirection, Orientation},
+        world_data::{Block, World},
+        Pos3,
+    };
+
+    fn world_with(solid: &[Pos3], air: &[Pos3]) -> World {
+        let mut world = World::new("test");
+        for pos in solid {
+            world.set_block(Block::new(Some("minecraft:stone".into()), pos, "test"));
+        }
+        for pos in air {
+            world.set_block(Block::new(None, pos, "test"));
+        }
+        world
+    }
+
+    fn walk(start: Pos3, orientation: Orientation, path: &[MoveDirection]) -> Pos3 {
+        path.iter()
+            .fold((start, orientation), |(p, o), dir| dir.apply(p, o))
+            .0
+    }
+
+    #[test]
+    fn already_at_goal() {
+        let world = World::new("test");
+        let path = find_path(
+            &world,
+            Pos3::ZERO,
+            Pos3::ZERO,
+            Orientation::North,
+            &Default::default(),
+        );
+        assert_eq!(path, Ok(vec![]));
+    }
+
+    #[test]
+    fn straight_line_through_known_air() {
+        let air = (0..=3).map(|z| Pos3::new(0, 0, -z)).collect::<Vec<_>>();
+        let world = world_with(&[], &air);
+        let goal = Pos3::new(0, 0, -3);
+        let path = find_path(
+            &world,
+            Pos3::ZERO,
+            goal,
+            Orientation::North,
+            &Default::default(),
+        )
+        .unwrap();
+        assert_eq!(path, vec![MoveDirection::Forward; 3]);
+    }
+
+    #[test]
+    fn turns_count_as_moves() {
+        let air = (0..=2).map(|x| Pos3::new(x, 0, 0)).collect::<Vec<_>>();
+        let world = world_with(&[], &air);
+        let path = find_path(
+            &world,
+            Pos3::ZERO,
+            Pos3::new(2, 0, 0),
+            Orientation::North,
+            &Default::default(),
+        )
+        .unwrap();
+        // either turn right and go forward or turn left and back up, both are 3 moves
+        assert_eq!(path.len(), 3);
+        assert_eq!(
+            walk(Pos3::ZERO, Orientation::North, &path),
+            Pos3::new(2, 0, 0)
+        );
+    }
+
+    #[test]
+    fn goes_around_walls() {
+        // a wall directly in front, the only known gap is one block up
+        let solid = [Pos3::new(0, 0, -1), Pos3::new(0, -1, -1)];
+        let air = [
+            Pos3::new(0, 1, 0),
+            Pos3::new(0, 1, -1),
+            Pos3::new(0, 1, -2),
+            Pos3::new(0, 0, -2),
+        ];
+        let world = world_with(&solid, &air);
+        let options = PathfindingOptions {
+            unknown_block_penalty: 100,
+            ..Default::default()
+        };
+        let goal = Pos3::new(0, 0, -2);
+        let path = find_path(&world, Pos3::ZERO, goal, Orientation::North, &options).unwrap();
+        assert_eq!(walk(Pos3::ZERO, Orientation::North, &path), goal);
+        assert_eq!(path.len(), 4);
+        let mut pos_and_orient = (Pos3::ZERO, Orientation::North);
+        for dir in path {
+            pos_and_orient = dir.apply(pos_and_orient.0, pos_and_orient.1);
+            assert!(!solid.contains(&pos_and_orient.0));
+        }
+    }
+
+    #[test]
+    fn prefers_known_air_over_unknown() {
+        // straight ahead is unknown, a detour through known air is one block longer
+        let air = [
+            Pos3::new(0, 1, 0),
+            Pos3::new(0, 1, -1),
+            Pos3::new(0, 1, -2),
+            Pos3::new(0, 0, -2),
+        ];
+        let world = world_with(&[], &air);
+        let goal = Pos3::new(0, 0, -2);
+        let path = find_path(
+            &world,
+            Pos3::ZERO,
+            goal,
+            Orientation::North,
+            &Default::default(),
+        )
+        .unwrap();
+        assert!(path.contains(&MoveDirection::Up));
+
+        let options = PathfindingOptions {
+            unknown_block_penalty: 0,
+            ..Default::default()
+        };
+        let path = find_path(&world, Pos3::ZERO, goal, Orientation::North, &options).unwrap();
+        assert_eq!(path, vec![MoveDirection::Forward; 2]);
+    }
+
+    #[test]
+    fn blocked_goal() {
+        let goal = Pos3::new(5, 0, 0);
+        let world = world_with(&[goal], &[]);
+        let path = find_path(
+            &world,
+            Pos3::ZERO,
+            goal,
+            Orientation::North,
+            &Default::default(),
+        );
+        assert_eq!(path, Err(PathfindingError::GoalBlocked(goal)));
+    }
+
+    #[test]
+    fn enclosed_start_has_no_path() {
+        let solid = [
+            Pos3::new(1, 0, 0),
+            Pos3::new(-1, 0, 0),
+            Pos3::new(0, 1, 0),
+            Pos3::new(0, -1, 0),
+            Pos3::new(0, 0, 1),
+            Pos3::new(0, 0, -1),
+        ];
+        let world = world_with(&solid, &[]);
+        let path = find_path(
+            &world,
+            Pos3::ZERO,
+            Pos3::new(10, 0, 0),
+            Orientation::North,
+            &Default::default(),
+        );
+        assert_eq!(path, Err(PathfindingError::NoPath));
+    }
+
+    #[test]
+    fn gives_up_eventually() {
+        let options = PathfindingOptions {
+            max_visited_nodes: 50,
+            ..Default::default()
+        };
+        let world = World::new("test");
+        let path = find_path(
+            &world,
+            Pos3::ZERO,
+            Pos3::new(1000, 0, 0),
+            Orientation::North,
+            &options,
+        );
+        assert_eq!(path, Err(PathfindingError::SearchLimitReached(50)));
+    }
+
+    #[test]
+    fn water_is_passable() {
+        let mut world = World::new("test");
+        world.set_block(Block::new(
+            Some("minecraft:water".into()),
+            &Pos3::new(0, 0, -1),
+            "test",
+        ));
+        let path = find_path(
+            &world,
+            Pos3::ZERO,
+            Pos3::new(0, 0, -1),
+            Orientation::North,
+            &Default::default(),
+        );
+        assert_eq!(path, Ok(vec![MoveDirection::Forward]));
+    }
 
     #[test]
-    fn it_works() {
+    fn goes_around_blocks_taken_at_the_time() {
+        let air = [Pos3::new(0, 0, -1), Pos3::new(0, 0, -2), Pos3::new(0, 1, 0)]
+            .into_iter()
+            .chain((-2..=0).map(|z| Pos3::new(0, 1, z - 1)))
+            .collect::<Vec<_>>();
+        let world = world_with(&[], &air);
+        let goal = Pos3::new(0, 0, -2);
+        let taken = Pos3::new(0, 0, -1);
+        let options = PathfindingOptions {
+            unknown_block_penalty: 100,
+            ..Default::default()
+        };
+        // only taken while the turtle would get there, going straight is fine later
+        let path = find_path_avoiding(
+            &world,
+            Pos3::ZERO,
+            goal,
+            Orientation::North,
+            &options,
+            |p, m| p == taken && m == 1,
+        )
+        .unwrap();
+        assert_eq!(walk(Pos3::ZERO, Orientation::North, &path), goal);
+        let first = walk(Pos3::ZERO, Orientation::North, &path[..1]);
+        assert_ne!(first, taken);
+        let path = find_path_avoiding(
+            &world,
+            Pos3::ZERO,
+            goal,
+            Orientation::North,
+            &options,
+            |p, m| p == taken && m == 5,
+        )
+        .unwrap();
+        assert_eq!(path, vec![MoveDirection::Forward; 2]);
+    }
+
+    #[test]
+    fn refuges_are_known_free_blocks_off_the_route() {
+        // a one wide tunnel going north with a niche to the east at its far end, the only way
+        // out is through the tunnel
+        let tunnel = (0..=4).map(|z| Pos3::new(0, 0, -z)).collect::<Vec<_>>();
+        let niche = Pos3::new(1, 0, -4);
+        let air = tunnel.iter().copied().chain([niche]).collect::<Vec<_>>();
+        let solid = (0..=4)
+            .flat_map(|z| {
+                [
+                    Pos3::new(-1, 0, -z),
+                    Pos3::new(0, 1, -z),
+                    Pos3::new(0, -1, -z),
+                ]
+            })
+            .chain((0..4).map(|z| Pos3::new(1, 0, -z)))
+            .chain([Pos3::new(0, 0, 1)])
+            .collect::<Vec<_>>();
+        let world = world_with(&solid, &air);
+        let options = PathfindingOptions::default();
+        let path = find_refuge(
+            &world,
+            Pos3::ZERO,
+            Orientation::North,
+            &options,
+            |_, _| false,
+            |p| tunnel.contains(&p),
+        )
+        .unwrap();
+        assert_eq!(walk(Pos3::ZERO, Orientation::North, &path), niche);
+
+        // another turtle stands in the way
+        let path = find_refuge(
+            &world,
+            Pos3::ZERO,
+            Orientation::North,
+            &options,
+            |p, _| p == Pos3::new(0, 0, -2),
+            |p| tunnel.contains(&p),
+        );
+        assert!(path.is_err());
+
+        // already off the route
+        let path = find_refuge(
+            &world,
+            Pos3::ZERO,
+            Orientation::North,
+            &options,
+            |_, _| false,
+            |p| p != Pos3::ZERO,
+        );
+        assert_eq!(path, Ok(vec![]));
     }
 }
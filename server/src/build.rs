@@ -0,0 +1,438 @@
+//! Building blueprints. The missing blocks get placed bottom up, each one by a turtle standing
+//! right above it.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use common::{
+    blueprint::{Blueprint, BlueprintStatus},
+    client_packets::{BuildStatus, GoToStatus},
+    turtle::Maybe,
+    turtle_packets::S2TPackets,
+    world_data::{Block, Knowledge, World},
+    Pos3,
+};
+use log::{error, info};
+
+use crate::{
+    data_types::server_turtle::ServerTurtle,
+    db::{finish_build_job, load_region, DB},
+};
+
+const UP: Pos3 = Pos3 { x: 0, y: 1, z: 0 };
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlaceOp {
+    pub pos: Pos3,
+    /// Block and item id, they are the same for most blocks
+    pub block: String,
+}
+
+/// Everything that has to be placed for `blueprint` to match `world`, in order. Layers go bottom
+/// up, in a layer blocks resting on something come first, then the ones next to them and
+/// floating ones last. Positions where something else is in the way are left out
+pub fn plan_build(blueprint: &Blueprint, world: &World) -> Vec<PlaceOp> {
+    let mut layers: BTreeMap<i32, Vec<Pos3>> = BTreeMap::new();
+    let mut blocks = HashMap::new();
+    for (pos, planned, status) in blueprint.diff(world) {
+        let missing = matches!(status, BlueprintStatus::Missing | BlueprintStatus::Unknown);
+        if planned.is_air || !missing {
+            continue;
+        }
+        layers.entry(pos.y).or_default().push(pos);
+        blocks.insert(pos, planned.id.clone());
+    }
+    let supported = |pos: &Pos3| {
+        let below = *pos - UP;
+        matches!(world.get_knowledge(&below), Knowledge::Block(_))
+            || blueprint.get(&below).is_some_and(|b| !b.is_air)
+    };
+
+    let mut ops = Vec::with_capacity(blocks.len());
+    for (_, mut layer) in layers {
+        // back and forth along x, so the turtle doesn't walk back for every row
+        layer.sort_by_key(|p| (p.z, if p.z.rem_euclid(2) == 0 { p.x } else { -p.x }));
+        let mut left = layer.iter().copied().collect::<HashSet<_>>();
+        let mut queue = layer
+            .iter()
+            .copied()
+            .filter(supported)
+            .collect::<VecDeque<_>>();
+        for pos in &queue {
+            left.remove(pos);
+        }
+        loop {
+            while let Some(pos) = queue.pop_front() {
+                ops.push(PlaceOp {
+                    pos,
+                    block: blocks[&pos].clone(),
+                });
+                for offset in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                    let next = pos + Pos3::new(offset.0, 0, offset.1);
+                    if left.remove(&next) {
+                        queue.push_back(next);
+                    }
+                }
+            }
+            // nothing left that touches a placed block
+            match layer.iter().find(|p| left.contains(p)) {
+                Some(pos) => {
+                    left.remove(pos);
+                    queue.push_back(*pos);
+                }
+                None => break,
+            }
+        }
+    }
+    ops
+}
+
+/// Splits `ops` into one strip per turtle, along the longer side of the blueprint. The strips
+/// are cut from the whole blueprint instead of what is left, so a turtle gets the same strip
+/// when the build is resumed
+pub fn assign_build(
+    blueprint: &Blueprint,
+    ops: Vec<PlaceOp>,
+    turtles: &[i32],
+) -> HashMap<i32, Vec<PlaceOp>> {
+    let mut turtles = turtles.to_vec();
+    turtles.sort_unstable();
+    turtles.dedup();
+    let mut shares = turtles
+        .iter()
+        .map(|t| (*t, Vec::new()))
+        .collect::<HashMap<_, _>>();
+    if turtles.is_empty() {
+        return shares;
+    }
+
+    let along_x = blueprint.size().x >= blueprint.size().z;
+    let key = |pos: &Pos3| if along_x { pos.x } else { pos.z };
+    let mut keys = blueprint
+        .iter()
+        .filter(|(_, planned)| !planned.is_air)
+        .map(|(pos, _)| key(&pos))
+        .collect::<Vec<_>>();
+    keys.sort_unstable();
+    let cuts = (1..turtles.len())
+        .filter_map(|i| keys.get(i * keys.len() / turtles.len()).copied())
+        .collect::<Vec<_>>();
+    for op in ops {
+        let strip = cuts.iter().filter(|c| **c <= key(&op.pos)).count();
+        shares.get_mut(&turtles[strip]).unwrap().push(op);
+    }
+    shares
+}
+
+/// Items needed to place `ops`, by id
+pub fn materials<'a>(ops: impl IntoIterator<Item = &'a PlaceOp>) -> Vec<(String, u32)> {
+    let mut counts = BTreeMap::new();
+    for op in ops {
+        *counts.entry(op.block.clone()).or_default() += 1;
+    }
+    counts.into_iter().collect()
+}
+
+/// Plans the build of `blueprint` against what the db knows and splits it between `turtles`
+pub async fn plan_shares(
+    db: &DB,
+    blueprint: Blueprint,
+    turtles: Vec<i32>,
+) -> Result<HashMap<i32, Vec<PlaceOp>>, sqlx::Error> {
+    // the layer below decides what is supported
+    let world = load_region(db, &blueprint.world, blueprint.anchor - UP, blueprint.max()).await?;
+    tokio::task::spawn_blocking(move || {
+        let ops = plan_build(&blueprint, &world);
+        assign_build(&blueprint, ops, &turtles)
+    })
+    .await
+    .map_err(|err| sqlx::Error::Io(err.into()))
+}
+
+pub struct Build {
+    blueprint: String,
+    ops: VecDeque<PlaceOp>,
+    /// The first op was sent to the turtle, waiting for it to report the block
+    placing: bool,
+    /// Waiting for the inventory to have some of this
+    missing: Option<String>,
+    skipped: u32,
+}
+
+impl Build {
+    fn status(&self) -> BuildStatus {
+        BuildStatus::Building {
+            blueprint: self.blueprint.clone(),
+            remaining: self.ops.len() as u32,
+            materials: materials(&self.ops),
+        }
+    }
+
+    fn skip_first(&mut self) {
+        self.ops.pop_front();
+        self.placing = false;
+        self.skipped += 1;
+    }
+}
+
+pub enum BuildEvent {
+    /// A go to ended, only `Arrived` and `Failed` matter
+    GoTo(GoToStatus),
+    PlaceFailed,
+    BlockChanged(Block),
+    InventoryChanged,
+}
+
+/// A build drives the turtle with go tos, so clients get their status as well
+pub enum BuildUpdate {
+    GoTo(GoToStatus),
+    Build(BuildStatus),
+}
+
+impl ServerTurtle {
+    /// The blueprint the turtle is building right now
+    pub fn building(&self) -> Option<&str> {
+        self.build.as_ref().map(|b| b.blueprint.as_str())
+    }
+
+    /// Starts placing `ops` for `blueprint`, replaces any running build or go to
+    pub async fn start_build(&mut self, blueprint: &str, ops: Vec<PlaceOp>) -> Vec<BuildUpdate> {
+        let build = Build {
+            blueprint: blueprint.to_owned(),
+            ops: ops.into(),
+            placing: false,
+            missing: None,
+            skipped: 0,
+        };
+        let mut updates = vec![BuildUpdate::Build(build.status())];
+        self.build = None;
+        self.next_place(build, &mut updates).await;
+        updates
+    }
+
+    /// Drops the running build and whatever go to it was doing
+    pub fn stop_build(&mut self) -> Option<BuildStatus> {
+        let build = self.build.take()?;
+        self.stop_go_to();
+        Some(BuildStatus::Stopped {
+            blueprint: build.blueprint,
+        })
+    }
+
+    /// Advances the running build (if any), returns the updates clients should get
+    pub async fn drive_build(&mut self, event: BuildEvent) -> Vec<BuildUpdate> {
+        let Some(mut build) = self.build.take() else {
+            return Vec::new();
+        };
+        let stand_on = build.ops.front().map(|op| op.pos + UP);
+        let mut updates = Vec::new();
+        match event {
+            BuildEvent::GoTo(GoToStatus::Arrived { target })
+                if !build.placing && build.missing.is_none() && Some(target) == stand_on =>
+            {
+                self.place(build, &mut updates).await;
+                return updates;
+            }
+            BuildEvent::GoTo(GoToStatus::Failed { target, reason })
+                if !build.placing && build.missing.is_none() && Some(target) == stand_on =>
+            {
+                info!(
+                    "turtle {} skips a block of {}: {reason}",
+                    self.index, build.blueprint
+                );
+                build.skip_first();
+            }
+            BuildEvent::PlaceFailed if build.placing => build.skip_first(),
+            BuildEvent::BlockChanged(block) if !block.is_air => {
+                let Some(i) = build.ops.iter().position(|op| op.pos == block.pos) else {
+                    self.build = Some(build);
+                    return updates;
+                };
+                let op = build.ops.remove(i).expect("position is in bounds");
+                if op.block != block.id {
+                    build.skipped += 1;
+                }
+                if i != 0 {
+                    // somebody else placed it, the turtle goes on with what it is doing
+                    updates.push(BuildUpdate::Build(build.status()));
+                    self.build = Some(build);
+                    return updates;
+                }
+                build.placing = false;
+                build.missing = None;
+            }
+            BuildEvent::InventoryChanged if build.missing.is_some() => {
+                build.missing = None;
+                self.next_place(build, &mut updates).await;
+                return updates;
+            }
+            _ => {
+                self.build = Some(build);
+                return updates;
+            }
+        }
+        updates.push(BuildUpdate::Build(build.status()));
+        self.next_place(build, &mut updates).await;
+        updates
+    }
+
+    /// Sends the turtle above the next block, places it right away if it is there already
+    async fn next_place(&mut self, mut build: Build, updates: &mut Vec<BuildUpdate>) {
+        loop {
+            let Some(op) = build.ops.front() else {
+                return self.finish_build(build, updates).await;
+            };
+            let statuses = self.start_go_to(op.pos + UP).await;
+            let last = statuses.last().cloned();
+            updates.extend(statuses.into_iter().map(BuildUpdate::GoTo));
+            match last {
+                Some(GoToStatus::Arrived { .. }) => return self.place(build, updates).await,
+                Some(GoToStatus::Failed { reason, .. }) => {
+                    info!(
+                        "turtle {} skips a block of {}: {reason}",
+                        self.index, build.blueprint
+                    );
+                    build.skip_first();
+                    updates.push(BuildUpdate::Build(build.status()));
+                }
+                _ => {
+                    self.build = Some(build);
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn place(&mut self, mut build: Build, updates: &mut Vec<BuildUpdate>) {
+        let item = build
+            .ops
+            .front()
+            .expect("placing needs a block")
+            .block
+            .clone();
+        let has_item = |slot: &Maybe<common::turtle::Item>| matches!(slot, Maybe::Some(i) if i.name == item && i.count > 0);
+        let code = match &self.inventory {
+            Maybe::Some(inv) if has_item(&inv[inv.selected_slot as usize - 1]) => {
+                Some("turtle.placeDown()".to_owned())
+            }
+            Maybe::Some(inv) => inv
+                .iter()
+                .position(has_item)
+                .map(|slot| format!("turtle.select({}) turtle.placeDown()", slot + 1)),
+            Maybe::None => None,
+        };
+        match code {
+            Some(code) => {
+                build.placing = true;
+                self.build = Some(build);
+                self.send_ws(S2TPackets::RunLuaCode(code)).await;
+            }
+            None => {
+                updates.push(BuildUpdate::Build(BuildStatus::MissingMaterial {
+                    blueprint: build.blueprint.clone(),
+                    item: item.clone(),
+                }));
+                build.missing = Some(item);
+                self.build = Some(build);
+            }
+        }
+    }
+
+    async fn finish_build(&mut self, build: Build, updates: &mut Vec<BuildUpdate>) {
+        info!(
+            "turtle {} is done with its part of {}",
+            self.index, build.blueprint
+        );
+        let db = self.get_db();
+        if let Err(err) = finish_build_job(&db, &self.world, &build.blueprint, self.index).await {
+            error!("failed to store the end of a build: {err}");
+        }
+        updates.push(BuildUpdate::Build(BuildStatus::Done {
+            blueprint: build.blueprint,
+            skipped: build.skipped,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::world_data::{BlockState, PaletteEntry};
+
+    fn entry(id: &str) -> PaletteEntry {
+        PaletteEntry {
+            id: id.to_owned(),
+            is_air: false,
+            state: BlockState::default(),
+        }
+    }
+
+    fn positions(ops: &[PlaceOp]) -> Vec<Pos3> {
+        ops.iter().map(|op| op.pos).collect()
+    }
+
+    #[test]
+    fn builds_are_planned_bottom_up() {
+        // a bridge, the middle of its first layer floats over a gap
+        let mut blueprint = Blueprint::new("bridge", "test", Pos3::zero(), Pos3::new(4, 2, 1));
+        for x in 0..4 {
+            blueprint.set(Pos3::new(x, 0, 0), entry("minecraft:stone"));
+        }
+        blueprint.set(Pos3::new(0, 1, 0), entry("minecraft:torch"));
+        blueprint.set(Pos3::new(3, 1, 0), entry("minecraft:torch"));
+
+        let mut world = World::new("test");
+        let block = |id: Option<&str>, pos: Pos3| Block::new(id.map(Into::into), &pos, "test");
+        world.set_block(block(Some("minecraft:dirt"), Pos3::new(3, -1, 0)));
+        world.set_block(block(Some("minecraft:stone"), Pos3::new(0, 0, 0)));
+        world.set_block(block(Some("minecraft:dirt"), Pos3::new(0, 1, 0)));
+        world.set_block(block(None, Pos3::new(1, 0, 0)));
+
+        let ops = plan_build(&blueprint, &world);
+        // the stone that is already there and the torch with dirt in the way are left out, the
+        // block on the dirt comes first and the others hang on to it
+        assert_eq!(
+            positions(&ops),
+            [
+                Pos3::new(3, 0, 0),
+                Pos3::new(2, 0, 0),
+                Pos3::new(1, 0, 0),
+                Pos3::new(3, 1, 0),
+            ]
+        );
+        assert_eq!(
+            materials(&ops),
+            [
+                ("minecraft:stone".to_owned(), 3),
+                ("minecraft:torch".to_owned(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn strips_stay_the_same_while_building() {
+        let mut blueprint = Blueprint::new("floor", "test", Pos3::zero(), Pos3::new(6, 1, 2));
+        for x in 0..6 {
+            for z in 0..2 {
+                blueprint.set(Pos3::new(x, 0, z), entry("minecraft:stone"));
+            }
+        }
+        let mut world = World::new("test");
+        let shares = assign_build(&blueprint, plan_build(&blueprint, &world), &[7, 2, 7]);
+        assert_eq!(shares.len(), 2);
+        assert!(shares[&2].iter().all(|op| op.pos.x < 3));
+        assert!(shares[&7].iter().all(|op| op.pos.x >= 3));
+        assert_eq!(shares[&2].len(), 6);
+
+        for x in 0..4 {
+            world.set_block(Block::new(
+                Some("minecraft:stone".into()),
+                &Pos3::new(x, 0, 0),
+                "test",
+            ));
+        }
+        let shares = assign_build(&blueprint, plan_build(&blueprint, &world), &[2, 7]);
+        assert_eq!(shares[&2].len(), 3);
+        assert!(shares[&7].iter().all(|op| op.pos.x >= 3));
+        assert_eq!(shares[&7].len(), 5);
+    }
+}
@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use common::{client_packets::S2CPackets, Pos3};
+use log::error;
+use tokio::sync::Mutex;
+
+use crate::{
+    data_types::client_map::ClientMap,
+    db::{load_chunks, CHUNK_BATCH, DB},
+};
+
+/// Upper bound for what clients can ask for, a radius of 32 is already ~140k chunks
+pub const MAX_RENDER_DISTANCE: u32 = 32;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkView {
+    pub world: String,
+    /// Chunk position the client is looking at
+    pub center: Pos3,
+}
+
+/// Every chunk position in a sphere of `distance` chunks around `center`, closest first
+pub fn chunks_in_range(center: Pos3, distance: u32) -> Vec<Pos3> {
+    let r = distance as i32;
+    let mut chunks = Vec::new();
+    for x in -r..=r {
+        for y in -r..=r {
+            for z in -r..=r {
+                let dist = x * x + y * y + z * z;
+                if dist <= r * r {
+                    chunks.push((dist, center + Pos3::new(x, y, z)));
+                }
+            }
+        }
+    }
+    chunks.sort_by_key(|(dist, _)| *dist);
+    chunks.into_iter().map(|(_, pos)| pos).collect()
+}
+
+/// Loads `chunks` of `world` from the db and sends the ones that have blocks to the client, in
+/// order. Runs as its own task, so big views neither block other clients nor arrive all at once.
+pub async fn stream_chunks(
+    db: Arc<DB>,
+    clients: Arc<Mutex<ClientMap>>,
+    client_index: i32,
+    world: String,
+    chunks: Vec<Pos3>,
+) {
+    for batch in chunks.chunks(CHUNK_BATCH) {
+        let mut loaded = match load_chunks(&db, &world, batch).await {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                error!("loading chunks of {world} failed: {err}");
+                return;
+            }
+        };
+        for pos in batch {
+            let chunk = loaded.remove(pos);
+            let mut clients = clients.lock().await;
+            let Some(client) = clients.get_client_mut(&client_index) else {
+                return;
+            };
+            if !client.mark_chunk_loaded(&world, *pos, chunk.is_some()) {
+                return;
+            }
+            if let Some(chunk) = chunk {
+                client
+                    .send_msg(&S2CPackets::SetChunk {
+                        world: world.clone(),
+                        chunk,
+                    })
+                    .await;
+            }
+        }
+    }
+}
+
+/// Moves the view of a client and starts streaming whatever it is missing now
+pub async fn update_view(
+    db: Arc<DB>,
+    clients: &Arc<Mutex<ClientMap>>,
+    client_index: i32,
+    view: ChunkView,
+) {
+    let mut client_map = clients.lock().await;
+    let Some(client) = client_map.get_client_mut(&client_index) else {
+        return;
+    };
+    let world = view.world.clone();
+    let (unload, load) = client.set_view(view);
+    if !unload.is_empty() {
+        client
+            .send_msg(&S2CPackets::UnloadChunks {
+                world: world.clone(),
+                chunks: unload,
+            })
+            .await;
+    }
+    let stream = tokio::spawn(stream_chunks(
+        db,
+        clients.clone(),
+        client_index,
+        world,
+        load,
+    ));
+    client.set_chunk_stream(stream);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closest_chunks_come_first() {
+        let center = Pos3::new(3, -2, 7);
+        let chunks = chunks_in_range(center, 2);
+        assert_eq!(chunks[0], center);
+        assert_eq!(chunks.len(), 33);
+        assert!(chunks.contains(&(center + Pos3::new(0, 2, 0))));
+        assert!(!chunks.contains(&(center + Pos3::new(2, 2, 0))));
+        let dist = |p: &Pos3| {
+            let d = *p - center;
+            d.x * d.x + d.y * d.y + d.z * d.z
+        };
+        assert!(chunks.windows(2).all(|w| dist(&w[0]) <= dist(&w[1])));
+    }
+}
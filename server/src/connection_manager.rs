@@ -1,32 +1,414 @@
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::data_types::client_map;
+use crate::build::{plan_shares, BuildEvent, BuildUpdate};
+use crate::chunk_streaming::{update_view, ChunkView};
+use crate::data_types::client_map::{self, ClientMap};
 
-use crate::data_types::server_client::{ClientComms, ServerClient};
+use crate::data_types::server_client::{ClientComms, Encoding, ServerClient};
 use crate::data_types::server_turtle::{ServerTurtle, WsRecv, WsSend};
 use crate::data_types::turtle_map::TurtleMap;
-use crate::db::{pos_to_db_pos, pos_to_key, DB};
+use crate::db::{
+    add_task, assign_task, delete_blueprint, delete_build_job, delete_job, delete_task,
+    delete_waypoint, fail_task, load_blueprint, load_blueprints, load_build_job, load_homes,
+    load_job, load_task_step, load_tasks, load_turtle, load_turtles, load_waypoint, load_waypoints,
+    load_world, pos_to_db_pos, release_all_tasks, save_block, save_build_job, save_home, save_job,
+    save_waypoint, search_blocks, worlds_with_queued_tasks, DB,
+};
+use crate::go_to::GoToEvent;
+use crate::import::{import_to_db, ImportFormat};
+use crate::inventory;
+use crate::jobs::{JobEvent, JobUpdate};
+use crate::reservations::Reservations;
+use crate::tasks::{pick_turtle, task_start, Candidate};
 
-use common::client_packets::{C2SPackets, MovedTurtleData, S2CPackets, SetTurtlesData};
-use common::turtle::{Maybe, Turtle};
+use common::client_packets::{
+    C2SPackets, Capability, GoToStatus, HandshakeData, InventoryStatus, JobStatus, MovedTurtleData,
+    S2CPackets, SetTurtlesData, TaskKind, TaskState, UpdateTurtleData, PROTOCOL_VERSION,
+};
+use common::turtle::{InventoryAction, Maybe, MoveDirection, Turtle};
 use common::turtle_packets::{S2TPackets, SetupInfoData, T2SPackets};
+use common::waypoint::Target;
 use common::world_data::{get_chunk_containing_block, Block, World};
+use common::Pos3;
 
 use futures_channel::mpsc::unbounded;
 
 use futures_util::{pin_mut, SinkExt, StreamExt};
-use log::{error, info};
+use log::{error, info, warn};
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::Mutex;
 
+/// What this server can do on top of plain JSON packets
+const SUPPORTED_CAPABILITIES: &[Capability] = &[Capability::BinaryEncoding];
+
+/// How often queued tasks are offered to idle turtles, on top of whenever something changes
+const DISPATCH_INTERVAL: Duration = Duration::from_secs(5);
+
 pub enum TurtleCommBus {
     /// stupid fucking workaround. cant do this in ServerTurtle because the borrow checker; That fuck
     Packet((i32, T2SPackets)),
     Moved(i32),
+    MoveFailed(i32),
+    PlaceFailed(i32),
+    DigFailed(i32, MoveDirection),
     RemoveMe(i32),
     InvUpdate(i32),
     FuelUpdate(i32),
     UpdateBlock(Block),
+    StdOut(i32, String),
+    Executables(i32),
+    /// What came of an inventory action a client asked for
+    InventoryResult(i32, InventoryStatus),
+    /// The tasks of the world changed, clients hear about it and idle turtles get queued ones
+    TasksChanged(String),
+    /// Idle turtles of the world get queued tasks, clients only hear about it if any did
+    DispatchTasks(String),
+    /// A turtle of the world started waiting for another one, the ones waiting check whether
+    /// they wait for each other
+    Waiting(String),
+}
+
+/// Every turtle of `world` the db knows about, marked online if it is connected right now
+async fn world_turtles(db: &DB, server_turtles: &TurtleMap, world: &str) -> Vec<Turtle> {
+    let online = server_turtles
+        .get_common_turtles()
+        .into_iter()
+        .filter(|t| t.world == world)
+        .map(|t| t.index)
+        .collect::<Vec<_>>();
+    match load_turtles(db, world).await {
+        Ok(t) => t
+            .into_iter()
+            .map(|mut t| {
+                t.is_online = online.contains(&t.index);
+                t
+            })
+            .collect(),
+        Err(err) => {
+            error!("{err}");
+            Vec::new()
+        }
+    }
+}
+
+fn go_to_update(turtle: &ServerTurtle, status: GoToStatus) -> S2CPackets {
+    S2CPackets::GoToUpdate(UpdateTurtleData {
+        index: turtle.index,
+        world: turtle.world.clone(),
+        data: status,
+    })
+}
+
+fn build_update(turtle: &ServerTurtle, update: BuildUpdate) -> S2CPackets {
+    match update {
+        BuildUpdate::GoTo(status) => go_to_update(turtle, status),
+        BuildUpdate::Build(status) => S2CPackets::BuildUpdate(UpdateTurtleData {
+            index: turtle.index,
+            world: turtle.world.clone(),
+            data: status,
+        }),
+    }
+}
+
+async fn broadcast_build_updates(
+    turtle: &ServerTurtle,
+    updates: Vec<BuildUpdate>,
+    clients: &mut ClientMap,
+) {
+    for update in updates {
+        clients.broadcast(build_update(turtle, update)).await;
+    }
+}
+
+fn job_update(turtle: &ServerTurtle, update: JobUpdate) -> S2CPackets {
+    match update {
+        JobUpdate::GoTo(status) => go_to_update(turtle, status),
+        JobUpdate::Job(status) => S2CPackets::JobUpdate(UpdateTurtleData {
+            index: turtle.index,
+            world: turtle.world.clone(),
+            data: status,
+        }),
+    }
+}
+
+/// A task the turtle works on ends with its job
+async fn broadcast_job_updates(
+    turtle: &mut ServerTurtle,
+    updates: Vec<JobUpdate>,
+    clients: &mut ClientMap,
+) {
+    for update in updates {
+        turtle.drive_task(&update).await;
+        clients.broadcast(job_update(turtle, update)).await;
+    }
+}
+
+/// Advances the go to of `turtle`, a build or job it is part of hears about the end of it
+async fn drive_go_to(turtle: &mut ServerTurtle, event: GoToEvent, clients: &mut ClientMap) {
+    for status in turtle.drive_go_to(event).await {
+        clients
+            .broadcast(go_to_update(turtle, status.clone()))
+            .await;
+        if matches!(
+            status,
+            GoToStatus::Arrived { .. } | GoToStatus::Failed { .. }
+        ) {
+            let updates = turtle.drive_build(BuildEvent::GoTo(status.clone())).await;
+            broadcast_build_updates(turtle, updates, clients).await;
+            drive_job(turtle, JobEvent::GoTo(status.clone()), clients).await;
+            turtle.drive_task(&JobUpdate::GoTo(status)).await;
+        }
+    }
+}
+
+async fn drive_build(turtle: &mut ServerTurtle, event: BuildEvent, clients: &mut ClientMap) {
+    let updates = turtle.drive_build(event).await;
+    broadcast_build_updates(turtle, updates, clients).await;
+}
+
+async fn drive_job(turtle: &mut ServerTurtle, event: JobEvent, clients: &mut ClientMap) {
+    let updates = turtle.drive_job(event).await;
+    broadcast_job_updates(turtle, updates, clients).await;
+}
+
+/// Lets the turtles of `world` other than `except` know somebody moved, so the ones that wait
+/// can get going
+async fn others_moved(
+    turtles: &mut TurtleMap,
+    clients: &mut ClientMap,
+    world: &str,
+    except: Option<i32>,
+) {
+    for t in turtles
+        .iter_mut()
+        .filter(|t| t.world == world && Some(t.get_instance_id()) != except)
+    {
+        let walking = t.go_to.is_some();
+        drive_go_to(t, GoToEvent::OthersMoved, clients).await;
+        if !walking {
+            drive_job(t, JobEvent::OthersMoved, clients).await;
+        }
+    }
+}
+
+/// Stops the build or job of `turtle`, before it gets told to do something else. A task it was
+/// working on goes back into the queue
+async fn stop_work(turtle: &mut ServerTurtle, clients: &mut ClientMap) {
+    turtle.release_task(false).await;
+    if let Some(status) = turtle.stop_build() {
+        clients
+            .broadcast(build_update(turtle, BuildUpdate::Build(status)))
+            .await;
+    }
+    if let Some(status) = turtle.stop_job() {
+        clients
+            .broadcast(job_update(turtle, JobUpdate::Job(status)))
+            .await;
+    }
+}
+
+/// Stops every turtle of `world` that builds `blueprint`
+async fn stop_builds(
+    turtles: &mut TurtleMap,
+    clients: &Mutex<ClientMap>,
+    world: &str,
+    blueprint: &str,
+) {
+    let mut clients = clients.lock().await;
+    for t in turtles.iter_mut().filter(|t| t.world == world) {
+        if t.building() != Some(blueprint) {
+            continue;
+        }
+        if let Some(status) = t.stop_build() {
+            clients
+                .broadcast(build_update(t, BuildUpdate::Build(status)))
+                .await;
+        }
+    }
+}
+
+/// Picks up the build `turtle` was part of before it disconnected, or the server restarted
+async fn resume_build(db: &DB, turtle: &mut ServerTurtle, clients: &Mutex<ClientMap>) {
+    let (name, turtles) = match load_build_job(db, &turtle.world, turtle.index).await {
+        Ok(Some(job)) => job,
+        Ok(None) => return,
+        Err(err) => {
+            error!("loading the build of turtle {} failed: {err}", turtle.index);
+            return;
+        }
+    };
+    let shares = match load_blueprint(db, &turtle.world, &name).await {
+        Ok(Some(blueprint)) => plan_shares(db, blueprint, turtles).await,
+        Ok(None) => return,
+        Err(err) => Err(err),
+    };
+    let ops = match shares {
+        Ok(mut shares) => shares.remove(&turtle.index).unwrap_or_default(),
+        Err(err) => {
+            error!("planning the build of {name} failed: {err}");
+            return;
+        }
+    };
+    info!("turtle {} resumes building {name}", turtle.index);
+    let updates = turtle.start_build(&name, ops).await;
+    broadcast_build_updates(turtle, updates, &mut *clients.lock().await).await;
+}
+
+/// Picks up the job `turtle` had before it disconnected, or the server restarted. Returns
+/// whether there was one
+async fn resume_job(db: &DB, turtle: &mut ServerTurtle, clients: &Mutex<ClientMap>) -> bool {
+    let (kind, step) = match load_job(db, &turtle.world, turtle.index).await {
+        Ok(Some(job)) => job,
+        Ok(None) => return false,
+        Err(err) => {
+            error!("loading the job of turtle {} failed: {err}", turtle.index);
+            return false;
+        }
+    };
+    info!("turtle {} resumes its job at step {step}", turtle.index);
+    let updates = match turtle.start_job(kind, step).await {
+        Ok(updates) => updates,
+        Err(reason) => {
+            if let Err(err) = delete_job(db, &turtle.world, turtle.index).await {
+                error!("removing the job of turtle {} failed: {err}", turtle.index);
+            }
+            vec![JobUpdate::Job(JobStatus::Failed { reason })]
+        }
+    };
+    broadcast_job_updates(turtle, updates, &mut *clients.lock().await).await;
+    true
+}
+
+/// Tells every client what blueprints `world` has now
+async fn send_blueprints(db: &DB, clients: &Mutex<client_map::ClientMap>, world: String) {
+    match load_blueprints(db, &world).await {
+        Ok(blueprints) => {
+            clients
+                .lock()
+                .await
+                .broadcast(S2CPackets::Blueprints { world, blueprints })
+                .await
+        }
+        Err(err) => error!("loading the blueprints of {world} failed: {err}"),
+    }
+}
+
+/// Tells every client what waypoints `world` has now
+async fn send_waypoints(db: &DB, clients: &Mutex<client_map::ClientMap>, world: String) {
+    match load_waypoints(db, &world).await {
+        Ok(waypoints) => {
+            clients
+                .lock()
+                .await
+                .broadcast(S2CPackets::Waypoints { world, waypoints })
+                .await
+        }
+        Err(err) => error!("loading the waypoints of {world} failed: {err}"),
+    }
+}
+
+/// Looks up where `target` is, or tells the client why it couldn't
+async fn resolve_target(
+    db: &DB,
+    clients: &Mutex<ClientMap>,
+    client_index: &i32,
+    world: &str,
+    target: Target,
+) -> Option<Pos3> {
+    let name = match target {
+        Target::Pos(pos) => return Some(pos),
+        Target::Waypoint(name) => name,
+    };
+    let reason = match load_waypoint(db, world, &name).await {
+        Ok(Some(pos)) => return Some(pos),
+        Ok(None) => format!("{world} has no waypoint called {name}"),
+        Err(err) => {
+            error!("loading waypoint {name} of {world} failed: {err}");
+            "loading the waypoint failed".to_owned()
+        }
+    };
+    clients
+        .lock()
+        .await
+        .send_to(S2CPackets::WaypointError { name, reason }, client_index)
+        .await;
+    None
+}
+
+/// Gives queued tasks of `world` to idle turtles. Clients get the queue if `changed` or any
+/// task was handed out
+async fn dispatch_tasks(
+    db: &DB,
+    turtles: &mut TurtleMap,
+    clients: &mut ClientMap,
+    world: &str,
+    mut changed: bool,
+) {
+    let tasks = match load_tasks(db, world).await {
+        Ok(tasks) => tasks,
+        Err(err) => {
+            error!("loading the tasks of {world} failed: {err}");
+            return;
+        }
+    };
+    for task in tasks.into_iter().filter(|t| t.state == TaskState::Queued) {
+        let start = match task_start(db, world, &task.kind).await {
+            Ok(start) => start,
+            Err(reason) => {
+                if let Err(err) = fail_task(db, task.id, &reason).await {
+                    error!("failing task {} did not work: {err}", task.id);
+                }
+                changed = true;
+                continue;
+            }
+        };
+        let candidates = turtles
+            .iter_mut()
+            .filter(|t| t.world == world && t.is_idle())
+            .map(|t| Candidate {
+                index: t.index,
+                position: t.position,
+                fuel: t.fuel,
+            })
+            .collect::<Vec<_>>();
+        let Some(index) = pick_turtle(start, &candidates) else {
+            continue;
+        };
+        let Some(t) = turtles.get_turtle_mut_id_and_world(index, world) else {
+            continue;
+        };
+        let step = match load_task_step(db, task.id).await {
+            Ok(step) => step,
+            Err(err) => {
+                error!("loading task {} failed: {err}", task.id);
+                continue;
+            }
+        };
+        if let Err(err) = assign_task(db, task.id, index).await {
+            error!("assigning task {} failed: {err}", task.id);
+            continue;
+        }
+        changed = true;
+        match t.start_task(task.id, task.kind, step).await {
+            Ok(updates) => broadcast_job_updates(t, updates, clients).await,
+            Err(reason) => {
+                if let Err(err) = fail_task(db, task.id, &reason).await {
+                    error!("failing task {} did not work: {err}", task.id);
+                }
+            }
+        }
+    }
+    if !changed {
+        return;
+    }
+    match load_tasks(db, world).await {
+        Ok(tasks) => {
+            let world = world.to_owned();
+            clients.broadcast(S2CPackets::Tasks { world, tasks }).await
+        }
+        Err(err) => error!("loading the tasks of {world} failed: {err}"),
+    }
 }
 
 pub async fn main(
@@ -35,11 +417,40 @@ pub async fn main(
     db: Arc<DB>,
 ) -> anyhow::Result<()> {
     let server_turtles = Arc::new(Mutex::new(TurtleMap::new()));
+    let reservations = Arc::new(std::sync::Mutex::new(Reservations::new()));
     let server_clients = Arc::new(Mutex::new(client_map::ClientMap::new()));
     let (turtle_comms_tx, mut turtle_comms_rx) = unbounded::<TurtleCommBus>();
     let (client_comms_tx, mut client_comms_rx) = unbounded::<(i32, ClientComms)>();
     pin_mut!(turtle_comms_tx, client_comms_tx);
 
+    // nobody is working on anything yet
+    if let Err(err) = release_all_tasks(&db).await {
+        error!("putting the tasks back into the queue failed: {err}");
+    }
+    let dispatch_tx = turtle_comms_tx.clone();
+    let local_db = db.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(DISPATCH_INTERVAL);
+        loop {
+            interval.tick().await;
+            let worlds = match worlds_with_queued_tasks(&local_db).await {
+                Ok(worlds) => worlds,
+                Err(err) => {
+                    error!("looking for queued tasks failed: {err}");
+                    continue;
+                }
+            };
+            for world in worlds {
+                if dispatch_tx
+                    .unbounded_send(TurtleCommBus::DispatchTasks(world))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        }
+    });
+
     let local_db = db.clone();
     let local_server_turtles = server_turtles.clone();
     let local_server_clients = server_clients.clone();
@@ -55,7 +466,52 @@ pub async fn main(
                         .execute_the_client(&client_index)
                         .await;
                 }
+                ClientComms::InvalidPacket(err) => {
+                    warn!("invalid packet from client {client_index}: {err}");
+                    local_server_clients
+                        .lock()
+                        .await
+                        .send_to(
+                            S2CPackets::ProtocolError(format!("invalid packet: {err}")),
+                            &client_index,
+                        )
+                        .await;
+                }
                 ClientComms::Packet(packet) => match packet {
+                    C2SPackets::Handshake(HandshakeData {
+                        version,
+                        capabilities,
+                    }) => {
+                        let mut clients = local_server_clients.lock().await;
+                        if version != PROTOCOL_VERSION {
+                            let reason = format!(
+                                "client speaks protocol version {version}, but the server only speaks {PROTOCOL_VERSION}"
+                            );
+                            warn!("{reason}");
+                            clients
+                                .send_to(S2CPackets::ProtocolError(reason), &client_index)
+                                .await;
+                            clients.execute_the_client(&client_index).await;
+                            continue;
+                        }
+                        let capabilities = capabilities
+                            .into_iter()
+                            .filter(|c| SUPPORTED_CAPABILITIES.contains(c))
+                            .collect::<Vec<_>>();
+                        let Some(client) = clients.get_client_mut(&client_index) else {
+                            continue;
+                        };
+                        let binary = capabilities.contains(&Capability::BinaryEncoding);
+                        client
+                            .send_msg(&S2CPackets::Handshake(HandshakeData {
+                                version,
+                                capabilities,
+                            }))
+                            .await;
+                        if binary {
+                            client.set_encoding(Encoding::Binary);
+                        }
+                    }
                     // C2SPackets::BreakBlock { index, world, dir } => {
                     //     if let Some(t) = local_server_turtles
                     //         .lock()
@@ -93,61 +549,74 @@ pub async fn main(
                     //     }
                     // }
                     C2SPackets::RequestTurtles(world) => {
-                        let indexes = local_server_turtles
-                            .lock()
-                            .await
-                            .get_common_turtles()
-                            .into_iter()
-                            .filter(|t| t.world == world)
-                            .map(|t| t.index)
-                            .collect::<Vec<_>>();
-                        use crate::db::DbTurtle;
-                        let w = sqlx::query_as!(
-                            DbTurtle,
-                            "SELECT * FROM turtles WHERE world = ?",
-                            world
-                        )
-                        .fetch_all(&*local_db)
-                        .await;
-                        let turtles = match w {
-                            Ok(t) => t
-                                .into_iter()
-                                .map(Turtle::from)
-                                .map(|mut t| {
-                                    t.is_online = indexes.contains(&t.index);
-                                    t
-                                })
-                                .collect::<Vec<_>>(),
+                        let server_turtles = local_server_turtles.lock().await;
+                        let turtles = world_turtles(&local_db, &server_turtles, &world).await;
+                        let homes = match load_homes(&local_db, &world).await {
+                            Ok(homes) => homes,
                             Err(err) => {
-                                error!("{err}");
+                                error!("loading the homes of {world} failed: {err}");
                                 Vec::new()
                             }
                         };
-                        local_server_clients
-                            .lock()
-                            .await
-                            .broadcast(S2CPackets::SetTurtles(SetTurtlesData { turtles, world }))
+                        let mut clients = local_server_clients.lock().await;
+                        clients
+                            .broadcast(S2CPackets::SetTurtles(SetTurtlesData {
+                                turtles,
+                                world: world.clone(),
+                            }))
                             .await;
+                        for (index, home) in homes {
+                            clients
+                                .broadcast(S2CPackets::TurtleHome(UpdateTurtleData {
+                                    index,
+                                    world: world.clone(),
+                                    data: Some(home),
+                                }))
+                                .await;
+                        }
                     }
                     C2SPackets::RequestWorld(name) => {
-                        use crate::db::DbBlock;
-                        let mut world = World::new(&name);
-                        let block_iter =
-                            sqlx::query_as!(DbBlock, "SELECT * FROM blocks WHERE world = ?", name)
-                                .fetch_all(&*local_db)
-                                .await
-                                .unwrap()
-                                .into_iter()
-                                .map(Block::from);
-                        for block in block_iter {
-                            world.set_block(block);
-                        }
+                        let world = match load_world(&local_db, &name).await {
+                            Ok(w) => w,
+                            Err(err) => {
+                                error!("{err}");
+                                World::new(&name)
+                            }
+                        };
                         local_server_clients
                             .lock()
                             .await
                             .send_to(S2CPackets::SetWorld(world), &client_index)
                             .await;
                     }
+                    C2SPackets::UpdateView { world, center } => {
+                        update_view(
+                            local_db.clone(),
+                            &local_server_clients,
+                            client_index,
+                            ChunkView { world, center },
+                        )
+                        .await;
+                    }
+                    C2SPackets::SetChunkRenderDistance(distance) => {
+                        let view = {
+                            let mut clients = local_server_clients.lock().await;
+                            let Some(client) = clients.get_client_mut(&client_index) else {
+                                continue;
+                            };
+                            client.set_chunk_render_distance(distance);
+                            client.get_view().cloned()
+                        };
+                        if let Some(view) = view {
+                            update_view(
+                                local_db.clone(),
+                                &local_server_clients,
+                                client_index,
+                                view,
+                            )
+                            .await;
+                        }
+                    }
                     C2SPackets::RequestWorlds => {
                         let worlds = match sqlx::query!("SELECT name FROM worlds;")
                             .fetch_all(&*local_db)
@@ -184,7 +653,512 @@ pub async fn main(
                             t.send_ws(S2TPackets::RunLuaCode(code)).await;
                         }
                     }
-                    C2SPackets::StdInForTurtle { index, value } => todo!(),
+                    C2SPackets::TurtleInventory {
+                        index,
+                        world,
+                        action,
+                    } => {
+                        let mut server_turtles = local_server_turtles.lock().await;
+                        let towards = match &action {
+                            InventoryAction::Give { to, .. } => {
+                                let other = server_turtles
+                                    .get_turtle_mut_id_and_world(*to, &world)
+                                    .map(|t| t.position);
+                                server_turtles
+                                    .get_turtle_mut_id_and_world(index, &world)
+                                    .zip(other)
+                                    .and_then(|(t, other)| {
+                                        inventory::towards(t.position, t.orientation, other)
+                                    })
+                                    .map(Some)
+                                    .ok_or(format!("turtle {to} is not next to turtle {index}"))
+                            }
+                            _ => Ok(None),
+                        };
+                        let Some(t) = server_turtles.get_turtle_mut_id_and_world(index, &world)
+                        else {
+                            warn!("turtle {index} in {world} is not online");
+                            continue;
+                        };
+                        match inventory::check(&action).and(towards) {
+                            Ok(towards) => {
+                                t.send_ws(S2TPackets::Inventory { action, towards }).await
+                            }
+                            Err(reason) => {
+                                local_server_clients
+                                    .lock()
+                                    .await
+                                    .broadcast(S2CPackets::InventoryResult(UpdateTurtleData {
+                                        index,
+                                        world,
+                                        data: InventoryStatus::Failed { action, reason },
+                                    }))
+                                    .await;
+                            }
+                        }
+                    }
+                    C2SPackets::StdInForTurtle {
+                        index,
+                        world,
+                        value,
+                    } => {
+                        if let Some(t) = local_server_turtles
+                            .lock()
+                            .await
+                            .get_turtle_mut_id_and_world(index, &world)
+                        {
+                            t.send_ws(S2TPackets::StdIn(value)).await;
+                        }
+                    }
+                    C2SPackets::SubscribeToStdOut { index, world } => {
+                        let mut server_turtles = local_server_turtles.lock().await;
+                        let scrollback = server_turtles
+                            .get_turtle_mut_id_and_world(index, &world)
+                            .map(|t| t.std_out.text())
+                            .unwrap_or_default();
+                        let mut clients = local_server_clients.lock().await;
+                        let Some(client) = clients.get_client_mut(&client_index) else {
+                            continue;
+                        };
+                        client.subscribe_to_std_out(world.clone(), index);
+                        client
+                            .send_msg(&S2CPackets::StdOutScrollback(UpdateTurtleData {
+                                index,
+                                world,
+                                data: scrollback,
+                            }))
+                            .await;
+                    }
+                    C2SPackets::UnsubscribeFromStdOut { index, world } => {
+                        if let Some(client) = local_server_clients
+                            .lock()
+                            .await
+                            .get_client_mut(&client_index)
+                        {
+                            client.unsubscribe_from_std_out(world, index);
+                        }
+                    }
+                    C2SPackets::RequestExecutables {
+                        index,
+                        world,
+                        refresh,
+                    } => {
+                        let mut server_turtles = local_server_turtles.lock().await;
+                        let Some(t) = server_turtles.get_turtle_mut_id_and_world(index, &world)
+                        else {
+                            continue;
+                        };
+                        match t.executables.clone() {
+                            Some(executables) if !refresh => {
+                                local_server_clients
+                                    .lock()
+                                    .await
+                                    .send_to(
+                                        S2CPackets::TurtleExecutables(UpdateTurtleData {
+                                            index,
+                                            world,
+                                            data: executables,
+                                        }),
+                                        &client_index,
+                                    )
+                                    .await;
+                            }
+                            // the answer gets broadcast once the turtle sends it
+                            _ => t.send_ws(S2TPackets::GetExecutables).await,
+                        }
+                    }
+                    C2SPackets::RunProgram {
+                        index,
+                        world,
+                        name,
+                        args,
+                    } => {
+                        if let Some(t) = local_server_turtles
+                            .lock()
+                            .await
+                            .get_turtle_mut_id_and_world(index, &world)
+                        {
+                            t.send_ws(S2TPackets::RunProgram { name, args }).await;
+                        }
+                    }
+                    C2SPackets::SaveFileToTurtle {
+                        index,
+                        world,
+                        path,
+                        contents,
+                    } => {
+                        if let Some(t) = local_server_turtles
+                            .lock()
+                            .await
+                            .get_turtle_mut_id_and_world(index, &world)
+                        {
+                            t.send_ws(S2TPackets::SaveFile { path, contents }).await;
+                        }
+                    }
+                    C2SPackets::GoTo {
+                        index,
+                        world,
+                        target,
+                    } => {
+                        let Some(target) = resolve_target(
+                            &local_db,
+                            &local_server_clients,
+                            &client_index,
+                            &world,
+                            target,
+                        )
+                        .await
+                        else {
+                            continue;
+                        };
+                        let mut server_turtles = local_server_turtles.lock().await;
+                        let Some(t) = server_turtles.get_turtle_mut_id_and_world(index, &world)
+                        else {
+                            warn!("go to for turtle {index} in {world}, which is not online");
+                            continue;
+                        };
+                        let mut clients = local_server_clients.lock().await;
+                        stop_work(t, &mut clients).await;
+                        let updates = t.start_go_to(target).await;
+                        for status in updates {
+                            clients.broadcast(go_to_update(t, status)).await;
+                        }
+                    }
+                    C2SPackets::SearchBlocks { world, pattern } => {
+                        let (blocks, truncated) =
+                            match search_blocks(&local_db, &world, &pattern).await {
+                                Ok(o) => o,
+                                Err(err) => {
+                                    error!("block search for {pattern:?} failed: {err}");
+                                    (Vec::new(), false)
+                                }
+                            };
+                        local_server_clients
+                            .lock()
+                            .await
+                            .send_to(
+                                S2CPackets::BlockSearchResults {
+                                    world,
+                                    pattern,
+                                    blocks,
+                                    truncated,
+                                },
+                                &client_index,
+                            )
+                            .await;
+                    }
+                    C2SPackets::ImportBlueprint {
+                        world,
+                        name,
+                        anchor,
+                        file_name,
+                        data,
+                    } => {
+                        let imported = match ImportFormat::from_file_name(&file_name) {
+                            Ok(format) => {
+                                import_to_db(&local_db, data, format, &name, &world, anchor).await
+                            }
+                            Err(err) => Err(err),
+                        };
+                        match imported {
+                            Ok(blueprint) => {
+                                info!(
+                                    "imported {file_name} as blueprint {name} in {world}, {} blocks",
+                                    blueprint.planned()
+                                );
+                                send_blueprints(&local_db, &local_server_clients, world).await;
+                            }
+                            Err(err) => {
+                                warn!("importing {file_name} as {name} failed: {err}");
+                                let reason = err.to_string();
+                                local_server_clients
+                                    .lock()
+                                    .await
+                                    .send_to(
+                                        S2CPackets::BlueprintError { name, reason },
+                                        &client_index,
+                                    )
+                                    .await;
+                            }
+                        }
+                    }
+                    C2SPackets::RequestBlueprints(world) => {
+                        let blueprints = match load_blueprints(&local_db, &world).await {
+                            Ok(b) => b,
+                            Err(err) => {
+                                error!("loading the blueprints of {world} failed: {err}");
+                                continue;
+                            }
+                        };
+                        local_server_clients
+                            .lock()
+                            .await
+                            .send_to(S2CPackets::Blueprints { world, blueprints }, &client_index)
+                            .await;
+                    }
+                    C2SPackets::RemoveBlueprint { world, name } => {
+                        if let Err(err) = delete_blueprint(&local_db, &world, &name).await {
+                            error!("removing blueprint {name} of {world} failed: {err}");
+                            continue;
+                        }
+                        let mut server_turtles = local_server_turtles.lock().await;
+                        stop_builds(&mut server_turtles, &local_server_clients, &world, &name)
+                            .await;
+                        drop(server_turtles);
+                        send_blueprints(&local_db, &local_server_clients, world).await;
+                    }
+                    C2SPackets::StartBuild {
+                        world,
+                        blueprint,
+                        turtles,
+                    } => {
+                        let plan = match load_blueprint(&local_db, &world, &blueprint).await {
+                            Ok(Some(plan)) => plan,
+                            Ok(None) => {
+                                warn!("build of {blueprint} in {world}, which doesn't exist");
+                                continue;
+                            }
+                            Err(err) => {
+                                error!("loading blueprint {blueprint} failed: {err}");
+                                continue;
+                            }
+                        };
+                        // only builds that could be planned get stored, turtles that connect
+                        // later would plan a broken one again and again
+                        let mut shares = match plan_shares(&local_db, plan, turtles.clone()).await {
+                            Ok(shares) => shares,
+                            Err(err) => {
+                                error!("planning the build of {blueprint} failed: {err}");
+                                continue;
+                            }
+                        };
+                        if let Err(err) =
+                            save_build_job(&local_db, &world, &blueprint, &turtles).await
+                        {
+                            error!("storing the build of {blueprint} failed: {err}");
+                            continue;
+                        }
+                        let mut server_turtles = local_server_turtles.lock().await;
+                        stop_builds(
+                            &mut server_turtles,
+                            &local_server_clients,
+                            &world,
+                            &blueprint,
+                        )
+                        .await;
+                        let mut clients = local_server_clients.lock().await;
+                        for t in server_turtles.iter_mut().filter(|t| t.world == world) {
+                            // offline ones start once they connect
+                            if let Some(ops) = shares.remove(&t.index) {
+                                if let Some(status) = t.stop_job() {
+                                    clients
+                                        .broadcast(job_update(t, JobUpdate::Job(status)))
+                                        .await;
+                                }
+                                let updates = t.start_build(&blueprint, ops).await;
+                                broadcast_build_updates(t, updates, &mut clients).await;
+                            }
+                        }
+                    }
+                    C2SPackets::StopBuild { world, blueprint } => {
+                        if let Err(err) = delete_build_job(&local_db, &world, &blueprint).await {
+                            error!("removing the build of {blueprint} failed: {err}");
+                        }
+                        let mut server_turtles = local_server_turtles.lock().await;
+                        stop_builds(
+                            &mut server_turtles,
+                            &local_server_clients,
+                            &world,
+                            &blueprint,
+                        )
+                        .await;
+                    }
+                    C2SPackets::StartJob {
+                        index,
+                        world,
+                        kind,
+                        at,
+                    } => {
+                        let kind = match at {
+                            Some(name) => match resolve_target(
+                                &local_db,
+                                &local_server_clients,
+                                &client_index,
+                                &world,
+                                Target::Waypoint(name),
+                            )
+                            .await
+                            {
+                                Some(start) => kind.moved_to(start),
+                                None => continue,
+                            },
+                            None => kind,
+                        };
+                        let mut server_turtles = local_server_turtles.lock().await;
+                        let Some(t) = server_turtles.get_turtle_mut_id_and_world(index, &world)
+                        else {
+                            warn!("job for turtle {index} in {world}, which is not online");
+                            continue;
+                        };
+                        let mut clients = local_server_clients.lock().await;
+                        stop_work(t, &mut clients).await;
+                        if let Err(err) = save_job(&local_db, &world, index, &kind).await {
+                            error!("storing the job of turtle {index} failed: {err}");
+                            continue;
+                        }
+                        let updates = match t.start_job(kind, 0).await {
+                            Ok(updates) => updates,
+                            Err(reason) => {
+                                if let Err(err) = delete_job(&local_db, &world, index).await {
+                                    error!("removing the job of turtle {index} failed: {err}");
+                                }
+                                vec![JobUpdate::Job(JobStatus::Failed { reason })]
+                            }
+                        };
+                        broadcast_job_updates(t, updates, &mut clients).await;
+                    }
+                    C2SPackets::SetHome { index, world, home } => {
+                        if let Err(err) = save_home(&local_db, &world, index, home).await {
+                            error!("storing the home of turtle {index} failed: {err}");
+                            continue;
+                        }
+                        let mut server_turtles = local_server_turtles.lock().await;
+                        if let Some(t) = server_turtles.get_turtle_mut_id_and_world(index, &world) {
+                            t.home = home;
+                        }
+                        local_server_clients
+                            .lock()
+                            .await
+                            .broadcast(S2CPackets::TurtleHome(UpdateTurtleData {
+                                index,
+                                world,
+                                data: home,
+                            }))
+                            .await;
+                    }
+                    C2SPackets::SaveWaypoint { world, waypoint } => {
+                        if waypoint.name.trim().is_empty() {
+                            let reason = "waypoints need a name".to_owned();
+                            local_server_clients
+                                .lock()
+                                .await
+                                .send_to(
+                                    S2CPackets::WaypointError {
+                                        name: waypoint.name,
+                                        reason,
+                                    },
+                                    &client_index,
+                                )
+                                .await;
+                            continue;
+                        }
+                        if let Err(err) = save_waypoint(&local_db, &world, &waypoint).await {
+                            error!(
+                                "storing waypoint {} of {world} failed: {err}",
+                                waypoint.name
+                            );
+                            continue;
+                        }
+                        send_waypoints(&local_db, &local_server_clients, world).await;
+                    }
+                    C2SPackets::RemoveWaypoint { world, name } => {
+                        if let Err(err) = delete_waypoint(&local_db, &world, &name).await {
+                            error!("removing waypoint {name} of {world} failed: {err}");
+                            continue;
+                        }
+                        send_waypoints(&local_db, &local_server_clients, world).await;
+                    }
+                    C2SPackets::RequestWaypoints(world) => {
+                        let waypoints = match load_waypoints(&local_db, &world).await {
+                            Ok(w) => w,
+                            Err(err) => {
+                                error!("loading the waypoints of {world} failed: {err}");
+                                continue;
+                            }
+                        };
+                        local_server_clients
+                            .lock()
+                            .await
+                            .send_to(S2CPackets::Waypoints { world, waypoints }, &client_index)
+                            .await;
+                    }
+                    C2SPackets::StopJob { index, world } => {
+                        if let Err(err) = delete_job(&local_db, &world, index).await {
+                            error!("removing the job of turtle {index} failed: {err}");
+                        }
+                        let mut server_turtles = local_server_turtles.lock().await;
+                        let Some(t) = server_turtles.get_turtle_mut_id_and_world(index, &world)
+                        else {
+                            continue;
+                        };
+                        if let Some(status) = t.stop_job() {
+                            local_server_clients
+                                .lock()
+                                .await
+                                .broadcast(job_update(t, JobUpdate::Job(status)))
+                                .await;
+                        }
+                        t.end_task(Err("stopped by a client".into())).await;
+                    }
+                    C2SPackets::QueueTask {
+                        world,
+                        kind,
+                        priority,
+                    } => {
+                        if let Err(err) = add_task(&local_db, &world, &kind, priority).await {
+                            error!("queueing a task in {world} failed: {err}");
+                            continue;
+                        }
+                        let mut server_turtles = local_server_turtles.lock().await;
+                        let mut clients = local_server_clients.lock().await;
+                        dispatch_tasks(&local_db, &mut server_turtles, &mut clients, &world, true)
+                            .await;
+                    }
+                    C2SPackets::CancelTask { world, id } => {
+                        if let Err(err) = delete_task(&local_db, &world, id).await {
+                            error!("removing task {id} failed: {err}");
+                            continue;
+                        }
+                        let mut server_turtles = local_server_turtles.lock().await;
+                        let mut clients = local_server_clients.lock().await;
+                        let worker = server_turtles.iter_mut().find(|t| {
+                            t.world == world && t.task.as_ref().is_some_and(|task| task.id == id)
+                        });
+                        if let Some(t) = worker {
+                            if let Some(task) = t.task.take() {
+                                if let TaskKind::Job(_) = task.kind {
+                                    if let Err(err) = delete_job(&local_db, &world, t.index).await {
+                                        error!(
+                                            "removing the job of turtle {} failed: {err}",
+                                            t.index
+                                        );
+                                    }
+                                }
+                            }
+                            t.stop_go_to();
+                            if let Some(status) = t.stop_job() {
+                                clients
+                                    .broadcast(job_update(t, JobUpdate::Job(status)))
+                                    .await;
+                            }
+                        }
+                        dispatch_tasks(&local_db, &mut server_turtles, &mut clients, &world, true)
+                            .await;
+                    }
+                    C2SPackets::RequestTasks(world) => {
+                        let tasks = match load_tasks(&local_db, &world).await {
+                            Ok(t) => t,
+                            Err(err) => {
+                                error!("loading the tasks of {world} failed: {err}");
+                                continue;
+                            }
+                        };
+                        local_server_clients
+                            .lock()
+                            .await
+                            .send_to(S2CPackets::Tasks { world, tasks }, &client_index)
+                            .await;
+                    }
                 },
             }
         }
@@ -199,46 +1173,40 @@ pub async fn main(
                 TurtleCommBus::RemoveMe(index) => {
                     info!("/kill @e[type=trutle,id={}] ", &index);
                     let mut server_turtles = local_server_turtles.lock().await;
-                    let world = server_turtles.drop_turtle(&index).map(|t| t.world.clone());
+                    let world = match server_turtles.drop_turtle(&index) {
+                        Some(mut t) => {
+                            t.release_task(true).await;
+                            Some(t.world.clone())
+                        }
+                        None => None,
+                    };
                     if let Some(world) = world {
-                        let indexes = local_server_turtles
-                            .lock()
-                            .await
-                            .get_common_turtles()
-                            .into_iter()
-                            .filter(|t| t.world == world)
-                            .map(|t| t.index)
-                            .collect::<Vec<_>>();
-                        use crate::db::DbTurtle;
-                        let w = sqlx::query_as!(
-                            DbTurtle,
-                            "SELECT * FROM turtles WHERE world = ?",
-                            world
-                        )
-                        .fetch_all(&*local_db)
-                        .await;
-                        let turtles = match w {
-                            Ok(t) => t
-                                .into_iter()
-                                .map(Turtle::from)
-                                .map(|mut t| {
-                                    t.is_online = indexes.contains(&t.index);
-                                    t
-                                })
-                                .collect::<Vec<_>>(),
-                            Err(err) => {
-                                error!("{err}");
-                                Vec::new()
-                            }
-                        };
-                        local_server_clients
-                            .lock()
-                            .await
+                        let turtles = world_turtles(&local_db, &server_turtles, &world).await;
+                        let mut clients = local_server_clients.lock().await;
+                        others_moved(&mut server_turtles, &mut clients, &world, None).await;
+                        clients
                             .broadcast(S2CPackets::SetTurtles(SetTurtlesData { turtles, world }))
                             .await;
                     }
                 }
 
+                TurtleCommBus::TasksChanged(world) => {
+                    let mut server_turtles = local_server_turtles.lock().await;
+                    let mut clients = local_server_clients.lock().await;
+                    dispatch_tasks(&local_db, &mut server_turtles, &mut clients, &world, true)
+                        .await;
+                }
+                TurtleCommBus::DispatchTasks(world) => {
+                    let mut server_turtles = local_server_turtles.lock().await;
+                    let mut clients = local_server_clients.lock().await;
+                    dispatch_tasks(&local_db, &mut server_turtles, &mut clients, &world, false)
+                        .await;
+                }
+                TurtleCommBus::Waiting(world) => {
+                    let mut server_turtles = local_server_turtles.lock().await;
+                    let mut clients = local_server_clients.lock().await;
+                    others_moved(&mut server_turtles, &mut clients, &world, None).await;
+                }
                 TurtleCommBus::Packet((i, p)) => {
                     match local_server_turtles
                         .lock()
@@ -253,48 +1221,83 @@ pub async fn main(
                     }
                 }
                 TurtleCommBus::Moved(index) => {
-                    let sts = local_server_turtles.lock().await;
-                    let t = sts.get_turtle(index).unwrap();
+                    let mut sts = local_server_turtles.lock().await;
+                    let Some(t) = sts.get_turtle_mut(index) else {
+                        continue;
+                    };
                     let msg = MovedTurtleData {
                         index: t.index,
                         new_orientation: t.orientation,
                         new_pos: t.position,
                         world: t.world.clone(),
                     };
-                    local_server_clients
-                        .lock()
-                        .await
-                        .broadcast(S2CPackets::MovedTurtle(msg))
-                        .await;
+                    let mut clients = local_server_clients.lock().await;
+                    clients.broadcast(S2CPackets::MovedTurtle(msg)).await;
+                    // moves of a go to are none of the job's business, it hears when it ends
+                    let walking = t.go_to.is_some();
+                    drive_go_to(t, GoToEvent::Moved, &mut clients).await;
+                    if !walking {
+                        drive_job(t, JobEvent::Moved, &mut clients).await;
+                    }
+                    let world = t.world.clone();
+                    others_moved(&mut sts, &mut clients, &world, Some(index)).await;
+                }
+                TurtleCommBus::MoveFailed(index) => {
+                    let mut sts = local_server_turtles.lock().await;
+                    let Some(t) = sts.get_turtle_mut(index) else {
+                        continue;
+                    };
+                    let mut clients = local_server_clients.lock().await;
+                    let walking = t.go_to.is_some();
+                    drive_go_to(t, GoToEvent::MoveFailed, &mut clients).await;
+                    if !walking {
+                        drive_job(t, JobEvent::MoveFailed, &mut clients).await;
+                    }
+                }
+                TurtleCommBus::PlaceFailed(index) => {
+                    let mut sts = local_server_turtles.lock().await;
+                    let Some(t) = sts.get_turtle_mut(index) else {
+                        continue;
+                    };
+                    let mut clients = local_server_clients.lock().await;
+                    drive_build(t, BuildEvent::PlaceFailed, &mut clients).await;
+                }
+                TurtleCommBus::DigFailed(index, direction) => {
+                    let mut sts = local_server_turtles.lock().await;
+                    let Some(t) = sts.get_turtle_mut(index) else {
+                        continue;
+                    };
+                    let mut clients = local_server_clients.lock().await;
+                    drive_job(t, JobEvent::DigFailed(direction), &mut clients).await;
                 }
                 TurtleCommBus::UpdateBlock(block) => {
-                    // let _ = local_db.set_block(&block).await;
-                    let chunk_key = pos_to_key(&get_chunk_containing_block(&block.pos));
-                    let db_pos = pos_to_db_pos(&block.pos);
-                    let _ = sqlx::query!(
-                        "INSERT OR REPLACE INTO blocks VALUES (?,?,?,?,?);",
-                        chunk_key,
-                        block.id,
-                        block.world,
-                        db_pos,
-                        block.is_air,
-                    )
-                    .execute(&*local_db)
-                    .await;
-                    local_server_clients
-                        .lock()
-                        .await
-                        .broadcast(S2CPackets::WorldUpdate(block))
-                        .await;
+                    if let Err(err) = save_block(&local_db, &block).await {
+                        error!("failed to store block: {err}");
+                    }
+                    // turtles before clients, like everywhere else
+                    let mut sts = local_server_turtles.lock().await;
+                    let mut clients = local_server_clients.lock().await;
+                    for t in sts.iter_mut().filter(|t| t.world == block.world) {
+                        if !block.is_air {
+                            drive_go_to(t, GoToEvent::BlockChanged(block.pos), &mut clients).await;
+                            drive_build(t, BuildEvent::BlockChanged(block.clone()), &mut clients)
+                                .await;
+                        }
+                        drive_job(t, JobEvent::BlockChanged(block.clone()), &mut clients).await;
+                    }
+                    let chunk = get_chunk_containing_block(&block.pos);
+                    for client in clients.iter_mut() {
+                        client.note_block_update(&block.world, chunk);
+                    }
+                    clients.broadcast(S2CPackets::WorldUpdate(block)).await;
                 }
                 TurtleCommBus::InvUpdate(index) => {
-                    let sts = local_server_turtles.lock().await;
-                    let t = sts.get_turtle(index);
+                    let mut sts = local_server_turtles.lock().await;
+                    let t = sts.get_turtle_mut(index);
                     if let Some(t) = t {
+                        let mut clients = local_server_clients.lock().await;
                         if let Maybe::Some(inv) = t.inventory.clone() {
-                            local_server_clients
-                                .lock()
-                                .await
+                            clients
                                 .broadcast(S2CPackets::TurtleInventoryUpdate(
                                     common::client_packets::UpdateTurtleData {
                                         index: t.index,
@@ -304,6 +1307,57 @@ pub async fn main(
                                 ))
                                 .await;
                         }
+                        drive_build(t, BuildEvent::InventoryChanged, &mut clients).await;
+                        drive_job(t, JobEvent::InventoryChanged, &mut clients).await;
+                    }
+                }
+                TurtleCommBus::StdOut(index, text) => {
+                    let sts = local_server_turtles.lock().await;
+                    if let Some(t) = sts.get_turtle(index) {
+                        local_server_clients
+                            .lock()
+                            .await
+                            .send_to_std_out_subscribers(
+                                S2CPackets::StdOutFromTurtle(UpdateTurtleData {
+                                    index: t.index,
+                                    world: t.world.clone(),
+                                    data: text,
+                                }),
+                                &t.world,
+                                t.index,
+                            )
+                            .await;
+                    }
+                }
+                TurtleCommBus::InventoryResult(index, status) => {
+                    let sts = local_server_turtles.lock().await;
+                    if let Some(t) = sts.get_turtle(index) {
+                        local_server_clients
+                            .lock()
+                            .await
+                            .broadcast(S2CPackets::InventoryResult(UpdateTurtleData {
+                                index: t.index,
+                                world: t.world.clone(),
+                                data: status,
+                            }))
+                            .await;
+                    }
+                }
+                TurtleCommBus::Executables(index) => {
+                    let sts = local_server_turtles.lock().await;
+                    if let Some((t, executables)) = sts
+                        .get_turtle(index)
+                        .and_then(|t| Some((t, t.executables.clone()?)))
+                    {
+                        local_server_clients
+                            .lock()
+                            .await
+                            .broadcast(S2CPackets::TurtleExecutables(UpdateTurtleData {
+                                index: t.index,
+                                world: t.world.clone(),
+                                data: executables,
+                            }))
+                            .await;
                     }
                 }
                 TurtleCommBus::FuelUpdate(index) => {
@@ -350,16 +1404,8 @@ pub async fn main(
             let db = local_db.clone();
             let mut server_turtles = local_server_turtles.lock().await;
             info!("new turtle with index: {}", info.index);
-            use crate::db::DbTurtle;
-            let db_turtle = sqlx::query_as!(
-                DbTurtle,
-                "SELECT * FROM turtles WHERE id = ? AND world = ?;",
-                info.index,
-                info.world
-            )
-            .fetch_one(&*local_db)
-            .await;
-            let t = match db_turtle.map(Turtle::from) {
+            let db_turtle = load_turtle(&local_db, &info.world, info.index).await;
+            let t = match db_turtle {
                 Ok(turtle) => turtle,
                 Err(sqlx::Error::RowNotFound) => {
                     let dummy =
@@ -367,8 +1413,11 @@ pub async fn main(
                     let db_pos = pos_to_db_pos(&dummy.position);
                     let orient_str = dummy.orientation.to_string();
                     // TODO: Check if this fails and do something
-                    let _ =sqlx::query!(
-                        "INSERT INTO turtles VALUES (?,?,?,?,?,?,?)",
+                    let _ = sqlx::query!(
+                        "
+                        INSERT INTO turtles (id, name, position, orientation, fuel, max_fuel, world)
+                        VALUES (?,?,?,?,?,?,?)
+                        ",
                         dummy.index,
                         dummy.name,
                         db_pos,
@@ -388,42 +1437,42 @@ pub async fn main(
                 }
             };
 
-            let mut st =
-                ServerTurtle::new(t, send, recv, turtle_comms_tx.clone(), db.clone()).await;
-            st.on_msg_recived(T2SPackets::Batch(data)).await.unwrap();
-            let t: Turtle = st.to_owned();
+            let mut st = ServerTurtle::new(
+                t,
+                send,
+                recv,
+                turtle_comms_tx.clone(),
+                db.clone(),
+                reservations.clone(),
+            )
+            .await;
+            if let Err(err) = st.on_msg_recived(T2SPackets::Batch(data)).await {
+                error!("Trutle Setup Err: {err}");
+            }
+            let world = st.world.clone();
+            let instance_id = st.get_instance_id();
             server_turtles.push(st);
-            let world = t.world;
-            let indexes = local_server_turtles
-                .lock()
-                .await
-                .get_common_turtles()
-                .into_iter()
-                .filter(|t| t.world == world)
-                .map(|t| t.index)
-                .collect::<Vec<_>>();
-            let w = sqlx::query_as!(DbTurtle, "SELECT * FROM turtles WHERE world = ?", world)
-                .fetch_all(&*local_db)
-                .await;
-            let turtles = match w {
-                Ok(t) => t
-                    .into_iter()
-                    .map(Turtle::from)
-                    .map(|mut t| {
-                        t.is_online = indexes.contains(&t.index);
-                        t
-                    })
-                    .collect::<Vec<_>>(),
-                Err(err) => {
-                    error!("{err}");
-                    Vec::new()
-                }
-            };
+            // still holding the turtle map, so clients get this before any packet of the new turtle
+            let turtles = world_turtles(&local_db, &server_turtles, &world).await;
             local_server_clients
                 .lock()
                 .await
                 .broadcast(S2CPackets::SetTurtles(SetTurtlesData { turtles, world }))
                 .await;
+            if let Some(t) = server_turtles.get_turtle_mut(instance_id) {
+                match load_homes(&local_db, &t.world).await {
+                    Ok(homes) => {
+                        t.home = homes.iter().find(|(i, _)| *i == t.index).map(|(_, h)| *h)
+                    }
+                    Err(err) => error!("loading the home of turtle {} failed: {err}", t.index),
+                }
+                if !resume_job(&local_db, t, &local_server_clients).await {
+                    resume_build(&local_db, t, &local_server_clients).await;
+                }
+                let world = t.world.clone();
+                let mut clients = local_server_clients.lock().await;
+                dispatch_tasks(&local_db, &mut server_turtles, &mut clients, &world, false).await;
+            }
         }
     });
     //
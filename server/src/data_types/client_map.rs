@@ -21,6 +21,22 @@ impl ClientMap {
             c.send_msg(&msg).await;
         }
     }
+    /// Sends `msg` to every client that subscribed to the output of that turtle
+    pub async fn send_to_std_out_subscribers(&mut self, msg: S2CPackets, world: &str, index: i32) {
+        for c in self
+            .0
+            .values_mut()
+            .filter(|c| c.is_subscribed_to_std_out(world, index))
+        {
+            c.send_msg(&msg).await;
+        }
+    }
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut ServerClient> {
+        self.0.values_mut()
+    }
+    pub fn get_client_mut(&mut self, id: &i32) -> Option<&mut ServerClient> {
+        self.0.get_mut(id)
+    }
     pub async fn send_to(&mut self, msg: S2CPackets, id: &i32) -> Option<()> {
         self.0.get_mut(id)?.send_msg(&msg).await;
         Some(())
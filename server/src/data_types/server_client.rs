@@ -1,4 +1,9 @@
-use common::client_packets::{C2SPackets, S2CPackets};
+use std::collections::{HashMap, HashSet};
+
+use common::{
+    client_packets::{C2SPackets, S2CPackets},
+    wire, Pos3,
+};
 
 use futures::StreamExt;
 use futures_channel::mpsc::UnboundedSender;
@@ -11,20 +16,37 @@ use tokio::task::JoinHandle;
 use tungstenite::Message;
 
 use super::server_turtle::{WsRecv, WsSend};
+use crate::chunk_streaming::{chunks_in_range, ChunkView, MAX_RENDER_DISTANCE};
 
 #[derive(Clone, Debug)]
 pub enum ClientComms {
     Packet(C2SPackets),
+    /// The client sent something that isn't a packet we know
+    InvalidPacket(String),
     #[allow(non_camel_case_types)]
     KILL_ME,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    /// [`wire`], once the client asked for it in the handshake
+    Binary,
+}
+
 pub struct ServerClient {
     ws_send: WsSend,
     msg_send: UnboundedSender<(i32, ClientComms)>,
     ws_read_handle: Option<JoinHandle<()>>,
     index: i32,
     chunk_render_distance: u32,
+    /// (world, index) of every turtle whose output this client wants
+    std_out_subscriptions: HashSet<(String, i32)>,
+    encoding: Encoding,
+    view: Option<ChunkView>,
+    /// Chunks of the viewed world that were streamed, and whether the client got blocks for them
+    loaded_chunks: HashMap<Pos3, bool>,
+    chunk_stream: Option<JoinHandle<()>>,
 }
 
 impl ServerClient {
@@ -40,6 +62,11 @@ impl ServerClient {
             index: random(),
             ws_read_handle: None,
             chunk_render_distance: 8,
+            std_out_subscriptions: HashSet::new(),
+            encoding: Encoding::Json,
+            view: None,
+            loaded_chunks: HashMap::new(),
+            chunk_stream: None,
         };
         s.init(ws_recv);
         s
@@ -54,10 +81,20 @@ impl ServerClient {
                     Err(_) | Ok(Message::Close(_)) => {
                         _ = send.send((index, ClientComms::KILL_ME)).await;
                     }
+                    // whatever was negotiated, the frame type says how it's encoded
                     Ok(Message::Text(msg)) => {
-                        if let Ok(msg) = serde_json::from_str::<C2SPackets>(&msg) {
-                            _ = send.send((index, ClientComms::Packet(msg))).await
-                        }
+                        let comms = match serde_json::from_str::<C2SPackets>(&msg) {
+                            Ok(msg) => ClientComms::Packet(msg),
+                            Err(err) => ClientComms::InvalidPacket(err.to_string()),
+                        };
+                        _ = send.send((index, comms)).await
+                    }
+                    Ok(Message::Binary(msg)) => {
+                        let comms = match wire::from_bytes::<C2SPackets>(&msg) {
+                            Ok(msg) => ClientComms::Packet(msg),
+                            Err(err) => ClientComms::InvalidPacket(err.to_string()),
+                        };
+                        _ = send.send((index, comms)).await
                     }
                     _ => {}
                 }
@@ -65,8 +102,11 @@ impl ServerClient {
         });
     }
     pub async fn send_msg(&mut self, msg: &S2CPackets) {
-        let text = serde_json::to_string(msg).unwrap();
-        if let Err(err) = self.ws_send.send(Message::Text(text)).await {
+        let msg = match self.encoding {
+            Encoding::Json => Message::Text(serde_json::to_string(msg).unwrap()),
+            Encoding::Binary => Message::Binary(wire::to_bytes(msg).unwrap()),
+        };
+        if let Err(err) = self.ws_send.send(msg).await {
             error!("Error When sending Shit to Client: {}", err);
             _ = self.msg_send.send((self.get_index(), ClientComms::KILL_ME));
         }
@@ -74,11 +114,79 @@ impl ServerClient {
     pub fn get_index(&self) -> i32 {
         self.index
     }
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+    pub fn get_view(&self) -> Option<&ChunkView> {
+        self.view.as_ref()
+    }
+    pub fn set_chunk_render_distance(&mut self, distance: u32) {
+        self.chunk_render_distance = distance.min(MAX_RENDER_DISTANCE);
+    }
+    /// Switches to `view`, returns the chunks the client should drop and the ones it still needs,
+    /// closest first
+    pub fn set_view(&mut self, view: ChunkView) -> (Vec<Pos3>, Vec<Pos3>) {
+        if self.view.as_ref().is_some_and(|v| v.world != view.world) {
+            self.loaded_chunks.clear();
+        }
+        let wanted = chunks_in_range(view.center, self.chunk_render_distance);
+        let wanted_set = wanted.iter().copied().collect::<HashSet<_>>();
+        let mut unload = Vec::new();
+        self.loaded_chunks.retain(|chunk, sent| {
+            let keep = wanted_set.contains(chunk);
+            if !keep && *sent {
+                unload.push(*chunk);
+            }
+            keep
+        });
+        let load = wanted
+            .into_iter()
+            .filter(|c| !self.loaded_chunks.contains_key(c))
+            .collect();
+        self.view = Some(view);
+        (unload, load)
+    }
+    /// Remembers that `chunk` of `world` was streamed, false if the client isn't looking at that
+    /// world anymore
+    pub fn mark_chunk_loaded(&mut self, world: &str, chunk: Pos3, sent: bool) -> bool {
+        if self.view.as_ref().is_some_and(|v| v.world == world) {
+            self.loaded_chunks.insert(chunk, sent);
+            return true;
+        }
+        false
+    }
+    /// A block in `chunk` of `world` was broadcast, so the client has to drop that chunk later
+    pub fn note_block_update(&mut self, world: &str, chunk: Pos3) {
+        if self.view.as_ref().is_some_and(|v| v.world == world) {
+            if let Some(sent) = self.loaded_chunks.get_mut(&chunk) {
+                *sent = true;
+            }
+        }
+    }
+    /// Replaces the task sending chunks to this client
+    pub fn set_chunk_stream(&mut self, stream: JoinHandle<()>) {
+        if let Some(old) = self.chunk_stream.replace(stream) {
+            old.abort();
+        }
+    }
+    pub fn subscribe_to_std_out(&mut self, world: String, index: i32) {
+        self.std_out_subscriptions.insert((world, index));
+    }
+    pub fn unsubscribe_from_std_out(&mut self, world: String, index: i32) {
+        self.std_out_subscriptions.remove(&(world, index));
+    }
+    pub fn is_subscribed_to_std_out(&self, world: &str, index: i32) -> bool {
+        self.std_out_subscriptions
+            .contains(&(world.to_owned(), index))
+    }
     pub async fn delete(mut self) {
         _ = self.msg_send.close().await;
         _ = self.ws_send.close().await;
         if let Some(h) = self.ws_read_handle {
             h.abort();
         }
+        if let Some(h) = self.chunk_stream {
+            h.abort();
+        }
     }
 }
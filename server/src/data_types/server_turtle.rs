@@ -1,15 +1,18 @@
 use std::{
     ops::{Deref, DerefMut},
-    sync::Arc,
+    sync::{Arc, MutexGuard},
 };
 
 use common::{
-    turtle::{MoveDirection, TurnDir, Turtle},
+    client_packets::InventoryStatus,
+    std_out::Scrollback,
+    turtle::{Maybe, MoveDirection, Turtle, TurtleInventory},
     turtle_packets::{S2TPackets, SetupInfoData, T2SPackets},
-    world_data::Block,
+    world_data::{Block, Observation},
     Pos3,
 };
 
+use chrono::Utc;
 use futures_channel::mpsc::UnboundedSender;
 use futures_util::{
     stream::{SplitSink, SplitStream},
@@ -19,25 +22,42 @@ use log::error;
 #[allow(unused_imports)]
 use log::info;
 use rand::random;
-use serde_json::{from_str, to_string_pretty};
+use serde_json::{from_str, to_string};
 use tokio::{net::TcpStream, task::JoinHandle};
 use tokio_tungstenite::WebSocketStream;
 use tungstenite::Message;
 
 use crate::{
+    build::Build,
     connection_manager::TurtleCommBus,
-    db::{pos_to_db_pos, DB},
+    db::{pos_to_db_pos, save_inventory, DB},
+    go_to::GoTo,
+    jobs::Job,
+    reservations::{Reservations, SharedReservations},
+    tasks::Assignment,
 };
 pub type WsSend = SplitSink<WebSocketStream<TcpStream>, Message>;
 pub type WsRecv = SplitStream<WebSocketStream<TcpStream>>;
 
 pub struct ServerTurtle {
     db: Arc<DB>,
+    reservations: SharedReservations,
     inner: Turtle,
     send: WsSend,
     comm_bus: UnboundedSender<TurtleCommBus>,
     ws_read_task: Option<JoinHandle<()>>,
     instance_id: i32,
+    pub go_to: Option<GoTo>,
+    pub build: Option<Build>,
+    pub job: Option<Job>,
+    /// The queued task it works on, see [`crate::tasks`]
+    pub task: Option<Assignment>,
+    /// Where jobs go to refuel
+    pub home: Option<Pos3>,
+    /// Recent output, so clients that subscribe later still see it
+    pub std_out: Scrollback,
+    /// Last list of programs the turtle reported, `None` until it was asked once
+    pub executables: Option<Vec<String>>,
 }
 impl Deref for ServerTurtle {
     type Target = Turtle;
@@ -56,6 +76,9 @@ impl Drop for ServerTurtle {
         if let Some(w) = &self.ws_read_task {
             w.abort()
         }
+        if let Ok(mut reservations) = self.reservations.lock() {
+            reservations.remove(&self.inner.world, self.inner.index);
+        }
     }
 }
 
@@ -67,39 +90,52 @@ impl ServerTurtle {
         recv: WsRecv,
         comm_bus: UnboundedSender<TurtleCommBus>,
         db: Arc<DB>,
+        reservations: SharedReservations,
     ) -> ServerTurtle {
         let mut turtle = ServerTurtle {
             inner,
             send,
             comm_bus,
             db,
+            reservations,
             ws_read_task: None,
             instance_id: random(),
+            go_to: None,
+            build: None,
+            job: None,
+            task: None,
+            home: None,
+            std_out: Scrollback::default(),
+            executables: None,
         };
         turtle.init(recv).await;
         turtle
+            .reservations()
+            .set_position(&turtle.world, turtle.index, turtle.position);
+        turtle
     }
 
     #[inline(always)]
-    async fn re_packet(&mut self, msg: T2SPackets) -> Result<(), futures_channel::mpsc::SendError> {
-        self.comm(TurtleCommBus::Packet((self.instance_id, msg)))
-            .await
-    }
-    #[inline(always)]
-    async fn comm(&mut self, msg: TurtleCommBus) -> Result<(), futures_channel::mpsc::SendError> {
+    pub(crate) async fn comm(
+        &mut self,
+        msg: TurtleCommBus,
+    ) -> Result<(), futures_channel::mpsc::SendError> {
         self.comm_bus.send(msg).await
     }
 
     pub async fn on_msg_recived(&mut self, msg: T2SPackets) -> anyhow::Result<()> {
         match msg {
             T2SPackets::Ping => {}
+            // handled right away, otherwise packets sent after the batch could overtake it
             T2SPackets::Batch(packets) => {
                 for p in packets {
-                    self.re_packet(p).await?;
+                    Box::pin(self.on_msg_recived(p)).await?;
                 }
             }
             T2SPackets::SetPos(pos) => {
                 self.position = pos;
+                self.reservations()
+                    .set_position(&self.world, self.index, pos);
                 let db_pos = pos_to_db_pos(&self.position);
                 sqlx::query!(
                     "
@@ -110,7 +146,7 @@ impl ServerTurtle {
                     self.index,
                     self.world,
                 )
-                .fetch_one(&*self.db)
+                .execute(&*self.db)
                 .await?;
             }
             T2SPackets::SetMaxFuel(max_fuel) => {
@@ -124,7 +160,7 @@ impl ServerTurtle {
                     self.index,
                     self.world,
                 )
-                .fetch_one(&*self.db)
+                .execute(&*self.db)
                 .await?;
             }
 
@@ -140,24 +176,14 @@ impl ServerTurtle {
                     self.index,
                     self.world,
                 )
-                .fetch_one(&*self.db)
+                .execute(&*self.db)
                 .await?;
             }
             T2SPackets::SetupInfo(SetupInfoData { .. }) => {}
             T2SPackets::InventoryUpdate(inv) => {
-                self.inventory = Some(inv).into();
-                // let inv_str = serde_json::to_string(&self.inventory)?;
-                // sqlx::query!(
-                //     "
-                //     UPDATE turtles SET inventory = ? 
-                //     WHERE id = ? AND world = ?;
-                //     ",
-                //     inv_str,
-                //     self.index,
-                //     self.world
-                // )
-                // .fetch_one(&*self.db)
-                // .await?;
+                let old = std::mem::replace(&mut self.inventory, Some(inv.clone()).into());
+                let old: Option<Box<TurtleInventory>> = old.into();
+                save_inventory(&self.db, &self.world, self.index, old.as_deref(), &inv).await?;
                 self.comm(TurtleCommBus::InvUpdate(self.instance_id))
                     .await?;
             }
@@ -171,8 +197,13 @@ impl ServerTurtle {
                     self.index,
                     self.world,
                 )
-                .fetch_one(&*self.db)
+                .execute(&*self.db)
                 .await?;
+                {
+                    let mut reservations = self.reservations();
+                    reservations.remove(&self.world, self.index);
+                    reservations.set_position(&w_name, self.index, self.position);
+                }
                 self.world = w_name;
             }
             T2SPackets::NameUpdate(name) => {
@@ -186,7 +217,7 @@ impl ServerTurtle {
                     self.index,
                     self.world,
                 )
-                .fetch_one(&*self.db)
+                .execute(&*self.db)
                 .await?;
             }
             T2SPackets::FuelUpdate(fuel) => {
@@ -201,81 +232,116 @@ impl ServerTurtle {
                     self.index,
                     self.world,
                 )
-                .fetch_one(&*self.db)
+                .execute(&*self.db)
                 .await?;
                 self.comm(TurtleCommBus::FuelUpdate(self.instance_id))
                     .await?;
             }
             T2SPackets::Moved { direction } => {
-                let mut p = self.position;
-                let mut o = self.orientation;
-                match direction {
-                    MoveDirection::Forward => {
-                        let forward = self.inner.get_forward_vec();
-                        p += forward;
-                    }
-                    MoveDirection::Back => {
-                        let forward = self.inner.get_forward_vec();
-                        p -= forward;
-                    }
-                    MoveDirection::Up => p += Pos3::new(0, 1, 0),
-                    MoveDirection::Down => p += Pos3::new(0, -1, 0),
-                    MoveDirection::Left => o = self.inner.turn(TurnDir::Left),
-                    MoveDirection::Right => o = self.inner.turn(TurnDir::Right),
-                };
-                self.comm(TurtleCommBus::UpdateBlock(Block::new(
-                    None,
-                    &p,
-                    &self.world,
-                )))
-                .await?;
-                self.re_packet(T2SPackets::SetPos(p)).await?;
-                self.re_packet(T2SPackets::SetOrientation(o)).await?;
+                let (p, o) = direction.apply(self.position, self.orientation);
+                let block = Block::new(None, &p, &self.world).with_observation(self.observation());
+                self.comm(TurtleCommBus::UpdateBlock(block)).await?;
+                Box::pin(self.on_msg_recived(T2SPackets::SetPos(p))).await?;
+                Box::pin(self.on_msg_recived(T2SPackets::SetOrientation(o))).await?;
                 _ = self
                     .comm_bus
                     .send(TurtleCommBus::Moved(self.instance_id))
                     .await;
             }
+            T2SPackets::MoveFailed { direction, reason } => {
+                info!(
+                    "turtle {} failed to move {:?}: {:?}",
+                    self.index, direction, reason
+                );
+                self.comm(TurtleCommBus::MoveFailed(self.instance_id))
+                    .await?;
+            }
+            T2SPackets::PlaceFailed { direction, reason } => {
+                info!(
+                    "turtle {} failed to place {:?}: {:?}",
+                    self.index, direction, reason
+                );
+                self.comm(TurtleCommBus::PlaceFailed(self.instance_id))
+                    .await?;
+            }
+            T2SPackets::DigFailed { direction, reason } => {
+                info!(
+                    "turtle {} failed to dig {:?}: {:?}",
+                    self.index, direction, reason
+                );
+                self.comm(TurtleCommBus::DigFailed(self.instance_id, direction))
+                    .await?;
+            }
             T2SPackets::Blocks { up, down, front } => {
                 // info!("up: {:?}", up);
                 // info!("front: {:?}", front);
                 // info!("down: {:?}", down);
                 use TurtleCommBus::UpdateBlock;
-                self.comm(UpdateBlock(Block::new(
-                    up.into(),
-                    &(self.position + Pos3::new(0, 1, 0)),
-                    &self.world,
-                )))
-                .await?;
-                self.comm(UpdateBlock(Block::new(
-                    front.into(),
-                    &(self.position + self.get_forward_vec()),
-                    &self.world,
-                )))
-                .await?;
-                self.comm(UpdateBlock(Block::new(
-                    down.into(),
-                    &(self.position + Pos3::new(0, -1, 0)),
-                    &self.world,
-                )))
-                .await?;
+                let observation = self.observation();
+                for (block, pos) in [
+                    (up, self.position + Pos3::new(0, 1, 0)),
+                    (front, self.position + self.get_forward_vec()),
+                    (down, self.position + Pos3::new(0, -1, 0)),
+                ] {
+                    let block = Block::inspected(block.into(), &pos, &self.world);
+                    self.comm(UpdateBlock(block.with_observation(observation)))
+                        .await?;
+                }
+            }
+            T2SPackets::Executables(executables) => {
+                self.executables = Some(executables);
+                self.comm(TurtleCommBus::Executables(self.instance_id))
+                    .await?;
+            }
+            T2SPackets::StdOut(text) => {
+                self.std_out.push(&text);
+                self.comm(TurtleCommBus::StdOut(self.instance_id, text))
+                    .await?;
+            }
+            T2SPackets::InventoryResult { action, reason } => {
+                // nobody asked for the drops of a delivery
+                if self.drive_task_drop(&action).await {
+                    return Ok(());
+                }
+                let status = match reason {
+                    Maybe::None => InventoryStatus::Done(action),
+                    Maybe::Some(reason) => InventoryStatus::Failed { action, reason },
+                };
+                self.comm(TurtleCommBus::InventoryResult(self.instance_id, status))
+                    .await?;
             }
-            T2SPackets::Executables(_) => todo!(),
-            T2SPackets::StdOut(_) => todo!(),
         }
 
         Ok(())
     }
     #[allow(dead_code)]
     pub async fn send_ws(&mut self, packet: S2TPackets) {
-        self.send
-            .send(Message::Text(to_string_pretty(&packet).unwrap()))
+        // the turtle can be gone before its RemoveMe got handled
+        if let Err(err) = self
+            .send
+            .send(Message::Text(to_string(&packet).unwrap()))
             .await
-            .unwrap();
+        {
+            error!("sending to turtle {} failed: {err}", self.index);
+        }
     }
     pub fn get_instance_id(&self) -> i32 {
         self.instance_id
     }
+    pub fn get_db(&self) -> Arc<DB> {
+        self.db.clone()
+    }
+    /// Where the turtles are going, see [`crate::reservations`]
+    pub fn reservations(&self) -> MutexGuard<'_, Reservations> {
+        self.reservations.lock().unwrap()
+    }
+    /// Stamp for blocks this turtle just saw
+    fn observation(&self) -> Observation {
+        Observation {
+            at: Utc::now().timestamp().try_into().unwrap_or_default(),
+            by: self.index,
+        }
+    }
 
     pub async fn move_(&mut self, dir: MoveDirection) {
         // self.send_ws(S2TPackets::Move(vec![dir])).await;
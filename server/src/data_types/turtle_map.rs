@@ -27,6 +27,9 @@ impl TurtleMap {
     pub fn get_turtle_mut(&mut self, id: i32) -> Option<&mut ServerTurtle> {
         self.0.get_mut(&id)
     }
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut ServerTurtle> {
+        self.0.values_mut()
+    }
 
     pub fn get_turtle_mut_id_and_world(
         &mut self,
@@ -1,11 +1,20 @@
-use common::turtle::{Maybe, Orientation};
+use chrono::{DateTime, Utc};
+use common::blueprint::Blueprint;
+use common::client_packets::{JobKind, Task, TaskKind, TaskState};
+use common::turtle::{Item, Maybe, Orientation, Turtle, TurtleInventory};
+use common::waypoint::Waypoint;
 
-use common::world_data::Block;
+use common::world_data::{
+    get_chunk_containing_block, Block, BlockState, Chunk, Observation, World,
+};
 use common::Pos3;
 
 use sqlx::SqlitePool;
 
-use std::str::FromStr;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
 
 pub type DB = SqlitePool;
 
@@ -17,6 +26,9 @@ pub(crate) struct DbBlock {
     pub(crate) world_pos: String,
     #[allow(dead_code)]
     pub(crate) chunk_key: i64,
+    pub(crate) observed_at: Option<i64>,
+    pub(crate) observed_by: Option<i64>,
+    pub(crate) state: Option<String>,
 }
 
 impl From<DbBlock> for Block {
@@ -27,10 +39,49 @@ impl From<DbBlock> for Block {
             pos: parse_pos3_from_db_str(&value.world_pos)
                 .expect("DB should really have a valid pos string"),
             is_air: value.is_air,
+            observed: value
+                .observed_at
+                .zip(value.observed_by)
+                .map(|(at, by)| Observation {
+                    at: at.try_into().unwrap_or_default(),
+                    by: by.try_into().unwrap_or_default(),
+                }),
+            state: value
+                .state
+                .and_then(|s| serde_json::from_str(&s).ok())
+                .unwrap_or_default(),
         }
     }
 }
 
+/// Stores `block`, replacing whatever was known about its position
+pub async fn save_block(db: &DB, block: &Block) -> Result<(), sqlx::Error> {
+    let chunk_key = pos_to_key(&get_chunk_containing_block(&block.pos));
+    let db_pos = pos_to_db_pos(&block.pos);
+    let observed_at = block.observed.map(|o| o.at);
+    let observed_by = block.observed.map(|o| o.by);
+    let state = match block.state == BlockState::default() {
+        true => None,
+        false => serde_json::to_string(&block.state).ok(),
+    };
+    sqlx::query!(
+        "INSERT OR REPLACE INTO blocks
+        (chunk_key, id, world, world_pos, is_air, observed_at, observed_by, state)
+        VALUES (?,?,?,?,?,?,?,?);",
+        chunk_key,
+        block.id,
+        block.world,
+        db_pos,
+        block.is_air,
+        observed_at,
+        observed_by,
+        state,
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct DbTurtle {
     pub(crate) id: i64,
@@ -40,6 +91,7 @@ pub(crate) struct DbTurtle {
     pub(crate) fuel: i64,
     pub(crate) max_fuel: i64,
     pub(crate) world: String,
+    pub(crate) selected_slot: Option<i64>,
 }
 
 impl From<DbTurtle> for common::turtle::Turtle {
@@ -50,7 +102,16 @@ impl From<DbTurtle> for common::turtle::Turtle {
                 .try_into()
                 .expect("should fit since everywhere is i32, only db is i64"),
             name: value.name,
-            inventory: Maybe::None,
+            // the slots get filled in by `load_turtles`
+            inventory: value
+                .selected_slot
+                .map(|slot| {
+                    Box::new(TurtleInventory {
+                        selected_slot: slot.try_into().expect("slots are 1 to 16"),
+                        ..Default::default()
+                    })
+                })
+                .into(),
             position: parse_pos3_from_db_str(&value.position)
                 .expect("DB should really have a valid pos string"),
             orientation: Orientation::from_str(&value.orientation)
@@ -69,24 +130,789 @@ impl From<DbTurtle> for common::turtle::Turtle {
     }
 }
 
+/// Loads every known block of a world
+pub async fn load_world(db: &DB, name: &str) -> Result<World, sqlx::Error> {
+    let mut world = World::new(name);
+    let blocks = sqlx::query_as!(DbBlock, "SELECT * FROM blocks WHERE world = ?", name)
+        .fetch_all(db)
+        .await?;
+    for block in blocks.into_iter().map(Block::from) {
+        world.set_block(block);
+    }
+    Ok(world)
+}
+
+#[derive(Clone, Debug)]
+struct DbInventorySlot {
+    turtle_id: i64,
+    slot: i64,
+    name: Option<String>,
+    count: i64,
+}
+
+impl DbInventorySlot {
+    fn fill(self, inventory: &mut TurtleInventory) {
+        let Some(item) = usize::try_from(self.slot - 1)
+            .ok()
+            .and_then(|i| inventory.inv.get_mut(i))
+        else {
+            return;
+        };
+        *item = self
+            .name
+            .map(|name| Item {
+                count: self.count.try_into().unwrap_or_default(),
+                name,
+            })
+            .into();
+    }
+}
+
+/// Every turtle of `world` the db knows about, with its last known inventory
+pub async fn load_turtles(db: &DB, world: &str) -> Result<Vec<Turtle>, sqlx::Error> {
+    let mut turtles = sqlx::query_as!(DbTurtle, "SELECT * FROM turtles WHERE world = ?", world)
+        .fetch_all(db)
+        .await?
+        .into_iter()
+        .map(Turtle::from)
+        .collect::<Vec<_>>();
+    let slots = sqlx::query_as!(
+        DbInventorySlot,
+        "SELECT turtle_id, slot, name as 'name?', count FROM turtle_inventories WHERE world = ?",
+        world
+    )
+    .fetch_all(db)
+    .await?;
+    for slot in slots {
+        let inventory = turtles
+            .iter_mut()
+            .find(|t| i64::from(t.index) == slot.turtle_id)
+            .and_then(|t| match &mut t.inventory {
+                Maybe::Some(inv) => Some(inv),
+                Maybe::None => None,
+            });
+        if let Some(inventory) = inventory {
+            slot.fill(inventory);
+        }
+    }
+    Ok(turtles)
+}
+
+pub async fn load_turtle(db: &DB, world: &str, id: i32) -> Result<Turtle, sqlx::Error> {
+    load_turtles(db, world)
+        .await?
+        .into_iter()
+        .find(|t| t.index == id)
+        .ok_or(sqlx::Error::RowNotFound)
+}
+
+/// Replaces the stored inventory of a turtle and records every slot that changed since `old`
+pub async fn save_inventory(
+    db: &DB,
+    world: &str,
+    id: i32,
+    old: Option<&TurtleInventory>,
+    new: &TurtleInventory,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now().timestamp_millis();
+    let mut tx = db.begin().await?;
+    sqlx::query!(
+        "UPDATE turtles SET selected_slot = ? WHERE id = ? AND world = ?;",
+        new.selected_slot,
+        id,
+        world,
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM turtle_inventories WHERE world = ? AND turtle_id = ?;",
+        world,
+        id
+    )
+    .execute(&mut *tx)
+    .await?;
+    for (i, item) in new.inv.iter().enumerate() {
+        let slot = i as i64 + 1;
+        let item: Option<&Item> = match item {
+            Maybe::Some(item) => Some(item),
+            Maybe::None => None,
+        };
+        if let Some(item) = item {
+            sqlx::query!(
+                "INSERT INTO turtle_inventories VALUES (?,?,?,?,?);",
+                world,
+                id,
+                slot,
+                item.name,
+                item.count,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        let unchanged = old.is_some_and(|old| match (&old.inv[i], item) {
+            (Maybe::Some(a), Some(b)) => a.name == b.name && a.count == b.count,
+            (Maybe::None, None) => true,
+            _ => false,
+        });
+        if !unchanged {
+            let (name, count) = item.map_or((None, 0), |i| (Some(&i.name), i.count));
+            sqlx::query!(
+                "INSERT INTO turtle_inventory_history VALUES (?,?,?,?,?,?);",
+                world,
+                id,
+                slot,
+                name,
+                count,
+                now,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+    tx.commit().await
+}
+
+/// What the turtle carried at `time`, `None` if it hadn't reported its inventory yet
+pub async fn inventory_at(
+    db: &DB,
+    world: &str,
+    id: i32,
+    time: DateTime<Utc>,
+) -> Result<Option<TurtleInventory>, sqlx::Error> {
+    let time = time.timestamp_millis();
+    let slots = sqlx::query_as!(
+        DbInventorySlot,
+        "
+        SELECT turtle_id, slot, name, count FROM turtle_inventory_history
+        WHERE rowid IN (
+            SELECT MAX(rowid) FROM turtle_inventory_history
+            WHERE world = ? AND turtle_id = ? AND changed_at <= ?
+            GROUP BY slot
+        );
+        ",
+        world,
+        id,
+        time,
+    )
+    .fetch_all(db)
+    .await?;
+    if slots.is_empty() {
+        return Ok(None);
+    }
+    let mut inventory = TurtleInventory::default();
+    for slot in slots {
+        slot.fill(&mut inventory);
+    }
+    Ok(Some(inventory))
+}
+
+/// Chunks asked for in one query, each one would otherwise be its own round trip
+pub const CHUNK_BATCH: usize = 512;
+
+/// The known blocks of the chunks at the chunk positions `positions`, by position. Chunks
+/// without any blocks are left out.
+pub async fn load_chunks(
+    db: &DB,
+    world: &str,
+    positions: &[Pos3],
+) -> Result<HashMap<Pos3, Chunk>, sqlx::Error> {
+    let keys = positions
+        .iter()
+        .map(|pos| pos_to_key(pos).to_string())
+        .collect::<Vec<_>>();
+    let keys = format!("[{}]", keys.join(","));
+    let blocks = sqlx::query_as!(
+        DbBlock,
+        "SELECT * FROM blocks WHERE world = ? AND chunk_key IN (SELECT value FROM json_each(?))",
+        world,
+        keys
+    )
+    .fetch_all(db)
+    .await?;
+    let wanted = positions.iter().collect::<HashSet<_>>();
+    let mut chunks = HashMap::new();
+    // keys only repeat 2^21 chunks apart, but rows from there weren't asked for either
+    for block in blocks.into_iter().map(Block::from) {
+        let pos = get_chunk_containing_block(&block.pos);
+        if wanted.contains(&pos) {
+            chunks
+                .entry(pos)
+                .or_insert_with(|| Chunk::new(pos))
+                .set_block(block);
+        }
+    }
+    Ok(chunks)
+}
+
+/// Every known block between the corners `min` and `max`, whole chunks at the edges
+pub async fn load_region(db: &DB, name: &str, min: Pos3, max: Pos3) -> Result<World, sqlx::Error> {
+    let (min, max) = (
+        get_chunk_containing_block(&min),
+        get_chunk_containing_block(&max),
+    );
+    let mut positions = Vec::new();
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                positions.push(Pos3::new(x, y, z));
+            }
+        }
+    }
+    let mut world = World::new(name);
+    for batch in positions.chunks(CHUNK_BATCH) {
+        for chunk in load_chunks(db, name, batch).await?.into_values() {
+            world.insert_chunk(chunk);
+        }
+    }
+    Ok(world)
+}
+
+/// Answers to a block search stop after this many blocks
+pub const MAX_SEARCH_RESULTS: u32 = 1000;
+
+/// Turns what a user typed into an sqlite GLOB for block ids. Ids without a namespace match any
+/// namespace, so `diamond_ore` finds `minecraft:diamond_ore`
+pub fn block_glob(pattern: &str) -> String {
+    let pattern = pattern.trim().to_lowercase();
+    match pattern.contains(':') || pattern.starts_with('*') {
+        true => pattern,
+        false => format!("*:{pattern}"),
+    }
+}
+
+/// Known solid blocks whose id matches the GLOB `pattern`, most recently seen first. The bool is
+/// set when there were more than [`MAX_SEARCH_RESULTS`]
+pub async fn search_blocks(
+    db: &DB,
+    world: &str,
+    pattern: &str,
+) -> Result<(Vec<Block>, bool), sqlx::Error> {
+    let glob = block_glob(pattern);
+    let limit = MAX_SEARCH_RESULTS + 1;
+    let blocks = sqlx::query_as!(
+        DbBlock,
+        "SELECT * FROM blocks WHERE world = ? AND is_air = 0 AND id GLOB ?
+        ORDER BY observed_at DESC LIMIT ?",
+        world,
+        glob,
+        limit
+    )
+    .fetch_all(db)
+    .await?;
+    let truncated = blocks.len() > MAX_SEARCH_RESULTS as usize;
+    let blocks = blocks
+        .into_iter()
+        .take(MAX_SEARCH_RESULTS as usize)
+        .map(Block::from)
+        .collect();
+    Ok((blocks, truncated))
+}
+
+/// Stores `blueprint`, replacing the one with the same name in its world
+pub async fn save_blueprint(db: &DB, blueprint: &Blueprint) -> Result<(), sqlx::Error> {
+    let data = serde_json::to_string(blueprint).map_err(std::io::Error::from)?;
+    sqlx::query!(
+        "INSERT OR REPLACE INTO blueprints VALUES (?,?,?);",
+        blueprint.world,
+        blueprint.name,
+        data
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Every blueprint of `world`, by name
+pub async fn load_blueprints(db: &DB, world: &str) -> Result<Vec<Blueprint>, sqlx::Error> {
+    sqlx::query!(
+        "SELECT data FROM blueprints WHERE world = ? ORDER BY name;",
+        world
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|r| serde_json::from_str(&r.data).map_err(|e| sqlx::Error::Decode(e.into())))
+    .collect()
+}
+
+pub async fn delete_blueprint(db: &DB, world: &str, name: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM blueprints WHERE world = ? AND name = ?;",
+        world,
+        name
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub async fn load_blueprint(
+    db: &DB,
+    world: &str,
+    name: &str,
+) -> Result<Option<Blueprint>, sqlx::Error> {
+    sqlx::query!(
+        "SELECT data FROM blueprints WHERE world = ? AND name = ?;",
+        world,
+        name
+    )
+    .fetch_optional(db)
+    .await?
+    .map(|r| serde_json::from_str(&r.data).map_err(|e| sqlx::Error::Decode(e.into())))
+    .transpose()
+}
+
+/// Replaces the build of `blueprint`, the turtles leave whatever build they were part of before
+pub async fn save_build_job(
+    db: &DB,
+    world: &str,
+    blueprint: &str,
+    turtles: &[i32],
+) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    sqlx::query!(
+        "DELETE FROM build_jobs WHERE world = ? AND blueprint = ?;",
+        world,
+        blueprint
+    )
+    .execute(&mut *tx)
+    .await?;
+    for turtle in turtles {
+        sqlx::query!(
+            "DELETE FROM build_jobs WHERE world = ? AND turtle = ?;",
+            world,
+            turtle
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM jobs WHERE world = ? AND turtle = ?;",
+            world,
+            turtle
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO build_jobs (world, blueprint, turtle) VALUES (?,?,?);",
+            world,
+            blueprint,
+            turtle
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+/// The build `turtle` still has to work on and every turtle of it, done ones included
+pub async fn load_build_job(
+    db: &DB,
+    world: &str,
+    turtle: i32,
+) -> Result<Option<(String, Vec<i32>)>, sqlx::Error> {
+    let Some(job) = sqlx::query!(
+        "SELECT blueprint FROM build_jobs WHERE world = ? AND turtle = ? AND NOT done;",
+        world,
+        turtle
+    )
+    .fetch_optional(db)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let turtles = sqlx::query!(
+        "SELECT turtle FROM build_jobs WHERE world = ? AND blueprint = ? ORDER BY turtle;",
+        world,
+        job.blueprint
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|r| r.turtle as i32)
+    .collect();
+    Ok(Some((job.blueprint, turtles)))
+}
+
+/// Marks the part of `turtle` as done, the whole build goes away once every turtle is done
+pub async fn finish_build_job(
+    db: &DB,
+    world: &str,
+    blueprint: &str,
+    turtle: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE build_jobs SET done = TRUE WHERE world = ? AND blueprint = ? AND turtle = ?;",
+        world,
+        blueprint,
+        turtle
+    )
+    .execute(db)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM build_jobs WHERE world = ? AND blueprint = ?
+        AND NOT EXISTS (SELECT 1 FROM build_jobs WHERE world = ? AND blueprint = ? AND NOT done);",
+        world,
+        blueprint,
+        world,
+        blueprint
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_build_job(db: &DB, world: &str, blueprint: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM build_jobs WHERE world = ? AND blueprint = ?;",
+        world,
+        blueprint
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Replaces the job of `turtle`, it leaves the build it was part of
+pub async fn save_job(
+    db: &DB,
+    world: &str,
+    turtle: i32,
+    kind: &JobKind,
+) -> Result<(), sqlx::Error> {
+    let kind = serde_json::to_string(kind).expect("jobs are JSON encodable");
+    let mut tx = db.begin().await?;
+    sqlx::query!(
+        "DELETE FROM build_jobs WHERE world = ? AND turtle = ?;",
+        world,
+        turtle
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "INSERT OR REPLACE INTO jobs (world, turtle, kind) VALUES (?,?,?);",
+        world,
+        turtle,
+        kind
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await
+}
+
+/// The job of `turtle` and the step it got to
+pub async fn load_job(
+    db: &DB,
+    world: &str,
+    turtle: i32,
+) -> Result<Option<(JobKind, u32)>, sqlx::Error> {
+    let Some(r) = sqlx::query!(
+        "SELECT kind, step FROM jobs WHERE world = ? AND turtle = ?;",
+        world,
+        turtle
+    )
+    .fetch_optional(db)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let kind = serde_json::from_str(&r.kind).map_err(|e| sqlx::Error::Decode(e.into()))?;
+    Ok(Some((kind, r.step as u32)))
+}
+
+pub async fn save_job_step(
+    db: &DB,
+    world: &str,
+    turtle: i32,
+    step: u32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE jobs SET step = ? WHERE world = ? AND turtle = ?;",
+        step,
+        world,
+        turtle
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_job(db: &DB, world: &str, turtle: i32) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM jobs WHERE world = ? AND turtle = ?;",
+        world,
+        turtle
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Sets or clears the home of `turtle`
+pub async fn save_home(
+    db: &DB,
+    world: &str,
+    turtle: i32,
+    home: Option<Pos3>,
+) -> Result<(), sqlx::Error> {
+    match home {
+        Some(home) => {
+            let position = pos_to_db_pos(&home);
+            sqlx::query!(
+                "INSERT OR REPLACE INTO turtle_homes (world, turtle, position) VALUES (?,?,?);",
+                world,
+                turtle,
+                position
+            )
+            .execute(db)
+            .await?;
+        }
+        None => {
+            sqlx::query!(
+                "DELETE FROM turtle_homes WHERE world = ? AND turtle = ?;",
+                world,
+                turtle
+            )
+            .execute(db)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// The home of every turtle in `world` that has one
+pub async fn load_homes(db: &DB, world: &str) -> Result<Vec<(i32, Pos3)>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT turtle, position FROM turtle_homes WHERE world = ?;",
+        world
+    )
+    .fetch_all(db)
+    .await?;
+    rows.into_iter()
+        .map(|r| {
+            let pos = parse_pos3_from_db_str(&r.position)
+                .map_err(|err| sqlx::Error::Decode(err.into()))?;
+            Ok((r.turtle as i32, pos))
+        })
+        .collect()
+}
+
+/// Adds the waypoint or replaces the one with the same name
+pub async fn save_waypoint(db: &DB, world: &str, waypoint: &Waypoint) -> Result<(), sqlx::Error> {
+    let kind = serde_json::to_string(&waypoint.kind).expect("waypoint kinds are JSON encodable");
+    let position = pos_to_db_pos(&waypoint.position);
+    sqlx::query!(
+        "INSERT OR REPLACE INTO waypoints (world, name, kind, position) VALUES (?,?,?,?);",
+        world,
+        waypoint.name,
+        kind,
+        position
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_waypoint(db: &DB, world: &str, name: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM waypoints WHERE world = ? AND name = ?;",
+        world,
+        name
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub async fn load_waypoints(db: &DB, world: &str) -> Result<Vec<Waypoint>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT name, kind, position FROM waypoints WHERE world = ? ORDER BY name;",
+        world
+    )
+    .fetch_all(db)
+    .await?;
+    rows.into_iter()
+        .map(|r| {
+            let kind = serde_json::from_str(&r.kind).map_err(|e| sqlx::Error::Decode(e.into()))?;
+            let position = parse_pos3_from_db_str(&r.position)
+                .map_err(|err| sqlx::Error::Decode(err.into()))?;
+            Ok(Waypoint {
+                name: r.name,
+                kind,
+                position,
+            })
+        })
+        .collect()
+}
+
+pub async fn load_waypoint(db: &DB, world: &str, name: &str) -> Result<Option<Pos3>, sqlx::Error> {
+    sqlx::query!(
+        "SELECT position FROM waypoints WHERE world = ? AND name = ?;",
+        world,
+        name
+    )
+    .fetch_optional(db)
+    .await?
+    .map(|r| parse_pos3_from_db_str(&r.position).map_err(|err| sqlx::Error::Decode(err.into())))
+    .transpose()
+}
+
+pub async fn add_task(
+    db: &DB,
+    world: &str,
+    kind: &TaskKind,
+    priority: i32,
+) -> Result<i64, sqlx::Error> {
+    let kind = serde_json::to_string(kind).expect("tasks are JSON encodable");
+    let r = sqlx::query!(
+        "INSERT INTO tasks (world, kind, priority) VALUES (?,?,?);",
+        world,
+        kind,
+        priority
+    )
+    .execute(db)
+    .await?;
+    Ok(r.last_insert_rowid())
+}
+
+/// Highest priority first, then in the order they were queued
+pub async fn load_tasks(db: &DB, world: &str) -> Result<Vec<Task>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT id, kind, priority, turtle, retries, failure FROM tasks WHERE world = ?
+        ORDER BY priority DESC, id;",
+        world
+    )
+    .fetch_all(db)
+    .await?;
+    rows.into_iter()
+        .map(|r| {
+            let kind = serde_json::from_str(&r.kind).map_err(|e| sqlx::Error::Decode(e.into()))?;
+            let state = match (r.failure, r.turtle) {
+                (Some(reason), _) => TaskState::Failed { reason },
+                (None, Some(turtle)) => TaskState::Assigned {
+                    turtle: turtle as i32,
+                },
+                (None, None) => TaskState::Queued,
+            };
+            Ok(Task {
+                id: r.id,
+                kind,
+                priority: r.priority as i32,
+                state,
+                retries: r.retries as u32,
+            })
+        })
+        .collect()
+}
+
+/// Where a job task left off
+pub async fn load_task_step(db: &DB, id: i64) -> Result<u32, sqlx::Error> {
+    let r = sqlx::query!("SELECT step FROM tasks WHERE id = ?;", id)
+        .fetch_one(db)
+        .await?;
+    Ok(r.step as u32)
+}
+
+pub async fn assign_task(db: &DB, id: i64, turtle: i32) -> Result<(), sqlx::Error> {
+    sqlx::query!("UPDATE tasks SET turtle = ? WHERE id = ?;", turtle, id)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// Puts the task back into the queue, to go on at `step`. `retry` counts it as a retry
+pub async fn release_task(db: &DB, id: i64, step: u32, retry: bool) -> Result<(), sqlx::Error> {
+    let retry = retry as i64;
+    sqlx::query!(
+        "UPDATE tasks SET turtle = NULL, step = ?, retries = retries + ? WHERE id = ?;",
+        step,
+        retry,
+        id
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Puts every task back into the queue, when the server starts no turtle is working on any.
+/// The jobs of job tasks are dropped, whoever gets the task next goes on with it
+pub async fn release_all_tasks(db: &DB) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    sqlx::query!(
+        "UPDATE tasks SET step = COALESCE(
+            (SELECT step FROM jobs WHERE jobs.world = tasks.world AND jobs.turtle = tasks.turtle),
+            step
+        ) WHERE turtle IS NOT NULL;"
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM jobs WHERE EXISTS (
+            SELECT 1 FROM tasks WHERE tasks.world = jobs.world AND tasks.turtle = jobs.turtle
+        );"
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!("UPDATE tasks SET turtle = NULL WHERE turtle IS NOT NULL;")
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
+
+pub async fn fail_task(db: &DB, id: i64, reason: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE tasks SET turtle = NULL, failure = ? WHERE id = ?;",
+        reason,
+        id
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_task(db: &DB, world: &str, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM tasks WHERE id = ? AND world = ?;", id, world)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// Worlds with tasks nobody works on yet
+pub async fn worlds_with_queued_tasks(db: &DB) -> Result<Vec<String>, sqlx::Error> {
+    Ok(
+        sqlx::query!("SELECT DISTINCT world FROM tasks WHERE turtle IS NULL AND failure IS NULL;")
+            .fetch_all(db)
+            .await?
+            .into_iter()
+            .map(|r| r.world)
+            .collect(),
+    )
+}
+
 pub fn pos_to_db_pos(pos: &Pos3) -> String {
     format!("{};{};{}", pos.x, pos.y, pos.z)
 }
 
-pub const fn get_chunk_key_mask(i: usize) -> u32 {
-    match i {
-        0 => 0b001001001001001001001001001001001,
-        1 => 0b010010010010010010010010010010010,
-        2 => 0b001001001001001001001001001001001,
-        _ => 0,
-    }
+/// Bits per axis in a `chunk_key`
+const KEY_BITS: u32 = 21;
+
+/// A chunk coordinate moved into `0..2^KEY_BITS`, so negative ones keep their own keys
+const fn key_axis(c: i32) -> i64 {
+    (c as i64 + (1 << (KEY_BITS - 1))) & ((1 << KEY_BITS) - 1)
 }
 
-pub const fn pos_to_key(pos: &Pos3) -> u32 {
-    let x = pos.x.unsigned_abs() & get_chunk_key_mask(0);
-    let y = pos.y.unsigned_abs() & get_chunk_key_mask(1);
-    let z = pos.z.unsigned_abs() & get_chunk_key_mask(2);
-    x | y | z
+/// Key of a chunk position for the `chunk_key` column, the bits of x, y and z interleaved.
+/// Unique for chunks within 2^20 of the origin in every axis
+pub const fn pos_to_key(pos: &Pos3) -> i64 {
+    let (x, y, z) = (key_axis(pos.x), key_axis(pos.y), key_axis(pos.z));
+    let mut key = 0;
+    let mut bit = 0;
+    while bit < KEY_BITS {
+        key |= ((x >> bit) & 1) << (3 * bit)
+            | ((y >> bit) & 1) << (3 * bit + 1)
+            | ((z >> bit) & 1) << (3 * bit + 2);
+        bit += 1;
+    }
+    key
 }
 
 fn parse_pos3_from_db_str(str: &str) -> anyhow::Result<Pos3> {
@@ -103,3 +929,29 @@ fn parse_pos3_from_db_str(str: &str) -> anyhow::Result<Pos3> {
         .ok_or(anyhow::anyhow!("could not parse third int"))??;
     Ok(Pos3::new(x, y, z))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_keys_keep_signs_and_axes_apart() {
+        let origin = pos_to_key(&Pos3::new(0, 0, 0));
+        let keys = [
+            Pos3::new(1, 0, 0),
+            Pos3::new(-1, 0, 0),
+            Pos3::new(0, 1, 0),
+            Pos3::new(0, -1, 0),
+            Pos3::new(0, 0, 1),
+            Pos3::new(0, 0, -1),
+        ]
+        .map(|pos| pos_to_key(&pos));
+        for (i, key) in keys.iter().enumerate() {
+            assert_ne!(*key, origin);
+            assert!(!keys[i + 1..].contains(key));
+        }
+        // neighbours on one axis only differ in that axis' bits
+        assert_eq!(origin ^ keys[0], 0b1);
+        assert!(pos_to_key(&Pos3::new(-1_048_576, 0, 0)) >= 0);
+    }
+}
@@ -0,0 +1,422 @@
+//! Exports a region of a world as a Sponge schematic (`.schem`, for WorldEdit) or a vanilla
+//! structure (`.nbt`, for structure blocks and `/place template`).
+//!
+//! Nothing is known about blocks no turtle has seen. Schematics have to say something for every
+//! block, so those become air. Structures can leave blocks out, which keeps whatever is in the
+//! world where they get placed.
+
+use std::{collections::HashMap, io::Write, str::FromStr};
+
+use common::{
+    world_data::{BlockState, World},
+    Pos3,
+};
+use flate2::{write::GzEncoder, Compression};
+use thiserror::Error;
+
+use crate::{
+    db::{load_region, DB},
+    nbt::{write_named, Tag},
+};
+
+/// Minecraft 1.20.1, newer versions upgrade older data on load
+pub const DATA_VERSION: i32 = 3465;
+
+/// Exports bigger than this many blocks are refused, that's 256³
+pub const MAX_EXPORT_VOLUME: u64 = 1 << 24;
+
+const AIR: &str = "minecraft:air";
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("the region has {0} blocks, at most {MAX_EXPORT_VOLUME} can be exported")]
+    TooBig(u64),
+    #[error("{0:?} is not a position, expected x,y,z")]
+    InvalidPos(String),
+    #[error("unknown format {0:?}, expected schem or nbt")]
+    UnknownFormat(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Db(#[from] sqlx::Error),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Sponge schematic version 2
+    Schematic,
+    /// Vanilla structure file
+    Structure,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Schematic => "schem",
+            ExportFormat::Structure => "nbt",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = ExportError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "schem" | "sponge" => Ok(ExportFormat::Schematic),
+            "nbt" | "structure" => Ok(ExportFormat::Structure),
+            _ => Err(ExportError::UnknownFormat(s.to_owned())),
+        }
+    }
+}
+
+/// A box of blocks, both corners included
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub min: Pos3,
+    pub max: Pos3,
+}
+
+impl Region {
+    /// Any two opposite corners
+    pub fn new(a: Pos3, b: Pos3) -> Region {
+        Region {
+            min: Pos3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Pos3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    /// The smallest box with all of `points`, `None` without any
+    pub fn around(points: impl IntoIterator<Item = Pos3>) -> Option<Region> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Region::new(first, first), |region, p| {
+            Region::new(
+                Pos3::new(
+                    region.min.x.min(p.x),
+                    region.min.y.min(p.y),
+                    region.min.z.min(p.z),
+                ),
+                Pos3::new(
+                    region.max.x.max(p.x),
+                    region.max.y.max(p.y),
+                    region.max.z.max(p.z),
+                ),
+            )
+        }))
+    }
+
+    /// Grown by `by` blocks on every side
+    pub fn padded(&self, by: i32) -> Region {
+        let by = Pos3::new(by, by, by);
+        Region::new(self.min - by, self.max + by)
+    }
+
+    pub fn contains(&self, pos: Pos3) -> bool {
+        (self.min.x..=self.max.x).contains(&pos.x)
+            && (self.min.y..=self.max.y).contains(&pos.y)
+            && (self.min.z..=self.max.z).contains(&pos.z)
+    }
+
+    pub fn size(&self) -> Pos3 {
+        self.max - self.min + Pos3::new(1, 1, 1)
+    }
+
+    pub fn volume(&self) -> u64 {
+        let size = self.size();
+        size.x as u64 * size.y as u64 * size.z as u64
+    }
+
+    /// y, then z, then x, the order both formats store blocks in
+    fn positions(&self) -> impl Iterator<Item = Pos3> {
+        let Region { min, max } = *self;
+        (min.y..=max.y).flat_map(move |y| {
+            (min.z..=max.z).flat_map(move |z| (min.x..=max.x).map(move |x| Pos3::new(x, y, z)))
+        })
+    }
+}
+
+/// `x,y,z`
+pub fn parse_pos(s: &str) -> Result<Pos3, ExportError> {
+    let err = || ExportError::InvalidPos(s.to_owned());
+    let parts = s
+        .split(',')
+        .map(|p| p.trim().parse::<i32>().map_err(|_| err()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match parts[..] {
+        [x, y, z] => Ok(Pos3::new(x, y, z)),
+        _ => Err(err()),
+    }
+}
+
+/// Loads the region from the db and exports it, gzipped like minecraft expects
+pub async fn export_from_db(
+    db: &DB,
+    world: &str,
+    region: &Region,
+    format: ExportFormat,
+) -> Result<Vec<u8>, ExportError> {
+    check_volume(region)?;
+    let world = load_region(db, world, region.min, region.max).await?;
+    export(&world, region, format)
+}
+
+fn check_volume(region: &Region) -> Result<(), ExportError> {
+    match region.volume() {
+        v if v > MAX_EXPORT_VOLUME => Err(ExportError::TooBig(v)),
+        _ => Ok(()),
+    }
+}
+
+pub fn export(
+    world: &World,
+    region: &Region,
+    format: ExportFormat,
+) -> Result<Vec<u8>, ExportError> {
+    check_volume(region)?;
+    let (name, tag) = match format {
+        ExportFormat::Schematic => ("Schematic", schematic(world, region)),
+        ExportFormat::Structure => ("", structure(world, region)),
+    };
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    write_named(&mut encoder, name, &tag)?;
+    encoder.flush()?;
+    Ok(encoder.finish()?)
+}
+
+/// Block id and state the way both formats name palette entries, air if unknown
+fn block_at<'a>(world: &'a World, pos: &Pos3) -> Option<(&'a str, &'a BlockState)> {
+    let entry = world.get_entry(pos)?;
+    match entry.is_air {
+        true => Some((AIR, &entry.state)),
+        false => Some((&entry.id, &entry.state)),
+    }
+}
+
+fn pos_list(pos: Pos3) -> Tag {
+    Tag::List(vec![Tag::Int(pos.x), Tag::Int(pos.y), Tag::Int(pos.z)])
+}
+
+fn schematic(world: &World, region: &Region) -> Tag {
+    let size = region.size();
+    let mut palette = HashMap::<String, i32>::new();
+    let mut data = Vec::new();
+    for pos in region.positions() {
+        let name = match block_at(world, &pos) {
+            Some((id, state)) if id != AIR => format!("{id}{state}"),
+            _ => AIR.to_owned(),
+        };
+        let next = palette.len() as i32;
+        let index = *palette.entry(name).or_insert(next);
+        // varint, like every index in BlockData
+        let mut v = index as u32;
+        while v >= 0x80 {
+            data.push((v as u8 & 0x7f | 0x80) as i8);
+            v >>= 7;
+        }
+        data.push(v as i8);
+    }
+    let mut palette = palette.into_iter().collect::<Vec<_>>();
+    palette.sort_by_key(|(_, i)| *i);
+    let palette_max = palette.len() as i32;
+    Tag::compound([
+        ("Version", Tag::Int(2)),
+        ("DataVersion", Tag::Int(DATA_VERSION)),
+        // unsigned shorts, the volume limit keeps them in range
+        ("Width", Tag::Short(size.x as u16 as i16)),
+        ("Height", Tag::Short(size.y as u16 as i16)),
+        ("Length", Tag::Short(size.z as u16 as i16)),
+        (
+            "Offset",
+            Tag::IntArray(vec![region.min.x, region.min.y, region.min.z]),
+        ),
+        // WorldEdit pastes relative to the min corner with these
+        (
+            "Metadata",
+            Tag::compound([
+                ("WEOffsetX", Tag::Int(0)),
+                ("WEOffsetY", Tag::Int(0)),
+                ("WEOffsetZ", Tag::Int(0)),
+            ]),
+        ),
+        ("PaletteMax", Tag::Int(palette_max)),
+        (
+            "Palette",
+            Tag::Compound(palette.into_iter().map(|(k, i)| (k, Tag::Int(i))).collect()),
+        ),
+        ("BlockData", Tag::ByteArray(data)),
+        ("BlockEntities", Tag::List(Vec::new())),
+    ])
+}
+
+fn structure(world: &World, region: &Region) -> Tag {
+    let mut palette = Vec::<Tag>::new();
+    let mut indices = HashMap::<(&str, String), i32>::new();
+    let mut blocks = Vec::new();
+    for pos in region.positions() {
+        let Some((id, state)) = block_at(world, &pos) else {
+            continue;
+        };
+        let key = (id, state.to_string());
+        let state_index = *indices.entry(key).or_insert_with(|| {
+            let mut entry = vec![("Name".to_owned(), Tag::String(id.to_owned()))];
+            if !state.properties.is_empty() && id != AIR {
+                let properties = state
+                    .properties
+                    .iter()
+                    .map(|(k, v)| (k.clone(), Tag::String(v.clone())))
+                    .collect();
+                entry.push(("Properties".to_owned(), Tag::Compound(properties)));
+            }
+            palette.push(Tag::Compound(entry));
+            palette.len() as i32 - 1
+        });
+        blocks.push(Tag::compound([
+            ("pos", pos_list(pos - region.min)),
+            ("state", Tag::Int(state_index)),
+        ]));
+    }
+    Tag::compound([
+        ("DataVersion", Tag::Int(DATA_VERSION)),
+        ("size", pos_list(region.size())),
+        ("palette", Tag::List(palette)),
+        ("blocks", Tag::List(blocks)),
+        ("entities", Tag::List(Vec::new())),
+    ])
+}
+
+/// `backend export <world> <schem|nbt> <x,y,z> <x,y,z> [file]`, writes to `<world>.<ext>` if no
+/// file is given
+pub async fn cli(db: &DB, args: &[String]) -> anyhow::Result<()> {
+    let [world, format, from, to, rest @ ..] = args else {
+        anyhow::bail!("usage: export <world> <schem|nbt> <x,y,z> <x,y,z> [file]");
+    };
+    let format = format.parse::<ExportFormat>()?;
+    let region = Region::new(parse_pos(from)?, parse_pos(to)?);
+    let file = match rest.first() {
+        Some(file) => file.clone(),
+        None => format!("{world}.{}", format.extension()),
+    };
+    let bytes = export_from_db(db, world, &region, format).await?;
+    std::fs::write(&file, bytes)?;
+    println!("exported {} blocks of {world} to {file}", region.volume());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use common::{turtle_packets::InspectInfo, world_data::Block};
+    use flate2::read::GzDecoder;
+
+    use super::*;
+    use crate::nbt::read_named;
+
+    fn test_world() -> World {
+        let mut world = World::new("test");
+        let mut stairs = InspectInfo::new("minecraft:oak_stairs");
+        stairs.state.insert("facing".into(), "north".into());
+        stairs.state.insert("half".into(), "bottom".into());
+        world.set_block(Block::inspected(Some(stairs), &Pos3::new(1, 0, 0), "test"));
+        world.set_block(Block::new(
+            Some("minecraft:stone".into()),
+            &Pos3::new(0, 0, 0),
+            "test",
+        ));
+        world.set_block(Block::new(None, &Pos3::new(0, 1, 0), "test"));
+        world
+    }
+
+    fn decode(bytes: &[u8]) -> (String, Tag) {
+        let mut raw = Vec::new();
+        GzDecoder::new(bytes).read_to_end(&mut raw).unwrap();
+        read_named(&mut raw.as_slice()).unwrap()
+    }
+
+    fn get<'a>(tag: &'a Tag, name: &str) -> &'a Tag {
+        let Tag::Compound(entries) = tag else {
+            panic!("{tag:?} is not a compound");
+        };
+        &entries.iter().find(|(k, _)| k == name).unwrap().1
+    }
+
+    #[test]
+    fn schematics_have_every_block() {
+        let region = Region::new(Pos3::new(1, 1, 1), Pos3::new(0, 0, 0));
+        let bytes = export(&test_world(), &region, ExportFormat::Schematic).unwrap();
+        let (name, tag) = decode(&bytes);
+        assert_eq!(name, "Schematic");
+        assert_eq!(get(&tag, "Width"), &Tag::Short(2));
+        assert_eq!(get(&tag, "Offset"), &Tag::IntArray(vec![0, 0, 0]));
+        let Tag::Compound(palette) = get(&tag, "Palette") else {
+            unreachable!()
+        };
+        let index = |name: &str| {
+            let (_, Tag::Int(i)) = palette.iter().find(|(k, _)| k == name).unwrap() else {
+                unreachable!()
+            };
+            *i as i8
+        };
+        assert_eq!(palette.len(), 3);
+        assert_eq!(get(&tag, "PaletteMax"), &Tag::Int(3));
+        let stone = index("minecraft:stone");
+        let stairs = index("minecraft:oak_stairs[facing=north,half=bottom]");
+        let air = index(AIR);
+        // y, z, x order, unknown and seen air are both air
+        let expected = vec![stone, stairs, air, air, air, air, air, air];
+        assert_eq!(get(&tag, "BlockData"), &Tag::ByteArray(expected));
+    }
+
+    #[test]
+    fn structures_leave_out_unknown_blocks() {
+        let region = Region::new(Pos3::new(0, 0, 0), Pos3::new(1, 1, 1));
+        let bytes = export(&test_world(), &region, ExportFormat::Structure).unwrap();
+        let (name, tag) = decode(&bytes);
+        assert_eq!(name, "");
+        assert_eq!(get(&tag, "size"), &pos_list(Pos3::new(2, 2, 2)));
+        let Tag::List(blocks) = get(&tag, "blocks") else {
+            unreachable!()
+        };
+        // stone, stairs and the air above the stone
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(get(&blocks[2], "pos"), &pos_list(Pos3::new(0, 1, 0)));
+        let Tag::List(palette) = get(&tag, "palette") else {
+            unreachable!()
+        };
+        let stairs = &palette[1];
+        assert_eq!(
+            get(stairs, "Name"),
+            &Tag::String("minecraft:oak_stairs".into())
+        );
+        assert_eq!(
+            get(get(stairs, "Properties"), "facing"),
+            &Tag::String("north".into())
+        );
+    }
+
+    #[test]
+    fn regions_are_checked() {
+        assert_eq!(parse_pos(" 1, -2,3").unwrap(), Pos3::new(1, -2, 3));
+        assert!(parse_pos("1,2").is_err());
+        assert!(parse_pos("1,2,x").is_err());
+        let huge = Region::new(Pos3::new(0, 0, 0), Pos3::new(300, 300, 300));
+        assert!(matches!(
+            export(&World::new("test"), &huge, ExportFormat::Structure),
+            Err(ExportError::TooBig(_))
+        ));
+    }
+
+    #[test]
+    fn regions_fit_around_points() {
+        assert_eq!(Region::around([]), None);
+        let region = Region::around([Pos3::new(2, 0, -1), Pos3::new(-3, 4, 1)]).unwrap();
+        assert_eq!(
+            region,
+            Region::new(Pos3::new(-3, 0, -1), Pos3::new(2, 4, 1))
+        );
+        let padded = region.padded(2);
+        assert!(padded.contains(Pos3::new(-5, -2, 3)));
+        assert!(!padded.contains(Pos3::new(-6, 0, 0)));
+        assert!(!region.contains(Pos3::new(0, 5, 0)));
+    }
+}
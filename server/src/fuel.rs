@@ -0,0 +1,93 @@
+//! What turtles burn and what moving around costs them. Jobs check before every step that the
+//! turtle can still make it back to its home (or the start of the job) and get fuel if not.
+
+use common::{
+    turtle::{Item, Maybe, MoveDirection},
+    Pos3,
+};
+
+use crate::{data_types::server_turtle::ServerTurtle, go_to::find_route, reservations::Obstacles};
+
+/// Fuel kept on top of the way back, for detours around blocks nobody knew about
+pub const FUEL_MARGIN: u32 = 16;
+
+/// Fuel burnt before the way back is looked up again, the estimate in between only grows
+pub const REESTIMATE_AFTER: u32 = 8;
+
+/// Fuel one item gives, `None` for items that don't burn or aren't worth it
+pub fn fuel_value(item: &str) -> Option<u32> {
+    Some(match item {
+        "minecraft:coal" | "minecraft:charcoal" => 80,
+        "minecraft:coal_block" => 800,
+        "minecraft:lava_bucket" => 1000,
+        "minecraft:blaze_rod" => 120,
+        "minecraft:dried_kelp_block" => 200,
+        _ => return None,
+    })
+}
+
+/// Turns are free, every other move burns one fuel
+pub fn move_cost(path: &[MoveDirection]) -> u32 {
+    path.iter()
+        .filter(|dir| !matches!(dir, MoveDirection::Left | MoveDirection::Right))
+        .count() as u32
+}
+
+/// Moves between the two without any turns or detours
+pub fn distance(a: Pos3, b: Pos3) -> u32 {
+    let d = a - b;
+    d.x.unsigned_abs() + d.y.unsigned_abs() + d.z.unsigned_abs()
+}
+
+impl ServerTurtle {
+    /// Moves it takes to get to `target` from where the turtle is. Without a known route it
+    /// is the straight distance, digging through is what jobs do anyway
+    pub async fn path_cost(&self, target: Pos3) -> u32 {
+        let db = self.get_db();
+        let route = find_route(
+            &db,
+            &self.world,
+            self.position,
+            self.orientation,
+            target,
+            Obstacles::default(),
+        );
+        match route.await {
+            Ok(path) => move_cost(&path),
+            Err(_) => distance(self.position, target),
+        }
+    }
+
+    /// Lua that burns the first fuel item of the inventory
+    pub fn refuel_code(&self) -> Option<String> {
+        let Maybe::Some(inv) = &self.inventory else {
+            return None;
+        };
+        let burns = |slot: &Maybe<Item>| match slot {
+            Maybe::Some(item) => item.count > 0 && fuel_value(&item.name).is_some(),
+            Maybe::None => false,
+        };
+        // a selection outside of 1 to 16 has nothing to burn
+        let selected = inv
+            .selected_slot
+            .checked_sub(1)
+            .and_then(|i| inv.get(i as usize));
+        if selected.is_some_and(burns) {
+            return Some("turtle.refuel()".into());
+        }
+        let slot = inv.iter().position(burns)?;
+        Some(format!("turtle.select({}) turtle.refuel()", slot + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turns_are_free() {
+        use MoveDirection::*;
+        assert_eq!(move_cost(&[Left, Forward, Up, Right, Right, Down, Back]), 4);
+        assert_eq!(move_cost(&[]), 0);
+    }
+}
@@ -0,0 +1,428 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Arc,
+};
+
+use common::{
+    client_packets::GoToStatus,
+    turtle::{MoveDirection, Orientation},
+    turtle_packets::S2TPackets,
+    world_data::World,
+    Pos3,
+};
+use log::{error, info};
+use pathfinding::{find_path_avoiding, find_refuge, PathfindingError, PathfindingOptions};
+
+use crate::{
+    connection_manager::TurtleCommBus,
+    data_types::server_turtle::ServerTurtle,
+    db::{load_region, DB},
+    export::Region,
+    fuel::move_cost,
+    reservations::Obstacles,
+};
+
+/// How often a route may be replanned before the turtle gives up
+const MAX_REPLANS: u32 = 32;
+
+/// How far the search for a place to make way in goes, it is only worth it close by
+const MAX_REFUGE_NODES: usize = 10_000;
+
+/// Known blocks loaded around the start and target of a search. Loading all of a big world
+/// takes long enough to stall every turtle
+const REGION_PADDING: i32 = 16;
+
+/// How often the loaded box grows when a route leaves it. After that the blocks outside of it
+/// count as unknown, like the ones nobody has seen yet
+const MAX_REGION_GROWTHS: u32 = 3;
+
+pub struct GoTo {
+    target: Pos3,
+    path: VecDeque<MoveDirection>,
+    /// The move the turtle is doing right now and where it should end up after it
+    pending: Option<(MoveDirection, Pos3, Orientation)>,
+    /// Set when the route got invalidated while a move was still pending
+    needs_replan: bool,
+    replans: u32,
+    /// The turtle that wasn't going anywhere the route was last planned around
+    avoided: Option<i32>,
+    /// Turtles this one got out of the way of, with the blocks it would have gone through. It
+    /// stays out of their way until their routes are clear of those
+    making_way: Option<(Vec<i32>, HashSet<Pos3>)>,
+}
+
+pub enum GoToEvent {
+    Moved,
+    MoveFailed,
+    BlockChanged(Pos3),
+    /// Another turtle of the world moved or went away, it might not be in the way anymore
+    OthersMoved,
+}
+
+pub fn lua_for_move(dir: MoveDirection) -> &'static str {
+    match dir {
+        MoveDirection::Forward => "turtle.forward()",
+        MoveDirection::Back => "turtle.back()",
+        MoveDirection::Up => "turtle.up()",
+        MoveDirection::Down => "turtle.down()",
+        MoveDirection::Left => "turtle.turnLeft()",
+        MoveDirection::Right => "turtle.turnRight()",
+    }
+}
+
+/// The moves of the best route the known world and the other turtles allow. If there is no way
+/// around the other turtles, it waits for them on the way
+pub async fn find_route(
+    db: &DB,
+    world: &str,
+    start: Pos3,
+    orientation: Orientation,
+    target: Pos3,
+    obstacles: Obstacles,
+) -> Result<Vec<MoveDirection>, String> {
+    search_around(db, world, start, orientation, &[target], move |world| {
+        let options = PathfindingOptions::default();
+        let find = |obstacles: &Obstacles| {
+            find_path_avoiding(world, start, target, orientation, &options, |pos, moves| {
+                obstacles.blocks(pos, moves)
+            })
+        };
+        find(&obstacles)
+            .or_else(|_| find(&obstacles.clone().parked_only()))
+            .or_else(|_| find(&Obstacles::default()))
+    })
+    .await
+}
+
+/// The moves to the closest known free block out of `avoid`, without going through turtles
+async fn find_way_out(
+    db: &DB,
+    world: &str,
+    start: Pos3,
+    orientation: Orientation,
+    obstacles: Obstacles,
+    avoid: HashSet<Pos3>,
+) -> Result<Vec<MoveDirection>, String> {
+    search_around(db, world, start, orientation, &[], move |world| {
+        let options = PathfindingOptions {
+            max_visited_nodes: MAX_REFUGE_NODES,
+            ..Default::default()
+        };
+        find_refuge(
+            world,
+            start,
+            orientation,
+            &options,
+            |pos, _| obstacles.has_turtle(pos),
+            |pos| avoid.contains(&pos),
+        )
+    })
+    .await
+}
+
+/// Runs `search` on the known blocks of a box around `start` and `points`. While the moves it
+/// finds leave the box, or it gives up before finding any, the box grows and it runs again
+async fn search_around<F>(
+    db: &DB,
+    world: &str,
+    start: Pos3,
+    orientation: Orientation,
+    points: &[Pos3],
+    search: F,
+) -> Result<Vec<MoveDirection>, String>
+where
+    F: Fn(&World) -> Result<Vec<MoveDirection>, PathfindingError> + Send + Sync + 'static,
+{
+    let search = Arc::new(search);
+    let mut around = vec![start];
+    around.extend_from_slice(points);
+    let mut padding = REGION_PADDING;
+    for growths in 0.. {
+        let region = Region::around(around.iter().copied())
+            .expect("the start is always in there")
+            .padded(padding);
+        let known = load_region(db, world, region.min, region.max)
+            .await
+            .map_err(|err| err.to_string())?;
+        let search = search.clone();
+        let found = tokio::task::spawn_blocking(move || search(&known))
+            .await
+            .map_err(|err| err.to_string())?;
+        let last_try = growths == MAX_REGION_GROWTHS;
+        match found {
+            Ok(path) => {
+                let (mut pos, mut o) = (start, orientation);
+                let outside = path
+                    .iter()
+                    .map(|dir| {
+                        (pos, o) = dir.apply(pos, o);
+                        pos
+                    })
+                    .filter(|p| !region.contains(*p))
+                    .collect::<Vec<_>>();
+                if outside.is_empty() || last_try {
+                    return Ok(path);
+                }
+                around.extend(outside);
+            }
+            Err(PathfindingError::SearchLimitReached(_)) if !last_try => {}
+            Err(err) => return Err(err.to_string()),
+        }
+        padding *= 2;
+    }
+    unreachable!("the last try always returns")
+}
+
+impl GoTo {
+    pub fn get_target(&self) -> Pos3 {
+        self.target
+    }
+
+    fn crosses(&self, pos: Pos3, orientation: Orientation, block: Pos3) -> bool {
+        self.cells(pos, orientation).any(|p| p == block)
+    }
+
+    /// Every block the rest of the route goes through, in order
+    fn cells(&self, pos: Pos3, orientation: Orientation) -> impl Iterator<Item = Pos3> + '_ {
+        let (mut pos, mut orientation) = self
+            .pending
+            .map(|(_, p, o)| (p, o))
+            .unwrap_or((pos, orientation));
+        self.path.iter().map(move |dir| {
+            (pos, orientation) = dir.apply(pos, orientation);
+            pos
+        })
+    }
+}
+
+impl ServerTurtle {
+    /// Plans a route to `target` and sends the first move, replaces any running go to
+    pub async fn start_go_to(&mut self, target: Pos3) -> Vec<GoToStatus> {
+        let mut go_to = GoTo {
+            target,
+            path: VecDeque::new(),
+            pending: None,
+            needs_replan: false,
+            replans: 0,
+            avoided: None,
+            making_way: None,
+        };
+        self.stop_go_to();
+        if let Err(reason) = self.plan_go_to(&mut go_to).await {
+            return vec![GoToStatus::Failed { target, reason }];
+        }
+        self.send_next_move(go_to).await
+    }
+
+    /// Drops the running go to and gives back the blocks the turtle reserved
+    pub fn stop_go_to(&mut self) {
+        self.go_to = None;
+        let mut reservations = self.reservations();
+        reservations.release(&self.world, self.index);
+        reservations.stop_waiting(&self.world, self.index);
+    }
+
+    /// Advances the running go to (if any), returns the status updates clients should get
+    pub async fn drive_go_to(&mut self, event: GoToEvent) -> Vec<GoToStatus> {
+        let Some(mut go_to) = self.go_to.take() else {
+            return Vec::new();
+        };
+        match event {
+            GoToEvent::Moved => {
+                match go_to.pending.take() {
+                    Some((_, pos, orientation))
+                        if pos == self.position && orientation == self.orientation => {}
+                    // somebody else moved the turtle
+                    _ => go_to.needs_replan = true,
+                }
+            }
+            GoToEvent::MoveFailed => {
+                go_to.pending = None;
+                go_to.needs_replan = true;
+            }
+            GoToEvent::BlockChanged(block) => {
+                go_to.needs_replan |= go_to.crosses(self.position, self.orientation, block);
+            }
+            GoToEvent::OthersMoved => {}
+        }
+        if go_to.pending.is_some() {
+            self.go_to = Some(go_to);
+            return Vec::new();
+        }
+        if go_to.path.is_empty() {
+            if let Some((turtles, cells)) = &go_to.making_way {
+                let routes = self.reservations().route_cells(&self.world, turtles);
+                if !routes.is_disjoint(cells) {
+                    self.go_to = Some(go_to);
+                    return Vec::new();
+                }
+                go_to.making_way = None;
+                go_to.needs_replan = true;
+            }
+        }
+
+        let target = go_to.target;
+        let mut updates = Vec::new();
+        if go_to.needs_replan {
+            go_to.needs_replan = false;
+            go_to.replans += 1;
+            if go_to.replans > MAX_REPLANS {
+                self.stop_go_to();
+                return vec![GoToStatus::Failed {
+                    target,
+                    reason: format!("gave up after {MAX_REPLANS} replans"),
+                }];
+            }
+            info!("replanning route of turtle {} to {:?}", self.index, target);
+            updates.push(GoToStatus::Replanning { target });
+            if let Err(reason) = self.plan_go_to(&mut go_to).await {
+                updates.push(GoToStatus::Failed { target, reason });
+                self.stop_go_to();
+                return updates;
+            }
+        }
+        updates.extend(self.send_next_move(go_to).await);
+        updates
+    }
+
+    async fn plan_go_to(&mut self, go_to: &mut GoTo) -> Result<(), String> {
+        let db = self.get_db();
+        let obstacles = self.reservations().obstacles(&self.world, self.index);
+        let path = find_route(
+            &db,
+            &self.world,
+            self.position,
+            self.orientation,
+            go_to.target,
+            obstacles,
+        )
+        .await?;
+        let cost = move_cost(&path);
+        if !self.has_unlimited_fuel() && cost > self.fuel as u32 {
+            return Err(format!(
+                "the route needs {cost} fuel, the turtle has {}",
+                self.fuel
+            ));
+        }
+        go_to.path = path.into();
+        Ok(())
+    }
+
+    /// Sends the next move, unless there is a turtle in the way
+    async fn send_next_move(&mut self, mut go_to: GoTo) -> Vec<GoToStatus> {
+        let target = go_to.target;
+        let Some(&dir) = go_to.path.front() else {
+            if go_to.making_way.is_some() {
+                // out of the way, it stays here until the others got past
+                self.reservations().release(&self.world, self.index);
+                self.go_to = Some(go_to);
+                return Vec::new();
+            }
+            self.stop_go_to();
+            return vec![match self.position == target {
+                true => GoToStatus::Arrived { target },
+                false => GoToStatus::Failed {
+                    target,
+                    reason: "route ended before reaching the target".into(),
+                },
+            }];
+        };
+        let (pos, orientation) = dir.apply(self.position, self.orientation);
+        let occupant = (pos != self.position)
+            .then(|| self.reservations().occupant(&self.world, self.index, pos))
+            .flatten();
+        if let Some((turtle, busy)) = occupant {
+            return Box::pin(self.wait_for(go_to, turtle, busy)).await;
+        }
+        go_to.path.pop_front();
+        go_to.pending = Some((dir, pos, orientation));
+        let cells = std::iter::once(pos)
+            .chain(go_to.cells(self.position, self.orientation))
+            .collect::<Vec<_>>();
+        self.reserve_way(cells);
+        let remaining_moves = go_to.path.len() as u32 + 1;
+        self.go_to = Some(go_to);
+        self.send_ws(S2TPackets::RunLuaCode(lua_for_move(dir).into()))
+            .await;
+        vec![GoToStatus::Moving {
+            target,
+            remaining_moves,
+        }]
+    }
+
+    /// Waits for `turtle` to get out of the way. One that isn't busy with anything is gone
+    /// around once, if the turtles wait for each other this one might have to make way
+    async fn wait_for(&mut self, mut go_to: GoTo, turtle: i32, busy: bool) -> Vec<GoToStatus> {
+        let target = go_to.target;
+        if !busy && go_to.avoided != Some(turtle) && go_to.making_way.is_none() {
+            go_to.avoided = Some(turtle);
+            let mut updates = vec![GoToStatus::Replanning { target }];
+            match self.plan_go_to(&mut go_to).await {
+                Ok(()) => updates.extend(self.send_next_move(go_to).await),
+                Err(reason) => {
+                    self.stop_go_to();
+                    updates.push(GoToStatus::Failed { target, reason });
+                }
+            }
+            return updates;
+        }
+        let (started, deadlock) = self.wait_on(turtle, true);
+        if let Some(turtles) = deadlock {
+            return self.make_way(go_to, turtles).await;
+        }
+        self.go_to = Some(go_to);
+        if !started {
+            return Vec::new();
+        }
+        info!("turtle {} waits for turtle {turtle}", self.index);
+        // the others might be waiting for this one
+        if let Err(err) = self.comm(TurtleCommBus::Waiting(self.world.clone())).await {
+            error!("{err}");
+        }
+        vec![GoToStatus::Waiting { target, turtle }]
+    }
+
+    /// Gets out of the way of `turtles`, which wait for this one, to the closest block off their
+    /// routes
+    async fn make_way(&mut self, mut go_to: GoTo, turtles: Vec<i32>) -> Vec<GoToStatus> {
+        let target = go_to.target;
+        let db = self.get_db();
+        let (obstacles, avoid) = {
+            let mut reservations = self.reservations();
+            reservations.stop_waiting(&self.world, self.index);
+            (
+                reservations.obstacles(&self.world, self.index),
+                reservations.route_cells(&self.world, &turtles),
+            )
+        };
+        let mut own = go_to
+            .cells(self.position, self.orientation)
+            .collect::<HashSet<_>>();
+        info!("turtle {} makes way for {turtles:?}", self.index);
+        let way_out = find_way_out(
+            &db,
+            &self.world,
+            self.position,
+            self.orientation,
+            obstacles,
+            avoid,
+        )
+        .await;
+        let path = match way_out {
+            Ok(path) => path,
+            Err(err) => {
+                self.stop_go_to();
+                let reason = format!("stuck with turtles {turtles:?} and no way out: {err}");
+                return vec![GoToStatus::Failed { target, reason }];
+            }
+        };
+        go_to.path = path.into();
+        // it has to go back the same way
+        own.extend(go_to.cells(self.position, self.orientation));
+        own.insert(self.position);
+        go_to.making_way = Some((turtles.clone(), own));
+        let mut updates = vec![GoToStatus::MakingWay { target, turtles }];
+        updates.extend(self.send_next_move(go_to).await);
+        updates
+    }
+}
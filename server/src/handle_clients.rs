@@ -2,7 +2,10 @@ use std::net::SocketAddr;
 
 use futures_util::StreamExt;
 use log::info;
-use tokio::{net::TcpStream, sync::mpsc::UnboundedSender};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc::UnboundedSender,
+};
 
 use crate::data_types::server_turtle::{WsRecv, WsSend};
 
@@ -19,3 +22,13 @@ pub async fn handle_connection(
     _ = client_connected.send(ws_stream.split());
     Ok(())
 }
+
+/// Hands every client that connects to `listener` over to the connection manager
+pub async fn accept_connections(
+    listener: TcpListener,
+    client_connected: UnboundedSender<(WsSend, WsRecv)>,
+) {
+    while let Ok((stream, addr)) = listener.accept().await {
+        tokio::spawn(handle_connection(stream, addr, client_connected.clone()));
+    }
+}
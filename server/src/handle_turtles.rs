@@ -3,8 +3,11 @@ use std::net::SocketAddr;
 use common::turtle_packets::{S2TPackets, SetupInfoData, T2SPackets};
 use futures::{SinkExt, StreamExt};
 use log::info;
-use serde_json::{from_str, to_string_pretty};
-use tokio::{net::TcpStream, sync::mpsc::UnboundedSender};
+use serde_json::{from_str, to_string};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc::UnboundedSender,
+};
 use tungstenite::Message;
 
 use crate::data_types::server_turtle::{WsRecv, WsSend};
@@ -25,13 +28,13 @@ pub async fn handle_connection(
     let (mut outgoing, mut incoming) = ws_stream.split();
     outgoing
         .send(tungstenite::Message::Text(
-            to_string_pretty(&S2TPackets::GetSetupInfo).unwrap(),
+            to_string(&S2TPackets::GetSetupInfo).unwrap(),
         ))
         .await
         .unwrap();
     while let Some(Ok(msg)) = incoming.next().await {
         if let Message::Text(msg) = msg {
-            info!("{}",msg);
+            info!("{}", msg);
             match from_str::<T2SPackets>(&msg).unwrap() {
                 T2SPackets::Batch(data) => {
                     let info = match data.as_slice() {
@@ -60,3 +63,18 @@ pub async fn handle_connection(
     }
     Ok(())
 }
+
+/// Hands every turtle that connects to `listener` over to the connection manager once it sent its
+/// setup info
+pub async fn accept_connections(
+    listener: TcpListener,
+    turtle_connected_send: UnboundedSender<(SetupInfoData, Vec<T2SPackets>, WsSend, WsRecv)>,
+) {
+    while let Ok((stream, addr)) = listener.accept().await {
+        tokio::spawn(handle_connection(
+            stream,
+            addr,
+            turtle_connected_send.clone(),
+        ));
+    }
+}
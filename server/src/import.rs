@@ -0,0 +1,579 @@
+//! Reads schematics into blueprints: Sponge schematics (`.schem`, versions 1 to 3), vanilla
+//! structures (`.nbt`) and Litematica schematics (`.litematic`).
+//!
+//! Air in a schematic means the spot should be empty. Structure voids and the parts of a
+//! structure or litematic without blocks mean the blueprint doesn't care what is there.
+
+use std::{collections::BTreeMap, io::Read, str::FromStr};
+
+use common::{
+    blueprint::Blueprint,
+    world_data::{BlockState, PaletteEntry},
+    Pos3,
+};
+use flate2::read::GzDecoder;
+use thiserror::Error;
+
+use crate::{
+    db::{save_blueprint, DB},
+    export::{parse_pos, MAX_EXPORT_VOLUME},
+    nbt::{read_named, Tag},
+};
+
+const AIRS: &[&str] = &["minecraft:air", "minecraft:cave_air", "minecraft:void_air"];
+const STRUCTURE_VOID: &str = "minecraft:structure_void";
+
+/// Uncompressed schematics bigger than this are refused before parsing them
+const MAX_FILE_SIZE: u64 = 256 << 20;
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+    #[error("unknown format {0:?}, expected schem, nbt or litematic")]
+    UnknownFormat(String),
+    #[error("not a valid schematic: {0}")]
+    Invalid(String),
+    #[error("the schematic has {0} blocks, at most {MAX_EXPORT_VOLUME} can be imported")]
+    TooBig(u64),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Db(#[from] sqlx::Error),
+}
+
+fn invalid(msg: impl Into<String>) -> ImportError {
+    ImportError::Invalid(msg.into())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// Sponge schematic, any version
+    Schematic,
+    /// Vanilla structure file
+    Structure,
+    Litematic,
+}
+
+impl ImportFormat {
+    /// Guesses by the extension of `file_name`
+    pub fn from_file_name(file_name: &str) -> Result<ImportFormat, ImportError> {
+        let extension = file_name.rsplit_once('.').map_or("", |(_, e)| e);
+        extension.to_lowercase().parse()
+    }
+}
+
+impl FromStr for ImportFormat {
+    type Err = ImportError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "schem" | "sponge" => Ok(ImportFormat::Schematic),
+            "nbt" | "structure" => Ok(ImportFormat::Structure),
+            "litematic" => Ok(ImportFormat::Litematic),
+            _ => Err(ImportError::UnknownFormat(s.to_owned())),
+        }
+    }
+}
+
+/// Turns `bytes` into a blueprint whose min corner is at `anchor`
+pub fn import(
+    bytes: &[u8],
+    format: ImportFormat,
+    name: &str,
+    world: &str,
+    anchor: Pos3,
+) -> Result<Blueprint, ImportError> {
+    let raw = gunzip(bytes)?;
+    let (_, root) = read_named(&mut raw.as_slice())?;
+    let mut blueprint = match format {
+        ImportFormat::Schematic => schematic(&root)?,
+        ImportFormat::Structure => structure(&root)?,
+        ImportFormat::Litematic => litematic(&root)?,
+    };
+    blueprint.name = name.to_owned();
+    blueprint.world = world.to_owned();
+    blueprint.anchor = anchor;
+    Ok(blueprint)
+}
+
+/// Imports and stores the blueprint, replacing one with the same name. Parsing big schematics
+/// takes a while, so it happens off the async threads
+pub async fn import_to_db(
+    db: &DB,
+    bytes: Vec<u8>,
+    format: ImportFormat,
+    name: &str,
+    world: &str,
+    anchor: Pos3,
+) -> Result<Blueprint, ImportError> {
+    if name.trim().is_empty() {
+        return Err(invalid("blueprints need a name"));
+    }
+    let (name, world) = (name.to_owned(), world.to_owned());
+    let blueprint =
+        tokio::task::spawn_blocking(move || import(&bytes, format, &name, &world, anchor))
+            .await
+            .map_err(std::io::Error::from)??;
+    save_blueprint(db, &blueprint).await?;
+    Ok(blueprint)
+}
+
+/// Every format is gzipped when minecraft or a mod writes it, plain nbt is accepted too
+fn gunzip(bytes: &[u8]) -> Result<Vec<u8>, ImportError> {
+    if !bytes.starts_with(&[0x1f, 0x8b]) {
+        return Ok(bytes.to_vec());
+    }
+    let mut raw = Vec::new();
+    GzDecoder::new(bytes)
+        .take(MAX_FILE_SIZE + 1)
+        .read_to_end(&mut raw)?;
+    match raw.len() as u64 > MAX_FILE_SIZE {
+        true => Err(invalid("too big once unpacked")),
+        false => Ok(raw),
+    }
+}
+
+fn field<'a>(tag: &'a Tag, name: &str) -> Result<&'a Tag, ImportError> {
+    tag.get(name)
+        .ok_or_else(|| invalid(format!("{name} missing")))
+}
+
+fn int_field(tag: &Tag, name: &str) -> Result<i64, ImportError> {
+    field(tag, name)?
+        .as_int()
+        .ok_or_else(|| invalid(format!("{name} is not a number")))
+}
+
+fn list<'a>(tag: &'a Tag, name: &str) -> Result<&'a [Tag], ImportError> {
+    match field(tag, name)? {
+        Tag::List(items) => Ok(items),
+        _ => Err(invalid(format!("{name} is not a list"))),
+    }
+}
+
+/// Empty blueprint of `size`, after checking it is small enough
+fn empty(size: [i64; 3]) -> Result<Blueprint, ImportError> {
+    if size.iter().any(|s| !(1..=i32::MAX as i64).contains(s)) {
+        return Err(invalid(format!("size {size:?}")));
+    }
+    let volume = size.iter().map(|s| *s as u64).product::<u64>();
+    if volume > MAX_EXPORT_VOLUME {
+        return Err(ImportError::TooBig(volume));
+    }
+    let size = Pos3::new(size[0] as i32, size[1] as i32, size[2] as i32);
+    Ok(Blueprint::new("", "", Pos3::zero(), size))
+}
+
+/// What the blueprint wants for a block, None for structure voids
+fn entry(id: &str, properties: BTreeMap<String, String>) -> Option<PaletteEntry> {
+    if id == STRUCTURE_VOID {
+        return None;
+    }
+    let is_air = AIRS.contains(&id);
+    Some(PaletteEntry {
+        id: match is_air {
+            true => String::new(),
+            false => id.to_owned(),
+        },
+        is_air,
+        state: BlockState {
+            properties: match is_air {
+                true => BTreeMap::new(),
+                false => properties,
+            },
+            tags: Default::default(),
+        },
+    })
+}
+
+/// `minecraft:oak_stairs[facing=north,half=bottom]`, how Sponge schematics name blocks
+fn parse_block_string(s: &str) -> Result<Option<PaletteEntry>, ImportError> {
+    let Some((id, rest)) = s.split_once('[') else {
+        return Ok(entry(s, BTreeMap::new()));
+    };
+    let properties = rest
+        .strip_suffix(']')
+        .ok_or_else(|| invalid(format!("block {s:?}")))?
+        .split(',')
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            p.split_once('=')
+                .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                .ok_or_else(|| invalid(format!("block {s:?}")))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(entry(id, properties))
+}
+
+/// `{Name: "minecraft:oak_stairs", Properties: {facing: "north"}}`, how structures and
+/// litematics name blocks
+fn parse_block_compound(tag: &Tag) -> Result<Option<PaletteEntry>, ImportError> {
+    let id = field(tag, "Name")?
+        .as_str()
+        .ok_or_else(|| invalid("Name is not a string"))?;
+    let mut properties = BTreeMap::new();
+    if let Some(Tag::Compound(entries)) = tag.get("Properties") {
+        for (k, v) in entries {
+            let v = v
+                .as_str()
+                .ok_or_else(|| invalid(format!("property {k} is not a string")))?;
+            properties.insert(k.clone(), v.to_owned());
+        }
+    }
+    Ok(entry(id, properties))
+}
+
+/// Palette keys of the blueprint by the palette index of the file, 0 for "don't care"
+fn palette_keys(
+    blueprint: &mut Blueprint,
+    entries: Vec<(usize, Option<PaletteEntry>)>,
+) -> Result<Vec<u16>, ImportError> {
+    let len = entries.iter().map(|(i, _)| i + 1).max().unwrap_or(0);
+    if len > u16::MAX as usize {
+        return Err(invalid(format!("palette of {len} blocks")));
+    }
+    let mut keys = vec![0; len];
+    for (i, entry) in entries {
+        if let Some(entry) = entry {
+            keys[i] = blueprint.palette_key(entry);
+        }
+    }
+    Ok(keys)
+}
+
+fn key(keys: &[u16], index: usize) -> Result<u16, ImportError> {
+    keys.get(index)
+        .copied()
+        .ok_or_else(|| invalid(format!("palette index {index} out of bounds")))
+}
+
+/// Every position of a blueprint of `size` in the order all formats store them, x first, then
+/// z, then y
+fn positions(size: Pos3) -> impl Iterator<Item = Pos3> {
+    (0..size.y).flat_map(move |y| {
+        (0..size.z).flat_map(move |z| (0..size.x).map(move |x| Pos3::new(x, y, z)))
+    })
+}
+
+fn schematic(root: &Tag) -> Result<Blueprint, ImportError> {
+    // version 3 nests everything one level deeper
+    let schem = root.get("Schematic").unwrap_or(root);
+    let dimension = |name| int_field(schem, name).map(|s| s as u16 as i64);
+    let mut blueprint = empty([
+        dimension("Width")?,
+        dimension("Height")?,
+        dimension("Length")?,
+    ])?;
+    let (palette, data) = match schem.get("Blocks") {
+        Some(blocks) => (field(blocks, "Palette")?, field(blocks, "Data")?),
+        None => (field(schem, "Palette")?, field(schem, "BlockData")?),
+    };
+    let Tag::Compound(palette) = palette else {
+        return Err(invalid("Palette is not a compound"));
+    };
+    let entries = palette
+        .iter()
+        .map(|(name, index)| {
+            let index = index
+                .as_int()
+                .and_then(|i| usize::try_from(i).ok())
+                .ok_or_else(|| invalid(format!("palette index of {name}")))?;
+            Ok((index, parse_block_string(name)?))
+        })
+        .collect::<Result<_, ImportError>>()?;
+    let keys = palette_keys(&mut blueprint, entries)?;
+    let Tag::ByteArray(data) = data else {
+        return Err(invalid("BlockData is not a byte array"));
+    };
+    let mut data = data.iter().map(|b| *b as u8);
+    for pos in positions(blueprint.size()) {
+        // varint
+        let mut index = 0usize;
+        for shift in (0..).step_by(7) {
+            let byte = data.next().ok_or_else(|| invalid("BlockData too short"))?;
+            if shift > 28 {
+                return Err(invalid("BlockData index too big"));
+            }
+            index |= (byte as usize & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        blueprint.set_key(pos, key(&keys, index)?);
+    }
+    Ok(blueprint)
+}
+
+fn int_list(tag: &Tag, name: &str) -> Result<[i64; 3], ImportError> {
+    let ints = list(tag, name)?
+        .iter()
+        .map(Tag::as_int)
+        .collect::<Option<Vec<_>>>();
+    ints.and_then(|i| i.try_into().ok())
+        .ok_or_else(|| invalid(format!("{name} is not x, y and z")))
+}
+
+fn structure(root: &Tag) -> Result<Blueprint, ImportError> {
+    let mut blueprint = empty(int_list(root, "size")?)?;
+    // structures with several palettes pick one at random when placed, the first has to do
+    let palette = match root.get("palettes") {
+        Some(Tag::List(palettes)) => match palettes.first() {
+            Some(Tag::List(palette)) => palette.as_slice(),
+            _ => return Err(invalid("palettes is empty")),
+        },
+        _ => list(root, "palette")?,
+    };
+    let entries = palette
+        .iter()
+        .enumerate()
+        .map(|(i, tag)| Ok((i, parse_block_compound(tag)?)))
+        .collect::<Result<_, ImportError>>()?;
+    let keys = palette_keys(&mut blueprint, entries)?;
+    for block in list(root, "blocks")? {
+        let [x, y, z] = int_list(block, "pos")?;
+        let state = int_field(block, "state")?;
+        let pos = Pos3::new(x as i32, y as i32, z as i32);
+        blueprint.set_key(pos, key(&keys, state as usize)?);
+    }
+    Ok(blueprint)
+}
+
+/// Litematica's `x`, `y`, `z` compounds
+fn xyz(tag: &Tag, name: &str) -> Result<[i64; 3], ImportError> {
+    let tag = field(tag, name)?;
+    Ok([
+        int_field(tag, "x")?,
+        int_field(tag, "y")?,
+        int_field(tag, "z")?,
+    ])
+}
+
+/// Litematics pack palette indices into longs with as few bits as possible, but at least 2.
+/// Indices can be split across two longs
+fn unpack(longs: &[i64], bits: u32, index: usize) -> Option<usize> {
+    let start = index * bits as usize;
+    let (word, offset) = (start / 64, start % 64);
+    let mask = (1u64 << bits) - 1;
+    let mut value = *longs.get(word)? as u64 >> offset;
+    if offset + bits as usize > 64 {
+        value |= (*longs.get(word + 1)? as u64) << (64 - offset);
+    }
+    Some((value & mask) as usize)
+}
+
+fn litematic(root: &Tag) -> Result<Blueprint, ImportError> {
+    let Some(Tag::Compound(regions)) = root.get("Regions") else {
+        return Err(invalid("Regions missing"));
+    };
+    if regions.is_empty() {
+        return Err(invalid("no regions"));
+    }
+    // a region's size is negative along axes it was selected "backwards" along, then its
+    // position is the max corner there
+    let mut boxes = Vec::new();
+    for (_, region) in regions {
+        let pos = xyz(region, "Position")?;
+        let size = xyz(region, "Size")?;
+        let min: [i64; 3] = std::array::from_fn(|i| pos[i] + size[i].min(0) + (size[i] < 0) as i64);
+        boxes.push((region, min, size.map(i64::abs)));
+    }
+    let min: [i64; 3] = std::array::from_fn(|i| boxes.iter().map(|b| b.1[i]).min().unwrap());
+    let max: [i64; 3] =
+        std::array::from_fn(|i| boxes.iter().map(|b| b.1[i] + b.2[i]).max().unwrap());
+    let mut blueprint = empty(std::array::from_fn(|i| max[i] - min[i]))?;
+    for (region, region_min, size) in boxes {
+        let entries = list(region, "BlockStatePalette")?
+            .iter()
+            .enumerate()
+            .map(|(i, tag)| Ok((i, parse_block_compound(tag)?)))
+            .collect::<Result<Vec<_>, ImportError>>()?;
+        let bits = (entries.len().max(2) as u32 - 1).ilog2() + 1;
+        let bits = bits.max(2);
+        let keys = palette_keys(&mut blueprint, entries)?;
+        let Tag::LongArray(longs) = field(region, "BlockStates")? else {
+            return Err(invalid("BlockStates is not a long array"));
+        };
+        let offset = Pos3::new(
+            (region_min[0] - min[0]) as i32,
+            (region_min[1] - min[1]) as i32,
+            (region_min[2] - min[2]) as i32,
+        );
+        let size = Pos3::new(size[0] as i32, size[1] as i32, size[2] as i32);
+        for (i, pos) in positions(size).enumerate() {
+            let index = unpack(longs, bits, i).ok_or_else(|| invalid("BlockStates too short"))?;
+            blueprint.set_key(pos + offset, key(&keys, index)?);
+        }
+    }
+    Ok(blueprint)
+}
+
+/// `backend import <world> <name> <file> <x,y,z>`, the format is taken from the extension
+pub async fn cli(db: &DB, args: &[String]) -> anyhow::Result<()> {
+    let [world, name, file, anchor] = args else {
+        anyhow::bail!("usage: import <world> <name> <file> <x,y,z>");
+    };
+    let format = ImportFormat::from_file_name(file)?;
+    let bytes = std::fs::read(file)?;
+    let blueprint = import_to_db(db, bytes, format, name, world, parse_pos(anchor)?).await?;
+    println!(
+        "imported {} blocks from {file} as {name} in {world}",
+        blueprint.planned()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{
+        turtle_packets::InspectInfo,
+        world_data::{Block, World},
+    };
+
+    use super::*;
+    use crate::{
+        export::{export, ExportFormat, Region},
+        nbt::write_named,
+    };
+
+    fn test_world() -> World {
+        let mut world = World::new("test");
+        let mut stairs = InspectInfo::new("minecraft:oak_stairs");
+        stairs.state.insert("facing".into(), "north".into());
+        world.set_block(Block::inspected(Some(stairs), &Pos3::new(1, 0, 2), "test"));
+        world.set_block(Block::new(
+            Some("minecraft:stone".into()),
+            &Pos3::new(0, 0, 0),
+            "test",
+        ));
+        world.set_block(Block::new(None, &Pos3::new(0, 1, 0), "test"));
+        world
+    }
+
+    fn id_at(blueprint: &Blueprint, pos: Pos3) -> Option<(&str, bool)> {
+        blueprint.get(&pos).map(|e| (e.id.as_str(), e.is_air))
+    }
+
+    #[test]
+    fn exports_can_be_imported() {
+        let region = Region::new(Pos3::new(0, 0, 0), Pos3::new(1, 1, 2));
+        let anchor = Pos3::new(100, 64, -20);
+        for format in [ExportFormat::Schematic, ExportFormat::Structure] {
+            let bytes = export(&test_world(), &region, format).unwrap();
+            let format = format.extension().parse().unwrap();
+            let blueprint = import(&bytes, format, "hut", "test", anchor).unwrap();
+            assert_eq!(blueprint.size(), Pos3::new(2, 2, 3));
+            assert_eq!(id_at(&blueprint, anchor), Some(("minecraft:stone", false)));
+            let stairs = blueprint.get(&(anchor + Pos3::new(1, 0, 2))).unwrap();
+            assert_eq!(stairs.id, "minecraft:oak_stairs");
+            assert_eq!(stairs.state.get("facing"), Some("north"));
+            assert_eq!(
+                id_at(&blueprint, anchor + Pos3::new(0, 1, 0)),
+                Some(("", true))
+            );
+            // schematics make unknown blocks air, structures leave them out
+            let unknown = id_at(&blueprint, anchor + Pos3::new(1, 1, 1));
+            match format {
+                ImportFormat::Structure => assert_eq!(unknown, None),
+                _ => assert_eq!(unknown, Some(("", true))),
+            }
+        }
+    }
+
+    fn gzip(name: &str, tag: &Tag) -> Vec<u8> {
+        let mut raw = Vec::new();
+        write_named(&mut raw, name, tag).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, &raw).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn block(name: &str) -> Tag {
+        Tag::compound([("Name", Tag::String(name.into()))])
+    }
+
+    fn xyz_tag(x: i32, y: i32, z: i32) -> Tag {
+        Tag::compound([("x", Tag::Int(x)), ("y", Tag::Int(y)), ("z", Tag::Int(z))])
+    }
+
+    #[test]
+    fn litematics_are_unpacked() {
+        // 5 palette entries take 3 bits, so the 22nd index starts at bit 63 and is split across
+        // both longs
+        let palette = ["minecraft:air", "minecraft:stone", "minecraft:dirt"]
+            .into_iter()
+            .chain(["minecraft:glass", STRUCTURE_VOID])
+            .map(block)
+            .collect::<Vec<_>>();
+        let indices = (0..24u64).map(|i| i % 5).collect::<Vec<_>>();
+        let mut longs = vec![0u64; 2];
+        for (i, v) in indices.iter().enumerate() {
+            let start = i * 3;
+            longs[start / 64] |= v << (start % 64);
+            if start % 64 + 3 > 64 {
+                longs[start / 64 + 1] |= v >> (64 - start % 64);
+            }
+        }
+        let region = Tag::compound([
+            // selected from x=3 down to x=0
+            ("Position", xyz_tag(3, 0, 0)),
+            ("Size", xyz_tag(-4, 3, 2)),
+            ("BlockStatePalette", Tag::List(palette)),
+            (
+                "BlockStates",
+                Tag::LongArray(longs.into_iter().map(|l| l as i64).collect()),
+            ),
+        ]);
+        let root = Tag::compound([
+            ("Version", Tag::Int(6)),
+            ("Regions", Tag::compound([("main", region)])),
+        ]);
+        let bytes = gzip("", &root);
+        let blueprint = import(&bytes, ImportFormat::Litematic, "l", "test", Pos3::zero()).unwrap();
+        assert_eq!(blueprint.size(), Pos3::new(4, 3, 2));
+        for (i, pos) in positions(blueprint.size()).enumerate() {
+            let expected = match indices[i] {
+                0 => Some(("", true)),
+                1 => Some(("minecraft:stone", false)),
+                2 => Some(("minecraft:dirt", false)),
+                3 => Some(("minecraft:glass", false)),
+                _ => None,
+            };
+            assert_eq!(id_at(&blueprint, pos), expected, "{pos:?}");
+        }
+    }
+
+    #[test]
+    fn broken_files_are_refused() {
+        let bytes = gzip("", &Tag::compound([("size", Tag::List(Vec::new()))]));
+        assert!(matches!(
+            import(&bytes, ImportFormat::Structure, "s", "test", Pos3::zero()),
+            Err(ImportError::Invalid(_))
+        ));
+        let huge = Tag::compound([
+            ("Width", Tag::Short(-1)),
+            ("Height", Tag::Short(-1)),
+            ("Length", Tag::Short(-1)),
+        ]);
+        assert!(matches!(
+            import(
+                &gzip("Schematic", &huge),
+                ImportFormat::Schematic,
+                "s",
+                "test",
+                Pos3::zero()
+            ),
+            Err(ImportError::TooBig(_))
+        ));
+        assert!(import(
+            &[1, 2, 3],
+            ImportFormat::Schematic,
+            "s",
+            "test",
+            Pos3::zero()
+        )
+        .is_err());
+        assert!(ImportFormat::from_file_name("house.schematic").is_err());
+        assert_eq!(
+            ImportFormat::from_file_name("House.Litematic").unwrap(),
+            ImportFormat::Litematic
+        );
+    }
+}
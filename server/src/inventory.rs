@@ -0,0 +1,119 @@
+//! Inventory actions clients ask turtles for. The server only checks them and finds the way to
+//! the other turtle of a give, the turtle does them and answers with InventoryResult.
+
+use common::{
+    turtle::{InventoryAction, MoveDirection, Orientation},
+    Pos3,
+};
+
+fn check_slot(slot: u8) -> Result<(), String> {
+    match (1..=16).contains(&slot) {
+        true => Ok(()),
+        false => Err(format!("slot {slot} is not between 1 and 16")),
+    }
+}
+
+fn check_side(direction: MoveDirection) -> Result<(), String> {
+    match direction {
+        MoveDirection::Forward | MoveDirection::Up | MoveDirection::Down => Ok(()),
+        _ => Err(format!(
+            "can't reach {direction:?}, only Forward, Up and Down"
+        )),
+    }
+}
+
+/// Why the turtle can't do `action` no matter what its inventory holds
+pub fn check(action: &InventoryAction) -> Result<(), String> {
+    match *action {
+        InventoryAction::Select { slot } | InventoryAction::Give { slot, .. } => check_slot(slot),
+        InventoryAction::Drop {
+            slot, direction, ..
+        }
+        | InventoryAction::Suck {
+            slot, direction, ..
+        } => check_slot(slot).and(check_side(direction)),
+        InventoryAction::Split { from, to, .. } | InventoryAction::Merge { from, to } => {
+            check_slot(from).and(check_slot(to))?;
+            match from == to {
+                true => Err(format!("slot {from} can't go onto itself")),
+                false => Ok(()),
+            }
+        }
+    }
+}
+
+/// Where the turtle at `pos` facing `orientation` has to drop things for them to land at
+/// `other`. Left, Right and Back mean turning first, `None` if `other` is not right next to it
+pub fn towards(pos: Pos3, orientation: Orientation, other: Pos3) -> Option<MoveDirection> {
+    let offset = other - pos;
+    if offset == Pos3::new(0, 1, 0) {
+        return Some(MoveDirection::Up);
+    }
+    if offset == Pos3::new(0, -1, 0) {
+        return Some(MoveDirection::Down);
+    }
+    let facing = |turns: &[MoveDirection]| {
+        turns
+            .iter()
+            .fold(orientation, |o, turn| turn.apply(pos, o).1)
+            .get_forward_vec()
+    };
+    [
+        (MoveDirection::Forward, facing(&[])),
+        (MoveDirection::Right, facing(&[MoveDirection::Right])),
+        (MoveDirection::Left, facing(&[MoveDirection::Left])),
+        (
+            MoveDirection::Back,
+            facing(&[MoveDirection::Right, MoveDirection::Right]),
+        ),
+    ]
+    .into_iter()
+    .find(|(_, forward)| *forward == offset)
+    .map(|(direction, _)| direction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn towards_turns_to_the_other_turtle() {
+        let pos = Pos3::new(0, 0, 0);
+        let north = Orientation::North;
+        let forward = north.get_forward_vec();
+        assert_eq!(towards(pos, north, forward), Some(MoveDirection::Forward));
+        assert_eq!(
+            towards(pos, north, pos - forward),
+            Some(MoveDirection::Back)
+        );
+        assert_eq!(
+            towards(pos, north, Pos3::new(0, -1, 0)),
+            Some(MoveDirection::Down)
+        );
+        let right = MoveDirection::Right.apply(pos, north).1.get_forward_vec();
+        assert_eq!(towards(pos, north, right), Some(MoveDirection::Right));
+        assert_eq!(towards(pos, north, pos - right), Some(MoveDirection::Left));
+        assert_eq!(towards(pos, north, forward + forward), None);
+        assert_eq!(towards(pos, north, forward + right), None);
+    }
+
+    #[test]
+    fn actions_need_real_slots_and_sides() {
+        assert!(check(&InventoryAction::Select { slot: 16 }).is_ok());
+        assert!(check(&InventoryAction::Select { slot: 0 }).is_err());
+        let drop = |direction| InventoryAction::Drop {
+            slot: 1,
+            direction,
+            count: None,
+        };
+        assert!(check(&drop(MoveDirection::Up)).is_ok());
+        assert!(check(&drop(MoveDirection::Left)).is_err());
+        assert!(check(&InventoryAction::Merge { from: 2, to: 2 }).is_err());
+        assert!(check(&InventoryAction::Split {
+            from: 2,
+            to: 17,
+            count: 1
+        })
+        .is_err());
+    }
+}
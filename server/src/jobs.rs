@@ -0,0 +1,826 @@
+//! Jobs a turtle works through on its own. A job is turned into a list of steps up front, after
+//! each step the index of the next one is stored, so the job goes on from there after a
+//! reconnect or a server restart. Before every step the turtle makes sure it has the fuel to get
+//! back, see [`crate::fuel`]. Surveys are the exception, they plan one viewpoint at a time, see
+//! [`crate::survey`].
+
+use std::{collections::HashSet, sync::Arc};
+
+use common::{
+    client_packets::{GoToStatus, JobKind, JobStatus, TunnelSize},
+    turtle::{get_rotated_orientation, MoveDirection, Orientation, TurnDir},
+    turtle_packets::S2TPackets,
+    world_data::{Block, World},
+    Pos3,
+};
+use log::{error, info};
+
+use crate::{
+    connection_manager::TurtleCommBus,
+    data_types::server_turtle::ServerTurtle,
+    db::{delete_job, load_region, save_job_step},
+    fuel::{FUEL_MARGIN, REESTIMATE_AFTER},
+    go_to::lua_for_move,
+    survey::{self, coverage, next_viewpoint},
+};
+
+const UP: Pos3 = Pos3 { x: 0, y: 1, z: 0 };
+const ONE: Pos3 = Pos3 { x: 1, y: 1, z: 1 };
+
+/// Jobs with more steps than this are refused
+const MAX_STEPS: usize = 1 << 17;
+
+/// How often a turtle tries to move into a dug out block, falling gravel needs a few
+const MAX_RETRIES: u32 = 8;
+
+/// How many of the next blocks a job moves into are reserved for it, see
+/// [`crate::reservations`]
+const RESERVED_STEPS: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Digs the neighbour if there is something and moves into it
+    DigTo(Pos3),
+    /// Digs the neighbour and stays
+    Dig(Pos3),
+    /// Looks at the neighbour
+    Inspect(Pos3),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Step {
+    /// Where the turtle has to stand, the target of the action is right next to it
+    pub from: Pos3,
+    pub action: Action,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Phase {
+    /// Going to the start of the step
+    Walking,
+    Turning,
+    /// Waiting for the result of the action
+    Acting,
+    /// Burning fuel from the inventory, the turtle had this much before
+    Refueling(i32),
+    /// Walking home for fuel
+    GoingHome,
+    /// Taking fuel out of the chest below the home
+    Restocking,
+    /// Another turtle is in the block it has to move into
+    Waiting,
+}
+
+pub struct Job {
+    kind: JobKind,
+    steps: Vec<Step>,
+    step: usize,
+    phase: Phase,
+    retries: u32,
+    /// Moves it takes to get back from where the turtle was and its fuel at that time. The
+    /// turtle can always walk back the way it came, so every fuel burnt since adds one move
+    return_cost: Option<(u32, i32)>,
+    /// Percentage of a survey's box that was known when its steps were planned
+    coverage: u8,
+    /// Viewpoints a survey could not get to
+    unreachable: HashSet<Pos3>,
+    /// The blocks of a survey's box and the ones around it, loaded when the first viewpoint is
+    /// planned and kept up to date from then on
+    known: Option<Arc<World>>,
+}
+
+pub enum JobEvent {
+    GoTo(GoToStatus),
+    Moved,
+    MoveFailed,
+    DigFailed(MoveDirection),
+    BlockChanged(Block),
+    InventoryChanged,
+    /// Another turtle of the world moved or went away
+    OthersMoved,
+}
+
+/// What clients should hear about, a job also moves the turtle with go tos
+pub enum JobUpdate {
+    GoTo(GoToStatus),
+    Job(JobStatus),
+}
+
+impl Job {
+    fn status(&self) -> JobStatus {
+        match self.kind {
+            JobKind::Survey { .. } => JobStatus::Surveying {
+                kind: self.kind.clone(),
+                coverage: self.coverage,
+            },
+            _ => JobStatus::Running {
+                kind: self.kind.clone(),
+                step: self.step as u32,
+                steps: self.steps.len() as u32,
+            },
+        }
+    }
+
+    /// Index of the step the job is at
+    pub fn step(&self) -> u32 {
+        self.step as u32
+    }
+
+    /// The next blocks the job moves into
+    fn upcoming_cells(&self) -> Vec<Pos3> {
+        self.steps[self.step..]
+            .iter()
+            .filter_map(|step| match step.action {
+                Action::DigTo(to) => Some(to),
+                Action::Dig(_) | Action::Inspect(_) => None,
+            })
+            .take(RESERVED_STEPS)
+            .collect()
+    }
+
+    /// A survey goes on without the viewpoint of the current step, other jobs can't skip steps
+    fn skip_viewpoint(&mut self) -> bool {
+        if !matches!(self.kind, JobKind::Survey { .. }) {
+            return false;
+        }
+        self.unreachable.insert(self.steps[self.step].from);
+        self.steps.clear();
+        self.step = 0;
+        true
+    }
+
+    /// Keeps the blocks a survey knows about current
+    fn note_block(&mut self, block: &Block) {
+        let (JobKind::Survey { min, max }, Some(known)) = (&self.kind, &mut self.known) else {
+            return;
+        };
+        if survey::contains(*min - ONE, *max + ONE, block.pos) {
+            Arc::make_mut(known).set_block(block.clone());
+        }
+    }
+}
+
+struct Planner {
+    pos: Pos3,
+    steps: Vec<Step>,
+}
+
+impl Planner {
+    fn new(pos: Pos3) -> Self {
+        Self {
+            pos,
+            steps: Vec::new(),
+        }
+    }
+
+    fn dig_to(&mut self, to: Pos3) {
+        self.steps.push(Step {
+            from: self.pos,
+            action: Action::DigTo(to),
+        });
+        self.pos = to;
+    }
+
+    fn dig(&mut self, at: Pos3) {
+        self.steps.push(Step {
+            from: self.pos,
+            action: Action::Dig(at),
+        });
+    }
+
+    /// Digs a straight line to `to`, x first, then z, then y
+    fn dig_line(&mut self, to: Pos3) {
+        while self.pos != to {
+            let d = to - self.pos;
+            let next = if d.x != 0 {
+                Pos3::new(d.x.signum(), 0, 0)
+            } else if d.z != 0 {
+                Pos3::new(0, 0, d.z.signum())
+            } else {
+                Pos3::new(0, d.y.signum(), 0)
+            };
+            self.dig_to(self.pos + next);
+        }
+    }
+}
+
+/// Blocks in the box from `min` to `max`, `None` if it is inverted or the box and the blocks
+/// around it don't fit into the coordinates
+fn box_volume(min: Pos3, max: Pos3) -> Option<usize> {
+    [(min.x, max.x), (min.y, max.y), (min.z, max.z)]
+        .into_iter()
+        .try_fold(1usize, |volume, (low, high)| {
+            low.checked_sub(1)?;
+            high.checked_add(1)?;
+            let length = high.checked_sub(low).filter(|d| *d >= 0)? as usize + 1;
+            volume.checked_mul(length)
+        })
+}
+
+/// Both ends included, from `a` to `b`
+fn between(a: i32, b: i32) -> Vec<i32> {
+    if a <= b {
+        (a..=b).collect()
+    } else {
+        (b..=a).rev().collect()
+    }
+}
+
+fn side_vecs(direction: Orientation) -> (Pos3, Pos3) {
+    (
+        get_rotated_orientation(direction, TurnDir::Left).get_forward_vec(),
+        get_rotated_orientation(direction, TurnDir::Right).get_forward_vec(),
+    )
+}
+
+/// Every step of `kind`, in order
+pub fn plan_job(kind: &JobKind) -> Result<Vec<Step>, String> {
+    let steps = match *kind {
+        JobKind::Quarry { min, max } => {
+            if min.x > max.x || min.y > max.y || min.z > max.z {
+                return Err("the min corner of a quarry has to be below the max corner".into());
+            }
+            if box_volume(min, max).is_none_or(|volume| volume > MAX_STEPS) {
+                return Err("the quarry is too big".into());
+            }
+            let mut p = Planner::new(Pos3::new(min.x, max.y + 1, min.z));
+            for y in (min.y..=max.y).rev() {
+                p.dig_to(Pos3::new(p.pos.x, y, p.pos.z));
+                // back and forth over the layer, from the corner the last one ended in
+                let start_z = if p.pos.z == min.z { min.z } else { max.z };
+                let end_z = if start_z == min.z { max.z } else { min.z };
+                for z in between(start_z, end_z) {
+                    p.dig_line(Pos3::new(p.pos.x, y, z));
+                    let end_x = if p.pos.x == min.x { max.x } else { min.x };
+                    p.dig_line(Pos3::new(end_x, y, z));
+                }
+            }
+            p.steps
+        }
+        JobKind::Tunnel {
+            start,
+            direction,
+            length,
+            size,
+        } => {
+            if length as usize > MAX_STEPS / 16 {
+                return Err("the tunnel is too long".into());
+            }
+            let forward = direction.get_forward_vec();
+            let mut p = Planner::new(start);
+            match size {
+                TunnelSize::OneByTwo => {
+                    for i in 1..=length as i32 {
+                        let floor = start + forward.scale(i);
+                        p.dig_to(floor);
+                        p.dig(floor + UP);
+                    }
+                }
+                TunnelSize::ThreeByThree => {
+                    let (left, right) = side_vecs(direction);
+                    for i in 1..=length as i32 {
+                        let mid = start + forward.scale(i) + UP;
+                        if i == 1 {
+                            p.dig_to(mid - UP);
+                        }
+                        p.dig_to(mid);
+                        p.dig(mid + UP);
+                        p.dig(mid - UP);
+                        for side in [left, right] {
+                            p.dig_to(mid + side);
+                            p.dig(mid + side + UP);
+                            p.dig(mid + side - UP);
+                            p.dig_to(mid);
+                        }
+                    }
+                }
+            }
+            p.steps
+        }
+        JobKind::BranchMine {
+            start,
+            direction,
+            length,
+            spacing,
+            branch_length,
+        } => {
+            if spacing == 0 {
+                return Err("branches need a spacing of at least one".into());
+            }
+            let branches = (length / spacing) as usize;
+            if length as usize + branches * branch_length as usize * 6 > MAX_STEPS {
+                return Err("the branch mine is too big".into());
+            }
+            let forward = direction.get_forward_vec();
+            let (left, right) = side_vecs(direction);
+            let mut p = Planner::new(start);
+            for i in 1..=length as i32 {
+                let floor = start + forward.scale(i);
+                p.dig_to(floor);
+                p.dig(floor + UP);
+                if !(i as u32).is_multiple_of(spacing) {
+                    continue;
+                }
+                for side in [left, right] {
+                    for j in 1..=branch_length as i32 {
+                        p.dig_to(floor + side.scale(j));
+                        p.dig(floor + side.scale(j) + UP);
+                    }
+                    p.dig_line(floor);
+                }
+            }
+            p.steps
+        }
+        JobKind::Survey { min, max } => {
+            if min.x > max.x || min.y > max.y || min.z > max.z {
+                return Err("the min corner of a survey has to be below the max corner".into());
+            }
+            if box_volume(min, max).is_none_or(|volume| volume > MAX_STEPS) {
+                return Err("the survey is too big".into());
+            }
+            // planned as the turtle goes
+            return Ok(Vec::new());
+        }
+    };
+    if steps.is_empty() {
+        return Err("the job has nothing to do".into());
+    }
+    Ok(steps)
+}
+
+impl ServerTurtle {
+    /// Starts `kind` at `step`, replaces any running job or go to
+    pub async fn start_job(&mut self, kind: JobKind, step: u32) -> Result<Vec<JobUpdate>, String> {
+        let steps = tokio::task::spawn_blocking({
+            let kind = kind.clone();
+            move || plan_job(&kind)
+        })
+        .await
+        .map_err(|err| err.to_string())??;
+        let job = Job {
+            kind,
+            steps,
+            step: step as usize,
+            phase: Phase::Walking,
+            retries: 0,
+            return_cost: None,
+            coverage: 0,
+            unreachable: HashSet::new(),
+            known: None,
+        };
+        let mut updates = vec![JobUpdate::Job(job.status())];
+        self.job = None;
+        self.stop_go_to();
+        self.advance_job(job, &mut updates).await;
+        Ok(updates)
+    }
+
+    /// Drops the running job and whatever go to it was doing
+    pub fn stop_job(&mut self) -> Option<JobStatus> {
+        self.job.take()?;
+        self.stop_go_to();
+        Some(JobStatus::Stopped)
+    }
+
+    /// Advances the running job (if any), returns the updates clients should get
+    pub async fn drive_job(&mut self, event: JobEvent) -> Vec<JobUpdate> {
+        let Some(mut job) = self.job.take() else {
+            return Vec::new();
+        };
+        if let JobEvent::BlockChanged(block) = &event {
+            if block.world == self.world {
+                job.note_block(block);
+            }
+        }
+        let step = job.steps[job.step];
+        let mut updates = Vec::new();
+        match (job.phase, event, step.action) {
+            (Phase::Walking, JobEvent::GoTo(GoToStatus::Arrived { target }), _)
+                if target == step.from =>
+            {
+                self.advance_job(job, &mut updates).await
+            }
+            (Phase::Walking, JobEvent::GoTo(GoToStatus::Failed { target, reason }), _)
+                if target == step.from =>
+            {
+                if job.skip_viewpoint() {
+                    self.advance_job(job, &mut updates).await
+                } else {
+                    self.fail_job(reason, &mut updates).await
+                }
+            }
+            (Phase::Turning, JobEvent::Moved, _) | (Phase::Waiting, JobEvent::OthersMoved, _) => {
+                self.advance_job(job, &mut updates).await
+            }
+            (Phase::Acting, JobEvent::Moved, Action::DigTo(target)) if target == self.position => {
+                self.complete_step(job, &mut updates).await
+            }
+            // something else moved the turtle, it goes back to where it should be
+            (Phase::Acting, JobEvent::Moved, Action::DigTo(_)) => {
+                self.advance_job(job, &mut updates).await
+            }
+            (Phase::Acting, JobEvent::MoveFailed, Action::DigTo(target)) => {
+                job.retries += 1;
+                if job.retries > MAX_RETRIES {
+                    let reason = format!("could not get into {target:?}");
+                    self.fail_job(reason, &mut updates).await
+                } else {
+                    self.advance_job(job, &mut updates).await
+                }
+            }
+            // nothing to dig is fine as well
+            (Phase::Acting, JobEvent::DigFailed(dir), Action::Dig(target))
+                if self.direction_to(target) == Some(dir) =>
+            {
+                self.complete_step(job, &mut updates).await
+            }
+            (Phase::Acting, JobEvent::BlockChanged(block), Action::Dig(target))
+                if block.is_air && block.pos == target && block.world == self.world =>
+            {
+                self.complete_step(job, &mut updates).await
+            }
+            // every inspect reports the blocks around the turtle, even if they stayed the same
+            (Phase::Acting, JobEvent::BlockChanged(block), Action::Inspect(target))
+                if block.pos == target && block.world == self.world =>
+            {
+                self.complete_step(job, &mut updates).await
+            }
+            // the inventory update comes last, selecting a slot before sends one as well
+            (Phase::Refueling(before), JobEvent::InventoryChanged, _)
+                if self.fuel > before || self.has_unlimited_fuel() =>
+            {
+                job.return_cost = None;
+                updates.push(JobUpdate::Job(job.status()));
+                self.advance_job(job, &mut updates).await
+            }
+            (Phase::GoingHome, JobEvent::GoTo(GoToStatus::Arrived { target }), _)
+                if Some(target) == self.home =>
+            {
+                self.restock(job).await
+            }
+            (Phase::GoingHome, JobEvent::GoTo(GoToStatus::Failed { target, reason }), _)
+                if Some(target) == self.home =>
+            {
+                self.fail_job(reason, &mut updates).await
+            }
+            (Phase::Restocking, JobEvent::InventoryChanged, _) => match self.refuel_code() {
+                Some(code) => self.refuel(job, code).await,
+                None => {
+                    let reason = "found no fuel in the chest below its home".to_owned();
+                    self.fail_job(reason, &mut updates).await
+                }
+            },
+            _ => self.job = Some(job),
+        }
+        updates
+    }
+
+    /// The move that reaches `target` from where the turtle is, turns not included
+    fn direction_to(&self, target: Pos3) -> Option<MoveDirection> {
+        let offset = target - self.position;
+        if offset == UP {
+            Some(MoveDirection::Up)
+        } else if offset == Pos3::ZERO - UP {
+            Some(MoveDirection::Down)
+        } else if offset.y == 0 && offset.x.abs() + offset.z.abs() == 1 {
+            Some(MoveDirection::Forward)
+        } else {
+            None
+        }
+    }
+
+    async fn complete_step(&mut self, mut job: Job, updates: &mut Vec<JobUpdate>) {
+        job.step += 1;
+        job.retries = 0;
+        let db = self.get_db();
+        if let Err(err) = save_job_step(&db, &self.world, self.index, job.step as u32).await {
+            error!("failed to store the progress of a job: {err}");
+        }
+        updates.push(JobUpdate::Job(job.status()));
+        self.advance_job(job, updates).await;
+    }
+
+    /// Walks to the current step and starts it, or finishes the job when there are no more
+    async fn advance_job(&mut self, mut job: Job, updates: &mut Vec<JobUpdate>) {
+        loop {
+            let Some(step) = job.steps.get(job.step).copied() else {
+                if let JobKind::Survey { min, max } = job.kind {
+                    match self.plan_survey(&mut job, min, max).await {
+                        Ok(more) => {
+                            updates.push(JobUpdate::Job(job.status()));
+                            if more {
+                                continue;
+                            }
+                        }
+                        Err(reason) => return self.fail_job(reason, updates).await,
+                    }
+                }
+                return self.finish_job(updates).await;
+            };
+            if let Some(needed) = self.fuel_needed(&mut job, step).await {
+                return self.get_fuel(job, needed, updates).await;
+            }
+            if self.position != step.from {
+                let statuses = self.start_go_to(step.from).await;
+                let last = statuses.last().cloned();
+                updates.extend(statuses.into_iter().map(JobUpdate::GoTo));
+                match last {
+                    Some(GoToStatus::Arrived { .. }) => continue,
+                    Some(GoToStatus::Failed { .. }) if job.skip_viewpoint() => continue,
+                    Some(GoToStatus::Failed { reason, .. }) => {
+                        return self.fail_job(reason, updates).await
+                    }
+                    _ => {
+                        job.phase = Phase::Walking;
+                        self.job = Some(job);
+                        return;
+                    }
+                }
+            }
+            let target = match step.action {
+                Action::DigTo(target) | Action::Dig(target) | Action::Inspect(target) => target,
+            };
+            let Some(dir) = self.direction_to(target) else {
+                let reason = format!("{target:?} is not next to {:?}", step.from);
+                return self.fail_job(reason, updates).await;
+            };
+            if dir == MoveDirection::Forward && self.get_forward_vec() != target - self.position {
+                let turn = if self.turn(TurnDir::Left).get_forward_vec() == target - self.position {
+                    MoveDirection::Left
+                } else {
+                    MoveDirection::Right
+                };
+                job.phase = Phase::Turning;
+                self.job = Some(job);
+                self.send_ws(S2TPackets::RunLuaCode(lua_for_move(turn).to_owned()))
+                    .await;
+                return;
+            }
+            if let Action::DigTo(target) = step.action {
+                let occupant = self
+                    .reservations()
+                    .occupant(&self.world, self.index, target);
+                if let Some((turtle, _)) = occupant {
+                    return self.wait_for_turtle(job, turtle, updates).await;
+                }
+            }
+            let code = match (step.action, dir) {
+                (Action::DigTo(_), MoveDirection::Up) => "turtle.digUp() turtle.up()",
+                (Action::DigTo(_), MoveDirection::Down) => "turtle.digDown() turtle.down()",
+                (Action::DigTo(_), _) => "turtle.dig() turtle.forward()",
+                (Action::Dig(_), MoveDirection::Up) => "turtle.digUp()",
+                (Action::Dig(_), MoveDirection::Down) => "turtle.digDown()",
+                (Action::Dig(_), _) => "turtle.dig()",
+                (Action::Inspect(_), MoveDirection::Up) => "turtle.inspectUp()",
+                (Action::Inspect(_), MoveDirection::Down) => "turtle.inspectDown()",
+                (Action::Inspect(_), _) => "turtle.inspect()",
+            };
+            let cells = job.upcoming_cells();
+            self.reserve_way(cells);
+            job.phase = Phase::Acting;
+            self.job = Some(job);
+            self.send_ws(S2TPackets::RunLuaCode(code.to_owned())).await;
+            return;
+        }
+    }
+
+    /// Waits for `turtle` to leave the block the job has to move into. Jobs can't make way, if
+    /// the turtles wait for each other and none of the others can either, the job fails
+    async fn wait_for_turtle(&mut self, mut job: Job, turtle: i32, updates: &mut Vec<JobUpdate>) {
+        let (started, deadlock) = self.wait_on(turtle, false);
+        if let Some(turtles) = deadlock {
+            let reason = format!("stuck waiting for turtles {turtles:?}");
+            return self.fail_job(reason, updates).await;
+        }
+        job.phase = Phase::Waiting;
+        self.job = Some(job);
+        if started {
+            info!("turtle {} waits for turtle {turtle}", self.index);
+            if let Err(err) = self.comm(TurtleCommBus::Waiting(self.world.clone())).await {
+                error!("{err}");
+            }
+        }
+    }
+
+    /// Plans the next viewpoint of a survey from what the world knows now, whether there is one
+    async fn plan_survey(&mut self, job: &mut Job, min: Pos3, max: Pos3) -> Result<bool, String> {
+        let world = match &job.known {
+            Some(world) => world.clone(),
+            None => {
+                let world = load_region(&self.get_db(), &self.world, min - ONE, max + ONE)
+                    .await
+                    .map_err(|err| err.to_string())?;
+                job.known.insert(Arc::new(world)).clone()
+            }
+        };
+        let (pos, unreachable) = (self.position, job.unreachable.clone());
+        let (coverage, steps) = tokio::task::spawn_blocking(move || {
+            let steps = next_viewpoint(&world, min, max, pos, &unreachable);
+            (coverage(&world, min, max), steps)
+        })
+        .await
+        .map_err(|err| err.to_string())?;
+        job.coverage = coverage;
+        job.steps = steps;
+        job.step = 0;
+        Ok(!job.steps.is_empty())
+    }
+
+    /// The fuel the turtle should have before it does `step`, `None` if it has that much
+    async fn fuel_needed(&mut self, job: &mut Job, step: Step) -> Option<u32> {
+        if self.has_unlimited_fuel() {
+            return None;
+        }
+        let fuel = self.fuel.max(0) as u32;
+        let d = step.from - self.position;
+        // getting there and the move, twice as the way back gets longer by the same
+        let step_cost = 2 * (d.x.unsigned_abs() + d.y.unsigned_abs() + d.z.unsigned_abs() + 1);
+        if let Some((cost, at)) = job.return_cost {
+            let burnt = (at - self.fuel).max(0) as u32;
+            let needed = cost + burnt + step_cost + FUEL_MARGIN;
+            if fuel >= needed {
+                return None;
+            }
+            // the route was just looked up, another search would hardly find a shorter one
+            if burnt < REESTIMATE_AFTER {
+                return Some(needed);
+            }
+        }
+        // only ask the pathfinder once it gets close
+        let home = self.home.unwrap_or(job.steps[0].from);
+        let cost = self.path_cost(home).await;
+        job.return_cost = Some((cost, self.fuel));
+        let needed = cost + step_cost + FUEL_MARGIN;
+        (fuel < needed).then_some(needed)
+    }
+
+    /// Burns fuel from the inventory, or goes home to get some
+    async fn get_fuel(&mut self, mut job: Job, needed: u32, updates: &mut Vec<JobUpdate>) {
+        if self.max_fuel >= 0 && needed > self.max_fuel as u32 {
+            let reason = format!(
+                "the job needs {needed} fuel, the turtle only holds {}",
+                self.max_fuel
+            );
+            return self.fail_job(reason, updates).await;
+        }
+        updates.push(JobUpdate::Job(JobStatus::Refueling { needed }));
+        if let Some(code) = self.refuel_code() {
+            return self.refuel(job, code).await;
+        }
+        let Some(home) = self.home else {
+            let reason = format!("needs {needed} fuel and has no home to get more");
+            return self.fail_job(reason, updates).await;
+        };
+        info!("turtle {} goes home for fuel", self.index);
+        if self.position == home {
+            return self.restock(job).await;
+        }
+        let statuses = self.start_go_to(home).await;
+        let last = statuses.last().cloned();
+        updates.extend(statuses.into_iter().map(JobUpdate::GoTo));
+        match last {
+            Some(GoToStatus::Arrived { .. }) => self.restock(job).await,
+            Some(GoToStatus::Failed { reason, .. }) => self.fail_job(reason, updates).await,
+            _ => {
+                job.phase = Phase::GoingHome;
+                self.job = Some(job);
+            }
+        }
+    }
+
+    async fn refuel(&mut self, mut job: Job, code: String) {
+        job.phase = Phase::Refueling(self.fuel);
+        self.job = Some(job);
+        self.send_ws(S2TPackets::RunLuaCode(code)).await;
+    }
+
+    async fn restock(&mut self, mut job: Job) {
+        job.phase = Phase::Restocking;
+        self.job = Some(job);
+        self.send_ws(S2TPackets::RunLuaCode("turtle.suckDown()".into()))
+            .await;
+    }
+
+    async fn finish_job(&mut self, updates: &mut Vec<JobUpdate>) {
+        info!("turtle {} is done with its job", self.index);
+        let db = self.get_db();
+        if let Err(err) = delete_job(&db, &self.world, self.index).await {
+            error!("failed to remove a finished job: {err}");
+        }
+        self.stop_go_to();
+        updates.push(JobUpdate::Job(JobStatus::Done));
+    }
+
+    async fn fail_job(&mut self, reason: String, updates: &mut Vec<JobUpdate>) {
+        info!("job of turtle {} failed: {reason}", self.index);
+        let db = self.get_db();
+        if let Err(err) = delete_job(&db, &self.world, self.index).await {
+            error!("failed to remove a failed job: {err}");
+        }
+        self.stop_go_to();
+        updates.push(JobUpdate::Job(JobStatus::Failed { reason }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn target(step: &Step) -> Pos3 {
+        match step.action {
+            Action::DigTo(p) | Action::Dig(p) | Action::Inspect(p) => p,
+        }
+    }
+
+    /// Steps start where the one before left the turtle and only reach neighbours
+    fn assert_walkable(steps: &[Step]) {
+        let mut pos = steps[0].from;
+        for step in steps {
+            assert_eq!(step.from, pos);
+            let d = target(step) - pos;
+            assert_eq!(d.x.abs() + d.y.abs() + d.z.abs(), 1, "{step:?}");
+            if let Action::DigTo(to) = step.action {
+                pos = to;
+            }
+        }
+    }
+
+    fn dug(steps: &[Step]) -> HashSet<Pos3> {
+        steps.iter().map(target).collect()
+    }
+
+    #[test]
+    fn quarries_cover_the_box() {
+        let (min, max) = (Pos3::new(-1, 3, 2), Pos3::new(1, 5, 5));
+        let steps = plan_job(&JobKind::Quarry { min, max }).unwrap();
+        assert_walkable(&steps);
+        assert_eq!(steps[0].from, Pos3::new(-1, 6, 2));
+        let dug = dug(&steps);
+        assert_eq!(dug.len(), 3 * 3 * 4);
+        assert!(dug.iter().all(|p| (min.x..=max.x).contains(&p.x)
+            && (min.y..=max.y).contains(&p.y)
+            && (min.z..=max.z).contains(&p.z)));
+        // every block is dug once
+        assert_eq!(steps.len(), dug.len());
+
+        let flipped = JobKind::Quarry { min: max, max: min };
+        assert!(plan_job(&flipped).is_err());
+    }
+
+    #[test]
+    fn boxes_past_the_coordinates_are_too_big() {
+        let (min, max) = (Pos3::new(i32::MIN, 0, 0), Pos3::new(i32::MAX, 0, 0));
+        assert!(plan_job(&JobKind::Quarry { min, max }).is_err());
+        assert!(plan_job(&JobKind::Survey { min, max }).is_err());
+        // the corner fits, the block above it where the quarry starts doesn't
+        let top = Pos3::new(0, i32::MAX, 0);
+        assert!(plan_job(&JobKind::Quarry { min: top, max: top }).is_err());
+    }
+
+    #[test]
+    fn tunnels_are_dug_in_front_of_the_start() {
+        let start = Pos3::new(0, 10, 0);
+        let tunnel = |size| JobKind::Tunnel {
+            start,
+            direction: Orientation::East,
+            length: 4,
+            size,
+        };
+        let steps = plan_job(&tunnel(TunnelSize::OneByTwo)).unwrap();
+        assert_walkable(&steps);
+        let expected = (1..=4)
+            .flat_map(|x| [Pos3::new(x, 10, 0), Pos3::new(x, 11, 0)])
+            .collect::<HashSet<_>>();
+        assert_eq!(dug(&steps), expected);
+
+        let steps = plan_job(&tunnel(TunnelSize::ThreeByThree)).unwrap();
+        assert_walkable(&steps);
+        let expected = (1..=4)
+            .flat_map(|x| (10..13).flat_map(move |y| (-1..=1).map(move |z| Pos3::new(x, y, z))))
+            .collect::<HashSet<_>>();
+        assert_eq!(dug(&steps), expected);
+    }
+
+    #[test]
+    fn branches_go_to_both_sides() {
+        let steps = plan_job(&JobKind::BranchMine {
+            start: Pos3::zero(),
+            direction: Orientation::North,
+            length: 6,
+            spacing: 3,
+            branch_length: 2,
+        })
+        .unwrap();
+        assert_walkable(&steps);
+        let dug = dug(&steps);
+        // the main tunnel, two branches on each side, all two high
+        assert_eq!(dug.len(), (6 + 2 * 2 * 2) * 2);
+        for x in [-2, -1, 1, 2] {
+            assert!(dug.contains(&Pos3::new(x, 0, -3)));
+            assert!(dug.contains(&Pos3::new(x, 1, -6)));
+            assert!(!dug.contains(&Pos3::new(x, 0, -4)));
+        }
+    }
+}
@@ -1,8 +1,19 @@
+pub mod build;
+pub mod chunk_streaming;
 pub mod connection_manager;
 pub mod data_types;
 pub mod db;
-// pub mod fake;
+pub mod export;
+pub mod fuel;
+pub mod go_to;
+pub mod import;
+pub mod inventory;
+pub mod jobs;
+pub mod nbt;
+pub mod reservations;
 pub mod send_util;
+pub mod survey;
+pub mod tasks;
 // mod turtle;
 pub mod handle_turtles;
 pub mod util;
@@ -2,14 +2,24 @@ use std::{env, sync::Arc};
 
 use anyhow::Result;
 use axum::{
-    extract::State,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
     routing::{get, post},
     Json, Router,
 };
-use backend::{db::DB, *};
+use backend::{
+    db::DB,
+    export::{export_from_db, parse_pos, ExportError, ExportFormat, Region},
+    import::{import_to_db, ImportError, ImportFormat},
+    *,
+};
+use chrono::{DateTime, Utc};
 use common::{
     extensions::Extensions,
+    turtle::TurtleInventory,
     turtle_packets::{SetupInfoData, T2SPackets},
+    waypoint::Waypoint,
 };
 
 use futures_util::{
@@ -25,7 +35,8 @@ use tokio::{
 use tokio_tungstenite::WebSocketStream;
 use tungstenite::Message;
 
-pub const SUPPORTED_EXTENSIONS: &[Extensions] = &[Extensions::PositionTracking];
+pub const SUPPORTED_EXTENSIONS: &[Extensions] =
+    &[Extensions::PositionTracking, Extensions::Pathfinding];
 
 type WsSend = SplitSink<WebSocketStream<TcpStream>, Message>;
 type WsRecv = SplitStream<WebSocketStream<TcpStream>>;
@@ -52,6 +63,99 @@ async fn add_world(State(db): State<Arc<DB>>, name: String) {
     // db.create_world(&name).await.unwrap();
 }
 
+#[derive(serde::Deserialize)]
+struct InventoryAt {
+    /// unix time in milliseconds, now if missing
+    at: Option<i64>,
+}
+
+async fn get_inventory_at(
+    State(db): State<Arc<DB>>,
+    Path((world, id)): Path<(String, i32)>,
+    Query(InventoryAt { at }): Query<InventoryAt>,
+) -> Result<Json<Option<TurtleInventory>>, (StatusCode, String)> {
+    let time = at
+        .and_then(DateTime::from_timestamp_millis)
+        .unwrap_or_else(Utc::now);
+    db::inventory_at(&db, &world, id, time)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+async fn get_waypoints(
+    State(db): State<Arc<DB>>,
+    Path(world): Path<String>,
+) -> Result<Json<Vec<Waypoint>>, (StatusCode, String)> {
+    db::load_waypoints(&db, &world)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+#[derive(serde::Deserialize)]
+struct ExportArea {
+    /// `x,y,z`
+    from: String,
+    /// `x,y,z`
+    to: String,
+}
+
+async fn export_world(
+    State(db): State<Arc<DB>>,
+    Path((world, format)): Path<(String, String)>,
+    Query(ExportArea { from, to }): Query<ExportArea>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let bad_request = |e: ExportError| (StatusCode::BAD_REQUEST, e.to_string());
+    let format = format.parse::<ExportFormat>().map_err(bad_request)?;
+    let region = Region::new(
+        parse_pos(&from).map_err(bad_request)?,
+        parse_pos(&to).map_err(bad_request)?,
+    );
+    let bytes = match export_from_db(&db, &world, &region, format).await {
+        Ok(bytes) => bytes,
+        Err(e @ ExportError::TooBig(_)) => return Err(bad_request(e)),
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    };
+    let disposition = format!("attachment; filename=\"{world}.{}\"", format.extension());
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_owned()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        bytes,
+    ))
+}
+
+#[derive(serde::Deserialize)]
+struct ImportOptions {
+    /// `x,y,z` of the min corner
+    anchor: String,
+    /// schem, nbt or litematic
+    format: String,
+}
+
+/// Stores the schematic in the body as a blueprint, answers with how many blocks it has
+async fn import_blueprint(
+    State(db): State<Arc<DB>>,
+    Path((world, name)): Path<(String, String)>,
+    Query(ImportOptions { anchor, format }): Query<ImportOptions>,
+    body: axum::body::Bytes,
+) -> Result<String, (StatusCode, String)> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+    let anchor = parse_pos(&anchor).map_err(|e| bad_request(e.to_string()))?;
+    let format = format
+        .parse::<ImportFormat>()
+        .map_err(|e| bad_request(e.to_string()))?;
+    match import_to_db(&db, body.to_vec(), format, &name, &world, anchor).await {
+        Ok(blueprint) => Ok(blueprint.planned().to_string()),
+        Err(e @ (ImportError::Io(_) | ImportError::Db(_))) => {
+            Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+        }
+        Err(e) => Err(bad_request(e.to_string())),
+    }
+}
+
 async fn get_supported_extensions() -> Json<Vec<&'static str>> {
     Json(
         SUPPORTED_EXTENSIONS
@@ -71,10 +175,21 @@ async fn main() -> Result<()> {
         .init();
     // let db = Arc::new(SqliteConnection::.await?);
     let db = Arc::new(DB::connect(&env::var("DATABASE_URL")?).await?);
+    let args = env::args().collect::<Vec<_>>();
+    if args.get(1).is_some_and(|a| a == "export") {
+        return export::cli(&db, &args[2..]).await;
+    }
+    if args.get(1).is_some_and(|a| a == "import") {
+        return import::cli(&db, &args[2..]).await;
+    }
     let app = Router::new()
         .route("/get_worlds", get(get_worlds))
+        .route("/export/:world/:format", get(export_world))
+        .route("/blueprints/:world/:name", post(import_blueprint))
         .route("/get_supported_extensions", get(get_supported_extensions))
         .route("/add_world", post(add_world))
+        .route("/inventory_history/:world/:id", get(get_inventory_at))
+        .route("/waypoints/:world", get(get_waypoints))
         .nest_service("/lua", tower_http::services::ServeDir::new("./lua"))
         .with_state(db.clone());
     let axum_listener = tokio::net::TcpListener::bind("0.0.0.0:9003").await?;
@@ -106,20 +221,10 @@ async fn main() -> Result<()> {
     info!("Trutle Socket Listening on: {}", turtle_addr);
 
     // Let's spawn the handling of each connection in a separate task.
-    let client_connected_tx = client_connected_tx.clone();
-    tokio::spawn(async move {
-        // info!("EXPLAIN!!!");
-        let listener = client_listener;
-        // This Is the Broken Listener!
-        while let Ok((stream, addr)) = listener.accept().await {
-            // info!("awdasd?!?!??!?!?!?!?");
-            tokio::spawn(backend::handle_clients::handle_connection(
-                stream,
-                addr,
-                client_connected_tx.clone(),
-            ));
-        }
-    });
+    tokio::spawn(backend::handle_clients::accept_connections(
+        client_listener,
+        client_connected_tx.clone(),
+    ));
 
     let db_ = db.clone();
     tokio::spawn(async {
@@ -128,14 +233,7 @@ async fn main() -> Result<()> {
             .unwrap();
     });
 
-    while let Ok((stream, addr)) = turtle_listener.accept().await {
-        // info!("dafuq?!");
-        tokio::spawn(backend::handle_turtles::handle_connection(
-            stream,
-            addr,
-            turtle_connected_tx.clone(),
-        ));
-    }
+    backend::handle_turtles::accept_connections(turtle_listener, turtle_connected_tx.clone()).await;
     Ok(())
     // loop {}
 }
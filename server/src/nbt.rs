@@ -0,0 +1,268 @@
+//! Just enough of minecraft's NBT format to read and write schematics and structures.
+//!
+//! Everything is big endian, strings are a u16 length followed by (modified) utf8. Block ids and
+//! states are plain ascii, so the modified part never matters here.
+
+use std::io::{self, Write};
+
+/// Minecraft refuses deeper nesting too, it keeps hostile files from overflowing the stack
+const MAX_DEPTH: usize = 512;
+
+/// A value with its type, compounds keep their insertion order
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    /// Every element has to have the same type, empty lists are written as lists of TAG_End
+    List(Vec<Tag>),
+    Compound(Vec<(String, Tag)>),
+    ByteArray(Vec<i8>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    /// The entry called `name` if this is a compound
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(entries) => entries.iter().find(|(k, _)| k == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Any integer as an i64, schematics aren't picky about which kind they use
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Tag::Byte(v) => Some(*v as i64),
+            Tag::Short(v) => Some(*v as i64),
+            Tag::Int(v) => Some(*v as i64),
+            Tag::Long(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn compound<const N: usize>(entries: [(&str, Tag); N]) -> Tag {
+        Tag::Compound(entries.map(|(k, v)| (k.to_owned(), v)).into())
+    }
+
+    fn write_payload(&self, w: &mut impl Write) -> io::Result<()> {
+        match self {
+            Tag::Byte(v) => w.write_all(&v.to_be_bytes()),
+            Tag::Short(v) => w.write_all(&v.to_be_bytes()),
+            Tag::Int(v) => w.write_all(&v.to_be_bytes()),
+            Tag::Long(v) => w.write_all(&v.to_be_bytes()),
+            Tag::Float(v) => w.write_all(&v.to_be_bytes()),
+            Tag::Double(v) => w.write_all(&v.to_be_bytes()),
+            Tag::String(v) => write_str(w, v),
+            Tag::List(items) => {
+                // 0 is TAG_End
+                w.write_all(&[items.first().map_or(0, Tag::id)])?;
+                write_len(w, items.len())?;
+                for item in items {
+                    debug_assert_eq!(item.id(), items[0].id(), "mixed list");
+                    item.write_payload(w)?;
+                }
+                Ok(())
+            }
+            Tag::Compound(entries) => {
+                for (name, tag) in entries {
+                    write_named(w, name, tag)?;
+                }
+                w.write_all(&[0])
+            }
+            Tag::ByteArray(v) => {
+                write_len(w, v.len())?;
+                let bytes = v.iter().map(|b| *b as u8).collect::<Vec<_>>();
+                w.write_all(&bytes)
+            }
+            Tag::IntArray(v) => {
+                write_len(w, v.len())?;
+                v.iter().try_for_each(|i| w.write_all(&i.to_be_bytes()))
+            }
+            Tag::LongArray(v) => {
+                write_len(w, v.len())?;
+                v.iter().try_for_each(|i| w.write_all(&i.to_be_bytes()))
+            }
+        }
+    }
+}
+
+fn write_len(w: &mut impl Write, len: usize) -> io::Result<()> {
+    let len = i32::try_from(len)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "nbt array too long"))?;
+    w.write_all(&len.to_be_bytes())
+}
+
+fn write_str(w: &mut impl Write, s: &str) -> io::Result<()> {
+    let len = u16::try_from(s.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "nbt string too long"))?;
+    w.write_all(&len.to_be_bytes())?;
+    w.write_all(s.as_bytes())
+}
+
+/// Writes `tag` with its type and name, a whole file is one named compound
+pub fn write_named(w: &mut impl Write, name: &str, tag: &Tag) -> io::Result<()> {
+    w.write_all(&[tag.id()])?;
+    write_str(w, name)?;
+    tag.write_payload(w)
+}
+
+/// Reads one named tag, the reverse of [`write_named`]. Fails on truncated or nonsensical data
+pub fn read_named(r: &mut &[u8]) -> io::Result<(String, Tag)> {
+    fn invalid(msg: String) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, msg)
+    }
+    fn take<'a>(r: &mut &'a [u8], n: usize) -> io::Result<&'a [u8]> {
+        if r.len() < n {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let (head, tail) = r.split_at(n);
+        *r = tail;
+        Ok(head)
+    }
+    fn array<const N: usize>(r: &mut &[u8]) -> io::Result<[u8; N]> {
+        Ok(take(r, N)?.try_into().unwrap())
+    }
+    fn int(r: &mut &[u8]) -> io::Result<i32> {
+        Ok(i32::from_be_bytes(array(r)?))
+    }
+    fn len(r: &mut &[u8]) -> io::Result<usize> {
+        let len = int(r)?;
+        usize::try_from(len).map_err(|_| invalid(format!("length {len}")))
+    }
+    fn string(r: &mut &[u8]) -> io::Result<String> {
+        let len = u16::from_be_bytes(array(r)?);
+        String::from_utf8(take(r, len as usize)?.to_vec()).map_err(|e| invalid(e.to_string()))
+    }
+    fn payload(r: &mut &[u8], id: u8, depth: usize) -> io::Result<Tag> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("nested too deep".to_owned()));
+        }
+        Ok(match id {
+            1 => Tag::Byte(take(r, 1)?[0] as i8),
+            2 => Tag::Short(i16::from_be_bytes(array(r)?)),
+            3 => Tag::Int(int(r)?),
+            4 => Tag::Long(i64::from_be_bytes(array(r)?)),
+            5 => Tag::Float(f32::from_be_bytes(array(r)?)),
+            6 => Tag::Double(f64::from_be_bytes(array(r)?)),
+            7 => {
+                let len = len(r)?;
+                Tag::ByteArray(take(r, len)?.iter().map(|b| *b as i8).collect())
+            }
+            8 => Tag::String(string(r)?),
+            9 => {
+                let item = take(r, 1)?[0];
+                let len = len(r)?;
+                // empty lists of TAG_End are fine, anything else of it isn't
+                if item == 0 && len > 0 {
+                    return Err(invalid("list of TAG_End".to_owned()));
+                }
+                Tag::List(
+                    (0..len)
+                        .map(|_| payload(r, item, depth + 1))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            10 => {
+                let mut entries = Vec::new();
+                loop {
+                    let id = take(r, 1)?[0];
+                    if id == 0 {
+                        break;
+                    }
+                    let name = string(r)?;
+                    entries.push((name, payload(r, id, depth + 1)?));
+                }
+                Tag::Compound(entries)
+            }
+            11 => {
+                let len = len(r)?;
+                Tag::IntArray((0..len).map(|_| int(r)).collect::<Result<_, _>>()?)
+            }
+            12 => {
+                let len = len(r)?;
+                Tag::LongArray(
+                    (0..len)
+                        .map(|_| Ok(i64::from_be_bytes(array(r)?)))
+                        .collect::<io::Result<_>>()?,
+                )
+            }
+            id => return Err(invalid(format!("tag {id}"))),
+        })
+    }
+    let id = take(r, 1)?[0];
+    let name = string(r)?;
+    Ok((name, payload(r, id, 0)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_survive_a_round_trip() {
+        let tag = Tag::compound([
+            ("Version", Tag::Int(2)),
+            ("Width", Tag::Short(-3)),
+            ("Name", Tag::String("minecraft:stone".into())),
+            ("Empty", Tag::List(Vec::new())),
+            (
+                "Pos",
+                Tag::List(vec![Tag::Int(1), Tag::Int(2), Tag::Int(3)]),
+            ),
+            ("Data", Tag::ByteArray(vec![0, -1, 127])),
+            ("Offset", Tag::IntArray(vec![-5, 0, 7])),
+            ("BlockStates", Tag::LongArray(vec![-1, 0, 1 << 62])),
+            (
+                "Motion",
+                Tag::List(vec![Tag::Double(0.5), Tag::Double(-2.0)]),
+            ),
+            ("Yaw", Tag::Float(90.0)),
+            ("Time", Tag::Long(1 << 40)),
+            ("Flag", Tag::Byte(1)),
+            ("Inner", Tag::compound([("x", Tag::Int(9))])),
+        ]);
+        let mut bytes = Vec::new();
+        write_named(&mut bytes, "Schematic", &tag).unwrap();
+        // a compound tag named "Schematic"
+        assert_eq!(&bytes[..3], [10, 0, 9]);
+        let (name, read) = read_named(&mut bytes.as_slice()).unwrap();
+        assert_eq!(name, "Schematic");
+        assert_eq!(read, tag);
+        assert_eq!(read.get("Width").and_then(Tag::as_int), Some(-3));
+        assert_eq!(
+            read.get("Name").and_then(Tag::as_str),
+            Some("minecraft:stone")
+        );
+        assert!(read_named(&mut &bytes[..bytes.len() - 1]).is_err());
+    }
+}
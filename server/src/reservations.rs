@@ -0,0 +1,338 @@
+//! Where turtles are and where they are going to be. A turtle that walks somewhere reserves the
+//! blocks on its way, each for the time slot it expects to get there in, and routes of other
+//! turtles are planned around those. Turtles still only move into blocks nobody stands in, a
+//! turtle that can't waits for the one in the way. When turtles end up waiting for each other,
+//! one of them makes way.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use common::Pos3;
+
+use crate::data_types::server_turtle::ServerTurtle;
+
+/// How long a move takes, 8 game ticks
+pub const MOVE_TIME: Duration = Duration::from_millis(400);
+
+pub type SharedReservations = Arc<Mutex<Reservations>>;
+
+pub struct Reservations {
+    start: Instant,
+    worlds: HashMap<String, WorldReservations>,
+}
+
+#[derive(Default)]
+struct WorldReservations {
+    positions: HashMap<i32, Pos3>,
+    /// Who gets a block in a time slot
+    slots: HashMap<(Pos3, u64), i32>,
+    /// Where every turtle wants to go, including the slots others got first
+    routes: HashMap<i32, Vec<(Pos3, u64)>>,
+    waits: HashMap<i32, Wait>,
+}
+
+#[derive(Clone, Copy)]
+struct Wait {
+    on: i32,
+    /// Turtles on a go to can get out of the way, jobs can't
+    can_yield: bool,
+}
+
+/// What a route has to stay clear of, see [`Reservations::obstacles`]
+#[derive(Clone, Default)]
+pub struct Obstacles {
+    /// The time slot the route starts in
+    now: u64,
+    slots: HashSet<(Pos3, u64)>,
+    /// Where turtles stand that aren't going anywhere
+    parked: HashSet<Pos3>,
+    /// Where the others stand right now
+    turtles: HashSet<Pos3>,
+}
+
+impl Obstacles {
+    /// Whether a route may be in `pos` after `moves` moves. The slot after counts as well, so
+    /// turtles don't run into each other head on
+    pub fn blocks(&self, pos: Pos3, moves: u32) -> bool {
+        let slot = self.now + moves as u64;
+        self.parked.contains(&pos)
+            || self.slots.contains(&(pos, slot))
+            || self.slots.contains(&(pos, slot + 1))
+    }
+
+    /// Whether another turtle stands in `pos` right now
+    pub fn has_turtle(&self, pos: Pos3) -> bool {
+        self.turtles.contains(&pos)
+    }
+
+    /// Only the turtles that aren't going anywhere, for when there is no way around the others
+    pub fn parked_only(mut self) -> Self {
+        self.slots.clear();
+        self
+    }
+}
+
+impl Reservations {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            worlds: HashMap::new(),
+        }
+    }
+
+    /// The time slot the server is in
+    pub fn now(&self) -> u64 {
+        (self.start.elapsed().as_millis() / MOVE_TIME.as_millis()) as u64
+    }
+
+    fn world(&mut self, world: &str) -> &mut WorldReservations {
+        self.worlds.entry(world.to_owned()).or_default()
+    }
+
+    pub fn set_position(&mut self, world: &str, turtle: i32, pos: Pos3) {
+        self.world(world).positions.insert(turtle, pos);
+    }
+
+    /// The turtle went away, it neither stands anywhere nor waits for anything
+    pub fn remove(&mut self, world: &str, turtle: i32) {
+        self.release(world, turtle);
+        let w = self.world(world);
+        w.positions.remove(&turtle);
+        w.waits.remove(&turtle);
+    }
+
+    /// Reserves `cells` for `turtle`, one time slot after the other starting with the next.
+    /// Replaces what it reserved before, blocks somebody else got first stay theirs. A turtle
+    /// with reservations is busy, even with none at all, others wait for it instead of going
+    /// around it
+    pub fn reserve(&mut self, world: &str, turtle: i32, cells: impl IntoIterator<Item = Pos3>) {
+        let now = self.now();
+        self.reserve_at(world, turtle, cells, now);
+    }
+
+    fn reserve_at(
+        &mut self,
+        world: &str,
+        turtle: i32,
+        cells: impl IntoIterator<Item = Pos3>,
+        now: u64,
+    ) {
+        self.release(world, turtle);
+        let w = self.world(world);
+        // whatever is in the past by now is of no use to anybody
+        w.slots.retain(|(_, slot), _| *slot >= now);
+        let route = cells
+            .into_iter()
+            .enumerate()
+            .map(|(i, pos)| (pos, now + 1 + i as u64))
+            .collect::<Vec<_>>();
+        for key in &route {
+            w.slots.entry(*key).or_insert(turtle);
+        }
+        w.routes.insert(turtle, route);
+    }
+
+    /// Gives back everything `turtle` reserved, it stays put from now on
+    pub fn release(&mut self, world: &str, turtle: i32) {
+        let w = self.world(world);
+        for key in w.routes.remove(&turtle).unwrap_or_default() {
+            if w.slots.get(&key) == Some(&turtle) {
+                w.slots.remove(&key);
+            }
+        }
+    }
+
+    /// What a route of `turtle` has to stay clear of, from now on
+    pub fn obstacles(&mut self, world: &str, turtle: i32) -> Obstacles {
+        let now = self.now();
+        self.obstacles_at(world, turtle, now)
+    }
+
+    fn obstacles_at(&mut self, world: &str, turtle: i32, now: u64) -> Obstacles {
+        let w = self.world(world);
+        let others = w.positions.iter().filter(|(t, _)| **t != turtle);
+        Obstacles {
+            now,
+            slots: w
+                .slots
+                .iter()
+                .filter(|((_, slot), t)| **t != turtle && *slot >= now)
+                .map(|(key, _)| *key)
+                .collect(),
+            parked: others
+                .clone()
+                .filter(|(t, _)| !w.routes.contains_key(t))
+                .map(|(_, pos)| *pos)
+                .collect(),
+            turtles: others.map(|(_, pos)| *pos).collect(),
+        }
+    }
+
+    /// The turtle standing in `pos` other than `turtle`, and whether it is going somewhere
+    pub fn occupant(&mut self, world: &str, turtle: i32, pos: Pos3) -> Option<(i32, bool)> {
+        let w = self.world(world);
+        w.positions
+            .iter()
+            .find(|(t, p)| **t != turtle && **p == pos)
+            .map(|(t, _)| (*t, w.routes.contains_key(t)))
+    }
+
+    /// Every block the routes of `turtles` still go through, with where they stand. Turtles that
+    /// aren't going anywhere don't count
+    pub fn route_cells(&mut self, world: &str, turtles: &[i32]) -> HashSet<Pos3> {
+        let w = self.world(world);
+        turtles
+            .iter()
+            .filter_map(|t| Some((w.routes.get(t)?, w.positions.get(t))))
+            .flat_map(|(route, pos)| route.iter().map(|(p, _)| *p).chain(pos.copied()))
+            .collect()
+    }
+
+    /// `turtle` waits for `on` to get out of the way, true if it didn't do so already
+    pub fn wait(&mut self, world: &str, turtle: i32, on: i32, can_yield: bool) -> bool {
+        let wait = Wait { on, can_yield };
+        let before = self.world(world).waits.insert(turtle, wait);
+        before.map(|w| w.on) != Some(on)
+    }
+
+    pub fn stop_waiting(&mut self, world: &str, turtle: i32) {
+        self.world(world).waits.remove(&turtle);
+    }
+
+    /// The others `turtle` waits for in a circle, if it is the one that has to break it. That
+    /// is the highest index that can make way, or the highest index if none can
+    pub fn deadlock(&mut self, world: &str, turtle: i32) -> Option<Vec<i32>> {
+        let w = self.world(world);
+        let mut circle = vec![turtle];
+        let mut current = w.waits.get(&turtle)?.on;
+        while current != turtle {
+            if circle.contains(&current) {
+                // waits for a circle it isn't part of
+                return None;
+            }
+            circle.push(current);
+            current = w.waits.get(&current)?.on;
+        }
+        let breaker = circle
+            .iter()
+            .max_by_key(|t| (w.waits[t].can_yield, **t))
+            .copied();
+        (breaker == Some(turtle)).then(|| circle.split_off(1))
+    }
+}
+
+impl Default for Reservations {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerTurtle {
+    /// Reserves the blocks the turtle moves into next, it doesn't wait for anybody anymore
+    pub fn reserve_way(&self, cells: Vec<Pos3>) {
+        let mut reservations = self.reservations();
+        reservations.stop_waiting(&self.world, self.index);
+        reservations.reserve(&self.world, self.index, cells);
+    }
+
+    /// Waits for `turtle`. Tells whether it didn't already, and the others it waits for in a
+    /// circle if it has to break that
+    pub fn wait_on(&self, turtle: i32, can_yield: bool) -> (bool, Option<Vec<i32>>) {
+        let mut reservations = self.reservations();
+        let started = reservations.wait(&self.world, self.index, turtle, can_yield);
+        (started, reservations.deadlock(&self.world, self.index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORLD: &str = "test";
+
+    fn line(x: std::ops::RangeInclusive<i32>) -> Vec<Pos3> {
+        x.map(|x| Pos3::new(x, 0, 0)).collect()
+    }
+
+    #[test]
+    fn routes_are_planned_around_reserved_slots() {
+        let mut r = Reservations::new();
+        r.set_position(WORLD, 0, Pos3::new(0, 0, 0));
+        r.set_position(WORLD, 1, Pos3::new(5, 0, 5));
+        r.reserve_at(WORLD, 0, line(1..=4), 10);
+
+        let obstacles = r.obstacles_at(WORLD, 1, 10);
+        // turtle 0 is in (2, 0, 0) after two moves
+        assert!(obstacles.blocks(Pos3::new(2, 0, 0), 2));
+        assert!(obstacles.blocks(Pos3::new(2, 0, 0), 1));
+        assert!(!obstacles.blocks(Pos3::new(2, 0, 0), 5));
+        assert!(obstacles.has_turtle(Pos3::new(0, 0, 0)));
+        // a turtle's own reservations are no obstacle
+        assert!(!r.obstacles_at(WORLD, 0, 10).blocks(Pos3::new(2, 0, 0), 2));
+
+        // turtle 1 isn't going anywhere
+        assert!(r.obstacles_at(WORLD, 0, 10).blocks(Pos3::new(5, 0, 5), 40));
+        assert!(!obstacles.parked_only().blocks(Pos3::new(2, 0, 0), 2));
+    }
+
+    #[test]
+    fn reservations_are_first_come_first_served() {
+        let mut r = Reservations::new();
+        r.reserve_at(WORLD, 0, line(1..=3), 0);
+        r.reserve_at(WORLD, 1, line(1..=3), 0);
+        assert!(r.obstacles_at(WORLD, 1, 0).blocks(Pos3::new(1, 0, 0), 1));
+        assert!(!r.obstacles_at(WORLD, 0, 0).blocks(Pos3::new(1, 0, 0), 1));
+
+        r.release(WORLD, 0);
+        assert!(!r.obstacles_at(WORLD, 1, 0).blocks(Pos3::new(1, 0, 0), 1));
+        // turtle 1 got nothing earlier, it has to reserve again
+        assert!(!r.obstacles_at(WORLD, 0, 0).blocks(Pos3::new(1, 0, 0), 1));
+        r.reserve_at(WORLD, 1, line(1..=3), 0);
+        assert!(r.obstacles_at(WORLD, 0, 0).blocks(Pos3::new(1, 0, 0), 1));
+    }
+
+    #[test]
+    fn occupants_and_their_routes() {
+        let mut r = Reservations::new();
+        r.set_position(WORLD, 0, Pos3::new(0, 0, 0));
+        r.set_position(WORLD, 1, Pos3::new(1, 0, 0));
+        assert_eq!(r.occupant(WORLD, 0, Pos3::new(1, 0, 0)), Some((1, false)));
+        assert_eq!(r.occupant(WORLD, 1, Pos3::new(1, 0, 0)), None);
+        r.reserve_at(WORLD, 1, line(2..=3), 0);
+        assert_eq!(r.occupant(WORLD, 0, Pos3::new(1, 0, 0)), Some((1, true)));
+        assert_eq!(
+            r.route_cells(WORLD, &[0, 1]),
+            line(1..=3).into_iter().collect()
+        );
+
+        r.remove(WORLD, 1);
+        assert_eq!(r.occupant(WORLD, 0, Pos3::new(1, 0, 0)), None);
+        assert!(r.route_cells(WORLD, &[1]).is_empty());
+    }
+
+    #[test]
+    fn the_highest_index_that_can_yield_breaks_deadlocks() {
+        let mut r = Reservations::new();
+        assert!(r.wait(WORLD, 0, 1, true));
+        assert!(!r.wait(WORLD, 0, 1, true));
+        assert_eq!(r.deadlock(WORLD, 0), None);
+        r.wait(WORLD, 1, 2, true);
+        r.wait(WORLD, 2, 0, false);
+        assert_eq!(r.deadlock(WORLD, 0), None);
+        assert_eq!(r.deadlock(WORLD, 1), Some(vec![2, 0]));
+        assert_eq!(r.deadlock(WORLD, 2), None);
+
+        // a turtle waiting for the circle isn't part of it
+        r.wait(WORLD, 3, 0, true);
+        assert_eq!(r.deadlock(WORLD, 3), None);
+
+        // jobs only break them if they have to
+        r.stop_waiting(WORLD, 1);
+        r.wait(WORLD, 0, 2, false);
+        assert_eq!(r.deadlock(WORLD, 0), None);
+        assert_eq!(r.deadlock(WORLD, 2), Some(vec![0]));
+    }
+}
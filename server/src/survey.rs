@@ -0,0 +1,170 @@
+//! Surveys fill in the unknown blocks of a box. Nothing is planned up front: the turtle goes to
+//! the closest known free block next to unknown blocks of the box (the frontier), looks at them,
+//! and picks the next one with what it learned. It is done when every block of the box is known,
+//! or when the rest can't be seen without digging.
+
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
+
+use common::{
+    world_data::{Knowledge, World},
+    Pos3,
+};
+use pathfinding::PathfindingOptions;
+
+use crate::jobs::{Action, Step};
+
+/// Up and down first, those need no turns
+const NEIGHBOURS: [Pos3; 6] = [
+    Pos3 { x: 0, y: 1, z: 0 },
+    Pos3 { x: 0, y: -1, z: 0 },
+    Pos3 { x: 0, y: 0, z: -1 },
+    Pos3 { x: 1, y: 0, z: 0 },
+    Pos3 { x: 0, y: 0, z: 1 },
+    Pos3 { x: -1, y: 0, z: 0 },
+];
+
+pub fn contains(min: Pos3, max: Pos3, pos: Pos3) -> bool {
+    (min.x..=max.x).contains(&pos.x)
+        && (min.y..=max.y).contains(&pos.y)
+        && (min.z..=max.z).contains(&pos.z)
+}
+
+fn cells(min: Pos3, max: Pos3) -> impl Iterator<Item = Pos3> {
+    (min.x..=max.x).flat_map(move |x| {
+        (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| Pos3::new(x, y, z)))
+    })
+}
+
+fn is_unknown(world: &World, pos: Pos3) -> bool {
+    matches!(world.get_knowledge(&pos), Knowledge::Unknown)
+}
+
+/// Whether a turtle can stand there, the same blocks go to routes go through
+fn is_free(world: &World, pos: Pos3, options: &PathfindingOptions) -> bool {
+    match world.get_knowledge(&pos) {
+        Knowledge::Air => true,
+        Knowledge::Block(id) => options.passable_blocks.iter().any(|b| b == id),
+        Knowledge::Unknown => false,
+    }
+}
+
+/// Percentage of the blocks between `min` and `max` that are known, rounded down so 100 means
+/// all of them
+pub fn coverage(world: &World, min: Pos3, max: Pos3) -> u8 {
+    let (mut known, mut total) = (0u64, 0u64);
+    for pos in cells(min, max) {
+        total += 1;
+        known += u64::from(!is_unknown(world, pos));
+    }
+    (known * 100 / total.max(1)) as u8
+}
+
+/// Known free blocks next to unknown blocks of the box, with how many of those each one sees.
+/// `pos` is where the turtle is, it is free even when no turtle reported the block yet
+pub fn frontier(world: &World, min: Pos3, max: Pos3, pos: Pos3) -> HashMap<Pos3, usize> {
+    let options = PathfindingOptions::default();
+    let mut frontier = HashMap::new();
+    for unknown in cells(min, max).filter(|p| is_unknown(world, *p)) {
+        for n in NEIGHBOURS.map(|d| unknown + d) {
+            if n == pos || is_free(world, n, &options) {
+                *frontier.entry(n).or_insert(0) += 1;
+            }
+        }
+    }
+    frontier
+}
+
+/// The steps that look at every unknown block of the box next to the closest viewpoint, the one
+/// that sees the most when there are several. Empty when there is nothing left to see from
+/// anywhere but `unreachable`
+pub fn next_viewpoint(
+    world: &World,
+    min: Pos3,
+    max: Pos3,
+    pos: Pos3,
+    unreachable: &HashSet<Pos3>,
+) -> Vec<Step> {
+    let Some(from) = frontier(world, min, max, pos)
+        .into_iter()
+        .filter(|(p, _)| !unreachable.contains(p))
+        .min_by_key(|(p, seen)| {
+            let d = *p - pos;
+            let distance = d.x.abs() + d.y.abs() + d.z.abs();
+            (distance, Reverse(*seen), p.x, p.y, p.z)
+        })
+        .map(|(p, _)| p)
+    else {
+        return Vec::new();
+    };
+    NEIGHBOURS
+        .map(|d| from + d)
+        .into_iter()
+        .filter(|p| contains(min, max, *p) && is_unknown(world, *p))
+        .map(|target| Step {
+            from,
+            action: Action::Inspect(target),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use common::world_data::Block;
+
+    use super::*;
+
+    fn set(world: &mut World, pos: Pos3, id: Option<&str>) {
+        world.set_block(Block::new(id.map(Into::into), &pos, "test"));
+    }
+
+    #[test]
+    fn coverage_counts_known_blocks() {
+        let mut world = World::new("test");
+        let (min, max) = (Pos3::new(0, 0, 0), Pos3::new(1, 0, 1));
+        assert_eq!(coverage(&world, min, max), 0);
+        set(&mut world, Pos3::new(0, 0, 0), None);
+        set(&mut world, Pos3::new(1, 0, 1), Some("minecraft:stone"));
+        // outside of the box
+        set(&mut world, Pos3::new(2, 0, 0), None);
+        assert_eq!(coverage(&world, min, max), 50);
+        set(&mut world, Pos3::new(1, 0, 0), None);
+        assert_eq!(coverage(&world, min, max), 75);
+        set(&mut world, Pos3::new(0, 0, 1), None);
+        assert_eq!(coverage(&world, min, max), 100);
+    }
+
+    #[test]
+    fn viewpoints_are_the_closest_known_free_blocks() {
+        let mut world = World::new("test");
+        let (min, max) = (Pos3::new(0, 0, 0), Pos3::new(4, 0, 0));
+        for x in 0..=2 {
+            set(&mut world, Pos3::new(x, 0, 0), None);
+        }
+        set(&mut world, Pos3::new(4, 1, 0), Some("minecraft:stone"));
+        let none = HashSet::new();
+        // the turtle stands in unknown space, it sees what is around it first
+        let steps = next_viewpoint(&world, min, max, Pos3::new(5, 0, 0), &none);
+        assert_eq!(
+            steps,
+            vec![Step {
+                from: Pos3::new(5, 0, 0),
+                action: Action::Inspect(Pos3::new(4, 0, 0)),
+            }]
+        );
+        let steps = next_viewpoint(&world, min, max, Pos3::new(0, 0, 0), &none);
+        assert_eq!(
+            steps,
+            vec![Step {
+                from: Pos3::new(2, 0, 0),
+                action: Action::Inspect(Pos3::new(3, 0, 0)),
+            }]
+        );
+        // stone is no viewpoint, with the other two out of reach nothing is left
+        let unreachable = HashSet::from([Pos3::new(2, 0, 0), Pos3::new(5, 0, 0)]);
+        let steps = next_viewpoint(&world, min, max, Pos3::new(5, 0, 0), &unreachable);
+        assert!(steps.is_empty());
+    }
+}
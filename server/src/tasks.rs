@@ -0,0 +1,278 @@
+//! Work queued for all turtles of a world. Whenever something changes, idle turtles get the
+//! queued tasks with the highest priority, each one goes to the closest turtle with the fuel to
+//! get to where it starts. The task of a turtle that goes offline goes back into the queue.
+
+use common::{
+    client_packets::{GoToStatus, JobStatus, TaskKind},
+    turtle::{InventoryAction, Maybe, MoveDirection},
+    turtle_packets::S2TPackets,
+    Pos3,
+};
+use log::{error, info};
+
+use crate::{
+    connection_manager::TurtleCommBus,
+    data_types::server_turtle::ServerTurtle,
+    db::{
+        delete_job, delete_task, fail_task, load_waypoint, release_task, save_job, save_job_step,
+        DB,
+    },
+    fuel::distance,
+    jobs::JobUpdate,
+};
+
+/// Empties `slot` into the chest below, a delivery does this for every slot
+fn drop_down(slot: u8) -> InventoryAction {
+    InventoryAction::Drop {
+        slot,
+        direction: MoveDirection::Down,
+        count: None,
+    }
+}
+
+/// The task a turtle works on
+pub struct Assignment {
+    pub id: i64,
+    pub kind: TaskKind,
+    /// Slot a delivery waits for the drop result of, `None` before it arrived
+    pub dropping: Option<u8>,
+}
+
+/// An idle turtle that could take a task
+#[derive(Clone, Copy)]
+pub struct Candidate {
+    pub index: i32,
+    pub position: Pos3,
+    pub fuel: i32,
+}
+
+/// Where a turtle has to go first for the task
+pub async fn task_start(db: &DB, world: &str, kind: &TaskKind) -> Result<Pos3, String> {
+    match kind {
+        TaskKind::Job(job) => Ok(job.start()),
+        TaskKind::Deliver { waypoint } => match load_waypoint(db, world, waypoint).await {
+            Ok(Some(pos)) => Ok(pos),
+            Ok(None) => Err(format!("there is no waypoint called {waypoint}")),
+            Err(err) => Err(err.to_string()),
+        },
+    }
+}
+
+/// The closest candidate with the fuel to get to `start`, ties go to the one with more fuel
+pub fn pick_turtle(start: Pos3, candidates: &[Candidate]) -> Option<i32> {
+    candidates
+        .iter()
+        .filter_map(|c| {
+            let distance = distance(c.position, start);
+            // unlimited fuel is -1
+            let fuel = if c.fuel < 0 { i64::MAX } else { c.fuel as i64 };
+            (fuel >= distance as i64).then_some((distance, -fuel, c.index))
+        })
+        .min()
+        .map(|(_, _, index)| index)
+}
+
+impl ServerTurtle {
+    /// Not working on anything the dispatcher would get in the way of
+    pub fn is_idle(&self) -> bool {
+        self.task.is_none() && self.job.is_none() && self.build.is_none() && self.go_to.is_none()
+    }
+
+    /// Starts the task, a job task goes on at `step`
+    pub async fn start_task(
+        &mut self,
+        id: i64,
+        kind: TaskKind,
+        step: u32,
+    ) -> Result<Vec<JobUpdate>, String> {
+        let db = self.get_db();
+        let updates = match &kind {
+            TaskKind::Job(job) => {
+                let saved = match save_job(&db, &self.world, self.index, job).await {
+                    Ok(()) => save_job_step(&db, &self.world, self.index, step).await,
+                    Err(err) => Err(err),
+                };
+                saved.map_err(|err| err.to_string())?;
+                let updates = self.start_job(job.clone(), step).await;
+                if updates.is_err() {
+                    if let Err(err) = delete_job(&db, &self.world, self.index).await {
+                        error!("removing the job of turtle {} failed: {err}", self.index);
+                    }
+                }
+                updates?
+            }
+            TaskKind::Deliver { .. } => {
+                let target = task_start(&db, &self.world, &kind).await?;
+                self.start_go_to(target)
+                    .await
+                    .into_iter()
+                    .map(JobUpdate::GoTo)
+                    .collect()
+            }
+        };
+        info!("turtle {} works on task {id}", self.index);
+        self.task = Some(Assignment {
+            id,
+            kind,
+            dropping: None,
+        });
+        Ok(updates)
+    }
+
+    /// Ends the task once its job or go to does
+    pub async fn drive_task(&mut self, update: &JobUpdate) {
+        let Some(task) = &self.task else {
+            return;
+        };
+        match (&task.kind, update) {
+            (TaskKind::Job(_), JobUpdate::Job(JobStatus::Done)) => self.end_task(Ok(())).await,
+            (TaskKind::Job(_), JobUpdate::Job(JobStatus::Failed { reason })) => {
+                self.end_task(Err(reason.clone())).await
+            }
+            (TaskKind::Deliver { .. }, JobUpdate::GoTo(GoToStatus::Arrived { .. })) => {
+                if let Some(task) = &mut self.task {
+                    task.dropping = Some(1);
+                }
+                self.send_ws(S2TPackets::Inventory {
+                    action: drop_down(1),
+                    towards: None,
+                })
+                .await;
+            }
+            (TaskKind::Deliver { .. }, JobUpdate::GoTo(GoToStatus::Failed { reason, .. })) => {
+                self.end_task(Err(reason.clone())).await
+            }
+            _ => {}
+        }
+    }
+
+    /// Drops the next slot of a delivery once the turtle answered the last drop, after the
+    /// last slot it failed if anything is left. `false` if `action` wasn't the delivery's.
+    pub async fn drive_task_drop(&mut self, action: &InventoryAction) -> bool {
+        let Some(Assignment {
+            dropping: Some(slot),
+            ..
+        }) = &mut self.task
+        else {
+            return false;
+        };
+        if *action != drop_down(*slot) {
+            return false;
+        }
+        // a failed drop is an empty slot or a full chest, what is left tells in the end
+        if *slot < 16 {
+            *slot += 1;
+            let action = drop_down(*slot);
+            self.send_ws(S2TPackets::Inventory {
+                action,
+                towards: None,
+            })
+            .await;
+            return true;
+        }
+        // the turtle reports its inventory before it answers
+        let left: u32 = match &self.inventory {
+            Maybe::Some(inv) => inv
+                .iter()
+                .map(|slot| match slot {
+                    Maybe::Some(item) => item.count,
+                    Maybe::None => 0,
+                })
+                .sum(),
+            Maybe::None => 0,
+        };
+        let result = match left {
+            0 => Ok(()),
+            left => Err(format!("{left} items didn't fit into the chest")),
+        };
+        self.end_task(result).await;
+        true
+    }
+
+    /// Takes the task out of the queue if it worked, marks it failed if not
+    pub async fn end_task(&mut self, result: Result<(), String>) {
+        let Some(task) = self.task.take() else {
+            return;
+        };
+        let db = self.get_db();
+        let stored = match result {
+            Ok(()) => {
+                info!("turtle {} is done with task {}", self.index, task.id);
+                delete_task(&db, &self.world, task.id).await
+            }
+            Err(reason) => {
+                info!("task {} of turtle {} failed: {reason}", task.id, self.index);
+                fail_task(&db, task.id, &reason).await
+            }
+        };
+        if let Err(err) = stored {
+            error!("storing the end of task {} failed: {err}", task.id);
+        }
+        self.tasks_changed().await;
+    }
+
+    /// Puts the task back into the queue, whoever gets it next goes on where this turtle left
+    /// off. `retry` if the turtle went away
+    pub async fn release_task(&mut self, retry: bool) {
+        let Some(task) = self.task.take() else {
+            return;
+        };
+        let db = self.get_db();
+        if let TaskKind::Job(_) = task.kind {
+            if let Err(err) = delete_job(&db, &self.world, self.index).await {
+                error!("removing the job of turtle {} failed: {err}", self.index);
+            }
+        }
+        let step = self.job.as_ref().map_or(0, |job| job.step());
+        info!("turtle {} gives task {} back", self.index, task.id);
+        if let Err(err) = release_task(&db, task.id, step, retry).await {
+            error!("putting task {} back failed: {err}", task.id);
+        }
+        self.tasks_changed().await;
+    }
+
+    async fn tasks_changed(&mut self) {
+        let world = self.world.clone();
+        if let Err(err) = self.comm(TurtleCommBus::TasksChanged(world)).await {
+            error!("{err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(index: i32, x: i32, fuel: i32) -> Candidate {
+        Candidate {
+            index,
+            position: Pos3::new(x, 0, 0),
+            fuel,
+        }
+    }
+
+    #[test]
+    fn the_closest_turtle_with_enough_fuel_gets_it() {
+        let start = Pos3::zero();
+        let candidates = [
+            candidate(0, 10, 100),
+            candidate(1, 4, 2),
+            candidate(2, -6, 50),
+        ];
+        assert_eq!(pick_turtle(start, &candidates), Some(2));
+        assert_eq!(pick_turtle(start, &candidates[1..2]), None);
+        assert_eq!(pick_turtle(start, &[]), None);
+    }
+
+    #[test]
+    fn ties_go_to_more_fuel() {
+        let start = Pos3::zero();
+        let candidates = [
+            candidate(0, 3, 20),
+            candidate(1, -3, -1),
+            candidate(2, 3, 90),
+        ];
+        assert_eq!(pick_turtle(start, &candidates), Some(1));
+        assert_eq!(pick_turtle(start, &[candidates[0], candidates[2]]), Some(2));
+    }
+}
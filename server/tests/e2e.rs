@@ -0,0 +1,1985 @@
+//! Runs the connection manager against an in memory db, with simulated turtles and a scripted
+//! client talking to it over real websockets.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use backend::{
+    connection_manager,
+    db::{inventory_at, load_region, load_world, save_block, save_blueprint, DB},
+    export::{export_from_db, ExportError, ExportFormat, Region},
+    handle_clients, handle_turtles,
+};
+use chrono::Utc;
+use common::{
+    blueprint::{Blueprint, BlueprintStatus},
+    client_packets::{
+        BuildStatus, C2SPackets, Capability, GoToStatus, HandshakeData, InventoryStatus, JobKind,
+        JobStatus, S2CPackets, Task, TaskKind, TaskState, TunnelSize, PROTOCOL_VERSION,
+    },
+    turtle::{InventoryAction, Item, Maybe, MoveDirection, Orientation, TurtleInventory},
+    turtle_packets::InspectInfo,
+    waypoint::{Target, Waypoint, WaypointKind},
+    wire,
+    world_data::{Block, BlockState, Knowledge, PaletteEntry},
+    Pos3,
+};
+use futures_util::{SinkExt, StreamExt};
+use sqlx::{sqlite::SqlitePoolOptions, Row};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc::unbounded_channel,
+    task::JoinHandle,
+    time::timeout,
+};
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use tungstenite::Message;
+use turtle_simulator::{run_turtle, world::CHEST_SLOTS, SharedWorld, SimTurtle, SimWorld};
+
+const WORLD: &str = "test_world";
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+struct TestServer {
+    client_addr: SocketAddr,
+    turtle_addr: SocketAddr,
+    db: Arc<DB>,
+}
+
+impl TestServer {
+    async fn start() -> TestServer {
+        // every connection to :memory: gets its own db, so the pool must never open a second one
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("../migrations").run(&db).await.unwrap();
+        sqlx::query("INSERT INTO worlds VALUES (?)")
+            .bind(WORLD)
+            .execute(&db)
+            .await
+            .unwrap();
+        let db = Arc::new(db);
+
+        let client_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let turtle_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = TestServer {
+            client_addr: client_listener.local_addr().unwrap(),
+            turtle_addr: turtle_listener.local_addr().unwrap(),
+            db: db.clone(),
+        };
+        let (turtle_connected_tx, turtle_connected_recv) = unbounded_channel();
+        let (client_connected_tx, client_connected_recv) = unbounded_channel();
+        tokio::spawn(handle_clients::accept_connections(
+            client_listener,
+            client_connected_tx,
+        ));
+        tokio::spawn(handle_turtles::accept_connections(
+            turtle_listener,
+            turtle_connected_tx,
+        ));
+        connection_manager::main(turtle_connected_recv, client_connected_recv, db)
+            .await
+            .unwrap();
+        server
+    }
+
+    fn spawn_turtle(
+        &self,
+        turtle: SimTurtle,
+        world: &SharedWorld,
+    ) -> JoinHandle<anyhow::Result<SimTurtle>> {
+        let url = format!("ws://{}", self.turtle_addr);
+        let world = world.clone();
+        tokio::spawn(async move { run_turtle(&url, turtle, world).await })
+    }
+}
+
+struct TestClient {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    /// Send packets in the wire format instead of JSON
+    binary: bool,
+}
+
+impl TestClient {
+    async fn connect(server: &TestServer) -> TestClient {
+        let (ws, _) = connect_async(format!("ws://{}", server.client_addr))
+            .await
+            .unwrap();
+        let mut client = TestClient { ws, binary: false };
+        // the server only answers clients it registered, after this broadcasts will reach us too
+        client.send(C2SPackets::RequestWorlds).await;
+        match client.recv().await {
+            S2CPackets::Worlds(worlds) => assert_eq!(worlds, vec![WORLD.to_string()]),
+            p => panic!("expected Worlds, got {p:?}"),
+        }
+        client
+    }
+
+    /// Connects and negotiates the binary encoding before anything else
+    async fn connect_binary(server: &TestServer) -> TestClient {
+        let (ws, _) = connect_async(format!("ws://{}", server.client_addr))
+            .await
+            .unwrap();
+        let mut client = TestClient { ws, binary: false };
+        let handshake = HandshakeData {
+            version: PROTOCOL_VERSION,
+            capabilities: vec![Capability::BinaryEncoding],
+        };
+        client.send(C2SPackets::Handshake(handshake.clone())).await;
+        match client.recv_frame().await {
+            Message::Text(text) => match serde_json::from_str(&text).unwrap() {
+                S2CPackets::Handshake(answer) => assert_eq!(answer, handshake),
+                p => panic!("expected Handshake, got {p:?}"),
+            },
+            msg => panic!("the handshake should be answered in JSON, got {msg:?}"),
+        }
+        client.binary = true;
+        client
+    }
+
+    async fn send(&mut self, packet: C2SPackets) {
+        let msg = match self.binary {
+            true => Message::Binary(wire::to_bytes(&packet).unwrap()),
+            false => Message::Text(serde_json::to_string(&packet).unwrap()),
+        };
+        self.ws.send(msg).await.unwrap();
+    }
+
+    async fn send_lua(&mut self, index: i32, code: &str) {
+        self.send(C2SPackets::SendLuaToTurtle {
+            index,
+            world: WORLD.into(),
+            code: code.into(),
+        })
+        .await;
+    }
+
+    async fn recv_frame(&mut self) -> Message {
+        timeout(TIMEOUT, self.ws.next())
+            .await
+            .expect("timed out waiting for a packet")
+            .expect("server closed the connection")
+            .unwrap()
+    }
+
+    async fn recv(&mut self) -> S2CPackets {
+        loop {
+            match self.recv_frame().await {
+                Message::Text(text) => {
+                    assert!(!self.binary, "got JSON after negotiating binary: {text}");
+                    return serde_json::from_str(&text).unwrap();
+                }
+                Message::Binary(bytes) => {
+                    assert!(self.binary, "got binary without negotiating it");
+                    return wire::from_bytes(&bytes).unwrap();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    async fn recv_n(&mut self, n: usize) -> Vec<S2CPackets> {
+        let mut packets = Vec::new();
+        for _ in 0..n {
+            packets.push(self.recv().await);
+        }
+        packets
+    }
+
+    /// Makes sure the server doesn't send anything we didn't expect
+    async fn assert_silent(&mut self) {
+        if let Ok(msg) = timeout(Duration::from_millis(300), self.ws.next()).await {
+            panic!("expected no more packets, got {msg:?}");
+        }
+    }
+}
+
+fn kind(packet: &S2CPackets) -> &'static str {
+    match packet {
+        S2CPackets::MovedTurtle(_) => "MovedTurtle",
+        S2CPackets::TurtleInventoryUpdate(_) => "TurtleInventoryUpdate",
+        S2CPackets::TurtleFuelUpdate(_) => "TurtleFuelUpdate",
+        S2CPackets::SetTurtles(_) => "SetTurtles",
+        S2CPackets::Worlds(_) => "Worlds",
+        S2CPackets::WorldUpdate(_) => "WorldUpdate",
+        S2CPackets::SetWorld(_) => "SetWorld",
+        S2CPackets::StdOutFromTurtle(_) => "StdOutFromTurtle",
+        S2CPackets::StdOutScrollback(_) => "StdOutScrollback",
+        S2CPackets::GoToUpdate(_) => "GoToUpdate",
+        S2CPackets::TurtleExecutables(_) => "TurtleExecutables",
+        S2CPackets::Handshake(_) => "Handshake",
+        S2CPackets::ProtocolError(_) => "ProtocolError",
+        S2CPackets::SetChunk { .. } => "SetChunk",
+        S2CPackets::UnloadChunks { .. } => "UnloadChunks",
+        S2CPackets::BlockSearchResults { .. } => "BlockSearchResults",
+        S2CPackets::Blueprints { .. } => "Blueprints",
+        S2CPackets::BlueprintError { .. } => "BlueprintError",
+        S2CPackets::BuildUpdate(_) => "BuildUpdate",
+        S2CPackets::JobUpdate(_) => "JobUpdate",
+        S2CPackets::TurtleHome(_) => "TurtleHome",
+        S2CPackets::Waypoints { .. } => "Waypoints",
+        S2CPackets::WaypointError { .. } => "WaypointError",
+        S2CPackets::Tasks { .. } => "Tasks",
+        S2CPackets::InventoryResult(_) => "InventoryResult",
+    }
+}
+
+fn kinds(packets: &[S2CPackets]) -> Vec<&'static str> {
+    packets.iter().map(kind).collect()
+}
+
+fn block(packet: &S2CPackets) -> &Block {
+    match packet {
+        S2CPackets::WorldUpdate(block) => block,
+        p => panic!("expected WorldUpdate, got {p:?}"),
+    }
+}
+
+fn assert_block(packet: &S2CPackets, pos: Pos3, id: Option<&str>) {
+    let block = block(packet);
+    assert_eq!(block.pos, pos);
+    assert_eq!(block.world, WORLD);
+    match id {
+        Some(id) => assert!(!block.is_air && block.id == id, "{block:?} should be {id}"),
+        None => assert!(block.is_air, "{block:?} should be air"),
+    }
+}
+
+fn flat_world() -> SharedWorld {
+    Arc::new(Mutex::new(SimWorld::flat(0)))
+}
+
+/// Spawns a turtle at the origin facing north and consumes the packets its connect causes
+async fn connect_turtle(
+    server: &TestServer,
+    client: &mut TestClient,
+    index: i32,
+    world: &SharedWorld,
+) -> JoinHandle<anyhow::Result<SimTurtle>> {
+    let turtle = SimTurtle::new(index, WORLD, Pos3::new(0, 0, 0), Orientation::North);
+    let handle = server.spawn_turtle(turtle, world);
+    let packets = client.recv_n(3).await;
+    assert_eq!(
+        kinds(&packets),
+        ["SetTurtles", "TurtleFuelUpdate", "TurtleInventoryUpdate"]
+    );
+    handle
+}
+
+#[tokio::test]
+async fn turtle_connect_and_disconnect() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    let turtle = SimTurtle::new(3, WORLD, Pos3::new(5, 2, 7), Orientation::East);
+    let handle = server.spawn_turtle(turtle, &world);
+
+    let packets = client.recv_n(3).await;
+    assert_eq!(
+        kinds(&packets),
+        ["SetTurtles", "TurtleFuelUpdate", "TurtleInventoryUpdate"]
+    );
+    let S2CPackets::SetTurtles(data) = &packets[0] else {
+        unreachable!()
+    };
+    assert_eq!(data.world, WORLD);
+    assert_eq!(data.turtles.len(), 1);
+    let turtle = &data.turtles[0];
+    assert_eq!(turtle.index, 3);
+    assert_eq!(turtle.name, "sim_3");
+    assert_eq!(turtle.position, Pos3::new(5, 2, 7));
+    assert_eq!(turtle.orientation, Orientation::East);
+    assert_eq!(turtle.fuel, 1000);
+    assert!(turtle.is_online);
+    let S2CPackets::TurtleFuelUpdate(fuel) = &packets[1] else {
+        unreachable!()
+    };
+    assert_eq!((fuel.index, fuel.data), (3, 1000));
+    client.assert_silent().await;
+
+    handle.abort();
+    match client.recv().await {
+        S2CPackets::SetTurtles(data) => {
+            assert_eq!(data.turtles.len(), 1);
+            assert!(!data.turtles[0].is_online);
+        }
+        p => panic!("expected SetTurtles, got {p:?}"),
+    }
+    client.assert_silent().await;
+
+    let row = sqlx::query("SELECT name, fuel, max_fuel FROM turtles WHERE id = 3 AND world = ?")
+        .bind(WORLD)
+        .fetch_one(&*server.db)
+        .await
+        .unwrap();
+    assert_eq!(row.get::<String, _>("name"), "sim_3");
+    assert_eq!(row.get::<i64, _>("fuel"), 1000);
+    assert_eq!(row.get::<i64, _>("max_fuel"), 100000);
+}
+
+#[tokio::test]
+async fn turtle_moves_are_broadcast() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    let _turtle = connect_turtle(&server, &mut client, 0, &world).await;
+
+    client.send_lua(0, "turtle.forward()").await;
+    let packets = client.recv_n(6).await;
+    assert_eq!(
+        kinds(&packets),
+        [
+            "WorldUpdate",
+            "MovedTurtle",
+            "TurtleFuelUpdate",
+            "WorldUpdate",
+            "WorldUpdate",
+            "WorldUpdate"
+        ]
+    );
+    // the turtle itself stands in air
+    assert_block(&packets[0], Pos3::new(0, 0, -1), None);
+    let S2CPackets::MovedTurtle(moved) = &packets[1] else {
+        unreachable!()
+    };
+    assert_eq!(moved.new_pos, Pos3::new(0, 0, -1));
+    assert_eq!(moved.new_orientation, Orientation::North);
+    let S2CPackets::TurtleFuelUpdate(fuel) = &packets[2] else {
+        unreachable!()
+    };
+    assert_eq!(fuel.data, 999);
+    // up, front, down relative to the new position
+    assert_block(&packets[3], Pos3::new(0, 1, -1), None);
+    assert_block(&packets[4], Pos3::new(0, 0, -2), None);
+    assert_block(&packets[5], Pos3::new(0, -1, -1), Some("minecraft:stone"));
+    client.assert_silent().await;
+
+    client.send_lua(0, "turtle.turnLeft()").await;
+    let packets = client.recv_n(6).await;
+    let S2CPackets::MovedTurtle(moved) = &packets[1] else {
+        panic!("expected MovedTurtle, got {:?}", packets[1]);
+    };
+    assert_eq!(moved.new_pos, Pos3::new(0, 0, -1));
+    assert_eq!(moved.new_orientation, Orientation::West);
+    assert_block(&packets[4], Pos3::new(-1, 0, -1), None);
+    client.assert_silent().await;
+}
+
+#[tokio::test]
+async fn blocks_are_discovered_and_stored() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    let front = Pos3::new(0, 0, -1);
+    world
+        .lock()
+        .unwrap()
+        .set_block(front, Some("minecraft:diamond_ore".into()));
+    let _turtle = connect_turtle(&server, &mut client, 0, &world).await;
+
+    // a failed move still reports what blocked it
+    client.send_lua(0, "turtle.forward()").await;
+    let packets = client.recv_n(3).await;
+    assert_eq!(kinds(&packets), ["WorldUpdate"; 3]);
+    assert_block(&packets[0], Pos3::new(0, 1, 0), None);
+    assert_block(&packets[1], front, Some("minecraft:diamond_ore"));
+    assert_block(&packets[2], Pos3::new(0, -1, 0), Some("minecraft:stone"));
+    for p in &packets {
+        let observed = block(p)
+            .observed
+            .expect("turtles should sign what they see");
+        assert_eq!(observed.by, 0);
+        assert!(observed.at > 0);
+    }
+    client.assert_silent().await;
+
+    client.send(C2SPackets::RequestWorld(WORLD.into())).await;
+    match client.recv().await {
+        S2CPackets::SetWorld(w) => {
+            let block = w.get_block(&front).expect("block should be stored");
+            assert_eq!(block.id, "minecraft:diamond_ore");
+            assert_eq!(block.observed.map(|o| o.by), Some(0));
+            assert_eq!(w.get_knowledge(&Pos3::new(0, 1, 0)), Knowledge::Air);
+            assert_eq!(w.get_knowledge(&Pos3::new(5, 5, 5)), Knowledge::Unknown);
+        }
+        p => panic!("expected SetWorld, got {p:?}"),
+    }
+
+    client.send_lua(0, "turtle.dig()").await;
+    let packets = client.recv_n(4).await;
+    assert_eq!(
+        kinds(&packets),
+        [
+            "WorldUpdate",
+            "WorldUpdate",
+            "WorldUpdate",
+            "TurtleInventoryUpdate"
+        ]
+    );
+    assert_block(&packets[1], front, None);
+    let S2CPackets::TurtleInventoryUpdate(inv) = &packets[3] else {
+        unreachable!()
+    };
+    assert!(matches!(
+        &inv.data[0],
+        Maybe::Some(Item { count: 1, name }) if name == "minecraft:diamond_ore"
+    ));
+    client.assert_silent().await;
+}
+
+#[tokio::test]
+async fn block_states_are_stored() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    let front = Pos3::new(0, 0, -1);
+    let mut wheat = InspectInfo::new("minecraft:wheat");
+    wheat.state.insert("age".into(), "7".into());
+    wheat.tags.insert("minecraft:crops".into());
+    world.lock().unwrap().set_inspected(front, wheat);
+    let _turtle = connect_turtle(&server, &mut client, 0, &world).await;
+
+    client.send_lua(0, "turtle.forward()").await;
+    let packets = client.recv_n(3).await;
+    let seen = block(&packets[1]);
+    assert_eq!(seen.pos, front);
+    assert_eq!(seen.state.get("age"), Some("7"));
+    assert!(seen.state.has_tag("minecraft:crops"));
+    assert!(block(&packets[2]).state.properties.is_empty());
+
+    client.send(C2SPackets::RequestWorld(WORLD.into())).await;
+    match client.recv().await {
+        S2CPackets::SetWorld(w) => {
+            let block = w.get_block(&front).expect("block should be stored");
+            assert_eq!(block.state.to_string(), "[age=7]");
+            assert!(block.state.has_tag("minecraft:crops"));
+        }
+        p => panic!("expected SetWorld, got {p:?}"),
+    }
+}
+
+fn slot_name(inv: &TurtleInventory, slot: usize) -> Option<&str> {
+    match &inv.inv[slot] {
+        Maybe::Some(item) => Some(&item.name),
+        Maybe::None => None,
+    }
+}
+
+#[tokio::test]
+async fn inventories_are_stored_with_history() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    world
+        .lock()
+        .unwrap()
+        .set_block(Pos3::new(0, 0, -1), Some("minecraft:diamond_ore".into()));
+    let turtle = connect_turtle(&server, &mut client, 0, &world).await;
+    let before_dig = Utc::now();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    client.send_lua(0, "turtle.dig()").await;
+    client.recv_n(4).await;
+    let after_dig = Utc::now();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    client.send_lua(0, "turtle.digDown()").await;
+    client.recv_n(4).await;
+
+    turtle.abort();
+    client.recv().await;
+    client.send(C2SPackets::RequestTurtles(WORLD.into())).await;
+    let turtle = match client.recv().await {
+        S2CPackets::SetTurtles(data) => data.turtles[0].clone(),
+        p => panic!("expected SetTurtles, got {p:?}"),
+    };
+    assert!(!turtle.is_online);
+    let Maybe::Some(inv) = turtle.inventory else {
+        panic!("offline turtles should still have their inventory");
+    };
+    assert_eq!(slot_name(&inv, 0), Some("minecraft:diamond_ore"));
+    assert_eq!(slot_name(&inv, 1), Some("minecraft:cobblestone"));
+
+    let at = |time| inventory_at(&server.db, WORLD, 0, time);
+    let inv = at(before_dig)
+        .await
+        .unwrap()
+        .expect("setup reported an inventory");
+    assert!(inv.inv.iter().all(|i| matches!(i, Maybe::None)));
+    let inv = at(after_dig).await.unwrap().unwrap();
+    assert_eq!(slot_name(&inv, 0), Some("minecraft:diamond_ore"));
+    assert_eq!(slot_name(&inv, 1), None);
+    let inv = at(Utc::now()).await.unwrap().unwrap();
+    assert_eq!(slot_name(&inv, 1), Some("minecraft:cobblestone"));
+    let before_connect = before_dig - chrono::Duration::hours(1);
+    assert!(at(before_connect).await.unwrap().is_none());
+}
+
+fn std_out(packet: &S2CPackets) -> &str {
+    match packet {
+        S2CPackets::StdOutFromTurtle(data) => &data.data,
+        p => panic!("expected StdOutFromTurtle, got {p:?}"),
+    }
+}
+
+async fn subscribe(client: &mut TestClient, index: i32) -> String {
+    client
+        .send(C2SPackets::SubscribeToStdOut {
+            index,
+            world: WORLD.into(),
+        })
+        .await;
+    match client.recv().await {
+        S2CPackets::StdOutScrollback(data) => {
+            assert_eq!((data.index, data.world.as_str()), (index, WORLD));
+            data.data
+        }
+        p => panic!("expected StdOutScrollback, got {p:?}"),
+    }
+}
+
+#[tokio::test]
+async fn std_out_and_std_in_are_routed() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    let _turtle = connect_turtle(&server, &mut client, 0, &world).await;
+    assert_eq!(subscribe(&mut client, 0).await, "");
+
+    client.send_lua(0, "print('hello', 1)").await;
+    assert_eq!(std_out(&client.recv().await), "hello\t1\n");
+
+    // joins late and only gets the scrollback, and output from now on
+    let mut late_client = TestClient::connect(&server).await;
+    assert_eq!(subscribe(&mut late_client, 0).await, "hello\t1\n");
+
+    client
+        .send_lua(0, "local name = read() print('hi ' .. name)")
+        .await;
+    late_client
+        .send(C2SPackets::StdInForTurtle {
+            index: 0,
+            world: WORLD.into(),
+            value: "bob".into(),
+        })
+        .await;
+    for c in [&mut client, &mut late_client] {
+        assert_eq!(std_out(&c.recv().await), "bob\n");
+        assert_eq!(std_out(&c.recv().await), "hi bob\n");
+        c.assert_silent().await;
+    }
+
+    client
+        .send(C2SPackets::UnsubscribeFromStdOut {
+            index: 0,
+            world: WORLD.into(),
+        })
+        .await;
+    client.send_lua(0, "print('bye')").await;
+    assert_eq!(std_out(&late_client.recv().await), "bye\n");
+    client.assert_silent().await;
+}
+
+fn executables(packet: &S2CPackets) -> Vec<String> {
+    match packet {
+        S2CPackets::TurtleExecutables(data) => data.data.clone(),
+        p => panic!("expected TurtleExecutables, got {p:?}"),
+    }
+}
+
+async fn request_executables(client: &mut TestClient, refresh: bool) {
+    client
+        .send(C2SPackets::RequestExecutables {
+            index: 0,
+            world: WORLD.into(),
+            refresh,
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn executables_are_cached_saved_and_run() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let mut other_client = TestClient::connect(&server).await;
+    let world = flat_world();
+    let _turtle = connect_turtle(&server, &mut client, 0, &world).await;
+    other_client.recv_n(3).await;
+
+    // nothing cached yet, so the turtle gets asked and everyone sees the answer
+    request_executables(&mut client, false).await;
+    assert!(executables(&client.recv().await).is_empty());
+    assert!(executables(&other_client.recv().await).is_empty());
+
+    client
+        .send(C2SPackets::SaveFileToTurtle {
+            index: 0,
+            world: WORLD.into(),
+            path: "greet".into(),
+            contents: "print('hi ' .. read())".into(),
+        })
+        .await;
+    assert_eq!(executables(&client.recv().await), ["greet"]);
+    assert_eq!(executables(&other_client.recv().await), ["greet"]);
+
+    // answered from the cache, only to the client that asked
+    request_executables(&mut client, false).await;
+    assert_eq!(executables(&client.recv().await), ["greet"]);
+    other_client.assert_silent().await;
+
+    assert_eq!(subscribe(&mut client, 0).await, "");
+    client
+        .send(C2SPackets::RunProgram {
+            index: 0,
+            world: WORLD.into(),
+            name: "greet".into(),
+            args: Vec::new(),
+        })
+        .await;
+    client
+        .send(C2SPackets::StdInForTurtle {
+            index: 0,
+            world: WORLD.into(),
+            value: "bob".into(),
+        })
+        .await;
+    assert_eq!(std_out(&client.recv().await), "bob\n");
+    assert_eq!(std_out(&client.recv().await), "hi bob\n");
+    client.assert_silent().await;
+}
+
+#[tokio::test]
+async fn binary_clients_get_the_same_packets() {
+    let server = TestServer::start().await;
+    let mut json_client = TestClient::connect(&server).await;
+    let mut binary_client = TestClient::connect_binary(&server).await;
+    binary_client.send(C2SPackets::RequestWorlds).await;
+    match binary_client.recv().await {
+        S2CPackets::Worlds(worlds) => assert_eq!(worlds, [WORLD]),
+        p => panic!("expected Worlds, got {p:?}"),
+    }
+
+    let world = flat_world();
+    let _turtle = connect_turtle(&server, &mut json_client, 0, &world).await;
+    let packets = binary_client.recv_n(3).await;
+    assert_eq!(
+        kinds(&packets),
+        ["SetTurtles", "TurtleFuelUpdate", "TurtleInventoryUpdate"]
+    );
+
+    binary_client.send_lua(0, "turtle.up()").await;
+    for client in [&mut json_client, &mut binary_client] {
+        let packets = client.recv_n(6).await;
+        assert_eq!(kinds(&packets)[1..3], ["MovedTurtle", "TurtleFuelUpdate"]);
+        client.assert_silent().await;
+    }
+}
+
+#[tokio::test]
+async fn protocol_errors_are_reported() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    client
+        .ws
+        .send(Message::Text("{\"NotAPacket\":1}".into()))
+        .await
+        .unwrap();
+    match client.recv().await {
+        S2CPackets::ProtocolError(err) => assert!(err.contains("NotAPacket"), "{err}"),
+        p => panic!("expected ProtocolError, got {p:?}"),
+    }
+    // still connected after that
+    client.send(C2SPackets::RequestWorlds).await;
+    assert_eq!(kind(&client.recv().await), "Worlds");
+
+    client
+        .send(C2SPackets::Handshake(HandshakeData {
+            version: PROTOCOL_VERSION + 1,
+            capabilities: Vec::new(),
+        }))
+        .await;
+    match client.recv().await {
+        S2CPackets::ProtocolError(err) => assert!(err.contains("protocol version"), "{err}"),
+        p => panic!("expected ProtocolError, got {p:?}"),
+    }
+    // a client that speaks another version gets dropped
+    let closed = timeout(TIMEOUT, client.ws.next()).await.unwrap();
+    assert!(matches!(
+        closed,
+        None | Some(Ok(Message::Close(_))) | Some(Err(_))
+    ));
+}
+
+async fn store_block(server: &TestServer, pos: Pos3, id: &str) {
+    save_block(&server.db, &Block::new(Some(id.into()), &pos, WORLD))
+        .await
+        .unwrap();
+}
+
+fn set_chunk(packet: &S2CPackets) -> Pos3 {
+    match packet {
+        S2CPackets::SetChunk { world, chunk } => {
+            assert_eq!(world, WORLD);
+            chunk.get_chunk_pos()
+        }
+        p => panic!("expected SetChunk, got {p:?}"),
+    }
+}
+
+#[tokio::test]
+async fn chunks_are_streamed_around_the_view() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    store_block(&server, Pos3::new(40, 3, 5), "minecraft:stone").await;
+    store_block(&server, Pos3::new(1, 2, -3), "minecraft:dirt").await;
+    store_block(&server, Pos3::new(200, 0, 0), "minecraft:gold_ore").await;
+
+    // without a view there is nothing to stream yet
+    client.send(C2SPackets::SetChunkRenderDistance(3)).await;
+    client.assert_silent().await;
+
+    let view = |center| C2SPackets::UpdateView {
+        world: WORLD.into(),
+        center,
+    };
+    client.send(view(Pos3::new(0, 0, 0))).await;
+    let packets = client.recv_n(2).await;
+    // closest first, the gold is too far away
+    assert_eq!(set_chunk(&packets[0]), Pos3::new(0, 0, -1));
+    assert_eq!(set_chunk(&packets[1]), Pos3::new(2, 0, 0));
+    let S2CPackets::SetChunk { chunk, .. } = &packets[0] else {
+        unreachable!()
+    };
+    assert_eq!(
+        chunk.get_block_id(&Pos3::new(1, 2, 13)).as_deref(),
+        Some("minecraft:dirt")
+    );
+    client.assert_silent().await;
+
+    // moving a bit only sends what is new
+    client.send(view(Pos3::new(1, 0, 0))).await;
+    client.assert_silent().await;
+
+    client.send(view(Pos3::new(11, 0, 0))).await;
+    let packets = client.recv_n(2).await;
+    match &packets[0] {
+        S2CPackets::UnloadChunks { world, chunks } => {
+            assert_eq!(world, WORLD);
+            let mut chunks = chunks.clone();
+            chunks.sort_by_key(|c| c.x);
+            assert_eq!(chunks, [Pos3::new(0, 0, -1), Pos3::new(2, 0, 0)]);
+        }
+        p => panic!("expected UnloadChunks, got {p:?}"),
+    }
+    assert_eq!(set_chunk(&packets[1]), Pos3::new(12, 0, 0));
+    client.assert_silent().await;
+
+    // a smaller distance drops what is out of range now
+    client.send(C2SPackets::SetChunkRenderDistance(0)).await;
+    match client.recv().await {
+        S2CPackets::UnloadChunks { chunks, .. } => assert_eq!(chunks, [Pos3::new(12, 0, 0)]),
+        p => panic!("expected UnloadChunks, got {p:?}"),
+    }
+    client.assert_silent().await;
+}
+
+/// Positions of the blocks the server finds for `pattern`, lowest first
+async fn search(client: &mut TestClient, pattern: &str) -> Vec<Pos3> {
+    client
+        .send(C2SPackets::SearchBlocks {
+            world: WORLD.into(),
+            pattern: pattern.into(),
+        })
+        .await;
+    match client.recv().await {
+        S2CPackets::BlockSearchResults {
+            world,
+            pattern: answered,
+            blocks,
+            truncated,
+        } => {
+            assert_eq!(world, WORLD);
+            assert_eq!(answered, pattern);
+            assert!(!truncated);
+            let mut found = blocks.into_iter().map(|b| b.pos).collect::<Vec<_>>();
+            found.sort_by_key(|p| p.y);
+            found
+        }
+        p => panic!("expected BlockSearchResults, got {p:?}"),
+    }
+}
+
+#[tokio::test]
+async fn blocks_can_be_searched() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    store_block(&server, Pos3::new(3, -40, 7), "minecraft:diamond_ore").await;
+    store_block(
+        &server,
+        Pos3::new(-5, -60, 2),
+        "minecraft:deepslate_diamond_ore",
+    )
+    .await;
+    store_block(&server, Pos3::new(0, -10, 0), "minecraft:iron_ore").await;
+    store_block(&server, Pos3::new(0, -11, 0), "minecraft:stone").await;
+
+    assert_eq!(
+        search(&mut client, "*_ore").await,
+        [
+            Pos3::new(-5, -60, 2),
+            Pos3::new(3, -40, 7),
+            Pos3::new(0, -10, 0)
+        ]
+    );
+    // no namespace means any namespace, but still the whole id
+    assert_eq!(
+        search(&mut client, "diamond_ore").await,
+        [Pos3::new(3, -40, 7)]
+    );
+    assert_eq!(search(&mut client, " *Diamond_Ore").await.len(), 2);
+    assert_eq!(search(&mut client, "minecraft:gold_ore").await, []);
+    client.assert_silent().await;
+}
+
+#[tokio::test]
+async fn regions_are_loaded_for_export() {
+    let server = TestServer::start().await;
+    store_block(&server, Pos3::new(2, 64, -3), "minecraft:chest").await;
+    store_block(&server, Pos3::new(40, 64, -3), "minecraft:furnace").await;
+    store_block(&server, Pos3::new(-500, 64, -3), "minecraft:stone").await;
+
+    let region = Region::new(Pos3::new(45, 70, -10), Pos3::new(0, 60, 0));
+    let world = load_region(&server.db, WORLD, region.min, region.max)
+        .await
+        .unwrap();
+    assert!(world.get_block(&Pos3::new(2, 64, -3)).is_some());
+    assert!(world.get_block(&Pos3::new(40, 64, -3)).is_some());
+    assert!(world.get_block(&Pos3::new(-500, 64, -3)).is_none());
+
+    let bytes = export_from_db(&server.db, WORLD, &region, ExportFormat::Schematic)
+        .await
+        .unwrap();
+    // gzip magic
+    assert_eq!(bytes[..2], [0x1f, 0x8b]);
+    let huge = Region::new(Pos3::new(0, 0, 0), Pos3::new(1000, 300, 1000));
+    assert!(matches!(
+        export_from_db(&server.db, WORLD, &huge, ExportFormat::Structure).await,
+        Err(ExportError::TooBig(_))
+    ));
+}
+
+fn blueprints(packet: S2CPackets) -> Vec<Blueprint> {
+    match packet {
+        S2CPackets::Blueprints { world, blueprints } => {
+            assert_eq!(world, WORLD);
+            blueprints
+        }
+        p => panic!("expected Blueprints, got {p:?}"),
+    }
+}
+
+#[tokio::test]
+async fn blueprints_are_imported_and_shared() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let mut other = TestClient::connect(&server).await;
+    store_block(&server, Pos3::new(0, 64, 0), "minecraft:stone").await;
+    store_block(&server, Pos3::new(1, 64, 0), "minecraft:oak_planks").await;
+    let region = Region::new(Pos3::new(0, 64, 0), Pos3::new(1, 65, 1));
+    let data = export_from_db(&server.db, WORLD, &region, ExportFormat::Structure)
+        .await
+        .unwrap();
+
+    // a copy of the two blocks, one block further east
+    let anchor = Pos3::new(1, 64, 0);
+    client
+        .send(C2SPackets::ImportBlueprint {
+            world: WORLD.into(),
+            name: "copy".into(),
+            anchor,
+            file_name: "copy.nbt".into(),
+            data,
+        })
+        .await;
+    for c in [&mut client, &mut other] {
+        let imported = blueprints(c.recv().await);
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].name, "copy");
+        assert_eq!(imported[0].anchor, anchor);
+        // unknown blocks aren't in structure files
+        assert_eq!(imported[0].planned(), 2);
+    }
+
+    let stored = load_region(
+        &server.db,
+        WORLD,
+        region.min,
+        region.max + Pos3::new(1, 0, 0),
+    )
+    .await
+    .unwrap();
+    let mut statuses = blueprints(request_blueprints(&mut other).await)[0]
+        .diff(&stored)
+        .map(|(pos, _, status)| (pos, status))
+        .collect::<Vec<_>>();
+    statuses.sort_by_key(|(pos, _)| pos.x);
+    assert_eq!(
+        statuses,
+        [
+            (Pos3::new(1, 64, 0), BlueprintStatus::Wrong),
+            (Pos3::new(2, 64, 0), BlueprintStatus::Unknown),
+        ]
+    );
+
+    client
+        .send(C2SPackets::ImportBlueprint {
+            world: WORLD.into(),
+            name: "broken".into(),
+            anchor,
+            file_name: "broken.schem".into(),
+            data: vec![0x1f, 0x8b, 0],
+        })
+        .await;
+    match client.recv().await {
+        S2CPackets::BlueprintError { name, .. } => assert_eq!(name, "broken"),
+        p => panic!("expected BlueprintError, got {p:?}"),
+    }
+    other.assert_silent().await;
+
+    client
+        .send(C2SPackets::RemoveBlueprint {
+            world: WORLD.into(),
+            name: "copy".into(),
+        })
+        .await;
+    assert!(blueprints(client.recv().await).is_empty());
+    assert!(blueprints(other.recv().await).is_empty());
+}
+
+async fn request_blueprints(client: &mut TestClient) -> S2CPackets {
+    client
+        .send(C2SPackets::RequestBlueprints(WORLD.into()))
+        .await;
+    client.recv().await
+}
+
+/// Reads packets until `pick` gets something out of one that `wanted` accepts
+async fn until<T>(
+    client: &mut TestClient,
+    pick: impl Fn(S2CPackets) -> Option<T>,
+    wanted: impl Fn(&T) -> bool,
+) -> T {
+    for _ in 0..500 {
+        if let Some(value) = pick(client.recv().await) {
+            if wanted(&value) {
+                return value;
+            }
+        }
+    }
+    panic!("the packet we waited for never came");
+}
+
+/// Reads packets until a build status `wanted` accepts shows up
+async fn until_build_status(
+    client: &mut TestClient,
+    wanted: impl Fn(&BuildStatus) -> bool,
+) -> BuildStatus {
+    let pick = |packet| match packet {
+        S2CPackets::BuildUpdate(update) => {
+            assert_eq!(update.world, WORLD);
+            Some(update.data)
+        }
+        _ => None,
+    };
+    until(client, pick, wanted).await
+}
+
+fn planned(id: &str) -> PaletteEntry {
+    PaletteEntry {
+        id: id.into(),
+        is_air: false,
+        state: BlockState::default(),
+    }
+}
+
+#[tokio::test]
+async fn blueprints_are_built_and_resumed() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    // two stone blocks with planks on top of one of them, right next to the turtle
+    let mut hut = Blueprint::new("hut", WORLD, Pos3::new(1, 0, 0), Pos3::new(2, 2, 1));
+    hut.set(Pos3::new(0, 0, 0), planned("minecraft:stone"));
+    hut.set(Pos3::new(1, 0, 0), planned("minecraft:stone"));
+    hut.set(Pos3::new(0, 1, 0), planned("minecraft:oak_planks"));
+    save_blueprint(&server.db, &hut).await.unwrap();
+
+    let mut turtle = SimTurtle::new(0, WORLD, Pos3::new(0, 0, 0), Orientation::North);
+    turtle.inventory.inv[2] = Maybe::Some(Item {
+        count: 5,
+        name: "minecraft:stone".into(),
+    });
+    let handle = server.spawn_turtle(turtle, &world);
+    client.recv_n(3).await;
+
+    client
+        .send(C2SPackets::StartBuild {
+            world: WORLD.into(),
+            blueprint: "hut".into(),
+            turtles: vec![0],
+        })
+        .await;
+    match client.recv().await {
+        S2CPackets::BuildUpdate(update) => assert_eq!(
+            update.data,
+            BuildStatus::Building {
+                blueprint: "hut".into(),
+                remaining: 3,
+                materials: vec![
+                    ("minecraft:oak_planks".into(), 1),
+                    ("minecraft:stone".into(), 2)
+                ],
+            }
+        ),
+        p => panic!("expected BuildUpdate, got {p:?}"),
+    }
+    let status = until_build_status(&mut client, |s| {
+        matches!(s, BuildStatus::MissingMaterial { .. })
+    })
+    .await;
+    assert_eq!(
+        status,
+        BuildStatus::MissingMaterial {
+            blueprint: "hut".into(),
+            item: "minecraft:oak_planks".into(),
+        }
+    );
+    for x in [1, 2] {
+        let block = world.lock().unwrap().get_block(&Pos3::new(x, 0, 0));
+        assert_eq!(block.as_deref(), Some("minecraft:stone"));
+    }
+
+    // the build survives the turtle going away
+    handle.abort();
+    let turtle = loop {
+        if let S2CPackets::SetTurtles(data) = client.recv().await {
+            break data.turtles[0].clone();
+        }
+    };
+    assert!(!turtle.is_online);
+    let mut sim = SimTurtle::new(0, WORLD, turtle.position, turtle.orientation);
+    sim.inventory.inv[0] = Maybe::Some(Item {
+        count: 1,
+        name: "minecraft:oak_planks".into(),
+    });
+    let _turtle = server.spawn_turtle(sim, &world);
+    let status = until_build_status(&mut client, |s| matches!(s, BuildStatus::Done { .. })).await;
+    assert_eq!(
+        status,
+        BuildStatus::Done {
+            blueprint: "hut".into(),
+            skipped: 0,
+        }
+    );
+    let block = world.lock().unwrap().get_block(&Pos3::new(1, 1, 0));
+    assert_eq!(block.as_deref(), Some("minecraft:oak_planks"));
+    let jobs = sqlx::query("SELECT * FROM build_jobs")
+        .fetch_all(&*server.db)
+        .await
+        .unwrap();
+    assert!(jobs.is_empty());
+}
+
+/// Reads packets until a job status `wanted` accepts shows up
+async fn until_job_status(
+    client: &mut TestClient,
+    wanted: impl Fn(&JobStatus) -> bool,
+) -> JobStatus {
+    let pick = |packet| match packet {
+        S2CPackets::JobUpdate(update) => {
+            assert_eq!(update.world, WORLD);
+            Some(update.data)
+        }
+        _ => None,
+    };
+    until(client, pick, wanted).await
+}
+
+#[tokio::test]
+async fn jobs_are_checkpointed_and_resumed() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    let start = Pos3::new(0, -5, 0);
+    world.lock().unwrap().set_block(start, None);
+    let turtle = SimTurtle::new(0, WORLD, start, Orientation::North);
+    let handle = server.spawn_turtle(turtle, &world);
+    client.recv_n(3).await;
+
+    let kind = JobKind::Tunnel {
+        start,
+        direction: Orientation::North,
+        length: 4,
+        size: TunnelSize::OneByTwo,
+    };
+    client
+        .send(C2SPackets::StartJob {
+            index: 0,
+            world: WORLD.into(),
+            kind: kind.clone(),
+            at: None,
+        })
+        .await;
+    match client.recv().await {
+        S2CPackets::JobUpdate(update) => assert_eq!(
+            update.data,
+            JobStatus::Running {
+                kind: kind.clone(),
+                step: 0,
+                steps: 8,
+            }
+        ),
+        p => panic!("expected JobUpdate, got {p:?}"),
+    }
+    until_job_status(
+        &mut client,
+        |s| matches!(s, JobStatus::Running { step, .. } if *step >= 3),
+    )
+    .await;
+
+    // the turtle goes away halfway, the job goes on from the stored step
+    handle.abort();
+    let turtle = loop {
+        if let S2CPackets::SetTurtles(data) = client.recv().await {
+            break data.turtles[0].clone();
+        }
+    };
+    assert!(!turtle.is_online);
+    let step: i64 = sqlx::query("SELECT step FROM jobs")
+        .fetch_one(&*server.db)
+        .await
+        .unwrap()
+        .get(0);
+    assert!(step >= 3);
+    let sim = SimTurtle::new(0, WORLD, turtle.position, turtle.orientation);
+    let _turtle = server.spawn_turtle(sim, &world);
+    let status = until_job_status(&mut client, |_| true).await;
+    assert_eq!(
+        status,
+        JobStatus::Running {
+            kind,
+            step: step as u32,
+            steps: 8,
+        }
+    );
+    until_job_status(&mut client, |s| *s == JobStatus::Done).await;
+    for z in 1..=4 {
+        for y in [-5, -4] {
+            let block = world.lock().unwrap().get_block(&Pos3::new(0, y, -z));
+            assert_eq!(block, None, "{z} {y}");
+        }
+    }
+    let jobs = sqlx::query("SELECT * FROM jobs")
+        .fetch_all(&*server.db)
+        .await
+        .unwrap();
+    assert!(jobs.is_empty());
+}
+
+/// Sends turtle `index` to `target` and reads packets until the go to ends
+async fn go_to(client: &mut TestClient, index: i32, target: impl Into<Target>) -> GoToStatus {
+    client
+        .send(C2SPackets::GoTo {
+            index,
+            world: WORLD.into(),
+            target: target.into(),
+        })
+        .await;
+    go_to_statuses(client).await.pop().unwrap()
+}
+
+/// Every go to status up to the one that ends the go to
+async fn go_to_statuses(client: &mut TestClient) -> Vec<GoToStatus> {
+    let mut statuses = Vec::new();
+    for _ in 0..100 {
+        if let S2CPackets::GoToUpdate(update) = client.recv().await {
+            let done = matches!(
+                update.data,
+                GoToStatus::Arrived { .. } | GoToStatus::Failed { .. }
+            );
+            statuses.push(update.data);
+            if done {
+                return statuses;
+            }
+        }
+    }
+    panic!("the go to never ended");
+}
+
+#[tokio::test]
+async fn go_tos_need_fuel() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    let mut turtle = SimTurtle::new(0, WORLD, Pos3::zero(), Orientation::North);
+    turtle.fuel = 2;
+    let _turtle = server.spawn_turtle(turtle, &world);
+    client.recv_n(3).await;
+    let target = Pos3::new(0, 0, -5);
+    assert_eq!(
+        go_to(&mut client, 0, target).await,
+        GoToStatus::Failed {
+            target,
+            reason: "the route needs 5 fuel, the turtle has 2".into(),
+        }
+    );
+
+    // unlimited fuel comes as -1
+    let mut turtle = SimTurtle::new(1, WORLD, Pos3::new(3, 0, 0), Orientation::North);
+    turtle.fuel = -1;
+    turtle.max_fuel = -1;
+    let _unlimited = server.spawn_turtle(turtle, &world);
+    client.recv_n(3).await;
+    let target = Pos3::new(3, 0, -5);
+    assert_eq!(
+        go_to(&mut client, 1, target).await,
+        GoToStatus::Arrived { target }
+    );
+}
+
+#[tokio::test]
+async fn go_tos_replan_around_blocks_seen_on_the_way() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    let mut walker = SimTurtle::new(0, WORLD, Pos3::zero(), Orientation::North);
+    walker.action_delay = Duration::from_millis(50);
+    let _walker = server.spawn_turtle(walker, &world);
+    client.recv_n(3).await;
+    // looks at the route from the side
+    let watcher = SimTurtle::new(1, WORLD, Pos3::new(1, 0, -8), Orientation::West);
+    let _watcher = server.spawn_turtle(watcher, &world);
+    client.recv_n(3).await;
+
+    let target = Pos3::new(0, 0, -10);
+    client
+        .send(C2SPackets::GoTo {
+            index: 0,
+            world: WORLD.into(),
+            target: target.into(),
+        })
+        .await;
+    let pick = |packet| match packet {
+        S2CPackets::GoToUpdate(update) => Some(update.data),
+        _ => None,
+    };
+    until(&mut client, pick, |s| {
+        matches!(s, GoToStatus::Moving { .. })
+    })
+    .await;
+    let block = Pos3::new(0, 0, -8);
+    world
+        .lock()
+        .unwrap()
+        .set_block(block, Some("minecraft:stone".into()));
+    client.send_lua(1, "turtle.inspect()").await;
+    let statuses = go_to_statuses(&mut client).await;
+    assert!(statuses.contains(&GoToStatus::Replanning { target }));
+    assert_eq!(statuses.last(), Some(&GoToStatus::Arrived { target }));
+    // went around it
+    assert!(world.lock().unwrap().get_block(&block).is_some());
+}
+
+#[tokio::test]
+async fn go_tos_replan_after_bumping_into_blocks() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    // the server only learns of it once the move into it was sent, so the move fails
+    let block = Pos3::new(0, 0, -3);
+    world
+        .lock()
+        .unwrap()
+        .set_block(block, Some("minecraft:stone".into()));
+    let _turtle = server.spawn_turtle(
+        SimTurtle::new(0, WORLD, Pos3::zero(), Orientation::North),
+        &world,
+    );
+    client.recv_n(3).await;
+
+    let target = Pos3::new(0, 0, -5);
+    client
+        .send(C2SPackets::GoTo {
+            index: 0,
+            world: WORLD.into(),
+            target: target.into(),
+        })
+        .await;
+    let statuses = go_to_statuses(&mut client).await;
+    assert!(matches!(statuses[0], GoToStatus::Moving { .. }));
+    assert!(statuses.contains(&GoToStatus::Replanning { target }));
+    assert_eq!(statuses.last(), Some(&GoToStatus::Arrived { target }));
+    assert!(world.lock().unwrap().get_block(&block).is_some());
+}
+
+#[tokio::test]
+async fn jobs_refuel_at_home() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    let home = Pos3::new(0, -5, 0);
+    let chest = home - Pos3::new(0, 1, 0);
+    world.lock().unwrap().set_block(home, None);
+    world.lock().unwrap().set_container(
+        chest,
+        vec![Item {
+            count: 2,
+            name: "minecraft:coal".into(),
+        }],
+    );
+    let mut turtle = SimTurtle::new(0, WORLD, home, Orientation::North);
+    turtle.fuel = 10;
+    let _turtle = server.spawn_turtle(turtle, &world);
+    client.recv_n(3).await;
+
+    client
+        .send(C2SPackets::SetHome {
+            index: 0,
+            world: WORLD.into(),
+            home: Some(home),
+        })
+        .await;
+    match client.recv().await {
+        S2CPackets::TurtleHome(update) => assert_eq!(update.data, Some(home)),
+        p => panic!("expected TurtleHome, got {p:?}"),
+    }
+    client
+        .send(C2SPackets::StartJob {
+            index: 0,
+            world: WORLD.into(),
+            kind: JobKind::Tunnel {
+                start: home,
+                direction: Orientation::North,
+                length: 6,
+                size: TunnelSize::OneByTwo,
+            },
+            at: None,
+        })
+        .await;
+    // the way back is free, one move there and back again plus the margin
+    let status = until_job_status(&mut client, |s| !matches!(s, JobStatus::Running { .. })).await;
+    assert_eq!(status, JobStatus::Refueling { needed: 18 });
+    until_job_status(&mut client, |s| *s == JobStatus::Done).await;
+    for z in 1..=6 {
+        let block = world.lock().unwrap().get_block(&Pos3::new(0, -5, -z));
+        assert_eq!(block, None);
+    }
+    let chest = world.lock().unwrap().container(&chest).map(<[_]>::len);
+    assert_eq!(chest, Some(0));
+}
+
+#[tokio::test]
+async fn waypoints_can_be_targeted_by_name() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    let _turtle = server.spawn_turtle(
+        SimTurtle::new(0, WORLD, Pos3::zero(), Orientation::North),
+        &world,
+    );
+    client.recv_n(3).await;
+
+    let entrance = Pos3::new(2, 0, -3);
+    let waypoint = Waypoint {
+        name: "mine".into(),
+        kind: WaypointKind::MineEntrance,
+        position: entrance,
+    };
+    client
+        .send(C2SPackets::SaveWaypoint {
+            world: WORLD.into(),
+            waypoint: waypoint.clone(),
+        })
+        .await;
+    match client.recv().await {
+        S2CPackets::Waypoints { world, waypoints } => {
+            assert_eq!(world, WORLD);
+            assert_eq!(waypoints, vec![waypoint.clone()]);
+        }
+        p => panic!("expected Waypoints, got {p:?}"),
+    }
+    assert_eq!(
+        go_to(&mut client, 0, Target::Waypoint("mine".into())).await,
+        GoToStatus::Arrived { target: entrance }
+    );
+
+    client
+        .send(C2SPackets::GoTo {
+            index: 0,
+            world: WORLD.into(),
+            target: Target::Waypoint("depot".into()),
+        })
+        .await;
+    // fuel and moves of the go to can still come in
+    loop {
+        if let S2CPackets::WaypointError { name, .. } = client.recv().await {
+            assert_eq!(name, "depot");
+            break;
+        }
+    }
+
+    // jobs are moved to start at the waypoint
+    client
+        .send(C2SPackets::StartJob {
+            index: 0,
+            world: WORLD.into(),
+            kind: JobKind::Tunnel {
+                start: Pos3::zero(),
+                direction: Orientation::North,
+                length: 2,
+                size: TunnelSize::OneByTwo,
+            },
+            at: Some("mine".into()),
+        })
+        .await;
+    let status = until_job_status(&mut client, |_| true).await;
+    let JobStatus::Running { kind, .. } = status else {
+        panic!("expected the job to run, got {status:?}");
+    };
+    assert_eq!(kind.start(), entrance);
+
+    client
+        .send(C2SPackets::RemoveWaypoint {
+            world: WORLD.into(),
+            name: "mine".into(),
+        })
+        .await;
+    loop {
+        if let S2CPackets::Waypoints { waypoints, .. } = client.recv().await {
+            assert!(waypoints.is_empty());
+            break;
+        }
+    }
+}
+
+/// Reads packets until the task queue looks like `wanted` wants
+async fn until_tasks(client: &mut TestClient, wanted: impl Fn(&[Task]) -> bool) -> Vec<Task> {
+    let pick = |packet| match packet {
+        S2CPackets::Tasks { world, tasks } => {
+            assert_eq!(world, WORLD);
+            Some(tasks)
+        }
+        _ => None,
+    };
+    until(client, pick, |tasks: &Vec<Task>| wanted(tasks)).await
+}
+
+#[tokio::test]
+async fn tasks_go_to_the_closest_idle_turtle() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    let chest = Pos3::new(8, -1, 0);
+    world.lock().unwrap().set_container(chest, Vec::new());
+    let _far = server.spawn_turtle(
+        SimTurtle::new(0, WORLD, Pos3::zero(), Orientation::North),
+        &world,
+    );
+    client.recv_n(3).await;
+    let mut near = SimTurtle::new(1, WORLD, Pos3::new(11, 0, 0), Orientation::North);
+    near.insert_item("minecraft:cobblestone", 10);
+    // fills up every other slot
+    near.insert_item("minecraft:dirt", 15 * 64);
+    let _near = server.spawn_turtle(near, &world);
+    client.recv_n(3).await;
+
+    client
+        .send(C2SPackets::SaveWaypoint {
+            world: WORLD.into(),
+            waypoint: Waypoint {
+                name: "drop".into(),
+                kind: WaypointKind::DropOff,
+                position: chest + Pos3::new(0, 1, 0),
+            },
+        })
+        .await;
+    client.recv().await;
+    let kind = TaskKind::Deliver {
+        waypoint: "drop".into(),
+    };
+    client
+        .send(C2SPackets::QueueTask {
+            world: WORLD.into(),
+            kind: kind.clone(),
+            priority: 0,
+        })
+        .await;
+    let tasks = until_tasks(&mut client, |_| true).await;
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].kind, kind);
+    assert_eq!(tasks[0].state, TaskState::Assigned { turtle: 1 });
+    // what clients do with the inventory meanwhile doesn't get mixed up with the delivery
+    client
+        .send(C2SPackets::TurtleInventory {
+            index: 1,
+            world: WORLD.into(),
+            action: InventoryAction::Select { slot: 5 },
+        })
+        .await;
+    until_tasks(&mut client, <[_]>::is_empty).await;
+    // done means the turtle reported an empty inventory after the drop
+    assert_eq!(
+        world.lock().unwrap().container(&chest).map(<[_]>::len),
+        Some(16)
+    );
+}
+
+#[tokio::test]
+async fn deliveries_fail_when_the_chest_is_full() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    let chest = Pos3::new(0, -1, -3);
+    let stack = Item {
+        count: 64,
+        name: "minecraft:dirt".into(),
+    };
+    world
+        .lock()
+        .unwrap()
+        .set_container(chest, vec![stack; CHEST_SLOTS]);
+    let mut turtle = SimTurtle::new(0, WORLD, Pos3::zero(), Orientation::North);
+    turtle.insert_item("minecraft:cobblestone", 10);
+    let _turtle = server.spawn_turtle(turtle, &world);
+    client.recv_n(3).await;
+
+    client
+        .send(C2SPackets::SaveWaypoint {
+            world: WORLD.into(),
+            waypoint: Waypoint {
+                name: "drop".into(),
+                kind: WaypointKind::DropOff,
+                position: chest + Pos3::new(0, 1, 0),
+            },
+        })
+        .await;
+    client.recv().await;
+    client
+        .send(C2SPackets::QueueTask {
+            world: WORLD.into(),
+            kind: TaskKind::Deliver {
+                waypoint: "drop".into(),
+            },
+            priority: 0,
+        })
+        .await;
+    let tasks = until_tasks(
+        &mut client,
+        |t| matches!(t, [task] if matches!(task.state, TaskState::Failed { .. })),
+    )
+    .await;
+    assert_eq!(
+        tasks[0].state,
+        TaskState::Failed {
+            reason: "10 items didn't fit into the chest".into()
+        }
+    );
+    assert_eq!(
+        world.lock().unwrap().container(&chest).map(<[_]>::len),
+        Some(CHEST_SLOTS)
+    );
+}
+
+#[tokio::test]
+async fn tasks_go_by_priority_and_survive_disconnects() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    let tunnel = |x| {
+        TaskKind::Job(JobKind::Tunnel {
+            start: Pos3::new(x, 0, 0),
+            direction: Orientation::North,
+            length: 6,
+            size: TunnelSize::OneByTwo,
+        })
+    };
+    for (x, priority) in [(0, 0), (3, 5)] {
+        client
+            .send(C2SPackets::QueueTask {
+                world: WORLD.into(),
+                kind: tunnel(x),
+                priority,
+            })
+            .await;
+    }
+    let tasks = until_tasks(&mut client, |t| t.len() == 2).await;
+    assert_eq!(tasks[0].kind, tunnel(3));
+    assert_eq!(tasks[1].kind, tunnel(0));
+    assert!(tasks.iter().all(|t| t.state == TaskState::Queued));
+
+    // the turtle is closer to the other tunnel, but priority goes first
+    let turtle = SimTurtle::new(0, WORLD, Pos3::zero(), Orientation::North);
+    let handle = server.spawn_turtle(turtle, &world);
+    let TaskKind::Job(first) = tunnel(3) else {
+        unreachable!()
+    };
+    until_job_status(
+        &mut client,
+        |s| matches!(s, JobStatus::Running { kind, step, .. } if *step >= 3 && *kind == first),
+    )
+    .await;
+    handle.abort();
+    let tasks = until_tasks(&mut client, |t| t[0].state == TaskState::Queued).await;
+    assert_eq!(tasks[0].kind, tunnel(3));
+    assert_eq!(tasks[0].retries, 1);
+    let step: i64 = sqlx::query("SELECT step FROM tasks WHERE priority = 5")
+        .fetch_one(&*server.db)
+        .await
+        .unwrap()
+        .get(0);
+    assert!(step >= 3);
+
+    // another turtle goes on where the first one stopped
+    let _other = server.spawn_turtle(
+        SimTurtle::new(1, WORLD, Pos3::new(1, 0, 0), Orientation::North),
+        &world,
+    );
+    let status = until_job_status(&mut client, |_| true).await;
+    assert!(matches!(status, JobStatus::Running { step: s, .. } if s as i64 == step));
+    let tasks = until_tasks(&mut client, |t| t.len() == 1).await;
+    assert_eq!(tasks[0].kind, tunnel(0));
+    until_tasks(&mut client, <[_]>::is_empty).await;
+}
+
+/// Blocks the server knows about, the same as in the simulated world
+async fn store_known(server: &TestServer, world: &SharedWorld, pos: Pos3, id: Option<&str>) {
+    world.lock().unwrap().set_block(pos, id.map(str::to_owned));
+    save_block(&server.db, &Block::new(id.map(str::to_owned), &pos, WORLD))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn turtles_in_a_one_wide_tunnel_make_way() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    // solid all around, a tunnel along x with a room at both ends
+    let world = Arc::new(Mutex::new(SimWorld::flat(5)));
+    for x in -4..=14 {
+        for y in -1..=1 {
+            for z in -2..=2 {
+                let room = (-3..=-1).contains(&x) || (11..=13).contains(&x);
+                let tunnel = (0..=10).contains(&x) && z == 0;
+                let air = y == 0 && (tunnel || room && (-1..=1).contains(&z));
+                let id = (!air).then_some("minecraft:stone");
+                store_known(&server, &world, Pos3::new(x, y, z), id).await;
+            }
+        }
+    }
+    let west = SimTurtle::new(0, WORLD, Pos3::new(-1, 0, 0), Orientation::East);
+    let _west = server.spawn_turtle(west, &world);
+    client.recv_n(3).await;
+    let east = SimTurtle::new(1, WORLD, Pos3::new(11, 0, 0), Orientation::West);
+    let _east = server.spawn_turtle(east, &world);
+    client.recv_n(3).await;
+
+    let targets = [Pos3::new(12, 0, 0), Pos3::new(-2, 0, 0)];
+    for (index, target) in targets.into_iter().enumerate() {
+        client
+            .send(C2SPackets::GoTo {
+                index: index as i32,
+                world: WORLD.into(),
+                target: target.into(),
+            })
+            .await;
+    }
+    let mut ended = [None, None];
+    let mut made_way = false;
+    while ended.iter().any(Option::is_none) {
+        let S2CPackets::GoToUpdate(update) = client.recv().await else {
+            continue;
+        };
+        match update.data {
+            GoToStatus::MakingWay { .. } => made_way = true,
+            status @ (GoToStatus::Arrived { .. } | GoToStatus::Failed { .. }) => {
+                ended[update.index as usize] = Some(status)
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(
+        ended,
+        targets.map(|target| Some(GoToStatus::Arrived { target }))
+    );
+    assert!(made_way);
+    let world = world.lock().unwrap();
+    assert_eq!(world.get_turtle_at(&targets[0]), Some(0));
+    assert_eq!(world.get_turtle_at(&targets[1]), Some(1));
+}
+
+#[tokio::test]
+async fn jobs_wait_for_turtles_in_their_way() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    let _digger = connect_turtle(&server, &mut client, 0, &world).await;
+    let in_the_way = SimTurtle::new(1, WORLD, Pos3::new(0, 0, -3), Orientation::North);
+    let _in_the_way = server.spawn_turtle(in_the_way, &world);
+    client.recv_n(3).await;
+
+    client
+        .send(C2SPackets::StartJob {
+            index: 0,
+            world: WORLD.into(),
+            kind: JobKind::Tunnel {
+                start: Pos3::zero(),
+                direction: Orientation::North,
+                length: 5,
+                size: TunnelSize::OneByTwo,
+            },
+            at: None,
+        })
+        .await;
+    loop {
+        match client.recv().await {
+            S2CPackets::MovedTurtle(moved) if moved.new_pos == Pos3::new(0, 0, -2) => break,
+            S2CPackets::JobUpdate(update) => {
+                assert!(!matches!(update.data, JobStatus::Failed { .. }))
+            }
+            _ => {}
+        }
+    }
+    // plenty of time to give up if it didn't wait
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let target = Pos3::new(2, 0, -3);
+    client
+        .send(C2SPackets::GoTo {
+            index: 1,
+            world: WORLD.into(),
+            target: target.into(),
+        })
+        .await;
+    let status = until_job_status(&mut client, |s| {
+        matches!(s, JobStatus::Done | JobStatus::Failed { .. })
+    })
+    .await;
+    assert_eq!(status, JobStatus::Done);
+    assert_eq!(world.lock().unwrap().get_turtle_at(&target), Some(1));
+}
+
+#[tokio::test]
+async fn surveys_look_at_everything_they_can_see() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    let _turtle = connect_turtle(&server, &mut client, 0, &world).await;
+
+    // two layers of air and one of stone can be seen, the layer below that can't
+    let (min, max) = (Pos3::new(0, -2, 0), Pos3::new(2, 1, 2));
+    let kind = JobKind::Survey { min, max };
+    client
+        .send(C2SPackets::StartJob {
+            index: 0,
+            world: WORLD.into(),
+            kind: kind.clone(),
+            at: None,
+        })
+        .await;
+    let mut coverage = 0;
+    loop {
+        let S2CPackets::JobUpdate(update) = client.recv().await else {
+            continue;
+        };
+        match update.data {
+            JobStatus::Surveying {
+                kind: k,
+                coverage: c,
+            } => {
+                assert_eq!(k, kind);
+                assert!(c >= coverage, "{c} after {coverage}");
+                coverage = c;
+            }
+            JobStatus::Done => break,
+            status => panic!("unexpected status {status:?}"),
+        }
+    }
+    assert_eq!(coverage, 75);
+    let known = load_world(&server.db, WORLD).await.unwrap();
+    for x in 0..=2 {
+        for z in 0..=2 {
+            for y in -1..=1 {
+                let pos = Pos3::new(x, y, z);
+                let expected = world.lock().unwrap().get_block(&pos);
+                let block = known.get_block(&pos).expect("every visible block is known");
+                assert_eq!((!block.is_air).then_some(block.id), expected, "{pos:?}");
+            }
+            let below = Pos3::new(x, -2, z);
+            assert!(known.get_block(&below).is_none(), "{below:?}");
+        }
+    }
+}
+
+/// Asks turtle 0 for `action` and reads until its result, keeping the inventories sent along
+/// the way
+async fn inventory_action(
+    client: &mut TestClient,
+    inventories: &mut HashMap<i32, TurtleInventory>,
+    action: InventoryAction,
+) -> InventoryStatus {
+    client
+        .send(C2SPackets::TurtleInventory {
+            index: 0,
+            world: WORLD.into(),
+            action,
+        })
+        .await;
+    loop {
+        match client.recv().await {
+            S2CPackets::TurtleInventoryUpdate(update) => {
+                inventories.insert(update.index, *update.data);
+            }
+            S2CPackets::InventoryResult(update) => {
+                assert_eq!(update.index, 0);
+                return update.data;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn slot_count(inventory: Option<&TurtleInventory>, slot: usize) -> u32 {
+    match inventory.map(|inv| &inv[slot - 1]) {
+        Some(Maybe::Some(item)) => item.count,
+        _ => 0,
+    }
+}
+
+#[tokio::test]
+async fn inventories_move_between_slots_chests_and_turtles() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect(&server).await;
+    let world = flat_world();
+    let chest = Pos3::new(0, -1, 0);
+    world.lock().unwrap().set_container(chest, Vec::new());
+    let mut giver = SimTurtle::new(0, WORLD, Pos3::zero(), Orientation::North);
+    giver.insert_item("minecraft:cobblestone", 10);
+    let _giver = server.spawn_turtle(giver, &world);
+    client.recv_n(3).await;
+    // to the right of turtle 0
+    let taker = Orientation::East.get_forward_vec();
+    let _taker = server.spawn_turtle(SimTurtle::new(1, WORLD, taker, Orientation::North), &world);
+    client.recv_n(3).await;
+    let mut inventories = HashMap::new();
+
+    let split = InventoryAction::Split {
+        from: 1,
+        to: 2,
+        count: 4,
+    };
+    let status = inventory_action(&mut client, &mut inventories, split.clone()).await;
+    assert_eq!(status, InventoryStatus::Done(split));
+    assert_eq!(slot_count(inventories.get(&0), 1), 6);
+    assert_eq!(slot_count(inventories.get(&0), 2), 4);
+
+    let merge = InventoryAction::Merge { from: 2, to: 1 };
+    let status = inventory_action(&mut client, &mut inventories, merge.clone()).await;
+    assert_eq!(status, InventoryStatus::Done(merge));
+    assert_eq!(slot_count(inventories.get(&0), 1), 10);
+    assert_eq!(slot_count(inventories.get(&0), 2), 0);
+
+    let select = InventoryAction::Select { slot: 3 };
+    let status = inventory_action(&mut client, &mut inventories, select.clone()).await;
+    assert_eq!(status, InventoryStatus::Done(select));
+    let drop = InventoryAction::Drop {
+        slot: 1,
+        direction: MoveDirection::Down,
+        count: Some(3),
+    };
+    let status = inventory_action(&mut client, &mut inventories, drop.clone()).await;
+    assert_eq!(status, InventoryStatus::Done(drop));
+    assert_eq!(slot_count(inventories.get(&0), 1), 7);
+    // dropping from a slot leaves the selection where it was
+    assert_eq!(inventories[&0].selected_slot, 3);
+    let stored = world
+        .lock()
+        .unwrap()
+        .container(&chest)
+        .unwrap()
+        .iter()
+        .map(|item| (item.name.clone(), item.count))
+        .collect::<Vec<_>>();
+    assert_eq!(stored, vec![("minecraft:cobblestone".to_owned(), 3)]);
+
+    // nothing to take in front of it
+    let suck = InventoryAction::Suck {
+        slot: 2,
+        direction: MoveDirection::Forward,
+        count: None,
+    };
+    let status = inventory_action(&mut client, &mut inventories, suck.clone()).await;
+    assert!(matches!(status, InventoryStatus::Failed { action, .. } if action == suck));
+    assert_eq!(inventories[&0].selected_slot, 3);
+
+    // the server turns it down before the turtle hears of it
+    let select = InventoryAction::Select { slot: 17 };
+    let status = inventory_action(&mut client, &mut inventories, select.clone()).await;
+    assert!(matches!(status, InventoryStatus::Failed { action, .. } if action == select));
+    let far = InventoryAction::Give {
+        slot: 1,
+        to: 7,
+        count: None,
+    };
+    let status = inventory_action(&mut client, &mut inventories, far.clone()).await;
+    assert_eq!(
+        status,
+        InventoryStatus::Failed {
+            action: far,
+            reason: "turtle 7 is not next to turtle 0".into(),
+        }
+    );
+
+    let give = InventoryAction::Give {
+        slot: 1,
+        to: 1,
+        count: Some(5),
+    };
+    client
+        .send(C2SPackets::TurtleInventory {
+            index: 0,
+            world: WORLD.into(),
+            action: give.clone(),
+        })
+        .await;
+    // it turns towards turtle 1 for the drop and back again afterwards
+    let mut facing = Vec::new();
+    let status = loop {
+        match client.recv().await {
+            S2CPackets::TurtleInventoryUpdate(update) => {
+                inventories.insert(update.index, *update.data);
+            }
+            S2CPackets::MovedTurtle(moved) if moved.index == 0 => {
+                facing.push(moved.new_orientation);
+            }
+            S2CPackets::InventoryResult(update) => break update.data,
+            _ => {}
+        }
+    };
+    assert_eq!(status, InventoryStatus::Done(give));
+    // the moves carry whatever it faces when they get sent, so only the last one is certain
+    assert_eq!(facing.len(), 2);
+    assert_eq!(facing.last(), Some(&Orientation::North));
+    assert_eq!(slot_count(inventories.get(&0), 1), 2);
+    assert_eq!(inventories[&0].selected_slot, 3);
+    // the other turtle reports on its own, that can come after the result
+    while slot_count(inventories.get(&1), 1) != 5 {
+        if let S2CPackets::TurtleInventoryUpdate(update) = client.recv().await {
+            inventories.insert(update.index, *update.data);
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! Fake turtles that talk to the server like `lua/trc_remote_control.lua` does, without minecraft.
+
+pub mod lua;
+pub mod turtle;
+pub mod world;
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        mpsc::{channel, Receiver},
+        Arc,
+    },
+};
+
+use common::{
+    turtle::Item,
+    turtle_packets::{S2TPackets, T2SPackets},
+};
+use futures_util::{SinkExt, StreamExt};
+use log::warn;
+use lua::Value;
+use serde_json::{from_str, to_string};
+use tokio::sync::mpsc::unbounded_channel;
+use tokio_tungstenite::connect_async;
+use tungstenite::Message;
+
+pub use turtle::{PacketSender, SharedWorld, SimTurtle};
+pub use world::SimWorld;
+
+/// What the thread of a turtle works through, one after the other
+pub(crate) enum Command {
+    Server(S2TPackets),
+    /// Items another turtle dropped into this one
+    Receive(Item),
+}
+
+/// Connects `turtle` to the turtle websocket at `url` and answers the server until the
+/// connection closes. Returns the turtle in the state it was left in.
+pub async fn run_turtle(
+    url: &str,
+    turtle: SimTurtle,
+    world: SharedWorld,
+) -> anyhow::Result<SimTurtle> {
+    let (ws, _) = connect_async(url).await?;
+    let (mut ws_send, mut ws_recv) = ws.split();
+    world.lock().unwrap().occupy(turtle.position, turtle.index);
+
+    let (packet_send, mut packet_recv) = unbounded_channel::<T2SPackets>();
+    let (command_send, command_recv) = channel::<Command>();
+    // the world only gets a weak handle, so the commands still end with the connection
+    let command_send = Arc::new(command_send);
+    world.lock().unwrap().set_inbox(turtle.index, &command_send);
+    // lua runs blocking, just like on a real turtle one command is handled after the other
+    let worker = tokio::task::spawn_blocking(move || {
+        handle_commands(turtle, world, command_recv, packet_send)
+    });
+
+    let writer = async move {
+        while let Some(packet) = packet_recv.recv().await {
+            ws_send.send(Message::Text(to_string(&packet)?)).await?;
+        }
+        _ = ws_send.close().await;
+        anyhow::Ok(())
+    };
+    let reader = async move {
+        while let Some(msg) = ws_recv.next().await {
+            match msg? {
+                Message::Text(msg) => match from_str::<S2TPackets>(&msg) {
+                    Ok(packet) => {
+                        if command_send.send(Command::Server(packet)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => warn!("invalid packet from server: {err}"),
+                },
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+        anyhow::Ok(())
+    };
+    let (read_res, write_res) = tokio::join!(reader, writer);
+    let turtle = worker.await?;
+    read_res?;
+    write_res?;
+    Ok(turtle)
+}
+
+fn handle_commands(
+    mut turtle: SimTurtle,
+    world: SharedWorld,
+    commands: Receiver<Command>,
+    out: PacketSender,
+) -> SimTurtle {
+    // commands that arrived while a program was waiting for input
+    let mut pending = VecDeque::new();
+    while let Some(command) = pending.pop_front().or_else(|| commands.recv().ok()) {
+        let command = match command {
+            Command::Server(command) => command,
+            Command::Receive(item) => {
+                turtle.receive(item, &out);
+                continue;
+            }
+        };
+        match command {
+            S2TPackets::GetSetupInfo => _ = out.send(turtle.setup_packet()),
+            S2TPackets::RunLuaCode(code) => {
+                run_lua(&code, &mut turtle, &world, &commands, &mut pending, &out)
+            }
+            S2TPackets::RunProgram { name, .. } => match turtle.files.get(&name).cloned() {
+                Some(code) => run_lua(&code, &mut turtle, &world, &commands, &mut pending, &out),
+                None => _ = out.send(T2SPackets::StdOut("No such program\n".into())),
+            },
+            S2TPackets::SaveFile { path, contents } => {
+                turtle.files.insert(path, contents);
+                _ = out.send(executables_packet(&turtle));
+            }
+            S2TPackets::GetExecutables => _ = out.send(executables_packet(&turtle)),
+            // nothing is reading right now
+            S2TPackets::StdIn(_) => {}
+            S2TPackets::Inventory { action, towards } => {
+                let reason = turtle.inventory_action(&action, towards, &world, &out);
+                _ = out.send(T2SPackets::InventoryResult {
+                    action,
+                    reason: reason.into(),
+                });
+            }
+        }
+    }
+    world.lock().unwrap().leave(&turtle.position);
+    turtle
+}
+
+fn executables_packet(turtle: &SimTurtle) -> T2SPackets {
+    T2SPackets::Executables(turtle.files.keys().cloned().collect())
+}
+
+/// Runs `code` with the globals `run_function_with_injected_globals` in util.lua provides
+fn run_lua(
+    code: &str,
+    turtle: &mut SimTurtle,
+    world: &SharedWorld,
+    commands: &Receiver<Command>,
+    pending: &mut VecDeque<Command>,
+    out: &PacketSender,
+) {
+    let res = lua::run(code, |name, args| match name {
+        "print" => {
+            let text = args.iter().map(Value::to_string).collect::<Vec<_>>();
+            _ = out.send(T2SPackets::StdOut(text.join("\t") + "\n"));
+            Ok(Vec::new())
+        }
+        "read" => {
+            let line = read_line(commands, pending)?;
+            // echo it, same as the lua runtime does
+            _ = out.send(T2SPackets::StdOut(format!("{line}\n")));
+            Ok(vec![Value::Str(line)])
+        }
+        _ => match name.strip_prefix("turtle.") {
+            Some(function) => turtle.call(function, args, world, out),
+            None => Err(format!("attempt to call a nil value (global '{name}')")),
+        },
+    });
+    if let Err(err) = res {
+        warn!("ERROR: {err}");
+    }
+}
+
+/// Blocks until the server sends a line of input, other commands are queued up for later
+fn read_line(
+    commands: &Receiver<Command>,
+    pending: &mut VecDeque<Command>,
+) -> Result<String, String> {
+    loop {
+        match commands.recv() {
+            Ok(Command::Server(S2TPackets::StdIn(line))) => return Ok(line),
+            Ok(command) => pending.push_back(command),
+            Err(_) => return Err("Terminated".into()),
+        }
+    }
+}
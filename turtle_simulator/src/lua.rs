@@ -0,0 +1,582 @@
+//! A tiny interpreter for the subset of lua the server sends with `RunLuaCode`: calls, locals,
+//! `if` and numeric `for` loops. Everything else is reported as an error, like a real turtle would
+//! fail to load code it doesn't understand.
+
+use std::{collections::HashMap, fmt::Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(i64),
+    Str(String),
+}
+
+impl Value {
+    fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    fn as_number(&self) -> Result<i64, String> {
+        match self {
+            Value::Number(n) => Ok(*n),
+            other => Err(format!(
+                "attempt to perform arithmetic on a {other:?} value"
+            )),
+        }
+    }
+}
+
+/// Same as lua's `tostring`
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Number(n) => write!(f, "{n}"),
+            Value::Str(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Name(String),
+    Number(i64),
+    Str(String),
+    Sym(&'static str),
+}
+
+const SYMBOLS: [&str; 13] = [
+    "==", "~=", "=", "(", ")", ",", "..", ".", "+", "-", ";", "<", ">",
+];
+
+fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+    let chars = src.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '-' && chars.get(i + 1) == Some(&'-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let num = chars[start..i].iter().collect::<String>();
+            tokens.push(Token::Number(num.parse().map_err(|_| "malformed number")?));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Name(chars[start..i].iter().collect()));
+        } else if c == '"' || c == '\'' {
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && chars[i] != c {
+                i += 1;
+            }
+            if i == chars.len() {
+                return Err("unfinished string".into());
+            }
+            tokens.push(Token::Str(chars[start..i].iter().collect()));
+            i += 1;
+        } else {
+            let rest = chars[i..].iter().take(2).collect::<String>();
+            let Some(sym) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) else {
+                return Err(format!("unexpected symbol near '{c}'"));
+            };
+            tokens.push(Token::Sym(sym));
+            i += sym.len();
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Value(Value),
+    Var(String),
+    /// Dotted function name like `turtle.forward`
+    Call(String, Vec<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+enum Stmt {
+    Local(Vec<String>, Vec<Expr>),
+    Assign(String, Expr),
+    Call(Expr),
+    Return(Vec<Expr>),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    For {
+        var: String,
+        from: Expr,
+        to: Expr,
+        step: Option<Expr>,
+        body: Vec<Stmt>,
+    },
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn is_name(&self, name: &str) -> bool {
+        matches!(self.peek(), Some(Token::Name(n)) if n == name)
+    }
+
+    fn is_sym(&self, sym: &str) -> bool {
+        matches!(self.peek(), Some(Token::Sym(s)) if *s == sym)
+    }
+
+    fn expect_sym(&mut self, sym: &str) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Sym(s)) if s == sym => Ok(()),
+            other => Err(format!("'{sym}' expected near {other:?}")),
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Name(n)) if n == keyword => Ok(()),
+            other => Err(format!("'{keyword}' expected near {other:?}")),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Name(n)) => Ok(n),
+            other => Err(format!("<name> expected near {other:?}")),
+        }
+    }
+
+    /// Parses statements until one of `terminators` (or the end of the input) is reached
+    fn block(&mut self, terminators: &[&str]) -> Result<Vec<Stmt>, String> {
+        let mut stmts = Vec::new();
+        while self.peek().is_some() && !terminators.iter().any(|t| self.is_name(t)) {
+            if self.is_sym(";") {
+                self.next();
+                continue;
+            }
+            stmts.push(self.statement()?);
+        }
+        Ok(stmts)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, String> {
+        if self.is_name("local") {
+            self.next();
+            let mut names = vec![self.name()?];
+            while self.is_sym(",") {
+                self.next();
+                names.push(self.name()?);
+            }
+            self.expect_sym("=")?;
+            return Ok(Stmt::Local(names, self.expr_list()?));
+        }
+        if self.is_name("return") {
+            self.next();
+            let values = match self.peek() {
+                None => Vec::new(),
+                Some(Token::Name(n)) if n == "end" || n == "else" => Vec::new(),
+                _ => self.expr_list()?,
+            };
+            return Ok(Stmt::Return(values));
+        }
+        if self.is_name("if") {
+            self.next();
+            let cond = self.expr()?;
+            self.expect_keyword("then")?;
+            let then = self.block(&["else", "end"])?;
+            let otherwise = match self.is_name("else") {
+                true => {
+                    self.next();
+                    self.block(&["end"])?
+                }
+                false => Vec::new(),
+            };
+            self.expect_keyword("end")?;
+            return Ok(Stmt::If(cond, then, otherwise));
+        }
+        if self.is_name("for") {
+            self.next();
+            let var = self.name()?;
+            self.expect_sym("=")?;
+            let from = self.expr()?;
+            self.expect_sym(",")?;
+            let to = self.expr()?;
+            let step = match self.is_sym(",") {
+                true => {
+                    self.next();
+                    Some(self.expr()?)
+                }
+                false => None,
+            };
+            self.expect_keyword("do")?;
+            let body = self.block(&["end"])?;
+            self.expect_keyword("end")?;
+            return Ok(Stmt::For {
+                var,
+                from,
+                to,
+                step,
+                body,
+            });
+        }
+        let name = self.name()?;
+        if self.is_sym("=") {
+            self.next();
+            return Ok(Stmt::Assign(name, self.expr()?));
+        }
+        match self.call_or_var(name)? {
+            call @ Expr::Call(..) => Ok(Stmt::Call(call)),
+            _ => Err("syntax error, expected a function call".into()),
+        }
+    }
+
+    fn expr_list(&mut self) -> Result<Vec<Expr>, String> {
+        let mut exprs = vec![self.expr()?];
+        while self.is_sym(",") {
+            self.next();
+            exprs.push(self.expr()?);
+        }
+        Ok(exprs)
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let lhs = self.concat()?;
+        for op in ["==", "~=", "<", ">"] {
+            if self.is_sym(op) {
+                self.next();
+                let rhs = self.concat()?;
+                return Ok(Expr::Binary(op_str(op), Box::new(lhs), Box::new(rhs)));
+            }
+        }
+        Ok(lhs)
+    }
+
+    fn concat(&mut self) -> Result<Expr, String> {
+        let lhs = self.additive()?;
+        if !self.is_sym("..") {
+            return Ok(lhs);
+        }
+        self.next();
+        // right associative like in lua
+        let rhs = self.concat()?;
+        Ok(Expr::Binary("..", Box::new(lhs), Box::new(rhs)))
+    }
+
+    fn additive(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while self.is_sym("+") || self.is_sym("-") {
+            let Some(Token::Sym(op)) = self.next() else {
+                unreachable!()
+            };
+            let rhs = self.unary()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.is_name("not") {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.is_sym("-") {
+            self.next();
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Value(Value::Number(n))),
+            Some(Token::Str(s)) => Ok(Expr::Value(Value::Str(s))),
+            Some(Token::Sym("(")) => {
+                let expr = self.expr()?;
+                self.expect_sym(")")?;
+                Ok(expr)
+            }
+            Some(Token::Name(n)) => match n.as_str() {
+                "nil" => Ok(Expr::Value(Value::Nil)),
+                "true" => Ok(Expr::Value(Value::Bool(true))),
+                "false" => Ok(Expr::Value(Value::Bool(false))),
+                _ => self.call_or_var(n),
+            },
+            other => Err(format!("unexpected symbol near {other:?}")),
+        }
+    }
+
+    fn call_or_var(&mut self, mut name: String) -> Result<Expr, String> {
+        while self.is_sym(".") {
+            self.next();
+            name = format!("{name}.{}", self.name()?);
+        }
+        if !self.is_sym("(") {
+            return Ok(Expr::Var(name));
+        }
+        self.next();
+        let args = match self.is_sym(")") {
+            true => Vec::new(),
+            false => self.expr_list()?,
+        };
+        self.expect_sym(")")?;
+        Ok(Expr::Call(name, args))
+    }
+}
+
+fn op_str(op: &str) -> &'static str {
+    SYMBOLS.iter().find(|s| **s == op).unwrap()
+}
+
+enum Flow {
+    Normal,
+    Return(Vec<Value>),
+}
+
+struct Interpreter<F> {
+    /// Innermost scope last
+    scopes: Vec<HashMap<String, Value>>,
+    call: F,
+}
+
+impl<F: FnMut(&str, &[Value]) -> Result<Vec<Value>, String>> Interpreter<F> {
+    fn lookup(&self, name: &str) -> Value {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .cloned()
+            .unwrap_or(Value::Nil)
+    }
+
+    fn assign(&mut self, name: &str, value: Value) {
+        let scope = match self.scopes.iter_mut().rev().find(|s| s.contains_key(name)) {
+            Some(scope) => scope,
+            // globals end up in the outermost scope
+            None => &mut self.scopes[0],
+        };
+        scope.insert(name.to_owned(), value);
+    }
+
+    fn block(&mut self, stmts: &[Stmt], scope: HashMap<String, Value>) -> Result<Flow, String> {
+        self.scopes.push(scope);
+        let flow = stmts
+            .iter()
+            .try_fold(Flow::Normal, |flow, stmt| match flow {
+                Flow::Normal => self.statement(stmt),
+                ret => Ok(ret),
+            });
+        self.scopes.pop();
+        flow
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> Result<Flow, String> {
+        match stmt {
+            Stmt::Local(names, exprs) => {
+                let mut values = self.values(exprs)?.into_iter();
+                for name in names {
+                    let value = values.next().unwrap_or(Value::Nil);
+                    self.scopes.last_mut().unwrap().insert(name.clone(), value);
+                }
+            }
+            Stmt::Assign(name, expr) => {
+                let value = self.value(expr)?;
+                self.assign(name, value);
+            }
+            Stmt::Call(expr) => {
+                self.eval(expr)?;
+            }
+            Stmt::Return(exprs) => return Ok(Flow::Return(self.values(exprs)?)),
+            Stmt::If(cond, then, otherwise) => {
+                let branch = match self.value(cond)?.is_truthy() {
+                    true => then,
+                    false => otherwise,
+                };
+                return self.block(branch, HashMap::new());
+            }
+            Stmt::For {
+                var,
+                from,
+                to,
+                step,
+                body,
+            } => {
+                let from = self.value(from)?.as_number()?;
+                let to = self.value(to)?.as_number()?;
+                let step = match step {
+                    Some(step) => self.value(step)?.as_number()?,
+                    None => 1,
+                };
+                if step == 0 {
+                    return Err("'for' step is zero".into());
+                }
+                let mut i = from;
+                while (step > 0 && i <= to) || (step < 0 && i >= to) {
+                    let scope = HashMap::from([(var.clone(), Value::Number(i))]);
+                    if let Flow::Return(values) = self.block(body, scope)? {
+                        return Ok(Flow::Return(values));
+                    }
+                    i += step;
+                }
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// Like lua only the last expression of a list can expand to multiple values
+    fn values(&mut self, exprs: &[Expr]) -> Result<Vec<Value>, String> {
+        let mut values = Vec::new();
+        for (i, expr) in exprs.iter().enumerate() {
+            match i + 1 == exprs.len() {
+                true => values.extend(self.eval(expr)?),
+                false => values.push(self.value(expr)?),
+            }
+        }
+        Ok(values)
+    }
+
+    fn value(&mut self, expr: &Expr) -> Result<Value, String> {
+        Ok(self.eval(expr)?.into_iter().next().unwrap_or(Value::Nil))
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Vec<Value>, String> {
+        let value = match expr {
+            Expr::Value(value) => value.clone(),
+            Expr::Var(name) => self.lookup(name),
+            Expr::Call(name, args) => {
+                let args = self.values(args)?;
+                return (self.call)(name, &args);
+            }
+            Expr::Not(expr) => Value::Bool(!self.value(expr)?.is_truthy()),
+            Expr::Neg(expr) => Value::Number(-self.value(expr)?.as_number()?),
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (self.value(lhs)?, self.value(rhs)?);
+                match *op {
+                    "==" => Value::Bool(lhs == rhs),
+                    "~=" => Value::Bool(lhs != rhs),
+                    "<" => Value::Bool(lhs.as_number()? < rhs.as_number()?),
+                    ">" => Value::Bool(lhs.as_number()? > rhs.as_number()?),
+                    "+" => Value::Number(lhs.as_number()? + rhs.as_number()?),
+                    "-" => Value::Number(lhs.as_number()? - rhs.as_number()?),
+                    ".." => match (&lhs, &rhs) {
+                        (Value::Str(_) | Value::Number(_), Value::Str(_) | Value::Number(_)) => {
+                            Value::Str(format!("{lhs}{rhs}"))
+                        }
+                        _ => return Err("attempt to concatenate a non string value".into()),
+                    },
+                    _ => unreachable!("unknown operator {op}"),
+                }
+            }
+        };
+        Ok(vec![value])
+    }
+}
+
+/// Runs `src`, every function call goes through `call` with its dotted name (`turtle.forward`)
+pub fn run(
+    src: &str,
+    call: impl FnMut(&str, &[Value]) -> Result<Vec<Value>, String>,
+) -> Result<Vec<Value>, String> {
+    let mut parser = Parser {
+        tokens: tokenize(src)?,
+        pos: 0,
+    };
+    let stmts = parser.block(&[])?;
+    let mut interpreter = Interpreter {
+        scopes: Vec::new(),
+        call,
+    };
+    match interpreter.block(&stmts, HashMap::new())? {
+        Flow::Return(values) => Ok(values),
+        Flow::Normal => Ok(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_logged(src: &str) -> (Result<Vec<Value>, String>, Vec<String>) {
+        let mut calls = Vec::new();
+        let res = run(src, |name, args| {
+            calls.push(format!("{name}{args:?}"));
+            Ok(vec![Value::Bool(true)])
+        });
+        (res, calls)
+    }
+
+    #[test]
+    fn single_call() {
+        let (res, calls) = run_logged("turtle.forward()");
+        assert_eq!(res, Ok(vec![]));
+        assert_eq!(calls, vec!["turtle.forward[]"]);
+    }
+
+    #[test]
+    fn for_loop_and_comments() {
+        let (res, calls) = run_logged(
+            "-- dig a short tunnel
+            for i = 1, 3 do
+                turtle.dig()
+                turtle.forward()
+            end
+            return turtle.select(2)",
+        );
+        assert_eq!(res, Ok(vec![Value::Bool(true)]));
+        assert_eq!(calls.len(), 7);
+        assert_eq!(calls[6], "turtle.select[Number(2)]");
+    }
+
+    #[test]
+    fn locals_and_if() {
+        let res = run(
+            "local ok, reason = turtle.forward()
+            if not ok then return reason else return 'moved' end",
+            |_, _| Ok(vec![Value::Bool(false), Value::Str("Out of fuel".into())]),
+        );
+        assert_eq!(res, Ok(vec![Value::Str("Out of fuel".into())]));
+    }
+
+    #[test]
+    fn concat() {
+        let res = run(
+            "return 'slot ' .. turtle.getSelectedSlot() .. '!'",
+            |_, _| Ok(vec![Value::Number(3)]),
+        );
+        assert_eq!(res, Ok(vec![Value::Str("slot 3!".into())]));
+        assert!(run_logged("return 'a' .. nil").0.is_err());
+    }
+
+    #[test]
+    fn errors() {
+        assert!(run_logged("turtle.forward(").0.is_err());
+        assert!(run_logged("x = 'unfinished").0.is_err());
+        assert!(run_logged("for i = 1, 2, 0 do end").0.is_err());
+        let res = run("turtle.fly()", |name, _| Err(format!("no {name}")));
+        assert_eq!(res, Err("no turtle.fly".into()));
+    }
+}
@@ -0,0 +1,74 @@
+use std::{
+    env,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use common::{turtle::Orientation, Pos3};
+use log::{error, info};
+use turtle_simulator::{run_turtle, SimTurtle, SimWorld};
+
+struct Args {
+    url: String,
+    count: i32,
+    world: String,
+    delay: Duration,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = Args {
+        url: "ws://127.0.0.1:9002".into(),
+        count: 1,
+        world: "test_world_01".into(),
+        delay: Duration::from_millis(400),
+    };
+    let mut iter = env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().with_context(|| format!("{arg} needs a value"));
+        match arg.as_str() {
+            "--url" => args.url = value()?,
+            "--count" => args.count = value()?.parse()?,
+            "--world" => args.world = value()?,
+            "--delay-ms" => args.delay = Duration::from_millis(value()?.parse()?),
+            _ => bail!(
+                "unknown argument {arg}, usage: turtle_simulator [--url <ws url>] [--count <n>] [--world <name>] [--delay-ms <ms>]"
+            ),
+        }
+    }
+    Ok(args)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    pretty_env_logger::formatted_builder()
+        .filter_level(log::LevelFilter::Info)
+        .init();
+    let args = parse_args()?;
+    let world = Arc::new(Mutex::new(SimWorld::flat(0)));
+
+    let mut tasks = Vec::new();
+    for index in 0..args.count {
+        let mut turtle = SimTurtle::new(
+            index,
+            &args.world,
+            Pos3::new(index * 2, 0, 0),
+            Orientation::North,
+        );
+        turtle.action_delay = args.delay;
+        let (url, world) = (args.url.clone(), world.clone());
+        tasks.push(tokio::spawn(async move {
+            match run_turtle(&url, turtle, world).await {
+                Ok(turtle) => info!(
+                    "turtle {} disconnected at {:?}",
+                    turtle.index, turtle.position
+                ),
+                Err(err) => error!("turtle {index} failed: {err}"),
+            }
+        }));
+    }
+    for task in tasks {
+        task.await?;
+    }
+    Ok(())
+}
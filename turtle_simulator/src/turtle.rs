@@ -0,0 +1,589 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use common::{
+    turtle::{InventoryAction, Item, Maybe, MoveDirection, Orientation, TurtleInventory},
+    turtle_packets::{SetupInfoData, T2SPackets},
+    Pos3,
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{lua::Value, world::SimWorld};
+
+pub type PacketSender = UnboundedSender<T2SPackets>;
+pub type SharedWorld = Arc<Mutex<SimWorld>>;
+
+const STACK_SIZE: u32 = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Side {
+    Up,
+    Forward,
+    Down,
+}
+
+impl From<Side> for MoveDirection {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::Up => MoveDirection::Up,
+            Side::Forward => MoveDirection::Forward,
+            Side::Down => MoveDirection::Down,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimTurtle {
+    pub index: i32,
+    pub name: String,
+    /// The trc world this turtle registers in
+    pub world: String,
+    pub position: Pos3,
+    pub orientation: Orientation,
+    /// -1 means unlimited, same as `util.fix_num_or_unlimited` does
+    pub fuel: i32,
+    pub max_fuel: i32,
+    pub inventory: TurtleInventory,
+    /// How long every action that would take a game tick or more blocks the turtle
+    pub action_delay: Duration,
+    /// Files saved on the turtle, every one of them counts as a program
+    pub files: BTreeMap<String, String>,
+}
+
+impl SimTurtle {
+    pub fn new(index: i32, world: &str, position: Pos3, orientation: Orientation) -> SimTurtle {
+        SimTurtle {
+            index,
+            name: format!("sim_{index}"),
+            world: world.to_owned(),
+            position,
+            orientation,
+            fuel: 1000,
+            max_fuel: 100000,
+            inventory: TurtleInventory::default(),
+            action_delay: Duration::ZERO,
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// What `sendSetupInfo` in trc_remote_control.lua sends
+    pub fn setup_packet(&self) -> T2SPackets {
+        T2SPackets::Batch(vec![
+            T2SPackets::SetupInfo(SetupInfoData {
+                facing: self.orientation,
+                position: self.position,
+                index: self.index,
+                world: self.world.clone(),
+            }),
+            T2SPackets::SetMaxFuel(self.max_fuel),
+            T2SPackets::FuelUpdate(self.fuel),
+            T2SPackets::NameUpdate(self.name.clone()),
+            self.inventory_packet(),
+        ])
+    }
+
+    fn inventory_packet(&self) -> T2SPackets {
+        T2SPackets::InventoryUpdate(Box::new(self.inventory.clone()))
+    }
+
+    fn blocks_packet(&self, world: &SimWorld) -> T2SPackets {
+        T2SPackets::Blocks {
+            up: world.inspect(&self.side_pos(Side::Up)).into(),
+            down: world.inspect(&self.side_pos(Side::Down)).into(),
+            front: world.inspect(&self.side_pos(Side::Forward)).into(),
+        }
+    }
+
+    fn side_pos(&self, side: Side) -> Pos3 {
+        match side {
+            Side::Up => self.position + Pos3::new(0, 1, 0),
+            Side::Forward => self.position + self.orientation.get_forward_vec(),
+            Side::Down => self.position + Pos3::new(0, -1, 0),
+        }
+    }
+
+    fn selected(&self) -> usize {
+        self.inventory.selected_slot as usize - 1
+    }
+
+    fn wait(&self) {
+        if !self.action_delay.is_zero() {
+            std::thread::sleep(self.action_delay);
+        }
+    }
+
+    /// Calls a function of the `turtle` api, mirrors the hijacked api in util.lua when it comes
+    /// to the packets that get send
+    pub fn call(
+        &mut self,
+        name: &str,
+        args: &[Value],
+        world: &SharedWorld,
+        out: &PacketSender,
+    ) -> Result<Vec<Value>, String> {
+        let out = |p: T2SPackets| {
+            _ = out.send(p);
+        };
+        let values = match name {
+            "forward" => self.move_(MoveDirection::Forward, world, out),
+            "back" => self.move_(MoveDirection::Back, world, out),
+            "up" => self.move_(MoveDirection::Up, world, out),
+            "down" => self.move_(MoveDirection::Down, world, out),
+            "turnLeft" => self.move_(MoveDirection::Left, world, out),
+            "turnRight" => self.move_(MoveDirection::Right, world, out),
+            "dig" => self.dig(Side::Forward, world, out),
+            "digUp" => self.dig(Side::Up, world, out),
+            "digDown" => self.dig(Side::Down, world, out),
+            "place" => self.place(Side::Forward, world, out),
+            "placeUp" => self.place(Side::Up, world, out),
+            "placeDown" => self.place(Side::Down, world, out),
+            "inspect" => self.inspect(Side::Forward, world, out),
+            "inspectUp" => self.inspect(Side::Up, world, out),
+            "inspectDown" => self.inspect(Side::Down, world, out),
+            "select" => {
+                let slot = slot_arg(args.first())?.ok_or("Expected number")?;
+                self.inventory.selected_slot = slot as u8;
+                out(self.inventory_packet());
+                vec![Value::Bool(true)]
+            }
+            "getSelectedSlot" => vec![Value::Number(self.inventory.selected_slot as i64)],
+            "getItemCount" => {
+                let slot = slot_arg(args.first())?.map_or(self.selected(), |s| s - 1);
+                let count = match &self.inventory[slot] {
+                    Maybe::Some(item) => item.count,
+                    Maybe::None => 0,
+                };
+                vec![Value::Number(count as i64)]
+            }
+            "getFuelLevel" => vec![fuel_value(self.fuel)],
+            "getFuelLimit" => vec![fuel_value(self.max_fuel)],
+            "refuel" => self.refuel(args.first(), out)?,
+            "suck" => self.suck(Side::Forward, args.first(), world, out)?,
+            "suckUp" => self.suck(Side::Up, args.first(), world, out)?,
+            "suckDown" => self.suck(Side::Down, args.first(), world, out)?,
+            "drop" => self.drop(Side::Forward, args.first(), world, out)?,
+            "dropUp" => self.drop(Side::Up, args.first(), world, out)?,
+            "dropDown" => self.drop(Side::Down, args.first(), world, out)?,
+            "transferTo" => self.transfer_to(args)?,
+            _ => return Err(format!("attempt to call a nil value (field '{name}')")),
+        };
+        Ok(values)
+    }
+
+    fn move_(
+        &mut self,
+        dir: MoveDirection,
+        world: &SharedWorld,
+        out: impl Fn(T2SPackets),
+    ) -> Vec<Value> {
+        let (pos, orientation) = dir.apply(self.position, self.orientation);
+        let is_turn = pos == self.position;
+        let mut world = world.lock().unwrap();
+        if !is_turn && self.fuel == 0 {
+            return failed("Out of fuel");
+        }
+        if !is_turn && !world.is_passable(&pos) {
+            out(self.blocks_packet(&world));
+            out(T2SPackets::MoveFailed {
+                direction: dir,
+                reason: Maybe::Some("Movement obstructed".into()),
+            });
+            return failed("Movement obstructed");
+        }
+        world.leave(&self.position);
+        world.occupy(pos, self.index);
+        self.position = pos;
+        self.orientation = orientation;
+        if !is_turn && self.fuel > 0 {
+            self.fuel -= 1;
+        }
+        out(T2SPackets::Batch(vec![
+            T2SPackets::Moved { direction: dir },
+            T2SPackets::FuelUpdate(self.fuel),
+        ]));
+        out(self.blocks_packet(&world));
+        drop(world);
+        self.wait();
+        vec![Value::Bool(true)]
+    }
+
+    fn dig(&mut self, side: Side, world: &SharedWorld, out: impl Fn(T2SPackets)) -> Vec<Value> {
+        let pos = self.side_pos(side);
+        let mut world = world.lock().unwrap();
+        let Some(block) = world.get_block(&pos) else {
+            out(T2SPackets::DigFailed {
+                direction: side.into(),
+                reason: Maybe::Some("Nothing to dig here".into()),
+            });
+            return failed("Nothing to dig here");
+        };
+        world.set_block(pos, None);
+        let item = match block.as_str() {
+            "minecraft:stone" => "minecraft:cobblestone".to_owned(),
+            _ => block,
+        };
+        self.insert_item(&item, 1);
+        out(self.blocks_packet(&world));
+        out(self.inventory_packet());
+        drop(world);
+        self.wait();
+        vec![Value::Bool(true)]
+    }
+
+    fn place(&mut self, side: Side, world: &SharedWorld, out: impl Fn(T2SPackets)) -> Vec<Value> {
+        let pos = self.side_pos(side);
+        let slot = self.selected();
+        let place_failed = |reason: &str| {
+            out(T2SPackets::PlaceFailed {
+                direction: side.into(),
+                reason: Maybe::Some(reason.into()),
+            });
+            failed(reason)
+        };
+        let Maybe::Some(item) = self.inventory[slot].clone() else {
+            return place_failed("No items to place");
+        };
+        let mut world = world.lock().unwrap();
+        if !world.is_passable(&pos) {
+            return place_failed("Cannot place block here");
+        }
+        world.set_block(pos, Some(item.name.clone()));
+        self.inventory[slot] = match item.count {
+            1 => Maybe::None,
+            count => Maybe::Some(Item {
+                count: count - 1,
+                name: item.name,
+            }),
+        };
+        out(self.blocks_packet(&world));
+        out(self.inventory_packet());
+        drop(world);
+        self.wait();
+        vec![Value::Bool(true)]
+    }
+
+    fn inspect(&self, side: Side, world: &SharedWorld, out: impl Fn(T2SPackets)) -> Vec<Value> {
+        let world = world.lock().unwrap();
+        out(self.blocks_packet(&world));
+        match world.inspect(&self.side_pos(side)) {
+            Some(info) => vec![Value::Bool(true), Value::Str(info.name)],
+            None => failed("No block to inspect"),
+        }
+    }
+
+    fn refuel(
+        &mut self,
+        count: Option<&Value>,
+        out: impl Fn(T2SPackets),
+    ) -> Result<Vec<Value>, String> {
+        let slot = self.selected();
+        let Maybe::Some(item) = self.inventory[slot].clone() else {
+            return Ok(failed("No items to combust"));
+        };
+        let fuel_per_item = match item.name.as_str() {
+            "minecraft:coal" | "minecraft:charcoal" => 80,
+            "minecraft:coal_block" => 800,
+            "minecraft:lava_bucket" => 1000,
+            _ => return Ok(failed("Items not combustible")),
+        };
+        if self.fuel >= 0 {
+            let wanted = match count {
+                Some(Value::Number(n)) => *n as u32,
+                Some(_) => return Err("bad argument #1 (number expected)".into()),
+                None => item.count,
+            };
+            let used = wanted.min(item.count);
+            self.fuel = (self.fuel + (used * fuel_per_item) as i32).min(self.max_fuel);
+            self.inventory[slot] = match item.count - used {
+                0 => Maybe::None,
+                count => Maybe::Some(Item {
+                    count,
+                    name: item.name,
+                }),
+            };
+        }
+        out(T2SPackets::Batch(vec![
+            T2SPackets::FuelUpdate(self.fuel),
+            self.inventory_packet(),
+        ]));
+        Ok(vec![Value::Bool(true)])
+    }
+
+    /// Takes a stack out of the chest on `side`, the inventory is sent either way
+    fn suck(
+        &mut self,
+        side: Side,
+        count: Option<&Value>,
+        world: &SharedWorld,
+        out: impl Fn(T2SPackets),
+    ) -> Result<Vec<Value>, String> {
+        let count = match count {
+            None | Some(Value::Nil) => STACK_SIZE,
+            Some(Value::Number(n)) => *n as u32,
+            Some(_) => return Err("bad argument #1 (number expected)".into()),
+        };
+        let taken = world
+            .lock()
+            .unwrap()
+            .take_items(&self.side_pos(side), count);
+        let values = match taken {
+            Some(item) => {
+                self.insert_item(&item.name, item.count);
+                vec![Value::Bool(true)]
+            }
+            None => failed("No items to take"),
+        };
+        out(self.inventory_packet());
+        Ok(values)
+    }
+
+    /// Puts items of the selected slot into the chest on `side`, without a chest they end up on
+    /// the ground and are gone. A full chest takes nothing. The inventory is sent either way
+    fn drop(
+        &mut self,
+        side: Side,
+        count: Option<&Value>,
+        world: &SharedWorld,
+        out: impl Fn(T2SPackets),
+    ) -> Result<Vec<Value>, String> {
+        let count = match count {
+            None | Some(Value::Nil) => STACK_SIZE,
+            Some(Value::Number(n)) => *n as u32,
+            Some(_) => return Err("bad argument #1 (number expected)".into()),
+        };
+        let slot = self.selected();
+        let pos = self.side_pos(side);
+        let values = match &mut self.inventory[slot] {
+            Maybe::Some(item) => {
+                let dropped = Item {
+                    count: count.min(item.count),
+                    name: item.name.clone(),
+                };
+                match world.lock().unwrap().put_items(&pos, dropped.clone()) {
+                    Ok(()) => {
+                        item.count -= dropped.count;
+                        if item.count == 0 {
+                            self.inventory[slot] = Maybe::None;
+                        }
+                        vec![Value::Bool(true)]
+                    }
+                    Err(_) => failed("No space for items"),
+                }
+            }
+            Maybe::None => failed("No items to drop"),
+        };
+        out(self.inventory_packet());
+        Ok(values)
+    }
+
+    /// Moves items of the selected slot onto another slot, like `transferTo` nothing is sent
+    fn transfer_to(&mut self, args: &[Value]) -> Result<Vec<Value>, String> {
+        let to = slot_arg(args.first())?.ok_or("Expected number")? - 1;
+        let count = match args.get(1) {
+            None | Some(Value::Nil) => STACK_SIZE,
+            Some(Value::Number(n)) => *n as u32,
+            Some(_) => return Err("bad argument #2 (number expected)".into()),
+        };
+        let from = self.selected();
+        let Maybe::Some(item) = self.inventory[from].clone() else {
+            return Ok(vec![Value::Bool(false)]);
+        };
+        let (space, already) = match &self.inventory[to] {
+            Maybe::None => (STACK_SIZE, 0),
+            Maybe::Some(other) if to != from && other.name == item.name => {
+                (STACK_SIZE.saturating_sub(other.count), other.count)
+            }
+            Maybe::Some(_) => (0, 0),
+        };
+        let moved = count.min(item.count).min(space);
+        if moved == 0 {
+            return Ok(vec![Value::Bool(false)]);
+        }
+        self.inventory[from] = match item.count - moved {
+            0 => Maybe::None,
+            count => Maybe::Some(Item {
+                count,
+                name: item.name.clone(),
+            }),
+        };
+        self.inventory[to] = Maybe::Some(Item {
+            count: already + moved,
+            name: item.name,
+        });
+        Ok(vec![Value::Bool(true)])
+    }
+
+    /// Items another turtle dropped into this one. A real turtle sends its inventory on the
+    /// `turtle_inventory` event that follows
+    pub fn receive(&mut self, item: Item, out: &PacketSender) {
+        self.insert_item(&item.name, item.count);
+        _ = out.send(self.inventory_packet());
+    }
+
+    /// Does `action` the way `run_inventory_action` in util.lua does, gives the reason if it
+    /// failed
+    pub fn inventory_action(
+        &mut self,
+        action: &InventoryAction,
+        towards: Option<MoveDirection>,
+        world: &SharedWorld,
+        out: &PacketSender,
+    ) -> Option<String> {
+        let number = |n: u32| Value::Number(n as i64);
+        let count = |c: Option<u32>| c.map_or(Value::Nil, number);
+        let result = match *action {
+            InventoryAction::Select { slot } => {
+                self.call("select", &[number(slot.into())], world, out)
+            }
+            InventoryAction::Drop {
+                slot,
+                direction,
+                count: c,
+            } => self.with_slot("drop", slot, direction, count(c), world, out),
+            InventoryAction::Suck {
+                slot,
+                direction,
+                count: c,
+            } => self.with_slot("suck", slot, direction, count(c), world, out),
+            InventoryAction::Split { from, to, count: c } => {
+                self.transfer(from, to, number(c), out)
+            }
+            InventoryAction::Merge { from, to } => self.transfer(from, to, Value::Nil, out),
+            InventoryAction::Give { slot, count: c, .. } => {
+                let turns: &[&str] = match towards {
+                    Some(MoveDirection::Left) => &["turnLeft"],
+                    Some(MoveDirection::Right) => &["turnRight"],
+                    Some(MoveDirection::Back) => &["turnRight", "turnRight"],
+                    _ => &[],
+                };
+                let direction = match towards {
+                    Some(d @ (MoveDirection::Up | MoveDirection::Down)) => d,
+                    _ => MoveDirection::Forward,
+                };
+                let result = turns
+                    .iter()
+                    .try_for_each(|turn| self.call(turn, &[], world, out).map(drop))
+                    .and_then(|_| self.with_slot("drop", slot, direction, count(c), world, out));
+                // facing the way it did before, whether the drop worked or not
+                for turn in turns.iter().rev() {
+                    let back = match *turn {
+                        "turnLeft" => "turnRight",
+                        _ => "turnLeft",
+                    };
+                    _ = self.call(back, &[], world, out);
+                }
+                result
+            }
+        };
+        match result {
+            Ok(values) => match values.as_slice() {
+                [Value::Bool(false), Value::Str(reason), ..] => Some(reason.clone()),
+                [Value::Bool(false), ..] => Some("Failed".into()),
+                _ => None,
+            },
+            Err(err) => Some(err),
+        }
+    }
+
+    /// `drop` or `suck` with `slot` selected, the selection stays as it was
+    fn with_slot(
+        &mut self,
+        name: &str,
+        slot: u8,
+        direction: MoveDirection,
+        count: Value,
+        world: &SharedWorld,
+        out: &PacketSender,
+    ) -> Result<Vec<Value>, String> {
+        slot_arg(Some(&Value::Number(slot.into())))?;
+        let side = match direction {
+            MoveDirection::Up => Side::Up,
+            MoveDirection::Down => Side::Down,
+            _ => Side::Forward,
+        };
+        let selected = self.inventory.selected_slot;
+        self.inventory.selected_slot = slot;
+        let values = match name {
+            "drop" => self.drop(side, Some(&count), world, |_| {}),
+            _ => self.suck(side, Some(&count), world, |_| {}),
+        };
+        self.inventory.selected_slot = selected;
+        _ = out.send(self.inventory_packet());
+        values
+    }
+
+    /// `transferTo` from any slot, the selection stays as it was
+    fn transfer(
+        &mut self,
+        from: u8,
+        to: u8,
+        count: Value,
+        out: &PacketSender,
+    ) -> Result<Vec<Value>, String> {
+        slot_arg(Some(&Value::Number(from.into())))?;
+        let selected = self.inventory.selected_slot;
+        self.inventory.selected_slot = from;
+        let values = self.transfer_to(&[Value::Number(to.into()), count]);
+        self.inventory.selected_slot = selected;
+        _ = out.send(self.inventory_packet());
+        match values? {
+            values if values.first() == Some(&Value::Bool(false)) => {
+                Ok(failed("Could not move any items"))
+            }
+            values => Ok(values),
+        }
+    }
+
+    /// Puts items into existing stacks first, then into free slots starting at the selected one,
+    /// whatever doesn't fit is lost
+    pub fn insert_item(&mut self, name: &str, mut count: u32) {
+        let order = (0..16)
+            .map(|i| (self.selected() + i) % 16)
+            .collect::<Vec<_>>();
+        for slot in order.iter() {
+            if let Maybe::Some(item) = &mut self.inventory[*slot] {
+                if item.name == name && item.count < STACK_SIZE {
+                    let moved = count.min(STACK_SIZE - item.count);
+                    item.count += moved;
+                    count -= moved;
+                }
+            }
+        }
+        for slot in order {
+            if count == 0 {
+                return;
+            }
+            if let Maybe::None = self.inventory[slot] {
+                let moved = count.min(STACK_SIZE);
+                self.inventory[slot] = Maybe::Some(Item {
+                    count: moved,
+                    name: name.to_owned(),
+                });
+                count -= moved;
+            }
+        }
+    }
+}
+
+fn failed(reason: &str) -> Vec<Value> {
+    vec![Value::Bool(false), Value::Str(reason.to_owned())]
+}
+
+fn fuel_value(fuel: i32) -> Value {
+    match fuel < 0 {
+        true => Value::Str("unlimited".into()),
+        false => Value::Number(fuel as i64),
+    }
+}
+
+fn slot_arg(arg: Option<&Value>) -> Result<Option<usize>, String> {
+    match arg {
+        None | Some(Value::Nil) => Ok(None),
+        Some(Value::Number(n)) if (1..=16).contains(n) => Ok(Some(*n as usize)),
+        Some(Value::Number(n)) => Err(format!("Slot number {n} out of range")),
+        Some(_) => Err("bad argument #1 (number expected)".into()),
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    collections::HashMap,
+    sync::{mpsc::Sender, Arc, Weak},
+};
+
+use common::{turtle::Item, turtle_packets::InspectInfo, Pos3};
+
+use crate::Command;
+
+/// Stacks a chest holds
+pub const CHEST_SLOTS: usize = 27;
+
+/// In memory voxel world, everything below `ground_level` is generated stone (with some ores)
+/// unless a turtle changed it.
+#[derive(Debug, Clone, Default)]
+pub struct SimWorld {
+    changed: HashMap<Pos3, Option<String>>,
+    /// State and tags of changed blocks that have some
+    states: HashMap<Pos3, InspectInfo>,
+    ground_level: Option<i32>,
+    /// Positions currently occupied by a turtle and its index
+    turtles: HashMap<Pos3, i32>,
+    /// Where items dropped into a turtle go, by index. Gone with the connection of the turtle
+    inboxes: HashMap<i32, Weak<Sender<Command>>>,
+    /// Stacks in chests, turtles take the first one first
+    containers: HashMap<Pos3, Vec<Item>>,
+}
+
+impl SimWorld {
+    /// A world that is only air
+    pub fn empty() -> SimWorld {
+        SimWorld::default()
+    }
+
+    /// Solid ground below `ground_level`
+    pub fn flat(ground_level: i32) -> SimWorld {
+        SimWorld {
+            ground_level: Some(ground_level),
+            ..Default::default()
+        }
+    }
+
+    fn generated(&self, pos: &Pos3) -> Option<String> {
+        let ground_level = self.ground_level?;
+        if pos.y >= ground_level {
+            return None;
+        }
+        let noise = (pos.x * 31 + pos.y * 17 + pos.z * 13).rem_euclid(41);
+        Some(match noise {
+            0 => "minecraft:coal_ore".into(),
+            1 if pos.y < ground_level - 8 => "minecraft:iron_ore".into(),
+            _ => "minecraft:stone".into(),
+        })
+    }
+
+    pub fn get_block(&self, pos: &Pos3) -> Option<String> {
+        match self.changed.get(pos) {
+            Some(block) => block.clone(),
+            None => self.generated(pos),
+        }
+    }
+
+    pub fn set_block(&mut self, pos: Pos3, block: Option<String>) {
+        self.states.remove(&pos);
+        self.changed.insert(pos, block);
+    }
+
+    /// Places a block with block state and tags
+    pub fn set_inspected(&mut self, pos: Pos3, info: InspectInfo) {
+        self.changed.insert(pos, Some(info.name.clone()));
+        self.states.insert(pos, info);
+    }
+
+    /// What a turtle would see with `turtle.inspect`, other turtles included
+    pub fn inspect(&self, pos: &Pos3) -> Option<InspectInfo> {
+        if self.turtles.contains_key(pos) {
+            return Some(InspectInfo::new("computercraft:turtle_normal"));
+        }
+        match self.states.get(pos) {
+            Some(info) => Some(info.clone()),
+            None => self.get_block(pos).map(InspectInfo::new),
+        }
+    }
+
+    /// Water and the like don't stop turtles
+    pub fn is_passable(&self, pos: &Pos3) -> bool {
+        !self.turtles.contains_key(pos)
+            && self.get_block(pos).is_none_or(|b| b == "minecraft:water")
+    }
+
+    /// Places a chest holding `items`
+    pub fn set_container(&mut self, pos: Pos3, items: Vec<Item>) {
+        self.set_block(pos, Some("minecraft:chest".into()));
+        self.containers.insert(pos, items);
+    }
+
+    pub fn container(&self, pos: &Pos3) -> Option<&[Item]> {
+        self.containers.get(pos).map(Vec::as_slice)
+    }
+
+    /// Up to `count` items of the first stack in the chest at `pos`
+    pub fn take_items(&mut self, pos: &Pos3, count: u32) -> Option<Item> {
+        let items = self.containers.get_mut(pos)?;
+        let first = items.first_mut()?;
+        let taken = count.min(first.count);
+        first.count -= taken;
+        let name = first.name.clone();
+        if first.count == 0 {
+            items.remove(0);
+        }
+        Some(Item { count: taken, name })
+    }
+
+    /// Puts `item` into the turtle or chest at `pos`, gives it back if the chest is full.
+    /// Without either it lands on the ground and is gone
+    pub fn put_items(&mut self, pos: &Pos3, item: Item) -> Result<(), Item> {
+        let inbox = self
+            .get_turtle_at(pos)
+            .and_then(|index| self.inboxes.get(&index))
+            .and_then(Weak::upgrade);
+        if let Some(inbox) = inbox {
+            if inbox.send(Command::Receive(item.clone())).is_ok() {
+                return Ok(());
+            }
+        }
+        match self.containers.get_mut(pos) {
+            Some(items) if items.len() >= CHEST_SLOTS => Err(item),
+            Some(items) => {
+                items.push(item);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn get_turtle_at(&self, pos: &Pos3) -> Option<i32> {
+        self.turtles.get(pos).copied()
+    }
+
+    pub fn occupy(&mut self, pos: Pos3, index: i32) {
+        self.turtles.insert(pos, index);
+    }
+
+    pub fn leave(&mut self, pos: &Pos3) {
+        self.turtles.remove(pos);
+    }
+
+    pub(crate) fn set_inbox(&mut self, index: i32, inbox: &Arc<Sender<Command>>) {
+        self.inboxes.insert(index, Arc::downgrade(inbox));
+    }
+}